@@ -0,0 +1,52 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+use std::{env, path::Path, time::Duration};
+
+use release_artifacts::{
+    abort_stale_uploads, capture_env, select_credentials, CredentialScope, RecoveredUpload,
+};
+use release_commands::apply_storage_profile;
+
+// Younger journals may belong to a save that is still uploading.
+const DEFAULT_MIN_AGE_HOURS: u64 = 24;
+
+#[tokio::main]
+pub(crate) async fn main() {
+    let min_age_hours = match env::args().nth(1).map(|hours| hours.parse::<u64>()) {
+        None => DEFAULT_MIN_AGE_HOURS,
+        Some(Ok(hours)) => hours,
+        Some(Err(_)) => {
+            eprintln!("usage: abort-stale-uploads [<min-age-hours>]");
+            std::process::exit(1);
+        }
+    };
+
+    let mut env = capture_env(Path::new("/etc/heroku"));
+    let commands_toml_path = env::var("RELEASE_COMMANDS_TOML").unwrap_or_default();
+    if let Err(error) = apply_storage_profile(Path::new(&commands_toml_path), &mut env) {
+        eprintln!("abort-stale-uploads failed: {error}");
+        std::process::exit(1);
+    }
+    select_credentials(&mut env, CredentialScope::ReadWrite);
+
+    match abort_stale_uploads(&env, Duration::from_secs(min_age_hours * 60 * 60)).await {
+        Ok(recovered) => {
+            for upload in &recovered {
+                match upload {
+                    RecoveredUpload::Completed(key) => println!("completed  {key}"),
+                    RecoveredUpload::Aborted(key) => println!("aborted    {key}"),
+                }
+            }
+            eprintln!(
+                "abort-stale-uploads complete, {} stale uploads recovered.",
+                recovered.len()
+            );
+            std::process::exit(0);
+        }
+        Err(error) => {
+            eprintln!("abort-stale-uploads failed: {error:#?}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+use std::{env, path::Path};
+
+use release_artifacts::{
+    capture_env, check_storage_access, select_credentials, Access, CredentialScope,
+};
+use release_commands::apply_storage_profile;
+
+#[tokio::main]
+pub(crate) async fn main() {
+    let mut env = capture_env(Path::new("/etc/heroku"));
+    let commands_toml_path = env::var("RELEASE_COMMANDS_TOML").unwrap_or_default();
+    if let Err(error) = apply_storage_profile(Path::new(&commands_toml_path), &mut env) {
+        eprintln!("check-storage-access failed: {error}");
+        std::process::exit(1);
+    }
+    select_credentials(&mut env, CredentialScope::ReadWrite);
+
+    match check_storage_access(&env).await {
+        Ok(checks) => {
+            let mut missing = 0;
+            for (action, access) in &checks {
+                match access {
+                    Access::Allowed => println!("allowed  {}", action.name()),
+                    Access::Denied => {
+                        missing += 1;
+                        println!(
+                            "denied   {}, which breaks: {}",
+                            action.name(),
+                            action.features()
+                        );
+                    }
+                    Access::Unknown => println!(
+                        "unknown  {}, which would break: {}",
+                        action.name(),
+                        action.features()
+                    ),
+                }
+            }
+            if missing > 0 {
+                eprintln!("check-storage-access failed, {missing} permissions missing.");
+                std::process::exit(1);
+            }
+            eprintln!("check-storage-access complete.");
+            std::process::exit(0);
+        }
+        Err(error) => {
+            eprintln!("check-storage-access failed: {error:#?}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+use std::{env, path::Path};
+
+use release_artifacts::{
+    capture_env, format_size, list_stored_archives, select_credentials, CredentialScope,
+};
+use release_commands::apply_storage_profile;
+
+#[tokio::main]
+pub(crate) async fn main() {
+    let mut env = capture_env(Path::new("/etc/heroku"));
+    let commands_toml_path = env::var("RELEASE_COMMANDS_TOML").unwrap_or_default();
+    if let Err(error) = apply_storage_profile(Path::new(&commands_toml_path), &mut env) {
+        eprintln!("du-release-artifacts failed: {error}");
+        std::process::exit(1);
+    }
+    select_credentials(&mut env, CredentialScope::ReadOnly);
+
+    match list_stored_archives(&env).await {
+        Ok(archives) => {
+            let total_size: u64 = archives.iter().map(|a| a.size).sum();
+            for archive in &archives {
+                println!("{:>12}  {}", format_size(archive.size), archive.key);
+            }
+            println!(
+                "{:>12}  total for {} archives",
+                format_size(total_size),
+                archives.len()
+            );
+            eprintln!("du-release-artifacts complete.");
+            std::process::exit(0);
+        }
+        Err(error) => {
+            eprintln!("du-release-artifacts failed: {error:#?}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -1,116 +1,115 @@
 // Required due to: https://github.com/rust-lang/rust/issues/95513
 #![allow(unused_crate_dependencies)]
 
-use core::time;
-use std::{
-    env,
-    path::Path,
-    process::{Command, Stdio},
-};
+//! The executor of the release sequence, installed on its own rather than as a symlink to
+//! `release-phase-multicall`, so that it starts without the async & AWS dependencies of artifact
+//! storage. Releases that use rerun protection, idempotency keys, or notifications, which need
+//! that stack, are handed to the multi-call binary.
 
-use release_commands::read_commands_config;
+#[path = "exec-release-commands/executor.rs"]
+mod executor;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let commands_toml_path = if let Some(p) = args.get(1) {
-        Path::new(p)
-    } else {
-        eprintln!("release-phase failed: exec command requires argument, the path to release-commands.toml");
-        std::process::exit(1);
-    };
-    match exec_release_sequence(commands_toml_path) {
-        Ok(()) => {
-            eprintln!("release-phase complete.");
-            // Work-around to allow logs to flush before exit.
-            std::thread::sleep(time::Duration::from_secs(1));
-            std::process::exit(0);
+    executor::main();
+}
+
+mod release_storage {
+    use std::{collections::HashMap, convert::Infallible, env, io::Write, path::Path};
+
+    use release_commands::{apply_storage_profile, read_commands_config};
+    use release_log::log_debug;
+
+    // Hands the release to the multi-call binary when it uses a feature that needs artifact
+    // storage or the async stack. Otherwise, there are no completion markers.
+    pub(crate) fn completion_markers(
+        commands_toml_path: &Path,
+    ) -> (HashMap<String, String>, Option<CompletionMarkers>) {
+        if uses_storage(commands_toml_path) {
+            hand_off();
         }
-        Err(error) => {
-            eprintln!("release-phase failed: {error}");
-            // Work-around to allow logs to flush before exit.
-            std::thread::sleep(time::Duration::from_secs(1));
-            std::process::exit(1);
+        log_debug!("release-phase rerun protection disabled, without RELEASE_PHASE_RERUN_PROTECTION, idempotency keys, or artifact storage");
+        (HashMap::new(), None)
+    }
+
+    // Whether notifications apply, or rerun protection, when opted into, or idempotency keys,
+    // with artifact storage. Merely configuring storage, such as to save artifacts, is not
+    // handed off. An invalid storage profile is handed off too, so that the multi-call binary
+    // warns of it.
+    fn uses_storage(commands_toml_path: &Path) -> bool {
+        let mut env: HashMap<String, String> = env::vars().collect();
+        if env
+            .get("RELEASE_PHASE_NOTIFY_URL")
+            .is_some_and(|url| !url.is_empty())
+        {
+            return true;
         }
+        let uses_markers = env
+            .get("RELEASE_PHASE_RERUN_PROTECTION")
+            .is_some_and(|v| v == "1" || v == "true")
+            || read_commands_config(commands_toml_path)
+                .is_ok_and(|commands| commands.uses_idempotency_keys());
+        uses_markers
+            && (apply_storage_profile(commands_toml_path, &mut env).is_err()
+                || env.contains_key("STATIC_ARTIFACTS_URL"))
+    }
+
+    // Replaces this process with the multi-call binary beside it, invoked as
+    // `exec-release-commands` with the same args. Returns, only warning, when it cannot.
+    fn hand_off() {
+        let Some(multicall) = env::current_exe()
+            .ok()
+            .map(|exe| exe.with_file_name("release-phase-multicall"))
+            .filter(|multicall| multicall.is_file())
+        else {
+            eprintln!("release-phase warning: rerun protection & notifications disabled, release-phase-multicall is not beside exec-release-commands");
+            return;
+        };
+        log_debug!("release-phase handing the release to {multicall:?}, for artifact storage");
+        std::io::stdout().flush().unwrap_or_default();
+        std::io::stderr().flush().unwrap_or_default();
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+
+            let error = std::process::Command::new(&multicall)
+                .arg0("exec-release-commands")
+                .args(env::args_os().skip(1))
+                .exec();
+            eprintln!("release-phase warning: rerun protection & notifications disabled, executing {multicall:?}, {error}");
+        }
+        #[cfg(not(unix))]
+        eprintln!("release-phase warning: rerun protection & notifications disabled, {multicall:?} can only be handed the release on Unix");
     }
-}
 
-fn exec_release_sequence(commands_toml_path: &Path) -> Result<(), release_commands::Error> {
-    let config = read_commands_config(commands_toml_path)?;
-    eprintln!("release-phase plan, {config}");
+    // Never constructed, as there is no storage for markers.
+    pub(crate) struct CompletionMarkers(Infallible);
 
-    if let Some(release_build_config) = config.release_build {
-        eprintln!("release-phase executing release-build command: {release_build_config}");
-        let mut cmd = Command::new(release_build_config.command);
-        if let Some(args) = release_build_config.args {
-            cmd.args(args.clone());
+    impl CompletionMarkers {
+        pub(crate) fn release_id(&self) -> &str {
+            match self.0 {}
         }
 
-        let status = cmd
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .status()
-            .map_err(release_commands::Error::ReleaseCommandExecError)?;
-
-        if status.code() != Some(0) {
-            return Err(release_commands::Error::ReleaseCommandExitedError(format!(
-                "command exited with status code {}",
-                status.code().expect("status code to exist")
-            )));
+        pub(crate) fn is_release_complete(&self) -> bool {
+            match self.0 {}
         }
-    };
-
-    if let Some(release_config) = config.release {
-        for config in &release_config {
-            eprintln!("release-phase executing release command: {config}");
-            let mut cmd = Command::new(&config.command);
-            if let Some(args) = &config.args {
-                cmd.args(args.clone());
-            }
-
-            let status = cmd
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .status()
-                .map_err(release_commands::Error::ReleaseCommandExecError)?;
-
-            if status.code() != Some(0) {
-                return Err(release_commands::Error::ReleaseCommandExitedError(format!(
-                    "command exited with status code {}",
-                    status.code().expect("status code to exist")
-                )));
-            }
+
+        pub(crate) fn mark_release_complete(&self) {
+            match self.0 {}
         }
-    };
 
-    Ok(())
-}
+        pub(crate) fn is_step_complete(&self, _idempotency_key: &str) -> bool {
+            match self.0 {}
+        }
+
+        pub(crate) fn mark_step_complete(&self, _idempotency_key: &str) {
+            match self.0 {}
+        }
+    }
 
-#[cfg(test)]
-mod tests {
-    use std::{
-        fs::{self, remove_file},
-        path::Path,
-    };
-
-    use crate::exec_release_sequence;
-
-    #[test]
-    fn invokes_command_sequence() {
-        let expected_output = r"1. Release Build from all release commands
-2. Release from all release commands
-3. Another release from all release commands
-";
-
-        exec_release_sequence(Path::new(
-            "tests/fixtures/uses_all_release_commands/release-commands.toml",
-        ))
-        .expect("release commands completed");
-
-        let result_path = Path::new(
-            "tests/fixtures/uses_all_release_commands/exec-release-commands-test-output.txt",
-        );
-        let result_output = fs::read_to_string(result_path).unwrap();
-        remove_file(result_path).expect("test result output file is deleted");
-        assert_eq!(result_output, expected_output);
+    // Releases with a notification target were handed off, so there is none to notify.
+    pub(crate) fn notify_release(
+        _env: &HashMap<String, String>,
+        _result: &Result<(), release_commands::Error>,
+    ) {
     }
 }
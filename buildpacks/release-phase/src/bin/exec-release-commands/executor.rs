@@ -0,0 +1,492 @@
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use release_commands::{
+    environment_snapshot, execute, lint_project_config, project_config_json_schema,
+    read_commands_config, simulate, take_flag, take_option, verify_commands_config,
+    CommandsVerification, Deadline, EventStream, ExecHooks, ExecOptions, ExecutionReport,
+    ResolvedPlan, Step, StepAction, StepOutcome,
+};
+use release_log::{log_debug, log_info};
+
+use crate::release_storage::{completion_markers, notify_release, CompletionMarkers};
+
+pub(crate) fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let print_procfile = take_flag(&mut args, "--print-procfile");
+    let verify = take_flag(&mut args, "--verify");
+    let flags = take_exec_flags(&mut args);
+    run_config_mode(&mut args);
+    let commands_toml_path = if let Some(p) = args.first() {
+        Path::new(p)
+    } else {
+        eprintln!("release-phase failed: exec command requires argument, the path to release-commands.toml");
+        exit(1);
+    };
+    // First, as without artifact storage, the release is handed to the multi-call binary when it
+    // uses rerun protection, idempotency keys, or notifications.
+    let (env, markers) = completion_markers(commands_toml_path);
+    if verify {
+        warn_if_changed(commands_toml_path);
+    }
+    if print_procfile {
+        exit(print_procfile_command(commands_toml_path));
+    }
+    let deadline = deadline_from_env(Instant::now());
+    if let Some(markers) = markers.as_ref().filter(|m| m.is_release_complete()) {
+        if flags.force {
+            log_info!(
+                "release-phase rerunning release {}, which already completed, with --force",
+                markers.release_id()
+            );
+        } else {
+            eprintln!(
+                "release-phase skipping, release {} already completed. To rerun it, use --force.",
+                markers.release_id()
+            );
+            exit(0);
+        }
+    }
+    let hooks = ReleaseHooks {
+        markers: markers.as_ref(),
+        step: flags.step,
+        events: EventStream::from_env(),
+    };
+    let options = ExecOptions {
+        prefix_output: flags.prefix_output,
+        force: flags.force,
+        hooks: Some(&hooks),
+        log_dir: env::var_os("RELEASE_LOG_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from),
+        deadline,
+    };
+    let report = exec_release_sequence(commands_toml_path, &options);
+    if let Some(report_path) = flags.report_path {
+        write_report(&report, Path::new(&report_path));
+    }
+    notify_release(&env, &report.result);
+    match &report.result {
+        Ok(()) => {
+            // Commands may have been skipped while stepping, so the release is not marked.
+            if let Some(markers) = markers.as_ref().filter(|_| flags.step.is_none()) {
+                markers.mark_release_complete();
+            }
+            eprintln!("release-phase complete.");
+        }
+        Err(error) => eprintln!("release-phase failed: {error}"),
+    }
+    exit(report.exit_code());
+}
+
+// The flags of executing the release sequence, taken before the path argument.
+struct ExecFlags {
+    prefix_output: bool,
+    force: bool,
+    report_path: Option<String>,
+    step: Option<StepMode>,
+}
+
+fn take_exec_flags(args: &mut Vec<String>) -> ExecFlags {
+    ExecFlags {
+        prefix_output: !take_flag(args, "--no-prefix"),
+        force: take_flag(args, "--force"),
+        report_path: take_option(args, "--report").unwrap_or_else(|error| {
+            eprintln!("release-phase failed: {error}");
+            exit(1);
+        }),
+        step: take_flag(args, "--step").then(|| {
+            if env::var("RELEASE_PHASE_STEP_AUTO_CONTINUE").is_ok_and(|v| v == "1" || v == "true") {
+                StepMode::AutoContinue
+            } else {
+                StepMode::Interactive
+            }
+        }),
+    }
+}
+
+// Runs, then exits, the modes that inspect the project config instead of executing it:
+// `--print-schema`, `--lint`, & `--dry-run`. Returns when none is selected.
+fn run_config_mode(args: &mut Vec<String>) {
+    if take_flag(args, "--print-schema") {
+        match project_config_json_schema() {
+            Ok(schema) => {
+                println!("{schema}");
+                exit(0);
+            }
+            Err(error) => {
+                eprintln!("release-phase failed: {error}");
+                exit(1);
+            }
+        }
+    }
+    if take_flag(args, "--lint") {
+        let json = take_flag(args, "--json");
+        let project_toml_path = args.first().map_or("project.toml", String::as_str);
+        exit(lint(Path::new(project_toml_path), json));
+    }
+    if take_flag(args, "--dry-run") {
+        let json = take_flag(args, "--json");
+        let project_toml_path = args.first().map_or("project.toml", String::as_str);
+        exit(dry_run(Path::new(project_toml_path), json));
+    }
+}
+
+fn print_procfile_command(commands_toml_path: &Path) -> i32 {
+    match read_commands_config(commands_toml_path) {
+        Ok(config) => {
+            println!("release: {}", config.to_shell_command());
+            0
+        }
+        Err(error) => {
+            eprintln!("release-phase failed: {error}");
+            1
+        }
+    }
+}
+
+// Exits once the output is flushed. The output of commands was already flushed line by line, as
+// it was copied, so no output is lost when the platform stops the release process on exit.
+fn exit(code: i32) -> ! {
+    io::stdout().flush().unwrap_or_default();
+    io::stderr().flush().unwrap_or_default();
+    std::process::exit(code);
+}
+
+// The deadline of RELEASE_PHASE_MAX_DURATION, exiting when it is invalid.
+fn deadline_from_env(start: Instant) -> Option<Deadline> {
+    Deadline::from_env(start).unwrap_or_else(|error| {
+        eprintln!("release-phase failed: {error}");
+        exit(1);
+    })
+}
+
+// Warns when release-commands.toml was changed in the image since it was written at build time.
+// Only warns, so that the release proceeds as it would have without verifying.
+fn warn_if_changed(commands_toml_path: &Path) {
+    match verify_commands_config(commands_toml_path) {
+        Ok(CommandsVerification::Unchanged) => {
+            log_info!("release-phase verified {commands_toml_path:?} is unchanged since build");
+        }
+        Ok(CommandsVerification::Changed { recorded, actual }) => {
+            eprintln!("release-phase warning: {commands_toml_path:?} was changed since build, from {recorded} to {actual}");
+        }
+        Ok(CommandsVerification::NotRecorded) => {
+            eprintln!("release-phase warning: {commands_toml_path:?} cannot be verified, no digest was recorded at build");
+        }
+        Err(error) => {
+            eprintln!("release-phase warning: {commands_toml_path:?} cannot be verified, {error}");
+        }
+    }
+}
+
+// Validates the release config of a project.toml, printing the plan that it resolves to, as
+// JSON when `json`. Returns the exit code: non-zero when the config is invalid.
+fn lint(project_toml_path: &Path, json: bool) -> i32 {
+    let project_config = match read_project_config(project_toml_path) {
+        Ok(project_config) => project_config,
+        Err(error) => {
+            eprintln!("release-phase lint failed: {error}");
+            return 1;
+        }
+    };
+    match lint_project_config(&project_config).and_then(|(config, warnings)| {
+        simulate(&project_config, toml::Table::new()).map(|plan| (config, warnings, plan))
+    }) {
+        Ok((config, warnings, plan)) => {
+            for warning in &warnings {
+                eprintln!("release-phase lint warning: {warning}");
+            }
+            if let Err(error) = print_plan(&plan, json) {
+                eprintln!("release-phase lint failed: {error}");
+                return 1;
+            }
+            if !json {
+                println!("release: {}", config.to_shell_command());
+            }
+            eprintln!("release-phase lint complete, {} warnings.", warnings.len());
+            0
+        }
+        Err(error) => {
+            eprintln!("release-phase lint failed: {error}");
+            1
+        }
+    }
+}
+
+// Prints the plan that a project.toml resolves to, as JSON when `json`, without executing any
+// of it. Returns the exit code: non-zero when the plan cannot be resolved.
+fn dry_run(project_toml_path: &Path, json: bool) -> i32 {
+    let printed = read_project_config(project_toml_path).and_then(|project_config| {
+        let plan = simulate(&project_config, toml::Table::new()).map_err(|e| e.to_string())?;
+        print_plan(&plan, json)
+    });
+    match printed {
+        Ok(()) => {
+            eprintln!("release-phase dry run complete, nothing was executed.");
+            0
+        }
+        Err(error) => {
+            eprintln!("release-phase dry run failed: {error}");
+            1
+        }
+    }
+}
+
+fn read_project_config(project_toml_path: &Path) -> Result<toml::Value, String> {
+    fs::read_to_string(project_toml_path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| toml::from_str::<toml::Value>(&contents).map_err(|e| e.to_string()))
+        .map_err(|error| format!("reading {project_toml_path:?}, {error}"))
+}
+
+fn print_plan(plan: &ResolvedPlan, json: bool) -> Result<(), String> {
+    if json {
+        println!("{}", plan.to_json().map_err(|e| e.to_string())?);
+    } else {
+        print!("release-phase plan:\n{plan}");
+    }
+    Ok(())
+}
+
+// How `--step` pauses before each command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StepMode {
+    Interactive,
+    // Announces each command without waiting, from `RELEASE_PHASE_STEP_AUTO_CONTINUE`.
+    AutoContinue,
+}
+
+// Logs the progress of the release sequence, pauses before each step for `--step`, records
+// the completion of steps with an idempotency key in the completion markers, and streams step
+// events, when configured.
+pub(crate) struct ReleaseHooks<'a> {
+    pub(crate) markers: Option<&'a CompletionMarkers>,
+    pub(crate) step: Option<StepMode>,
+    pub(crate) events: Option<EventStream>,
+}
+
+impl ExecHooks for ReleaseHooks<'_> {
+    fn progress(&self, message: &str) {
+        log_info!("{message}");
+    }
+
+    fn detail(&self, message: &str) {
+        log_debug!("{message}");
+    }
+
+    fn before_step(&self, step: &Step) -> StepAction {
+        let Some(mode) = self.step else {
+            return StepAction::Execute;
+        };
+        let description = format!(
+            "{}/{} {}: {}",
+            step.number, step.total, step.kind, step.executable
+        );
+        pause_for_step(&mut io::stdin().lock(), mode, &description)
+    }
+
+    fn is_step_complete(&self, idempotency_key: &str) -> bool {
+        self.markers
+            .is_some_and(|markers| markers.is_step_complete(idempotency_key))
+    }
+
+    fn mark_step_complete(&self, idempotency_key: &str) {
+        if let Some(markers) = self.markers {
+            markers.mark_step_complete(idempotency_key);
+        }
+    }
+
+    fn step_started(&self, step: &Step) {
+        if let Some(events) = &self.events {
+            events.step_started(step);
+        }
+    }
+
+    fn step_finished(&self, step: &Step, outcome: &StepOutcome) {
+        if let Some(events) = &self.events {
+            events.step_finished(step, outcome);
+        }
+    }
+}
+
+// Executes the release sequence of the config, printing the environment of a failed command.
+pub(crate) fn exec_release_sequence(
+    commands_toml_path: &Path,
+    options: &ExecOptions,
+) -> ExecutionReport {
+    let config = match read_commands_config(commands_toml_path) {
+        Ok(config) => config,
+        Err(error) => {
+            return ExecutionReport {
+                steps: vec![],
+                result: Err(error),
+            }
+        }
+    };
+    log_info!("release-phase plan, {config}");
+
+    let report = execute(&config, options);
+    if let Some(failed) = report.steps.iter().find(|s| s.outcome.is_failure()) {
+        eprintln!(
+            "{}",
+            environment_snapshot(&failed.executable, env::vars_os())
+        );
+    }
+    report
+}
+
+// Writes the JSON of the report to `--report`, warning when it cannot.
+fn write_report(report: &ExecutionReport, report_path: &Path) {
+    match report
+        .to_json()
+        .map_err(|e| e.to_string())
+        .and_then(|json| fs::write(report_path, json + "\n").map_err(|e| e.to_string()))
+    {
+        Ok(()) => log_debug!("release-phase wrote report to {report_path:?}"),
+        Err(error) => {
+            eprintln!("release-phase warning: writing report to {report_path:?}, {error}");
+        }
+    }
+}
+
+// Pauses before a command, for `--step`, until a line of input: empty to execute it, `s` to skip
+// it, or `q` to quit. Continues at the end of input, such as when not attached to a terminal.
+fn pause_for_step(input: &mut impl BufRead, mode: StepMode, description: &str) -> StepAction {
+    loop {
+        eprint!("release-phase step {description}\n  [Enter] to execute, [s]kip, or [q]uit: ");
+        if mode == StepMode::AutoContinue {
+            eprintln!("auto-continuing");
+            return StepAction::Execute;
+        }
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                eprintln!();
+                return StepAction::Execute;
+            }
+            Ok(_) => match line.trim() {
+                "" => return StepAction::Execute,
+                "s" | "skip" => return StepAction::Skip,
+                "q" | "quit" => return StepAction::Quit,
+                _ => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::{self, remove_file},
+        path::Path,
+    };
+
+    use release_commands::{ExecOptions, StepAction};
+
+    use super::{dry_run, exec_release_sequence, lint, pause_for_step, write_report, StepMode};
+
+    #[test]
+    fn invokes_command_sequence() {
+        let expected_output = r"1. Release Build from all release commands
+2. Release from all release commands
+3. Another release from all release commands
+";
+
+        exec_release_sequence(
+            Path::new("tests/fixtures/uses_all_release_commands/release-commands.toml"),
+            &ExecOptions {
+                prefix_output: true,
+                ..ExecOptions::default()
+            },
+        )
+        .result
+        .expect("release commands completed");
+
+        let result_path = Path::new(
+            "tests/fixtures/uses_all_release_commands/exec-release-commands-test-output.txt",
+        );
+        let result_output = fs::read_to_string(result_path).unwrap();
+        remove_file(result_path).expect("test result output file is deleted");
+        assert_eq!(result_output, expected_output);
+    }
+
+    #[test]
+    fn lint_validates_project_toml() {
+        assert_eq!(
+            lint(
+                Path::new("tests/fixtures/project_uses_release/project.toml"),
+                false
+            ),
+            0
+        );
+        assert_eq!(
+            lint(
+                Path::new("tests/fixtures/project_uses_release/project.toml"),
+                true
+            ),
+            0
+        );
+        assert_eq!(
+            lint(
+                Path::new("tests/fixtures/no_project_toml/project.toml"),
+                false
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn dry_run_resolves_plan_without_executing() {
+        let project_toml_path = Path::new("tests/fixtures/project_uses_release/project.toml");
+        assert_eq!(dry_run(project_toml_path, false), 0);
+        assert_eq!(dry_run(project_toml_path, true), 0);
+        assert_eq!(
+            dry_run(
+                Path::new("tests/fixtures/no_project_toml/project.toml"),
+                false
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn writes_report_of_sequence() {
+        let report_path = env::temp_dir().join(format!("exec-report-{}.json", std::process::id()));
+        let report = exec_release_sequence(
+            Path::new("tests/fixtures/uses_failing_release_command/release-commands.toml"),
+            &ExecOptions::default(),
+        );
+        write_report(&report, &report_path);
+        let report_json = fs::read_to_string(&report_path).unwrap();
+        remove_file(&report_path).unwrap_or_default();
+
+        assert!(
+            report_json.contains("\"schema-version\": 1"),
+            "{report_json}"
+        );
+        assert!(
+            report_json.contains("\"status\": \"failed\""),
+            "{report_json}"
+        );
+        assert!(
+            report_json.contains("\"command\": \"false\""),
+            "{report_json}"
+        );
+    }
+
+    #[test]
+    fn pause_for_step_reads_action() {
+        let pause = |input: &str, mode| pause_for_step(&mut input.as_bytes(), mode, "1/1 test");
+        assert_eq!(pause("\n", StepMode::Interactive), StepAction::Execute);
+        assert_eq!(pause("s\n", StepMode::Interactive), StepAction::Skip);
+        assert_eq!(pause("what\nq\n", StepMode::Interactive), StepAction::Quit);
+        assert_eq!(pause("", StepMode::Interactive), StepAction::Execute);
+        assert_eq!(pause("q\n", StepMode::AutoContinue), StepAction::Execute);
+    }
+}
@@ -0,0 +1,249 @@
+//! Rerun protection & release notifications of `exec-release-commands`, which use artifact
+//! storage, and so the async AWS stack. Only the multi-call binary has them, so that the
+//! executor installed on its own starts without that stack.
+
+use std::{collections::HashMap, env, path::Path};
+
+use release_artifacts::{
+    capture_env, has_completion_marker, notify, put_completion_marker, select_credentials,
+    CredentialScope, ReleaseNotification,
+};
+use release_commands::apply_storage_profile;
+use release_log::{log_debug, log_info};
+
+// The env of artifact storage & notifications, with the storage profile of the config
+// applied, and the completion markers in that storage.
+pub(crate) fn completion_markers(
+    commands_toml_path: &Path,
+) -> (HashMap<String, String>, Option<CompletionMarkers>) {
+    let mut env = capture_env(Path::new("/etc/heroku"));
+    for key in [
+        "RELEASE_PHASE_NOTIFY_URL",
+        "RELEASE_PHASE_RERUN_PROTECTION",
+        "HEROKU_APP_NAME",
+    ] {
+        if let Ok(value) = env::var(key) {
+            env.insert(key.to_string(), value);
+        }
+    }
+    match apply_storage_profile(commands_toml_path, &mut env) {
+        Ok(()) => {
+            select_credentials(&mut env, CredentialScope::ReadWrite);
+            let markers = CompletionMarkers::new(env.clone());
+            (env, markers)
+        }
+        Err(error) => {
+            eprintln!("release-phase warning: rerun protection disabled, {error}");
+            (env, None)
+        }
+    }
+}
+
+// Completion markers in artifact storage, keyed by RELEASE_ID, so that the platform retrying
+// a release process does not rerun its commands: the whole release, when opted into with
+// RELEASE_PHASE_RERUN_PROTECTION, and each step with an idempotency key. Without a RELEASE_ID
+// or artifact storage, there are none. Marker failures only warn, so that storage outages do
+// not block releases.
+pub(crate) struct CompletionMarkers {
+    env: HashMap<String, String>,
+    release_id: String,
+    protects_release: bool,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl CompletionMarkers {
+    pub(crate) fn new(env: HashMap<String, String>) -> Option<Self> {
+        let release_id = env.get("RELEASE_ID").filter(|id| !id.is_empty())?.clone();
+        let protects_release = env
+            .get("RELEASE_PHASE_RERUN_PROTECTION")
+            .is_some_and(|v| v == "1" || v == "true");
+        if !env.contains_key("STATIC_ARTIFACTS_URL") {
+            log_debug!("release-phase rerun protection disabled, without artifact storage");
+            return None;
+        }
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|error| {
+                eprintln!("release-phase warning: rerun protection disabled, {error}");
+            })
+            .ok()?;
+        Some(CompletionMarkers {
+            env,
+            release_id,
+            protects_release,
+            runtime,
+        })
+    }
+
+    pub(crate) fn release_id(&self) -> &str {
+        &self.release_id
+    }
+
+    pub(crate) fn is_release_complete(&self) -> bool {
+        self.protects_release && self.is_complete(&format!("{}/complete", self.release_id))
+    }
+
+    pub(crate) fn mark_release_complete(&self) {
+        if self.protects_release {
+            self.mark_complete(&format!("{}/complete", self.release_id));
+        }
+    }
+
+    pub(crate) fn is_step_complete(&self, idempotency_key: &str) -> bool {
+        self.is_complete(&format!("{}/steps/{idempotency_key}", self.release_id))
+    }
+
+    pub(crate) fn mark_step_complete(&self, idempotency_key: &str) {
+        self.mark_complete(&format!("{}/steps/{idempotency_key}", self.release_id));
+    }
+
+    fn is_complete(&self, marker: &str) -> bool {
+        self.runtime
+            .block_on(has_completion_marker(&self.env, marker))
+            .unwrap_or_else(|error| {
+                eprintln!("release-phase warning: checking completion marker {marker}, {error:#?}");
+                false
+            })
+    }
+
+    fn mark_complete(&self, marker: &str) {
+        if let Err(error) = self
+            .runtime
+            .block_on(put_completion_marker(&self.env, marker))
+        {
+            eprintln!("release-phase warning: recording completion marker {marker}, {error:#?}");
+        }
+    }
+}
+
+// Notifies the target of RELEASE_PHASE_NOTIFY_URL of the outcome of the release. The async
+// runtime is only started when a target is configured. Failures only warn, so that a
+// notification outage does not fail the release.
+pub(crate) fn notify_release(
+    env: &HashMap<String, String>,
+    result: &Result<(), release_commands::Error>,
+) {
+    if env
+        .get("RELEASE_PHASE_NOTIFY_URL")
+        .filter(|url| !url.is_empty())
+        .is_none()
+    {
+        return;
+    }
+    let notification = ReleaseNotification {
+        succeeded: result.is_ok(),
+        release_id: env.get("RELEASE_ID").filter(|id| !id.is_empty()).cloned(),
+        app: env.get("HEROKU_APP_NAME").cloned(),
+        detail: result.as_ref().err().map(ToString::to_string),
+    };
+    let notified = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|error| format!("{error}"))
+        .and_then(|runtime| {
+            runtime
+                .block_on(notify(env, &notification))
+                .map_err(|error| format!("{error:#?}"))
+        });
+    match notified {
+        Ok(true) => log_info!("release-phase notified: {}", notification.summary()),
+        Ok(false) => {}
+        Err(error) => eprintln!("release-phase warning: notifying of the release, {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        env,
+        fs::{self, remove_file},
+        path::Path,
+    };
+
+    use release_commands::ExecOptions;
+
+    use super::CompletionMarkers;
+    use crate::exec_release_commands::{exec_release_sequence, ReleaseHooks};
+
+    #[test]
+    fn skips_completed_idempotent_steps() {
+        let storage_path = env::temp_dir().join(format!("exec-steps-{}", std::process::id()));
+        let markers = CompletionMarkers::new(HashMap::from([
+            ("RELEASE_ID".to_string(), "v102".to_string()),
+            (
+                "STATIC_ARTIFACTS_URL".to_string(),
+                format!("file://{}", storage_path.to_string_lossy()),
+            ),
+        ]))
+        .expect("markers with storage");
+        let commands_toml_path =
+            Path::new("tests/fixtures/uses_idempotency_keys/release-commands.toml");
+
+        let hooks = ReleaseHooks {
+            markers: Some(&markers),
+            step: None,
+            events: None,
+        };
+        let mut options = ExecOptions {
+            hooks: Some(&hooks),
+            ..ExecOptions::default()
+        };
+        exec_release_sequence(commands_toml_path, &options)
+            .result
+            .unwrap();
+        exec_release_sequence(commands_toml_path, &options)
+            .result
+            .unwrap();
+        options.force = true;
+        exec_release_sequence(commands_toml_path, &options)
+            .result
+            .unwrap();
+
+        let result_path =
+            Path::new("tests/fixtures/uses_idempotency_keys/exec-release-commands-test-output.txt");
+        let result_output = fs::read_to_string(result_path).unwrap();
+        remove_file(result_path).expect("test result output file is deleted");
+        fs::remove_dir_all(&storage_path).unwrap_or_default();
+        assert_eq!(
+            result_output,
+            "Release Build\nRelease\nRelease\nRelease Build\nRelease\n"
+        );
+    }
+
+    #[test]
+    fn completion_markers_track_release() {
+        let storage_path = env::temp_dir().join(format!("exec-markers-{}", std::process::id()));
+        let test_env = HashMap::from([
+            ("RELEASE_ID".to_string(), "v102".to_string()),
+            (
+                "STATIC_ARTIFACTS_URL".to_string(),
+                format!("file://{}", storage_path.to_string_lossy()),
+            ),
+        ]);
+        let unprotected = CompletionMarkers::new(test_env.clone()).expect("markers with storage");
+        unprotected.mark_release_complete();
+        let unprotected_after = unprotected.is_release_complete();
+        let mut protected_env = test_env.clone();
+        protected_env.insert(
+            "RELEASE_PHASE_RERUN_PROTECTION".to_string(),
+            "1".to_string(),
+        );
+        let markers = CompletionMarkers::new(protected_env).expect("markers with storage");
+        let before = markers.is_release_complete();
+        markers.mark_release_complete();
+        let after = markers.is_release_complete();
+        fs::remove_dir_all(&storage_path).unwrap_or_default();
+
+        assert!(!unprotected_after, "rerun protection is opted into");
+        assert!(!before);
+        assert!(after);
+        let mut without_storage = test_env.clone();
+        without_storage.remove("STATIC_ARTIFACTS_URL");
+        assert!(CompletionMarkers::new(without_storage).is_none());
+        let mut without_release_id = test_env;
+        without_release_id.remove("RELEASE_ID");
+        assert!(CompletionMarkers::new(without_release_id).is_none());
+    }
+}
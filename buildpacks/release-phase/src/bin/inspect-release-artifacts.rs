@@ -0,0 +1,54 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+use std::{env, path::Path};
+
+use release_artifacts::{
+    capture_env, format_mode, format_size, inspect, select_credentials, CredentialScope,
+};
+use release_commands::apply_storage_profile;
+
+#[tokio::main]
+pub(crate) async fn main() {
+    let Some(release_id) = env::args().nth(1) else {
+        eprintln!("usage: inspect-release-artifacts <release-id>");
+        std::process::exit(1);
+    };
+
+    let mut env = capture_env(Path::new("/etc/heroku"));
+    let commands_toml_path = env::var("RELEASE_COMMANDS_TOML").unwrap_or_default();
+    if let Err(error) = apply_storage_profile(Path::new(&commands_toml_path), &mut env) {
+        eprintln!("inspect-release-artifacts failed: {error}");
+        std::process::exit(1);
+    }
+    select_credentials(&mut env, CredentialScope::ReadOnly);
+
+    match inspect(&env, &release_id).await {
+        Ok(entries) => {
+            let total_size: u64 = entries.iter().map(|e| e.size).sum();
+            for entry in &entries {
+                let link = entry
+                    .link_target
+                    .as_ref()
+                    .map_or(String::new(), |target| format!(" -> {target}"));
+                println!(
+                    "{} {:>12}  {}{link}",
+                    format_mode(entry.kind, entry.mode),
+                    format_size(entry.size),
+                    entry.path
+                );
+            }
+            println!(
+                "{} entries, {} total",
+                entries.len(),
+                format_size(total_size)
+            );
+            eprintln!("inspect-release-artifacts complete.");
+            std::process::exit(0);
+        }
+        Err(error) => {
+            eprintln!("inspect-release-artifacts failed: {error:#?}");
+            std::process::exit(1);
+        }
+    }
+}
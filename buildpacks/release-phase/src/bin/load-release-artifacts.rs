@@ -1,28 +1,86 @@
 // Required due to: https://github.com/rust-lang/rust/issues/95513
 #![allow(unused_crate_dependencies)]
 
-use std::{collections::HashMap, path::Path};
+use std::{env, path::Path};
 
-use libcnb::data::exec_d::ExecDProgramOutputKey;
-use libcnb::data::exec_d_program_output_key;
-use libcnb::exec_d::write_exec_d_program_output;
-
-use release_artifacts::{capture_env, load};
+use release_artifacts::{
+    apply_load_credentials, capture_env, load, log_info, scope_storage_to_prefix,
+    select_credentials, CredentialScope,
+};
+use release_commands::{apply_storage_profile, find_artifact_channel, take_option};
 
 #[tokio::main]
-async fn main() {
-    let source_dir = Path::new("static-artifacts");
+pub(crate) async fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let channel_name = match take_option(&mut args, "--channel") {
+        Ok(name) => name,
+        Err(message) => {
+            eprintln!("load-release-artifacts {message}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut env = capture_env(Path::new("/etc/heroku"));
+    let commands_toml_path = env::var("RELEASE_COMMANDS_TOML").unwrap_or_default();
+    if let Err(error) = apply_storage_profile(Path::new(&commands_toml_path), &mut env) {
+        eprintln!("load-release-artifacts failed: {error}");
+        std::process::exit(1);
+    }
+    select_credentials(&mut env, CredentialScope::ReadOnly);
+    match apply_load_credentials(&mut env) {
+        Ok(true) => log_info!("load-release-artifacts using read-only load credentials"),
+        Ok(false) => {}
+        Err(error) => {
+            eprintln!("load-release-artifacts failed: {error:#?}");
+            std::process::exit(1);
+        }
+    }
+
+    // Without a channel, this runs as an exec.d program, loading the default artifacts, or
+    // standalone, such as in the entrypoint of an image built elsewhere, into the given dir.
+    let Some(name) = channel_name else {
+        // Extracting outside the app dir supports images where it is mounted read-only.
+        let extract_dir = args
+            .into_iter()
+            .next()
+            .or_else(|| env.get("STATIC_ARTIFACTS_EXTRACT_DIR").cloned());
+        let source_dir = Path::new(extract_dir.as_deref().unwrap_or("static-artifacts"));
+        match load(&env, source_dir).await {
+            Ok(loaded_key) => {
+                eprintln!("load-release-artifacts complete.");
+                if is_exec_d() {
+                    write_exec_d_output(loaded_key, extract_dir);
+                } else {
+                    log_info!("load-release-artifacts loaded {loaded_key}");
+                }
+                std::process::exit(0);
+            }
+            Err(error) => {
+                eprintln!("load-release-artifacts failed: {error:#?}");
+                std::process::exit(1);
+            }
+        }
+    };
 
-    let env = capture_env(Path::new("/etc/heroku"));
+    let channel = match find_artifact_channel(Path::new(&commands_toml_path), &name) {
+        Ok(channel) => channel,
+        Err(error) => {
+            eprintln!("load-release-artifacts failed: {error}");
+            std::process::exit(1);
+        }
+    };
+    let key_prefix = channel.key_prefix.unwrap_or(name.clone());
+    if let Err(error) = scope_storage_to_prefix(&mut env, &key_prefix) {
+        eprintln!("load-release-artifacts failed: {error:#?}");
+        std::process::exit(1);
+    }
+    log_info!("load-release-artifacts loading artifact channel: {name}");
+    let destination_dir = args.into_iter().next().unwrap_or(channel.dir);
 
-    match load(&env, source_dir).await {
+    match load(&env, Path::new(&destination_dir)).await {
         Ok(loaded_key) => {
+            log_info!("load-release-artifacts loaded {loaded_key}");
             eprintln!("load-release-artifacts complete.");
-            let output_env: HashMap<ExecDProgramOutputKey, String> = HashMap::from([(
-                exec_d_program_output_key!("STATIC_ARTIFACTS_LOADED_FROM_KEY"),
-                loaded_key,
-            )]);
-            write_exec_d_program_output(output_env);
             std::process::exit(0);
         }
         Err(error) => {
@@ -31,3 +89,36 @@ async fn main() {
         }
     }
 }
+
+// Whether run as an exec.d program by the CNB launcher, which reads its output env from fd 3.
+// Otherwise, writing the output would fail, or clobber whatever else fd 3 is.
+fn is_exec_d() -> bool {
+    Path::new("/dev/fd/3").exists()
+}
+
+// Writes the loaded key, and the dir it was extracted into when given, to the env of the app's
+// processes.
+#[cfg(unix)]
+fn write_exec_d_output(loaded_key: String, extract_dir: Option<String>) {
+    use std::collections::HashMap;
+
+    use libcnb::data::exec_d::ExecDProgramOutputKey;
+    use libcnb::data::exec_d_program_output_key;
+    use libcnb::exec_d::write_exec_d_program_output;
+
+    let mut output_env: HashMap<ExecDProgramOutputKey, String> = HashMap::from([(
+        exec_d_program_output_key!("STATIC_ARTIFACTS_LOADED_FROM_KEY"),
+        loaded_key,
+    )]);
+    if let Some(extract_dir) = extract_dir {
+        output_env.insert(
+            exec_d_program_output_key!("STATIC_ARTIFACTS_PATH"),
+            extract_dir,
+        );
+    }
+    write_exec_d_program_output(output_env);
+}
+
+// The CNB launcher, & so exec.d, is Unix only, like libcnb.
+#[cfg(not(unix))]
+fn write_exec_d_output(_loaded_key: String, _extract_dir: Option<String>) {}
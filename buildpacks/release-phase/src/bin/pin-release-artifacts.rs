@@ -0,0 +1,34 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+use std::{env, path::Path};
+
+use release_artifacts::{capture_env, pin, select_credentials, CredentialScope};
+use release_commands::apply_storage_profile;
+
+#[tokio::main]
+pub(crate) async fn main() {
+    let Some(release_id) = env::args().nth(1) else {
+        eprintln!("usage: pin-release-artifacts <release-id>");
+        std::process::exit(1);
+    };
+
+    let mut env = capture_env(Path::new("/etc/heroku"));
+    let commands_toml_path = env::var("RELEASE_COMMANDS_TOML").unwrap_or_default();
+    if let Err(error) = apply_storage_profile(Path::new(&commands_toml_path), &mut env) {
+        eprintln!("pin-release-artifacts failed: {error}");
+        std::process::exit(1);
+    }
+    select_credentials(&mut env, CredentialScope::ReadWrite);
+
+    match pin(&env, &release_id).await {
+        Ok(()) => {
+            eprintln!("pin-release-artifacts complete, {release_id} is pinned.");
+            std::process::exit(0);
+        }
+        Err(error) => {
+            eprintln!("pin-release-artifacts failed: {error:#?}");
+            std::process::exit(1);
+        }
+    }
+}
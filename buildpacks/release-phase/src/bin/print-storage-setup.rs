@@ -0,0 +1,44 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+use std::{env, path::Path};
+
+use release_artifacts::{capture_env, storage_setup, SetupFormat};
+use release_commands::apply_storage_profile;
+
+pub(crate) fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let format = match args.as_slice() {
+        [] => Ok(SetupFormat::Terraform),
+        [flag, format] if flag == "--format" => format.parse::<SetupFormat>(),
+        _ => {
+            eprintln!("usage: print-storage-setup [--format terraform|cloudformation]");
+            std::process::exit(1);
+        }
+    };
+    let format = match format {
+        Ok(format) => format,
+        Err(error) => {
+            eprintln!("print-storage-setup failed: {error:?}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut env = capture_env(Path::new("/etc/heroku"));
+    let commands_toml_path = env::var("RELEASE_COMMANDS_TOML").unwrap_or_default();
+    if let Err(error) = apply_storage_profile(Path::new(&commands_toml_path), &mut env) {
+        eprintln!("print-storage-setup failed: {error}");
+        std::process::exit(1);
+    }
+
+    match storage_setup(&env, format) {
+        Ok(setup) => {
+            print!("{setup}");
+            std::process::exit(0);
+        }
+        Err(error) => {
+            eprintln!("print-storage-setup failed: {error:#?}");
+            std::process::exit(1);
+        }
+    }
+}
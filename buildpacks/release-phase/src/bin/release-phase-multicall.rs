@@ -0,0 +1,72 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+// The executables are included as modules, where their own crate-level attributes are ignored.
+#![allow(unused_attributes)]
+
+//! Every executable of the buildpack in one binary, which runs the one it is invoked as, by the
+//! name of its symlink, like busybox. The layer then holds a single copy of the async & AWS
+//! dependencies that the executables share, rather than one per executable. Only
+//! `exec-release-commands` is also installed on its own, without them, and hands releases that
+//! use rerun protection, idempotency keys, or notifications to this binary.
+
+use std::{env, path::Path};
+
+#[path = "abort-stale-uploads.rs"]
+mod abort_stale_uploads;
+#[path = "check-storage-access.rs"]
+mod check_storage_access;
+#[path = "du-release-artifacts.rs"]
+mod du_release_artifacts;
+#[path = "exec-release-commands/executor.rs"]
+mod exec_release_commands;
+#[path = "inspect-release-artifacts.rs"]
+mod inspect_release_artifacts;
+#[path = "load-release-artifacts.rs"]
+mod load_release_artifacts;
+#[path = "pin-release-artifacts.rs"]
+mod pin_release_artifacts;
+#[path = "print-storage-setup.rs"]
+mod print_storage_setup;
+// Rerun protection & notifications of `exec_release_commands`, which it finds at this path.
+#[path = "exec-release-commands/storage.rs"]
+mod release_storage;
+#[path = "save-release-artifacts.rs"]
+mod save_release_artifacts;
+#[path = "unpin-release-artifacts.rs"]
+mod unpin_release_artifacts;
+#[path = "verify-release-artifacts.rs"]
+mod verify_release_artifacts;
+
+const EXECUTABLES: [(&str, fn()); 11] = [
+    ("abort-stale-uploads", abort_stale_uploads::main),
+    ("check-storage-access", check_storage_access::main),
+    ("du-release-artifacts", du_release_artifacts::main),
+    ("exec-release-commands", exec_release_commands::main),
+    ("inspect-release-artifacts", inspect_release_artifacts::main),
+    ("load-release-artifacts", load_release_artifacts::main),
+    ("pin-release-artifacts", pin_release_artifacts::main),
+    ("print-storage-setup", print_storage_setup::main),
+    ("save-release-artifacts", save_release_artifacts::main),
+    ("unpin-release-artifacts", unpin_release_artifacts::main),
+    ("verify-release-artifacts", verify_release_artifacts::main),
+];
+
+fn main() {
+    let invoked_as = env::args_os().next().unwrap_or_default();
+    let name = Path::new(&invoked_as)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    if let Some((_, main)) = EXECUTABLES
+        .iter()
+        .find(|(executable, _)| *executable == name)
+    {
+        main();
+        return;
+    }
+    eprintln!(
+        "release-phase-multicall failed: invoked as `{name}`, it must be invoked by the name of one of its executables, such as with a symlink: {}",
+        EXECUTABLES.map(|(executable, _)| executable).join(", ")
+    );
+    std::process::exit(1);
+}
@@ -3,21 +3,64 @@
 
 use std::{env, path::Path};
 
-use release_artifacts::{capture_env, save};
+use release_artifacts::{
+    capture_env, log_info, save, scope_storage_to_prefix, select_credentials, CredentialScope,
+};
+use release_commands::{apply_storage_profile, find_artifact_channel, take_flag, take_option};
 
 #[tokio::main]
-async fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("save-release-artifacts requires argument: the source directory");
+pub(crate) async fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let json = take_flag(&mut args, "--json");
+    let channel_name = match take_option(&mut args, "--channel") {
+        Ok(name) => name,
+        Err(message) => {
+            eprintln!("save-release-artifacts {message}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut env = capture_env(Path::new("/etc/heroku"));
+    let commands_toml_path = env::var("RELEASE_COMMANDS_TOML").unwrap_or_default();
+    if let Err(error) = apply_storage_profile(Path::new(&commands_toml_path), &mut env) {
+        eprintln!("save-release-artifacts failed: {error}");
         std::process::exit(1);
     }
-    let source_dir = Path::new(&args[1]);
+    select_credentials(&mut env, CredentialScope::ReadWrite);
 
-    let env = capture_env(Path::new("/etc/heroku"));
+    let source_dir = if let Some(name) = channel_name {
+        let channel = match find_artifact_channel(Path::new(&commands_toml_path), &name) {
+            Ok(channel) => channel,
+            Err(error) => {
+                eprintln!("save-release-artifacts failed: {error}");
+                std::process::exit(1);
+            }
+        };
+        let key_prefix = channel.key_prefix.unwrap_or(name.clone());
+        if let Err(error) = scope_storage_to_prefix(&mut env, &key_prefix) {
+            eprintln!("save-release-artifacts failed: {error:#?}");
+            std::process::exit(1);
+        }
+        // The include & exclude patterns of static-artifacts/ do not apply to channels.
+        env.remove("STATIC_ARTIFACTS_INCLUDE");
+        env.remove("STATIC_ARTIFACTS_EXCLUDE");
+        log_info!("save-release-artifacts saving artifact channel: {name}");
+        args.into_iter().next().unwrap_or(channel.dir)
+    } else if let Some(source_dir) = args.into_iter().next() {
+        source_dir
+    } else {
+        eprintln!("save-release-artifacts requires argument: the source directory or file");
+        std::process::exit(1);
+    };
 
-    match save(&env, source_dir).await {
-        Ok(()) => {
+    match save(&env, Path::new(&source_dir)).await {
+        Ok(report) => {
+            if json {
+                match report.to_json() {
+                    Ok(report_json) => println!("{report_json}"),
+                    Err(error) => eprintln!("save-release-artifacts failed to print JSON: {error}"),
+                }
+            }
             eprintln!("save-release-artifacts complete.");
             std::process::exit(0);
         }
@@ -0,0 +1,43 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+use std::{env, path::Path};
+
+use release_artifacts::{capture_env, extraction_report_path, ExtractionReport};
+
+pub(crate) fn main() {
+    let env = capture_env(Path::new("/etc/heroku"));
+    let dir = env::args()
+        .nth(1)
+        .or_else(|| env.get("STATIC_ARTIFACTS_EXTRACT_DIR").cloned())
+        .unwrap_or_else(|| "static-artifacts".to_string());
+    let dir = Path::new(&dir);
+    let Some(report_path) = extraction_report_path(&env, dir) else {
+        eprintln!("verify-release-artifacts failed: STATIC_ARTIFACTS_EXTRACTION_REPORT is false, so no report was written");
+        std::process::exit(1);
+    };
+
+    let diff = ExtractionReport::read(&report_path).and_then(|report| {
+        eprintln!(
+            "verify-release-artifacts comparing {dir:?} to the {} files extracted from {}",
+            report.files.len(),
+            report.loaded_key
+        );
+        report.verify(dir)
+    });
+    match diff {
+        Ok(diff) if diff.is_empty() => {
+            eprintln!("verify-release-artifacts complete, unchanged since extracted.");
+            std::process::exit(0);
+        }
+        Ok(diff) => {
+            print!("{diff}");
+            eprintln!("verify-release-artifacts failed: changed since extracted.");
+            std::process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("verify-release-artifacts failed: {error:#?}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -15,10 +15,20 @@ locally with a minimal example and open an issue in the buildpack's GitHub repos
 pub(crate) enum ReleasePhaseBuildpackError {
     CannotInstallArtifactSaver(std::io::Error),
     CannotInstallArtifactLoader(std::io::Error),
+    CannotInstallArtifactUsageReporter(std::io::Error),
+    CannotInstallArtifactInspector(std::io::Error),
+    CannotInstallArtifactPinner(std::io::Error),
+    CannotInstallArtifactUnpinner(std::io::Error),
+    CannotInstallStaleUploadAborter(std::io::Error),
+    CannotInstallStorageSetupPrinter(std::io::Error),
+    CannotInstallStorageAccessChecker(std::io::Error),
+    CannotInstallArtifactVerifier(std::io::Error),
     CannotInstallCommandExecutor(std::io::Error),
     CannotCreatWebExecD(std::io::Error),
     CannotReadProjectToml(TomlFileError),
     ConfigurationFailed(release_commands::Error),
+    ReleaseBuildFailed(release_commands::Error),
+    CannotSaveArtifactsAtBuild(String),
 }
 
 pub(crate) fn on_error(error: libcnb::Error<ReleasePhaseBuildpackError>) {
@@ -34,50 +44,84 @@ pub(crate) fn on_error(error: libcnb::Error<ReleasePhaseBuildpackError>) {
 fn on_buildpack_error(error: ReleasePhaseBuildpackError, logger: Box<dyn StartedLogger>) {
     match error {
         ReleasePhaseBuildpackError::CannotInstallArtifactSaver(error) => {
-            print_error_details(logger, &error)
-                .announce()
-                .error(&formatdoc! {"
-                Cannot install save-release-artifacts for {buildpack_name}
-            ", buildpack_name = fmt::value(BUILDPACK_NAME) });
+            on_install_error("save-release-artifacts", &error, logger);
         }
         ReleasePhaseBuildpackError::CannotInstallArtifactLoader(error) => {
+            on_install_error("load-release-artifacts", &error, logger);
+        }
+        ReleasePhaseBuildpackError::CannotInstallArtifactUsageReporter(error) => {
+            on_install_error("du-release-artifacts", &error, logger);
+        }
+        ReleasePhaseBuildpackError::CannotInstallArtifactInspector(error) => {
+            on_install_error("inspect-release-artifacts", &error, logger);
+        }
+        ReleasePhaseBuildpackError::CannotInstallArtifactPinner(error) => {
+            on_install_error("pin-release-artifacts", &error, logger);
+        }
+        ReleasePhaseBuildpackError::CannotInstallArtifactUnpinner(error) => {
+            on_install_error("unpin-release-artifacts", &error, logger);
+        }
+        ReleasePhaseBuildpackError::CannotInstallStaleUploadAborter(error) => {
+            on_install_error("abort-stale-uploads", &error, logger);
+        }
+        ReleasePhaseBuildpackError::CannotInstallStorageSetupPrinter(error) => {
+            on_install_error("print-storage-setup", &error, logger);
+        }
+        ReleasePhaseBuildpackError::CannotInstallStorageAccessChecker(error) => {
+            on_install_error("check-storage-access", &error, logger);
+        }
+        ReleasePhaseBuildpackError::CannotInstallArtifactVerifier(error) => {
+            on_install_error("verify-release-artifacts", &error, logger);
+        }
+        ReleasePhaseBuildpackError::CannotInstallCommandExecutor(error) => {
+            on_install_error("exec-release-commands", &error, logger);
+        }
+        ReleasePhaseBuildpackError::CannotCreatWebExecD(error) => {
             print_error_details(logger, &error)
                 .announce()
                 .error(&formatdoc! {"
-                Cannot install load-release-artifacts for {buildpack_name}
+                Cannot create exec.d/web for {buildpack_name}
             ", buildpack_name = fmt::value(BUILDPACK_NAME) });
         }
-        ReleasePhaseBuildpackError::CannotInstallCommandExecutor(error) => {
+        ReleasePhaseBuildpackError::CannotReadProjectToml(error) => {
             print_error_details(logger, &error)
                 .announce()
                 .error(&formatdoc! {"
-                Cannot install exec-release-commands for {buildpack_name}
+                Error reading project.toml for {buildpack_name}
             ", buildpack_name = fmt::value(BUILDPACK_NAME) });
         }
-        ReleasePhaseBuildpackError::CannotCreatWebExecD(error) => {
+        ReleasePhaseBuildpackError::ConfigurationFailed(error) => {
             print_error_details(logger, &error)
                 .announce()
                 .error(&formatdoc! {"
-                Cannot create exec.d/web for {buildpack_name}
+                Configuration failed for {buildpack_name}
             ", buildpack_name = fmt::value(BUILDPACK_NAME) });
         }
-        ReleasePhaseBuildpackError::CannotReadProjectToml(error) => {
+        ReleasePhaseBuildpackError::ReleaseBuildFailed(error) => {
             print_error_details(logger, &error)
                 .announce()
                 .error(&formatdoc! {"
-                Error reading project.toml for {buildpack_name}
+                Release-build command failed during build for {buildpack_name}
             ", buildpack_name = fmt::value(BUILDPACK_NAME) });
         }
-        ReleasePhaseBuildpackError::ConfigurationFailed(error) => {
+        ReleasePhaseBuildpackError::CannotSaveArtifactsAtBuild(error) => {
             print_error_details(logger, &error)
                 .announce()
                 .error(&formatdoc! {"
-                Configuration failed for {buildpack_name}
+                Cannot save release artifacts during build for {buildpack_name}
             ", buildpack_name = fmt::value(BUILDPACK_NAME) });
         }
     }
 }
 
+fn on_install_error(executable: &str, error: &std::io::Error, logger: Box<dyn StartedLogger>) {
+    print_error_details(logger, error)
+        .announce()
+        .error(&formatdoc! {"
+            Cannot install {executable} for {buildpack_name}
+        ", buildpack_name = fmt::value(BUILDPACK_NAME) });
+}
+
 fn on_framework_error(
     error: &libcnb::Error<ReleasePhaseBuildpackError>,
     logger: Box<dyn StartedLogger>,
@@ -1,16 +1,42 @@
+// The buildpack runs in the Linux build images, with libcnb, which is Unix only, while
+// `release-phase run --local` also runs on developers' Windows laptops.
+#[cfg(unix)]
 mod errors;
+#[cfg(unix)]
+mod prefetch_artifacts;
+mod run_locally;
+#[cfg(unix)]
+mod run_release_build;
+#[cfg(unix)]
 mod setup_release_phase;
 
+#[cfg(unix)]
 use crate::errors::{on_error, ReleasePhaseBuildpackError};
+#[cfg(unix)]
 use libcnb::build::{BuildContext, BuildResult, BuildResultBuilder};
+#[cfg(unix)]
 use libcnb::data::build_plan::{BuildPlanBuilder, Require};
+#[cfg(unix)]
 use libcnb::data::launch::{LaunchBuilder, ProcessBuilder};
+#[cfg(unix)]
 use libcnb::data::process_type;
+#[cfg(unix)]
 use libcnb::detect::{DetectContext, DetectResult, DetectResultBuilder};
+#[cfg(unix)]
 use libcnb::generic::{GenericMetadata, GenericPlatform};
-use libcnb::{buildpack_main, Buildpack, Error};
+#[cfg(unix)]
+use libcnb::{Buildpack, Error};
+#[cfg(unix)]
 use libherokubuildpack::log::log_header;
+#[cfg(unix)]
+use release_commands::commands_toml_path;
+#[cfg(unix)]
 use setup_release_phase::setup_release_phase;
+use std::env;
+
+// Silence unused dependency warning for
+// dependencies only used by exec-release-commands
+use release_log as _;
 
 // Silence unused dependency warning for
 // dependencies only used in tests
@@ -23,16 +49,15 @@ use test_support as _;
 #[cfg(test)]
 use uuid as _;
 
-// Silence unused dependency warning for
-// dependencies used in bin/ executables
-use release_artifacts as _;
-use tokio as _;
-
+#[cfg(unix)]
 const BUILDPACK_NAME: &str = "Heroku Release Phase Buildpack";
+#[cfg(unix)]
 const BUILD_PLAN_ID: &str = "release-phase";
 
+#[cfg(unix)]
 pub(crate) struct ReleasePhaseBuildpack;
 
+#[cfg(unix)]
 impl Buildpack for ReleasePhaseBuildpack {
     type Platform = GenericPlatform;
     type Metadata = GenericMetadata;
@@ -60,9 +85,7 @@ impl Buildpack for ReleasePhaseBuildpack {
                                 process_type!("release"),
                                 [
                                     "exec-release-commands",
-                                    &release_phase_layer
-                                        .path()
-                                        .join("release-commands.toml")
+                                    &commands_toml_path(&release_phase_layer.path())
                                         .to_string_lossy(),
                                 ],
                             )
@@ -80,10 +103,26 @@ impl Buildpack for ReleasePhaseBuildpack {
     }
 }
 
+#[cfg(unix)]
 impl From<ReleasePhaseBuildpackError> for libcnb::Error<ReleasePhaseBuildpackError> {
     fn from(value: ReleasePhaseBuildpackError) -> Self {
         libcnb::Error::BuildpackError(value)
     }
 }
 
-buildpack_main!(ReleasePhaseBuildpack);
+// Runs `release-phase run`, or else the buildpack, as `detect` or `build`, by the name it is
+// invoked as.
+fn main() {
+    let mut args = env::args().skip(1);
+    if args.next().is_some_and(|command| command == "run") {
+        run_locally::main(args.collect());
+        return;
+    }
+    #[cfg(unix)]
+    libcnb::libcnb_runtime(&ReleasePhaseBuildpack);
+    #[cfg(not(unix))]
+    {
+        eprintln!("release-phase runs only `release-phase run --local <app-dir>` on this platform");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use crate::setup_release_phase::capture_storage_env;
+use crate::{ReleasePhaseBuildpack, ReleasePhaseBuildpackError};
+use libcnb::build::BuildContext;
+use libcnb::data::layer_name;
+use libcnb::layer::{
+    CachedLayerDefinition, InvalidMetadataAction, LayerState, RestoredLayerAction,
+};
+use libcnb::layer_env::{LayerEnv, ModificationBehavior, Scope};
+use libherokubuildpack::log::{log_info, log_warning};
+use release_artifacts::{prefetch, CredentialScope};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Clone)]
+pub(crate) struct PrefetchLayerMetadata {
+    key: String,
+}
+
+// Downloads the latest artifacts archive into a cached launch layer, so that loading at boot may
+// skip the download. Prefetch is best-effort: when storage is not configured in the build
+// environment, or the download fails, the artifacts are still downloaded at boot.
+pub(crate) fn prefetch_artifacts(
+    context: &BuildContext<ReleasePhaseBuildpack>,
+    commands_toml_path: &Path,
+) -> Result<(), libcnb::Error<ReleasePhaseBuildpackError>> {
+    let env = match capture_storage_env(context, commands_toml_path, CredentialScope::ReadOnly) {
+        Ok(env) => env,
+        Err(error) => {
+            log_warning("Skipping artifacts prefetch", format!("{error}"));
+            return Ok(());
+        }
+    };
+    if !env.contains_key("STATIC_ARTIFACTS_URL") {
+        log_info("Skipping artifacts prefetch, STATIC_ARTIFACTS_URL is not set during build.");
+        return Ok(());
+    }
+
+    let prefetch_layer = context.cached_layer(
+        layer_name!("prefetch"),
+        CachedLayerDefinition {
+            build: false,
+            launch: true,
+            invalid_metadata_action: &|_| InvalidMetadataAction::DeleteLayer,
+            restored_layer_action: &|metadata: &PrefetchLayerMetadata, _| {
+                (RestoredLayerAction::KeepLayer, metadata.key.clone())
+            },
+        },
+    )?;
+    let cached_key = match &prefetch_layer.state {
+        LayerState::Restored { cause } => Some(cause.clone()),
+        LayerState::Empty { .. } => None,
+    };
+    let archive_path = prefetch_layer.path().join("static-artifacts.tgz");
+
+    let prefetch_result = tokio::runtime::Runtime::new()
+        .map_err(|e| format!("{e:#?}"))
+        .and_then(|runtime| {
+            runtime
+                .block_on(prefetch(&env, &archive_path, cached_key.as_deref()))
+                .map_err(|e| format!("{e:#?}"))
+        });
+    match prefetch_result {
+        Ok(Some(key)) => {
+            log_info(format!("Prefetched artifacts archive {key}"));
+            prefetch_layer.write_metadata(PrefetchLayerMetadata { key: key.clone() })?;
+            prefetch_layer.write_env(
+                LayerEnv::new()
+                    .chainable_insert(
+                        Scope::Launch,
+                        ModificationBehavior::Override,
+                        "STATIC_ARTIFACTS_PREFETCHED_KEY",
+                        key,
+                    )
+                    .chainable_insert(
+                        Scope::Launch,
+                        ModificationBehavior::Override,
+                        "STATIC_ARTIFACTS_PREFETCHED_ARCHIVE",
+                        &archive_path,
+                    ),
+            )?;
+        }
+        Ok(None) => log_info("No existing artifacts to prefetch."),
+        Err(error) => log_warning("Artifacts prefetch failed", error),
+    }
+    Ok(())
+}
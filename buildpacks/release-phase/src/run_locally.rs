@@ -0,0 +1,206 @@
+//! `release-phase run --local <app-dir>` runs the release phase of an app locally, for developers
+//! to test their release config end to end without pack or Docker: generates the release config
+//! from the app's project.toml, executes the release sequence in the app dir, saving artifacts to
+//! temp `file://` storage, and then loads them back, like the launcher would at boot.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use release_artifacts::{load, log_info, scope_storage_to_prefix};
+use release_commands::{
+    execute, generate_commands_config, take_flag, write_commands_config, ExecHooks, ExecOptions,
+    ReleaseCommands,
+};
+
+/// Runs `release-phase run`, with the args that follow `run`.
+pub(crate) fn main(mut args: Vec<String>) {
+    let local = take_flag(&mut args, "--local");
+    let Some(app_dir) = args.first().filter(|_| local) else {
+        eprintln!("release-phase run failed: requires `--local <app-dir>`, the dir of the app's project.toml, as it only runs locally");
+        std::process::exit(1);
+    };
+    match run_locally(Path::new(app_dir)) {
+        Ok(local_dir) => {
+            log_info!("release-phase run left its storage & loaded artifacts in {local_dir:?}");
+            eprintln!("release-phase run complete.");
+        }
+        Err(error) => {
+            eprintln!("release-phase run failed: {error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+// Runs the release phase of the app, returning the temp dir of its storage & loaded artifacts.
+fn run_locally(app_dir: &Path) -> Result<PathBuf, String> {
+    let app_dir = fs::canonicalize(app_dir).map_err(|e| format!("app dir {app_dir:?}, {e}"))?;
+    let project_toml_path = app_dir.join("project.toml");
+    let mut project_config = fs::read_to_string(&project_toml_path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| toml::from_str::<toml::Value>(&contents).map_err(|e| e.to_string()))
+        .map_err(|e| format!("reading {project_toml_path:?}, {e}"))?;
+    // Without a build, a release-build that runs at build runs in the sequence instead, before
+    // its artifacts are saved, as it would at release, and so nothing is prefetched.
+    if let Some(phase) = project_config
+        .get_mut("com")
+        .and_then(|v| v.get_mut("heroku"))
+        .and_then(|v| v.get_mut("phase"))
+    {
+        if let Some(release_build) = phase
+            .get_mut("release-build")
+            .and_then(toml::Value::as_table_mut)
+        {
+            release_build.remove("run-at");
+        }
+        if let Some(artifacts) = phase
+            .get_mut("artifacts")
+            .and_then(toml::Value::as_table_mut)
+        {
+            artifacts.remove("prefetch");
+        }
+    }
+    let config =
+        generate_commands_config(&project_config, toml::Table::new()).map_err(|e| e.to_string())?;
+
+    let local_dir = env::temp_dir().join(format!("release-phase-local-{}", std::process::id()));
+    fs::create_dir_all(&local_dir).map_err(|e| format!("creating {local_dir:?}, {e}"))?;
+    let commands_toml_path =
+        write_commands_config(&local_dir, &config).map_err(|e| e.to_string())?;
+    let storage_env = local_storage_env(&local_dir.join("storage"));
+    set_process_env(&storage_env, &commands_toml_path)?;
+    env::set_current_dir(&app_dir).map_err(|e| format!("entering {app_dir:?}, {e}"))?;
+    log_info!("release-phase run plan, {config}");
+
+    let report = execute(
+        &config,
+        &ExecOptions {
+            prefix_output: true,
+            hooks: Some(&LocalHooks),
+            ..ExecOptions::default()
+        },
+    );
+    report.result.map_err(|e| e.to_string())?;
+
+    if config.has_artifacts() {
+        // Loading writes relative to the working dir too, such as single-file artifacts, which
+        // must not land in the app dir.
+        env::set_current_dir(&local_dir).map_err(|e| format!("entering {local_dir:?}, {e}"))?;
+        load_artifacts(&config, &storage_env, &local_dir.join("loaded"))?;
+    }
+    Ok(local_dir)
+}
+
+// The env of temp `file://` storage, for a release named for the time it runs.
+fn local_storage_env(storage_dir: &Path) -> HashMap<String, String> {
+    let run_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    HashMap::from([
+        ("STATIC_ARTIFACTS_URL".to_string(), file_url(storage_dir)),
+        ("RELEASE_ID".to_string(), format!("local-{run_time}")),
+    ])
+}
+
+// The `file://` URL of the absolute path, which on Windows, like `C:\Users`, has a drive &
+// backslashes.
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        format!("file:///{}", path.replace('\\', "/"))
+    } else {
+        format!("file://{path}")
+    }
+}
+
+// Sets the env inherited by release commands, and so by the artifact savers among them. Any
+// `STATIC_ARTIFACTS_*` vars of the developer's shell are removed, so that a local run never
+// writes to real storage. The executables beside `release-phase`, like `save-release-artifacts`,
+// are put first on the PATH.
+fn set_process_env(
+    storage_env: &HashMap<String, String>,
+    commands_toml_path: &Path,
+) -> Result<(), String> {
+    for (key, _) in env::vars().filter(|(key, _)| key.starts_with("STATIC_ARTIFACTS_")) {
+        env::remove_var(key);
+    }
+    for (key, value) in storage_env {
+        env::set_var(key, value);
+    }
+    env::set_var("RELEASE_COMMANDS_TOML", commands_toml_path);
+    let executables_dir = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .ok_or("locating the release-phase executables")?;
+    let saver = format!("save-release-artifacts{}", env::consts::EXE_SUFFIX);
+    if !executables_dir.join(&saver).exists() {
+        return Err(format!(
+            "{saver} is not beside release-phase in {executables_dir:?}, build all of the release-phase executables, such as with `cargo build -p release-phase`"
+        ));
+    }
+    let path = env::var_os("PATH").unwrap_or_default();
+    let paths = [executables_dir].into_iter().chain(env::split_paths(&path));
+    env::set_var(
+        "PATH",
+        env::join_paths(paths).map_err(|e| format!("setting PATH, {e}"))?,
+    );
+    Ok(())
+}
+
+// Loads the saved artifacts back from storage, as the launcher would at boot, each into its dir
+// within the destination, rather than over the app's own.
+fn load_artifacts(
+    config: &ReleaseCommands,
+    storage_env: &HashMap<String, String>,
+    destination_dir: &Path,
+) -> Result<(), String> {
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    let mut loads = vec![(
+        storage_env.clone(),
+        destination_dir.join(config.artifact_dir()),
+    )];
+    for (name, channel) in config.artifact_channels.iter().flatten() {
+        let mut channel_env = storage_env.clone();
+        let key_prefix = channel.key_prefix.clone().unwrap_or(name.clone());
+        scope_storage_to_prefix(&mut channel_env, &key_prefix).map_err(|e| format!("{e:#?}"))?;
+        loads.push((channel_env, destination_dir.join(&channel.dir)));
+    }
+    for (env, dir) in loads {
+        let loaded_key = runtime
+            .block_on(load(&env, &dir))
+            .map_err(|e| format!("loading artifacts into {dir:?}, {e:#?}"))?;
+        log_info!("release-phase run loaded {loaded_key} into {dir:?}");
+    }
+    Ok(())
+}
+
+// Logs the progress of the release sequence.
+struct LocalHooks;
+
+impl ExecHooks for LocalHooks {
+    fn progress(&self, message: &str) {
+        log_info!("{message}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::local_storage_env;
+
+    #[test]
+    fn local_storage_env_uses_file_storage() {
+        let env = local_storage_env(Path::new("/tmp/release-phase-local/storage"));
+        assert_eq!(
+            env.get("STATIC_ARTIFACTS_URL").map(String::as_str),
+            Some("file:///tmp/release-phase-local/storage")
+        );
+        assert!(env
+            .get("RELEASE_ID")
+            .is_some_and(|id| id.starts_with("local-")));
+    }
+}
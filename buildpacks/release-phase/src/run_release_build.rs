@@ -0,0 +1,88 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::setup_release_phase::{artifacts_filter_env, capture_storage_env};
+use crate::{ReleasePhaseBuildpack, ReleasePhaseBuildpackError};
+use libcnb::build::BuildContext;
+use libcnb::Platform;
+use libherokubuildpack::log::log_info;
+use release_artifacts::{save, scope_storage_to_prefix, CredentialScope};
+use release_commands::{Executable, ReleaseCommands};
+
+// Executes the release-build command inside the build container, and then saves its artifacts,
+// instead of during Release Phase.
+pub(crate) fn run_release_build(
+    context: &BuildContext<ReleasePhaseBuildpack>,
+    release_build: &Executable,
+    commands_config: &ReleaseCommands,
+    commands_toml_path: &Path,
+) -> Result<(), libcnb::Error<ReleasePhaseBuildpackError>> {
+    log_info(format!("Executing release-build command: {release_build}"));
+    release_build
+        .check_process()
+        .map_err(ReleasePhaseBuildpackError::ReleaseBuildFailed)?;
+    let status = release_build
+        .to_process()
+        .current_dir(&context.app_dir)
+        .envs(context.platform.env().iter())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .map_err(|e| {
+            ReleasePhaseBuildpackError::ReleaseBuildFailed(
+                release_commands::Error::ReleaseCommandExecError(e),
+            )
+        })?;
+    if !status.success() {
+        return Err(ReleasePhaseBuildpackError::ReleaseBuildFailed(
+            release_commands::Error::ReleaseCommandExitedError(format!(
+                "command exited with {status}"
+            )),
+        )
+        .into());
+    }
+
+    let mut env = capture_storage_env(context, commands_toml_path, CredentialScope::ReadWrite)
+        .map_err(ReleasePhaseBuildpackError::ConfigurationFailed)?;
+    // The release is not yet known during build, so the archive is named for the build. At boot,
+    // when no archive exists for the release, the latest one is loaded.
+    if !env.contains_key("RELEASE_ID") {
+        let build_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| Duration::as_secs(&d));
+        env.insert("RELEASE_ID".to_string(), format!("build-{build_time}"));
+    }
+
+    // The include & exclude patterns only apply to the artifact dir, not artifact channels.
+    let mut artifacts_env = env.clone();
+    for (name, patterns) in artifacts_filter_env(commands_config) {
+        artifacts_env.entry(name.to_string()).or_insert(patterns);
+    }
+
+    let runtime = tokio::runtime::Runtime::new()
+        .map_err(|e| ReleasePhaseBuildpackError::CannotSaveArtifactsAtBuild(format!("{e:#?}")))?;
+    log_info("Saving release artifacts");
+    runtime
+        .block_on(save(
+            &artifacts_env,
+            &context.app_dir.join(commands_config.artifact_dir()),
+        ))
+        .map_err(|e| ReleasePhaseBuildpackError::CannotSaveArtifactsAtBuild(format!("{e:#?}")))?;
+    for (name, channel) in commands_config.artifact_channels.iter().flatten() {
+        log_info(format!("Saving release artifacts for channel {name}"));
+        let mut channel_env = env.clone();
+        channel_env.remove("STATIC_ARTIFACTS_INCLUDE");
+        channel_env.remove("STATIC_ARTIFACTS_EXCLUDE");
+        let key_prefix = channel.key_prefix.clone().unwrap_or(name.clone());
+        scope_storage_to_prefix(&mut channel_env, &key_prefix).map_err(|e| {
+            ReleasePhaseBuildpackError::CannotSaveArtifactsAtBuild(format!("{e:#?}"))
+        })?;
+        runtime
+            .block_on(save(&channel_env, &context.app_dir.join(&channel.dir)))
+            .map_err(|e| {
+                ReleasePhaseBuildpackError::CannotSaveArtifactsAtBuild(format!("{e:#?}"))
+            })?;
+    }
+    Ok(())
+}
@@ -1,12 +1,22 @@
+use std::collections::HashMap;
 use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
 
+use crate::prefetch_artifacts::prefetch_artifacts;
+use crate::run_release_build::run_release_build;
 use crate::{ReleasePhaseBuildpack, ReleasePhaseBuildpackError, BUILD_PLAN_ID};
 use libcnb::data::layer_name;
 use libcnb::layer::LayerRef;
-use libcnb::{additional_buildpack_binary_path, read_toml_file};
+use libcnb::layer_env::{LayerEnv, ModificationBehavior, Scope};
+use libcnb::{additional_buildpack_binary_path, Platform};
 use libcnb::{build::BuildContext, layer::UncachedLayerDefinition};
 use libherokubuildpack::log::log_info;
-use release_commands::{generate_commands_config, write_commands_config};
+use release_artifacts::{format_size, select_credentials, CredentialScope};
+use release_commands::{
+    apply_storage_profile, commands_toml_path, generate_commands_config,
+    write_commands_config_from_project, ReleaseCommands,
+};
 use toml::Table;
 
 pub(crate) fn setup_release_phase(
@@ -15,20 +25,14 @@ pub(crate) fn setup_release_phase(
     Option<LayerRef<ReleasePhaseBuildpack, (), ()>>,
     libcnb::Error<ReleasePhaseBuildpackError>,
 > {
-    let project_toml_path = &context.app_dir.join("project.toml");
-    let project_toml = if project_toml_path.is_file() {
-        read_toml_file::<toml::Value>(project_toml_path)
-            .map_err(ReleasePhaseBuildpackError::CannotReadProjectToml)?
-    } else {
-        toml::Table::new().into()
-    };
+    let (project_toml, project_toml_source) = read_project_toml(&context.app_dir)?;
 
     let build_plan_config = generate_build_plan_config(context);
 
     let commands_config = generate_commands_config(&project_toml, build_plan_config)
         .map_err(ReleasePhaseBuildpackError::ConfigurationFailed)?;
 
-    if commands_config.release.is_none() && commands_config.release_build.is_none() {
+    if commands_config.release.is_none() && !commands_config.has_artifacts() {
         log_info("No release commands are configured.");
         return Ok(None);
     }
@@ -42,46 +46,266 @@ pub(crate) fn setup_release_phase(
     )?;
 
     log_info("Writing release-commands.toml");
-    write_commands_config(release_phase_layer.path().as_path(), &commands_config)
-        .map_err(ReleasePhaseBuildpackError::ConfigurationFailed)?;
+    let commands_toml_path = write_commands_config_from_project(
+        release_phase_layer.path().as_path(),
+        &commands_config,
+        &project_toml_source,
+    )
+    .map_err(ReleasePhaseBuildpackError::ConfigurationFailed)?;
+    release_phase_layer.write_env(generate_launch_env(
+        &release_phase_layer.path(),
+        &context.app_dir,
+        &commands_config,
+    ))?;
 
     log_info("Installing processes…");
     let exec_destination = release_phase_layer.path().join("bin");
     fs::create_dir_all(&exec_destination)
         .map_err(ReleasePhaseBuildpackError::CannotInstallCommandExecutor)?;
 
-    let main_exec = exec_destination.join("exec-release-commands");
-    log_info(format!("  {main_exec:?}"));
-    fs::copy(
-        additional_buildpack_binary_path!("exec-release-commands"),
-        main_exec,
-    )
-    .map_err(ReleasePhaseBuildpackError::CannotInstallCommandExecutor)?;
-
-    if commands_config.release_build.is_some() {
-        let save_exec = exec_destination.join("save-release-artifacts");
-        log_info(format!("  {save_exec:?}"));
-        fs::copy(
-            additional_buildpack_binary_path!("save-release-artifacts"),
-            save_exec,
-        )
-        .map_err(ReleasePhaseBuildpackError::CannotInstallArtifactSaver)?;
-
-        let web_exec_destination = release_phase_layer.path().join("exec.d/web");
-        let load_exec = web_exec_destination.join("load-release-artifacts");
-        log_info(format!("  {load_exec:?}"));
-        fs::create_dir_all(&web_exec_destination)
-            .map_err(ReleasePhaseBuildpackError::CannotCreatWebExecD)?;
-        fs::copy(
-            additional_buildpack_binary_path!("load-release-artifacts"),
-            load_exec,
-        )
-        .map_err(ReleasePhaseBuildpackError::CannotInstallArtifactLoader)?;
+    // The executor is installed on its own, so that it starts without the async & AWS stack.
+    // Every other executable is a symlink to the multi-call binary, which runs it by the link's
+    // name, and which the executor hands releases to when they use artifact storage.
+    install_exec(
+        &additional_buildpack_binary_path!("exec-release-commands"),
+        &exec_destination.join("exec-release-commands"),
+        ReleasePhaseBuildpackError::CannotInstallCommandExecutor,
+    )?;
+    let multicall_exec = exec_destination.join("release-phase-multicall");
+    install_exec(
+        &additional_buildpack_binary_path!("release-phase-multicall"),
+        &multicall_exec,
+        ReleasePhaseBuildpackError::CannotInstallCommandExecutor,
+    )?;
+
+    if commands_config.has_artifacts() {
+        install_artifact_links(&multicall_exec, &release_phase_layer.path())?;
+        run_at_build(context, &commands_config, &commands_toml_path)?;
     }
 
     Ok(Some(release_phase_layer))
 }
 
+// Reads `project.toml` and its source, which are empty when the app has none.
+fn read_project_toml(app_dir: &Path) -> Result<(toml::Value, String), ReleasePhaseBuildpackError> {
+    let project_toml_path = &app_dir.join("project.toml");
+    if !project_toml_path.is_file() {
+        return Ok((toml::Table::new().into(), String::new()));
+    }
+    let source = fs::read_to_string(project_toml_path)
+        .map_err(|e| ReleasePhaseBuildpackError::CannotReadProjectToml(e.into()))?;
+    let project_toml = toml::from_str(&source)
+        .map_err(|e| ReleasePhaseBuildpackError::CannotReadProjectToml(e.into()))?;
+    Ok((project_toml, source))
+}
+
+// Links the artifact executables, with the error of failing to install each, into `bin/`, and
+// `load-release-artifacts` into `exec.d/web`, so that it loads artifacts as web dynos boot.
+fn install_artifact_links(
+    multicall_exec: &Path,
+    layer_path: &Path,
+) -> Result<(), ReleasePhaseBuildpackError> {
+    let exec_destination = layer_path.join("bin");
+    for (name, error) in [
+        (
+            "save-release-artifacts",
+            ReleasePhaseBuildpackError::CannotInstallArtifactSaver as fn(_) -> _,
+        ),
+        (
+            "du-release-artifacts",
+            ReleasePhaseBuildpackError::CannotInstallArtifactUsageReporter,
+        ),
+        (
+            "inspect-release-artifacts",
+            ReleasePhaseBuildpackError::CannotInstallArtifactInspector,
+        ),
+        (
+            "pin-release-artifacts",
+            ReleasePhaseBuildpackError::CannotInstallArtifactPinner,
+        ),
+        (
+            "unpin-release-artifacts",
+            ReleasePhaseBuildpackError::CannotInstallArtifactUnpinner,
+        ),
+        (
+            "abort-stale-uploads",
+            ReleasePhaseBuildpackError::CannotInstallStaleUploadAborter,
+        ),
+        (
+            "print-storage-setup",
+            ReleasePhaseBuildpackError::CannotInstallStorageSetupPrinter,
+        ),
+        (
+            "check-storage-access",
+            ReleasePhaseBuildpackError::CannotInstallStorageAccessChecker,
+        ),
+        (
+            "verify-release-artifacts",
+            ReleasePhaseBuildpackError::CannotInstallArtifactVerifier,
+        ),
+    ] {
+        install_link(multicall_exec, &exec_destination.join(name), error)?;
+    }
+
+    let web_exec_destination = layer_path.join("exec.d/web");
+    fs::create_dir_all(&web_exec_destination)
+        .map_err(ReleasePhaseBuildpackError::CannotCreatWebExecD)?;
+    install_link(
+        multicall_exec,
+        &web_exec_destination.join("load-release-artifacts"),
+        ReleasePhaseBuildpackError::CannotInstallArtifactLoader,
+    )
+}
+
+// Runs the steps configured to happen during build, rather than in the release phase: the
+// `release-build` command, when it runs at build, then prefetching the latest artifacts.
+fn run_at_build(
+    context: &BuildContext<ReleasePhaseBuildpack>,
+    commands_config: &ReleaseCommands,
+    commands_toml_path: &Path,
+) -> Result<(), libcnb::Error<ReleasePhaseBuildpackError>> {
+    if let Some(release_build) = commands_config
+        .release_build
+        .as_ref()
+        .filter(|b| b.runs_at_build())
+    {
+        run_release_build(context, release_build, commands_config, commands_toml_path)?;
+    }
+
+    if commands_config
+        .artifacts
+        .as_ref()
+        .and_then(|a| a.prefetch)
+        .unwrap_or(false)
+    {
+        log_info("Prefetching latest release artifacts…");
+        prefetch_artifacts(context, commands_toml_path)?;
+    }
+    Ok(())
+}
+
+// Copies an executable of the buildpack into the layer, logging its size, which adds to that of
+// the app image.
+fn install_exec(
+    source: &Path,
+    destination: &Path,
+    error: fn(std::io::Error) -> ReleasePhaseBuildpackError,
+) -> Result<(), ReleasePhaseBuildpackError> {
+    let size = fs::copy(source, destination).map_err(error)?;
+    log_info(format!("  {destination:?} ({})", format_size(size)));
+    Ok(())
+}
+
+fn install_link(
+    multicall_exec: &Path,
+    destination: &Path,
+    error: fn(std::io::Error) -> ReleasePhaseBuildpackError,
+) -> Result<(), ReleasePhaseBuildpackError> {
+    symlink(multicall_exec, destination).map_err(error)?;
+    log_info(format!("  {destination:?}"));
+    Ok(())
+}
+
+fn generate_launch_env(
+    layer_path: &Path,
+    app_dir: &Path,
+    commands_config: &ReleaseCommands,
+) -> LayerEnv {
+    // Allows save & load of artifact channels to find their config at runtime.
+    let mut launch_env = LayerEnv::new().chainable_insert(
+        Scope::Launch,
+        ModificationBehavior::Override,
+        "RELEASE_COMMANDS_TOML",
+        commands_toml_path(layer_path),
+    );
+    // Allows web servers to serve the loaded artifacts, without glue code.
+    if commands_config.has_artifacts() {
+        let extract_dir = commands_config
+            .artifacts
+            .as_ref()
+            .and_then(|a| a.extract_dir.as_ref());
+        let artifacts_path = extract_dir.map_or_else(
+            || app_dir.join(commands_config.artifact_dir()),
+            PathBuf::from,
+        );
+        // Artifacts are loaded back into the configured artifact dir, rather than
+        // `static-artifacts/`, so that they are where the framework serves them from.
+        if extract_dir.is_some() || commands_config.artifact_dir.is_some() {
+            launch_env = launch_env.chainable_insert(
+                Scope::Launch,
+                ModificationBehavior::Default,
+                "STATIC_ARTIFACTS_EXTRACT_DIR",
+                &artifacts_path,
+            );
+        }
+        launch_env = launch_env.chainable_insert(
+            Scope::Launch,
+            ModificationBehavior::Override,
+            "STATIC_ARTIFACTS_PATH",
+            &artifacts_path,
+        );
+        for name in commands_config
+            .artifacts
+            .iter()
+            .flat_map(|a| a.path_env.iter().flatten())
+        {
+            launch_env = launch_env
+                .chainable_insert(
+                    Scope::Launch,
+                    ModificationBehavior::Append,
+                    name,
+                    &artifacts_path,
+                )
+                .chainable_insert(Scope::Launch, ModificationBehavior::Delimiter, name, ":");
+        }
+        for (name, patterns) in artifacts_filter_env(commands_config) {
+            launch_env = launch_env.chainable_insert(
+                Scope::Launch,
+                ModificationBehavior::Default,
+                name,
+                patterns,
+            );
+        }
+    }
+    launch_env
+}
+
+// The env vars that select the paths of `static-artifacts/` to archive, from the comma-joined
+// glob patterns of the artifacts config.
+pub(crate) fn artifacts_filter_env(
+    commands_config: &ReleaseCommands,
+) -> Vec<(&'static str, String)> {
+    let Some(artifacts) = &commands_config.artifacts else {
+        return vec![];
+    };
+    [
+        ("STATIC_ARTIFACTS_INCLUDE", &artifacts.include),
+        ("STATIC_ARTIFACTS_EXCLUDE", &artifacts.exclude),
+    ]
+    .into_iter()
+    .filter_map(|(name, patterns)| Some((name, patterns.as_ref()?.join(","))))
+    .collect()
+}
+
+// Collect artifact storage config from the build environment, applying the selected storage profile,
+// and the credentials of the scope.
+pub(crate) fn capture_storage_env(
+    context: &BuildContext<ReleasePhaseBuildpack>,
+    commands_toml_path: &Path,
+    scope: CredentialScope,
+) -> Result<HashMap<String, String>, release_commands::Error> {
+    let mut env: HashMap<String, String> = context
+        .platform
+        .env()
+        .iter()
+        .filter_map(|(k, v)| Some((k.to_str()?.to_string(), v.to_str()?.to_string())))
+        .filter(|(k, _)| k.starts_with("STATIC_ARTIFACTS_") || k == "RELEASE_ID")
+        .collect();
+    apply_storage_profile(commands_toml_path, &mut env)?;
+    select_credentials(&mut env, scope);
+    Ok(env)
+}
+
 // Load a table of Build Plan [requires.metadata] from context.
 // When a key is defined multiple times,
 // * for arrays: append the new array value to the existing array value
@@ -114,7 +338,11 @@ fn generate_build_plan_config(
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashSet, path::PathBuf};
+    use std::{
+        collections::HashSet,
+        ffi::OsString,
+        path::{Path, PathBuf},
+    };
 
     use libcnb::{
         build::BuildContext,
@@ -124,13 +352,30 @@ mod tests {
             buildpack_plan::{BuildpackPlan, Entry},
         },
         generic::GenericPlatform,
+        layer_env::Scope,
         Env, Target,
     };
+    use release_commands::{ArtifactsConfig, Executable, ReleaseCommands};
     use toml::toml;
 
     use crate::{ReleasePhaseBuildpack, BUILD_PLAN_ID};
 
-    use super::generate_build_plan_config;
+    use super::{generate_build_plan_config, generate_launch_env, read_project_toml};
+
+    #[test]
+    fn read_project_toml_keeps_source_with_comments() {
+        let app_dir = tempfile::tempdir().unwrap();
+        let source = "# The release of the app\n[com.heroku.phase.release]\ncommand = \"test\"\n";
+        std::fs::write(app_dir.path().join("project.toml"), source).unwrap();
+
+        let (project_toml, project_toml_source) = read_project_toml(app_dir.path()).unwrap();
+
+        assert_eq!(project_toml_source, source);
+        assert_eq!(
+            project_toml["com"]["heroku"]["phase"]["release"]["command"].as_str(),
+            Some("test")
+        );
+    }
 
     #[test]
     fn generate_build_plan_config_from_one_entry() {
@@ -273,6 +518,139 @@ mod tests {
         assert!(result.is_empty());
     }
 
+    #[test]
+    fn generate_launch_env_exports_artifacts_path() {
+        let commands_config = ReleaseCommands {
+            release_build: Some(Executable {
+                command: "bash".to_string(),
+                ..Executable::default()
+            }),
+            artifacts: Some(ArtifactsConfig {
+                path_env: Some(vec!["NGINX_ROOT".to_string()]),
+                ..ArtifactsConfig::default()
+            }),
+            ..ReleaseCommands::default()
+        };
+        let launch_env = generate_launch_env(
+            Path::new("/layers/release-phase/main"),
+            Path::new("/workspace"),
+            &commands_config,
+        )
+        .apply(Scope::Launch, &Env::new());
+        assert_eq!(
+            launch_env.get("STATIC_ARTIFACTS_PATH"),
+            Some(&OsString::from("/workspace/static-artifacts"))
+        );
+        assert_eq!(
+            launch_env.get("NGINX_ROOT"),
+            Some(&OsString::from("/workspace/static-artifacts"))
+        );
+        assert_eq!(
+            launch_env.get("RELEASE_COMMANDS_TOML"),
+            Some(&OsString::from(
+                "/layers/release-phase/main/release-commands.toml"
+            ))
+        );
+    }
+
+    #[test]
+    fn generate_launch_env_appends_artifacts_path_to_path_env() {
+        let commands_config = ReleaseCommands {
+            release_build: Some(Executable {
+                command: "bash".to_string(),
+                ..Executable::default()
+            }),
+            artifacts: Some(ArtifactsConfig {
+                path_env: Some(vec!["NGINX_ROOT".to_string()]),
+                ..ArtifactsConfig::default()
+            }),
+            ..ReleaseCommands::default()
+        };
+        let mut env = Env::new();
+        env.insert("NGINX_ROOT", "/workspace/public");
+        let launch_env = generate_launch_env(
+            Path::new("/layers/release-phase/main"),
+            Path::new("/workspace"),
+            &commands_config,
+        )
+        .apply(Scope::Launch, &env);
+        assert_eq!(
+            launch_env.get("NGINX_ROOT"),
+            Some(&OsString::from(
+                "/workspace/public:/workspace/static-artifacts"
+            ))
+        );
+    }
+
+    #[test]
+    fn generate_launch_env_exports_extract_dir() {
+        let commands_config = ReleaseCommands {
+            release_build: Some(Executable {
+                command: "bash".to_string(),
+                ..Executable::default()
+            }),
+            artifacts: Some(ArtifactsConfig {
+                extract_dir: Some("/tmp/static-artifacts".to_string()),
+                exclude: Some(vec!["node_modules".to_string(), "*.map".to_string()]),
+                ..ArtifactsConfig::default()
+            }),
+            ..ReleaseCommands::default()
+        };
+        let launch_env = generate_launch_env(
+            Path::new("/layers/release-phase/main"),
+            Path::new("/workspace"),
+            &commands_config,
+        )
+        .apply(Scope::Launch, &Env::new());
+        assert_eq!(
+            launch_env.get("STATIC_ARTIFACTS_PATH"),
+            Some(&OsString::from("/tmp/static-artifacts"))
+        );
+        assert_eq!(
+            launch_env.get("STATIC_ARTIFACTS_EXTRACT_DIR"),
+            Some(&OsString::from("/tmp/static-artifacts"))
+        );
+        assert_eq!(
+            launch_env.get("STATIC_ARTIFACTS_EXCLUDE"),
+            Some(&OsString::from("node_modules,*.map"))
+        );
+        assert_eq!(launch_env.get("STATIC_ARTIFACTS_INCLUDE"), None);
+    }
+
+    #[test]
+    fn generate_launch_env_exports_artifact_dir() {
+        let commands_config = ReleaseCommands {
+            artifacts_only: Some(true),
+            artifact_dir: Some("public".to_string()),
+            ..ReleaseCommands::default()
+        };
+        let launch_env = generate_launch_env(
+            Path::new("/layers/release-phase/main"),
+            Path::new("/workspace"),
+            &commands_config,
+        )
+        .apply(Scope::Launch, &Env::new());
+        assert_eq!(
+            launch_env.get("STATIC_ARTIFACTS_PATH"),
+            Some(&OsString::from("/workspace/public"))
+        );
+        assert_eq!(
+            launch_env.get("STATIC_ARTIFACTS_EXTRACT_DIR"),
+            Some(&OsString::from("/workspace/public"))
+        );
+    }
+
+    #[test]
+    fn generate_launch_env_without_release_build() {
+        let launch_env = generate_launch_env(
+            Path::new("/layers/release-phase/main"),
+            Path::new("/workspace"),
+            &ReleaseCommands::default(),
+        )
+        .apply(Scope::Launch, &Env::new());
+        assert_eq!(launch_env.get("STATIC_ARTIFACTS_PATH"), None);
+    }
+
     fn create_test_context(build_plan: Vec<Entry>) -> BuildContext<ReleasePhaseBuildpack> {
         let test_context: BuildContext<ReleasePhaseBuildpack> = BuildContext {
             layers_dir: PathBuf::new(),
@@ -1,16 +1,26 @@
 // Required due to: https://github.com/rust-lang/rust/issues/95513
 #![allow(unused_crate_dependencies)]
 
-use std::{fs, os::unix::fs::PermissionsExt};
+use std::{fs, os::unix::fs::PermissionsExt, time::Duration};
 
 use libcnb_test::{assert_contains, ContainerConfig};
 use tempfile::tempdir;
 use test_support::{
+    compose::Compose,
+    fixture::FixtureApp,
+    image::{assert_image_executable, read_release_commands_toml, RELEASE_PHASE_LAYER},
     release_phase_and_procfile_integration_test, release_phase_integration_test,
-    start_container_entrypoint,
+    release_phase_integration_test_with_config, start_container_entrypoint, wait_for_log,
 };
 use uuid::Uuid;
 
+const LOG_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The line of the web process's env, which is printed once the artifacts are loaded.
+fn is_loaded_from_key_line(line: &str) -> bool {
+    line.starts_with("STATIC_ARTIFACTS_LOADED_FROM_KEY=")
+}
+
 #[test]
 #[ignore = "integration test"]
 fn project_uses_release() {
@@ -35,6 +45,19 @@ fn project_uses_release() {
                 assert_contains!(log_output.stderr, "release-phase complete.");
             },
         );
+        let release_commands = read_release_commands_toml(&ctx);
+        let release = release_commands["release"]
+            .as_array()
+            .expect("release-commands.toml should have release commands");
+        assert_eq!(release.len(), 2);
+        assert_eq!(
+            release[1]["args"][1].as_str(),
+            Some("echo $TEST_ENV_INHERITED")
+        );
+        assert_image_executable(
+            &ctx,
+            &format!("{RELEASE_PHASE_LAYER}/bin/exec-release-commands"),
+        );
     });
 }
 
@@ -122,14 +145,164 @@ fn project_uses_release_build_and_web_process_loads_artifacts() {
                     .env("STATIC_ARTIFACTS_URL", &container_volume_url)
                     .bind_mount(&local_storage_path, container_volume_path),
                 &"web".to_string(),
+                |container| {
+                    wait_for_log(
+                        container,
+                        |line| line.contains("load-release-artifacts complete."),
+                        LOG_TIMEOUT,
+                    );
+                    assert_eq!(
+                        wait_for_log(container, is_loaded_from_key_line, LOG_TIMEOUT),
+                        [format!(
+                            "STATIC_ARTIFACTS_LOADED_FROM_KEY=release-{unique}.tgz"
+                        )]
+                    );
+                    assert_contains!(container.logs_now().stdout, "Hello static world!");
+                },
+            );
+        },
+    );
+}
+
+#[test]
+#[ignore = "integration test"]
+fn project_uses_release_build_with_minio_saves_gcs_and_loads() {
+    release_phase_and_procfile_integration_test(
+        "./fixtures/project_uses_release_build_with_web_process",
+        |ctx| {
+            assert_contains!(ctx.pack_stdout, "Successfully built image");
+            let compose = Compose::up(&ctx);
+            for release_id in ["v1", "v2"] {
+                compose.run(
+                    "release",
+                    ContainerConfig::new()
+                        .env("RELEASE_ID", release_id)
+                        .env("STATIC_ARTIFACTS_RETAIN_COUNT", "1"),
+                    |container| {
+                        let log_output = container.logs_now();
+                        assert_contains!(
+                            log_output.stderr,
+                            format!(
+                                "save-release-artifacts writing archive: release-{release_id}.tgz"
+                            )
+                            .as_str()
+                        );
+                        assert_contains!(log_output.stderr, "release-phase complete.");
+                    },
+                );
+            }
+            assert_eq!(compose.stored_keys(), vec!["release-v2.tgz"]);
+            compose.run(
+                "web",
+                ContainerConfig::new().env("RELEASE_ID", "v2"),
+                |container| {
+                    wait_for_log(
+                        container,
+                        |line| line.contains("load-release-artifacts complete."),
+                        LOG_TIMEOUT,
+                    );
+                    assert_eq!(
+                        wait_for_log(container, is_loaded_from_key_line, LOG_TIMEOUT),
+                        ["STATIC_ARTIFACTS_LOADED_FROM_KEY=release-v2.tgz"]
+                    );
+                    assert_contains!(container.logs_now().stdout, "Hello static world!");
+                },
+            );
+        },
+    );
+}
+
+#[test]
+#[ignore = "integration test"]
+fn generated_app_uses_release_build() {
+    let app = FixtureApp::new()
+        .release_build_artifacts(&[("images/logo.svg", "<svg/>"), ("index.html", "Hello!")])
+        .create();
+    release_phase_integration_test(app.path(), |ctx| {
+        assert_contains!(ctx.pack_stdout, "Successfully built image");
+        start_container_entrypoint(
+            &ctx,
+            ContainerConfig::new().env("RELEASE_ID", "xyz").env(
+                "STATIC_ARTIFACTS_URL",
+                "file:///workspace/static-artifacts-storage",
+            ),
+            &"release".to_string(),
+            |container| {
+                let log_output = container.logs_now();
+                assert_contains!(log_output.stdout, "Build in Release Phase Buildpack!");
+                assert_contains!(
+                    log_output.stderr,
+                    "save-release-artifacts writing archive: release-xyz.tgz"
+                );
+                assert_contains!(log_output.stderr, "release-phase complete.");
+            },
+        );
+    });
+}
+
+#[test]
+#[ignore = "integration test"]
+fn project_uses_artifact_channels() {
+    release_phase_integration_test("./fixtures/project_uses_artifact_channels", |ctx| {
+        assert_contains!(ctx.pack_stdout, "Release Phase");
+        assert_contains!(ctx.pack_stdout, "Successfully built image");
+        start_container_entrypoint(
+            &ctx,
+            ContainerConfig::new().env("RELEASE_ID", "xyz").env(
+                "STATIC_ARTIFACTS_URL",
+                "file:///workspace/static-artifacts-storage",
+            ),
+            &"release".to_string(),
+            |container| {
+                let log_output = container.logs_now();
+                assert_contains!(log_output.stderr, "release-phase plan");
+                assert_contains!(
+                    log_output.stderr,
+                    "save-release-artifacts writing archive: release-xyz.tgz"
+                );
+                assert_contains!(
+                    log_output.stderr,
+                    "save-release-artifacts saving artifact channel: docs"
+                );
+                assert_contains!(log_output.stderr, "release-phase complete.");
+            },
+        );
+    });
+}
+
+#[test]
+#[ignore = "integration test"]
+fn project_uses_release_build_at_build() {
+    release_phase_integration_test_with_config(
+        "./fixtures/project_uses_release_build_at_build",
+        |config| {
+            config.env(
+                "STATIC_ARTIFACTS_URL",
+                "file:///workspace/static-artifacts-storage",
+            );
+        },
+        |ctx| {
+            assert_contains!(ctx.pack_stdout, "Executing release-build command");
+            assert_contains!(ctx.pack_stdout, "Build in Release Phase Buildpack!");
+            assert_contains!(
+                ctx.pack_stdout,
+                "save-release-artifacts writing archive: release-build-"
+            );
+            assert_contains!(ctx.pack_stdout, "Successfully built image");
+            start_container_entrypoint(
+                &ctx,
+                ContainerConfig::new().env("RELEASE_ID", "xyz").env(
+                    "STATIC_ARTIFACTS_URL",
+                    "file:///workspace/static-artifacts-storage",
+                ),
+                &"release".to_string(),
                 |container| {
                     let log_output = container.logs_now();
-                    assert_contains!(log_output.stderr, "load-release-artifacts complete.");
                     assert_contains!(
-                        log_output.stdout,
-                        format!("STATIC_ARTIFACTS_LOADED_FROM_KEY=release-{unique}.tgz").as_str(),
+                        log_output.stderr,
+                        "release-phase skipping release-build command, it ran during build"
                     );
-                    assert_contains!(log_output.stdout, "Hello static world!");
+                    assert_contains!(log_output.stderr, "release-phase complete.");
                 },
             );
         },
@@ -0,0 +1,38 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+mod support;
+
+use std::fs;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use release_artifacts::create_archive;
+use support::{generate_tree, temp_path, TREE_SHAPES};
+
+fn bench_create_archive(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_archive");
+    group.sample_size(10);
+    for shape in &TREE_SHAPES {
+        let source_dir = temp_path(shape.name);
+        generate_tree(&source_dir, shape);
+        let destination = source_dir.with_extension("tgz");
+
+        group.throughput(Throughput::Bytes(shape.total_bytes()));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(shape.name),
+            &source_dir,
+            |b, source_dir| {
+                b.iter(|| {
+                    create_archive(source_dir, &destination).expect("archive should be created");
+                });
+            },
+        );
+
+        fs::remove_dir_all(&source_dir).unwrap_or_default();
+        fs::remove_file(&destination).unwrap_or_default();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_create_archive);
+criterion_main!(benches);
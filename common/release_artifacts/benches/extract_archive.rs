@@ -0,0 +1,45 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+mod support;
+
+use std::fs;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use release_artifacts::{create_archive, extract_archive};
+use support::{generate_tree, temp_path, TempDir, TREE_SHAPES};
+
+fn bench_extract_archive(c: &mut Criterion) {
+    let mut group = c.benchmark_group("extract_archive");
+    group.sample_size(10);
+    for shape in &TREE_SHAPES {
+        let source_dir = temp_path(shape.name);
+        generate_tree(&source_dir, shape);
+        let archive_path = source_dir.with_extension("tgz");
+        create_archive(&source_dir, &archive_path).expect("archive should be created");
+        fs::remove_dir_all(&source_dir).unwrap_or_default();
+
+        group.throughput(Throughput::Bytes(shape.total_bytes()));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(shape.name),
+            &archive_path,
+            |b, archive_path| {
+                b.iter_batched(
+                    || TempDir(temp_path("extracted")),
+                    |destination| {
+                        extract_archive(archive_path, &destination.0)
+                            .expect("archive should be extracted");
+                        destination
+                    },
+                    criterion::BatchSize::PerIteration,
+                );
+            },
+        );
+
+        fs::remove_file(&archive_path).unwrap_or_default();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_extract_archive);
+criterion_main!(benches);
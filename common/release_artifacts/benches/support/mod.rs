@@ -0,0 +1,96 @@
+// Each bench target compiles this module separately, and uses only part of it.
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+/// The shape of a synthetic artifact tree.
+pub(crate) struct TreeShape {
+    pub(crate) name: &'static str,
+    pub(crate) file_count: usize,
+    pub(crate) file_size: usize,
+    pub(crate) files_per_dir: usize,
+    pub(crate) depth: usize,
+}
+
+impl TreeShape {
+    pub(crate) fn total_bytes(&self) -> u64 {
+        (self.file_count * self.file_size) as u64
+    }
+}
+
+/// Shapes resembling real release artifacts: incremental build output of tiny files,
+/// a typical static site, a few large bundles, and deeply nested route directories.
+pub(crate) const TREE_SHAPES: [TreeShape; 4] = [
+    TreeShape {
+        name: "tiny-files",
+        file_count: 50_000,
+        file_size: 256,
+        files_per_dir: 100,
+        depth: 1,
+    },
+    TreeShape {
+        name: "static-site",
+        file_count: 2_000,
+        file_size: 16 * 1024,
+        files_per_dir: 50,
+        depth: 2,
+    },
+    TreeShape {
+        name: "large-files",
+        file_count: 4,
+        file_size: 16 * 1024 * 1024,
+        files_per_dir: 4,
+        depth: 1,
+    },
+    TreeShape {
+        name: "deeply-nested",
+        file_count: 2_000,
+        file_size: 1024,
+        files_per_dir: 10,
+        depth: 16,
+    },
+];
+
+/// Returns a unique path in the system temp directory.
+pub(crate) fn temp_path(label: &str) -> PathBuf {
+    env::temp_dir().join(format!(
+        "release-artifacts-bench-{label}-{}",
+        Uuid::new_v4()
+    ))
+}
+
+/// A directory removed on drop, so that outputs of each iteration are cleaned up untimed.
+pub(crate) struct TempDir(pub(crate) PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).unwrap_or_default();
+    }
+}
+
+/// Writes a synthetic tree of the given shape, with compressible source-like content.
+pub(crate) fn generate_tree(root: &Path, shape: &TreeShape) {
+    let content: Vec<u8> = "export default function Page() { return null }\n"
+        .bytes()
+        .cycle()
+        .take(shape.file_size)
+        .collect();
+    for i in 0..shape.file_count {
+        let group = i / shape.files_per_dir;
+        let mut dir = root.to_path_buf();
+        for level in 1..shape.depth {
+            dir.push(format!("level-{level}"));
+        }
+        dir.push(format!("group-{group:05}"));
+        if i % shape.files_per_dir == 0 {
+            fs::create_dir_all(&dir).expect("bench tree directory should be created");
+        }
+        fs::write(dir.join(format!("file-{i}.js")), &content)
+            .expect("bench tree file should be written");
+    }
+}
@@ -0,0 +1,109 @@
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+mod support;
+
+use std::fs;
+
+use aws_config::BehaviorVersion;
+use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+use aws_smithy_types::body::SdkBody;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use release_artifacts::{
+    create_archive, download_with_client, upload_with_client, ArchiveVerifier, ConflictPolicy,
+};
+use support::{generate_tree, temp_path, TempDir, TREE_SHAPES};
+
+// Streams bodies through the S3 client against an in-memory replay of responses,
+// so that these measure the artifact IO paths rather than the network.
+fn make_s3_client(response_body: SdkBody) -> aws_sdk_s3::Client {
+    let event = ReplayEvent::new(
+        http::Request::builder()
+            .body(SdkBody::empty())
+            .expect("request should be built"),
+        http::Response::builder()
+            .status(200)
+            .body(response_body)
+            .expect("response should be built"),
+    );
+    aws_sdk_s3::Client::from_conf(
+        aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .credentials_provider(aws_sdk_s3::config::Credentials::new(
+                "ABENCHCLIENT",
+                "abenchsecretkey",
+                None,
+                None,
+                "",
+            ))
+            .region(aws_sdk_s3::config::Region::new("us-east-1"))
+            .http_client(StaticReplayClient::new(vec![event]))
+            .build(),
+    )
+}
+
+fn bench_transfer(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime should start");
+    let bucket_name = "bench-bucket".to_string();
+    let bucket_key = "bench/release-v1.tgz".to_string();
+
+    let mut group = c.benchmark_group("transfer");
+    group.sample_size(10);
+    for shape in &TREE_SHAPES {
+        let source_dir = temp_path(shape.name);
+        generate_tree(&source_dir, shape);
+        let archive_path = source_dir.with_extension("tgz");
+        create_archive(&source_dir, &archive_path).expect("archive should be created");
+        fs::remove_dir_all(&source_dir).unwrap_or_default();
+        let archive_data = fs::read(&archive_path).expect("archive should be readable");
+        let archive_name = archive_path.to_string_lossy().to_string();
+
+        group.throughput(Throughput::Bytes(archive_data.len() as u64));
+        group.bench_function(BenchmarkId::new("upload", shape.name), |b| {
+            b.iter_batched(
+                || make_s3_client(SdkBody::empty()),
+                |s3| {
+                    runtime
+                        .block_on(upload_with_client(
+                            &s3,
+                            &bucket_name,
+                            &bucket_key,
+                            &archive_name,
+                        ))
+                        .expect("upload should succeed");
+                },
+                BatchSize::PerIteration,
+            );
+        });
+        group.bench_function(BenchmarkId::new("download", shape.name), |b| {
+            b.iter_batched(
+                || {
+                    (
+                        make_s3_client(SdkBody::from(archive_data.clone())),
+                        TempDir(temp_path("downloaded")),
+                    )
+                },
+                |(s3, destination)| {
+                    runtime
+                        .block_on(download_with_client(
+                            &s3,
+                            &bucket_name,
+                            &bucket_key,
+                            &destination.0,
+                            &ArchiveVerifier::default(),
+                            ConflictPolicy::Overwrite,
+                        ))
+                        .expect("download should succeed");
+                    destination
+                },
+                BatchSize::PerIteration,
+            );
+        });
+
+        fs::remove_file(&archive_path).unwrap_or_default();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_transfer);
+criterion_main!(benches);
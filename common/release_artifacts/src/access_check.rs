@@ -0,0 +1,277 @@
+//! Checks that the configured credentials may use `s3` storage as the buildpack does, by probing
+//! a small object under `release-phase-probe/`.
+
+use std::{collections::HashMap, hash::BuildHasher};
+
+use aws_sdk_s3::{
+    config::http::HttpResponse,
+    error::{ProvideErrorMetadata, SdkError},
+    primitives::ByteStream,
+};
+use uuid::Uuid;
+
+use crate::{
+    detect_storage_scheme, errors::ReleaseArtifactsError, generate_s3_client,
+    generate_s3_storage_prefix, guard_s3_credentials,
+};
+
+const PROBE_DIR: &str = "release-phase-probe";
+
+/// An S3 action that the buildpack depends on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum S3Action {
+    PutObject,
+    GetObject,
+    ListBucket,
+    DeleteObject,
+}
+
+impl S3Action {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            S3Action::PutObject => "s3:PutObject",
+            S3Action::GetObject => "s3:GetObject",
+            S3Action::ListBucket => "s3:ListBucket",
+            S3Action::DeleteObject => "s3:DeleteObject",
+        }
+    }
+
+    /// The buildpack features that break without the action.
+    #[must_use]
+    pub fn features(self) -> &'static str {
+        match self {
+            S3Action::PutObject => "save-release-artifacts, completion markers",
+            S3Action::GetObject => {
+                "load-release-artifacts, inspect-release-artifacts, rerun protection"
+            }
+            S3Action::ListBucket => {
+                "loading the latest archive, du-release-artifacts, garbage collection"
+            }
+            S3Action::DeleteObject => "garbage collection, save journals",
+        }
+    }
+}
+
+/// Whether a probe found the action allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Allowed,
+    Denied,
+    /// The probe could not tell, because it depends on another action that was denied.
+    Unknown,
+}
+
+/// Probes each action the buildpack uses in the configured storage location, deleting the probe
+/// object afterwards, when allowed. Only `s3` storage is checked.
+pub async fn check_storage_access<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<Vec<(S3Action, Access)>, ReleaseArtifactsError> {
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"s3" => {
+            guard_s3_credentials(env)?;
+            let (bucket_name, bucket_region, bucket_key_prefix) = generate_s3_storage_prefix(env)?;
+            let s3 = generate_s3_client(env, bucket_region).await;
+            let probe_key = format!("{bucket_key_prefix}{PROBE_DIR}/{}", Uuid::new_v4());
+            check_access_with_client(&s3, &bucket_name, &bucket_key_prefix, &probe_key).await
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) async fn check_access_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_key_prefix: &str,
+    probe_key: &str,
+) -> Result<Vec<(S3Action, Access)>, ReleaseArtifactsError> {
+    let put = access(
+        s3.put_object()
+            .bucket(bucket_name)
+            .key(probe_key)
+            .body(ByteStream::from_static(b"release-phase probe"))
+            .send()
+            .await,
+    )?;
+    let list = access(
+        s3.list_objects_v2()
+            .bucket(bucket_name)
+            .prefix(bucket_key_prefix)
+            .max_keys(1)
+            .send()
+            .await,
+    )?;
+    // Without the probe object, GetObject is allowed when S3 responds that it does not exist,
+    // but without ListBucket, S3 responds to a missing object as denied.
+    let get = match s3
+        .get_object()
+        .bucket(bucket_name)
+        .key(probe_key)
+        .send()
+        .await
+    {
+        Err(e) if e.code() == Some("NoSuchKey") => Access::Allowed,
+        result => match access(result)? {
+            Access::Denied if put != Access::Allowed && list != Access::Allowed => Access::Unknown,
+            get => get,
+        },
+    };
+    // Deleting an object that does not exist succeeds, so is probed either way.
+    let delete = access(
+        s3.delete_object()
+            .bucket(bucket_name)
+            .key(probe_key)
+            .send()
+            .await,
+    )?;
+    Ok(vec![
+        (S3Action::PutObject, put),
+        (S3Action::GetObject, get),
+        (S3Action::ListBucket, list),
+        (S3Action::DeleteObject, delete),
+    ])
+}
+
+// Whether a probe was allowed, failing for errors other than being denied, such as a missing
+// bucket or an unreachable endpoint.
+fn access<T, E>(
+    result: Result<T, SdkError<E, HttpResponse>>,
+) -> Result<Access, ReleaseArtifactsError>
+where
+    E: std::error::Error + ProvideErrorMetadata + Send + Sync + 'static,
+{
+    match result {
+        Ok(_) => Ok(Access::Allowed),
+        Err(e) if e.code() == Some("AccessDenied") => Ok(Access::Denied),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_config::BehaviorVersion;
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+
+    use super::{check_access_with_client, Access, S3Action};
+    use crate::make_s3_test_credentials;
+
+    const DENIED: &str = "<Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>";
+
+    fn test_s3_client(replay_client: &StaticReplayClient) -> aws_sdk_s3::Client {
+        aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        )
+    }
+
+    // Responses are replayed in order: PutObject, ListObjectsV2, GetObject, & DeleteObject.
+    fn replay_client(responses: [(u16, &str); 4]) -> StaticReplayClient {
+        StaticReplayClient::new(
+            responses
+                .iter()
+                .map(|(status, body)| {
+                    ReplayEvent::new(
+                        http::Request::builder()
+                            .uri("https://test-bucket.s3.us-east-1.amazonaws.com/")
+                            .body(SdkBody::empty())
+                            .unwrap(),
+                        http::Response::builder()
+                            .status(*status)
+                            .body(SdkBody::from(*body))
+                            .unwrap(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    async fn check(responses: [(u16, &str); 4]) -> Vec<(S3Action, Access)> {
+        let replay_client = replay_client(responses);
+        let s3 = test_s3_client(&replay_client);
+        check_access_with_client(
+            &s3,
+            "test-bucket",
+            "sub/path/",
+            "sub/path/release-phase-probe/test",
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn check_access_allows_every_action() {
+        let result = check([
+            (200, ""),
+            (
+                200,
+                "<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>",
+            ),
+            (200, "release-phase probe"),
+            (204, ""),
+        ])
+        .await;
+
+        assert!(result.iter().all(|(_, access)| *access == Access::Allowed));
+    }
+
+    #[tokio::test]
+    async fn check_access_reports_denied_actions() {
+        let result = check([
+            (403, DENIED),
+            (
+                200,
+                "<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>",
+            ),
+            (403, DENIED),
+            (204, ""),
+        ])
+        .await;
+
+        assert_eq!(
+            result,
+            vec![
+                (S3Action::PutObject, Access::Denied),
+                (S3Action::GetObject, Access::Denied),
+                (S3Action::ListBucket, Access::Allowed),
+                (S3Action::DeleteObject, Access::Allowed),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn check_access_allows_get_of_missing_probe() {
+        let result = check([
+            (403, DENIED),
+            (
+                200,
+                "<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>",
+            ),
+            (
+                404,
+                "<Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message></Error>",
+            ),
+            (403, DENIED),
+        ])
+        .await;
+
+        assert_eq!(result[1], (S3Action::GetObject, Access::Allowed));
+        assert_eq!(result[3], (S3Action::DeleteObject, Access::Denied));
+    }
+
+    #[tokio::test]
+    async fn check_access_cannot_tell_get_without_put_or_list() {
+        let result = check([(403, DENIED), (403, DENIED), (403, DENIED), (403, DENIED)]).await;
+
+        assert_eq!(result[1], (S3Action::GetObject, Access::Unknown));
+        assert!(result
+            .iter()
+            .filter(|(action, _)| *action != S3Action::GetObject)
+            .all(|(_, access)| *access == Access::Denied));
+    }
+}
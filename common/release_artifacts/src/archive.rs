@@ -0,0 +1,1160 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File, Metadata},
+    hash::BuildHasher,
+    io::{self, Read, Write},
+    num::NonZeroUsize,
+    path::{Component, Path, PathBuf},
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+use tar::{EntryType, Header, HeaderMode};
+
+use crate::{
+    compression::Decoder, errors::ReleaseArtifactsError, filter::ArtifactFilter, permissions,
+    ArchiveEntry, ArchiveEntryKind,
+};
+
+// Files up to this size are read ahead in parallel, larger files are streamed into the archive.
+const SMALL_FILE_MAX_BYTES: u64 = 64 * 1024;
+
+// Entries appended per read-ahead batch, which bounds read-ahead memory to about 32MB.
+const BATCH_SIZE: usize = 512;
+
+/// Path of the manifest, stored as the first entry of every archive, so that its contents can
+/// be listed by reading only the start of the archive.
+pub(crate) const MANIFEST_PATH: &str = ".release-artifacts-manifest.json";
+
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    // Set for single-file artifacts, to the file's path relative to the working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file_artifact: Option<String>,
+    entries: Vec<ArchiveEntry>,
+}
+
+type IndexedContents = Vec<(usize, Vec<u8>)>;
+
+/// Why an entry cannot be archived or unpacked faithfully, wrapped in the `io::Error` returned
+/// when creating or extracting an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArchivePathError {
+    /// The path is not valid UTF-8, so it cannot be listed in the manifest or served by URL.
+    NotUtf8(PathBuf),
+    /// The paths differ only by case, so one would overwrite the other on a case-insensitive
+    /// filesystem.
+    CaseCollision(PathBuf, PathBuf),
+    /// The path would be unpacked outside of the destination, such as through `..`.
+    OutsideDestination(PathBuf),
+    /// A name in the path cannot be unpacked on Windows, for the given reason.
+    NotWindowsSafe(PathBuf, String),
+    /// The path already exists in the destination, with `ConflictPolicy::Fail`.
+    Exists(PathBuf),
+    /// The path is that of the archive's manifest, so the artifact would be read as the manifest.
+    ReservedForManifest(PathBuf),
+}
+
+impl fmt::Display for ArchivePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchivePathError::NotUtf8(path) => write!(f, "artifact path {path:?} is not UTF-8"),
+            ArchivePathError::CaseCollision(path, other) => write!(
+                f,
+                "artifact paths {path:?} and {other:?} differ only by case"
+            ),
+            ArchivePathError::OutsideDestination(path) => write!(
+                f,
+                "archive entry {path:?} would be unpacked outside of the destination"
+            ),
+            ArchivePathError::NotWindowsSafe(path, reason) => {
+                write!(
+                    f,
+                    "artifact path {path:?} cannot be unpacked on Windows: {reason}"
+                )
+            }
+            ArchivePathError::Exists(path) => write!(
+                f,
+                "artifact path {path:?} already exists in the destination, and STATIC_ARTIFACTS_ON_CONFLICT is fail"
+            ),
+            ArchivePathError::ReservedForManifest(path) => write!(
+                f,
+                "artifact path {path:?} is reserved for the archive's manifest, rename or exclude it"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ArchivePathError {}
+
+impl From<ArchivePathError> for io::Error {
+    fn from(value: ArchivePathError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, value)
+    }
+}
+
+/// What extracting does with a file that already exists in the destination, such as an asset
+/// checked into the app's repo, from `STATIC_ARTIFACTS_ON_CONFLICT`. Directories that already
+/// exist are always merged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Replace the existing file with the artifact.
+    #[default]
+    Overwrite,
+    /// Keep the existing file, skipping the artifact.
+    KeepExisting,
+    /// Fail the extraction, with `ArchivePathError::Exists`.
+    Fail,
+}
+
+impl ConflictPolicy {
+    /// Reads `STATIC_ARTIFACTS_ON_CONFLICT`, `overwrite`, `keep-existing`, or `fail`, defaulting
+    /// to `overwrite`.
+    pub fn from_env<S: BuildHasher>(
+        env: &HashMap<String, String, S>,
+    ) -> Result<Self, ReleaseArtifactsError> {
+        match env
+            .get("STATIC_ARTIFACTS_ON_CONFLICT")
+            .map(|v| v.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            None | Some("" | "overwrite") => Ok(ConflictPolicy::Overwrite),
+            Some("keep-existing") => Ok(ConflictPolicy::KeepExisting),
+            Some("fail") => Ok(ConflictPolicy::Fail),
+            Some(value) => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+                "STATIC_ARTIFACTS_ON_CONFLICT '{value}' is not supported, use overwrite, keep-existing, or fail"
+            ))),
+        }
+    }
+}
+
+struct TreeEntry {
+    path: PathBuf,
+    archive_path: PathBuf,
+    metadata: Metadata,
+    link_target: Option<PathBuf>,
+}
+
+/// Appends the contents of the given directory selected by the filter to the root of the
+/// archive, in sorted order, preceded by a manifest of those contents.
+///
+/// Symlinks are stored as links, never followed. Every entry is stat'ed only once, and
+/// small files are read ahead in parallel, so that trees of hundreds of thousands of tiny
+/// files are not bound by per-file read latency.
+pub(crate) fn append_tree<W: Write>(
+    tar: &mut tar::Builder<W>,
+    source_dir: &Path,
+    filter: &ArtifactFilter,
+) -> io::Result<()> {
+    let entries = walk_tree(source_dir, filter)?;
+    append_manifest(tar, &entries, None)?;
+    let workers = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    for batch in entries.chunks(BATCH_SIZE) {
+        let contents = read_small_files(batch, workers)?;
+        for (entry, content) in batch.iter().zip(contents) {
+            append_entry(tar, entry, content)?;
+        }
+    }
+    Ok(())
+}
+
+/// Appends a single file to the archive, at its path relative to the working directory,
+/// preceded by a manifest marking it as a single-file artifact.
+pub(crate) fn append_file<W: Write>(
+    tar: &mut tar::Builder<W>,
+    source_file: &Path,
+) -> io::Result<()> {
+    let archive_path = portable_path(file_artifact_path(source_file)?)?;
+    if archive_path == Path::new(MANIFEST_PATH) {
+        return Err(ArchivePathError::ReservedForManifest(archive_path).into());
+    }
+    let entry = TreeEntry {
+        path: source_file.to_path_buf(),
+        archive_path,
+        metadata: fs::metadata(source_file)?,
+        link_target: None,
+    };
+    append_manifest(tar, std::slice::from_ref(&entry), Some(&entry.archive_path))?;
+    append_entry(tar, &entry, None)
+}
+
+// Normalizes a single-file artifact's path, which must stay within the working directory,
+// so that it can be restored to the same place.
+fn file_artifact_path(source_file: &Path) -> io::Result<PathBuf> {
+    let mut archive_path = PathBuf::new();
+    for component in source_file.components() {
+        match component {
+            Component::Normal(part) => archive_path.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("single-file artifact must be a relative path within the working directory, not {source_file:?}"),
+                ))
+            }
+        }
+    }
+    Ok(archive_path)
+}
+
+// Checks that the path is UTF-8 and that each of its names can be unpacked on Windows, so
+// that archives are portable to any consumer of their download URLs.
+fn portable_path(path: PathBuf) -> Result<PathBuf, ArchivePathError> {
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        let Some(name) = name.to_str() else {
+            return Err(ArchivePathError::NotUtf8(path));
+        };
+        if let Some(reason) = windows_unsafe_reason(name) {
+            return Err(ArchivePathError::NotWindowsSafe(path, reason));
+        }
+    }
+    Ok(path)
+}
+
+const WINDOWS_RESERVED_CHARS: &[char] = &['\\', '<', '>', ':', '"', '|', '?', '*'];
+
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// Explains why Windows cannot create a file with the given name, if it cannot.
+fn windows_unsafe_reason(name: &str) -> Option<String> {
+    if let Some(c) = name.chars().find(|c| WINDOWS_RESERVED_CHARS.contains(c)) {
+        let reason = if c == '\\' {
+            "'\\' is a path separator".to_string()
+        } else {
+            format!("'{c}' is a reserved character")
+        };
+        return Some(reason);
+    }
+    if name.chars().any(char::is_control) {
+        return Some("control characters are reserved".to_string());
+    }
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Some("names cannot end with '.' or ' '".to_string());
+    }
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    if WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        return Some(format!("'{stem}' is a reserved device name"));
+    }
+    None
+}
+
+// Lists the tree, so that each directory precedes its contents and siblings are sorted by name.
+// Fails for names that are not portable, or that collide with a sibling's when case is ignored.
+// Excluded paths are not searched, and directories that are not included are only listed when
+// they contain included paths.
+fn walk_tree(source_dir: &Path, filter: &ArtifactFilter) -> io::Result<Vec<TreeEntry>> {
+    let mut entries = vec![];
+    // Directories searched only for included paths, by their index in entries.
+    let mut searched_dirs: HashMap<PathBuf, usize> = HashMap::new();
+    let mut pending_dirs = vec![(PathBuf::new(), filter.includes_all())];
+    while let Some((relative_dir, is_included_dir)) = pending_dirs.pop() {
+        let mut dir_entries =
+            fs::read_dir(source_dir.join(&relative_dir))?.collect::<io::Result<Vec<_>>>()?;
+        dir_entries.sort_by_key(fs::DirEntry::file_name);
+        let mut sub_dirs = vec![];
+        let mut folded_names: HashMap<String, PathBuf> = HashMap::new();
+        for dir_entry in dir_entries {
+            // DirEntry::metadata does not traverse symlinks.
+            let metadata = dir_entry.metadata()?;
+            let archive_path = portable_path(relative_dir.join(dir_entry.file_name()))?;
+            let folded_name = dir_entry.file_name().to_string_lossy().to_lowercase();
+            if let Some(other) = folded_names.insert(folded_name, archive_path.clone()) {
+                return Err(ArchivePathError::CaseCollision(other, archive_path).into());
+            }
+            if filter.excludes(&archive_path) {
+                continue;
+            }
+            if archive_path == Path::new(MANIFEST_PATH) {
+                return Err(ArchivePathError::ReservedForManifest(archive_path).into());
+            }
+            let is_included = is_included_dir || filter.includes(&archive_path);
+            if metadata.is_dir() {
+                if !is_included {
+                    searched_dirs.insert(archive_path.clone(), entries.len());
+                }
+                sub_dirs.push((archive_path.clone(), is_included));
+            } else if !is_included {
+                continue;
+            }
+            let link_target = if metadata.is_symlink() {
+                let target = fs::read_link(dir_entry.path())?;
+                if target.to_str().is_none() {
+                    return Err(ArchivePathError::NotUtf8(archive_path).into());
+                }
+                Some(target)
+            } else {
+                None
+            };
+            entries.push(TreeEntry {
+                path: dir_entry.path(),
+                archive_path,
+                metadata,
+                link_target,
+            });
+        }
+        pending_dirs.extend(sub_dirs.into_iter().rev());
+    }
+    if searched_dirs.is_empty() {
+        return Ok(entries);
+    }
+    let mut is_listed = vec![true; entries.len()];
+    for index in searched_dirs.values() {
+        is_listed[*index] = false;
+    }
+    for entry in &entries {
+        if searched_dirs.contains_key(&entry.archive_path) {
+            continue;
+        }
+        for ancestor in entry.archive_path.ancestors().skip(1) {
+            match searched_dirs.get(ancestor) {
+                Some(index) if !is_listed[*index] => is_listed[*index] = true,
+                _ => break,
+            }
+        }
+    }
+    Ok(entries
+        .into_iter()
+        .zip(is_listed)
+        .filter_map(|(entry, is_listed)| is_listed.then_some(entry))
+        .collect())
+}
+
+fn read_small_files(batch: &[TreeEntry], workers: usize) -> io::Result<Vec<Option<Vec<u8>>>> {
+    let mut contents: Vec<Option<Vec<u8>>> = vec![None; batch.len()];
+    let small_files: Vec<usize> = batch
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.metadata.is_file() && e.metadata.len() <= SMALL_FILE_MAX_BYTES)
+        .map(|(i, _)| i)
+        .collect();
+    if small_files.is_empty() {
+        return Ok(contents);
+    }
+    if workers <= 1 {
+        for i in small_files {
+            contents[i] = Some(fs::read(&batch[i].path)?);
+        }
+        return Ok(contents);
+    }
+
+    let chunk_size = small_files.len().div_ceil(workers);
+    let results: Vec<io::Result<IndexedContents>> = thread::scope(|scope| {
+        let readers: Vec<_> = small_files
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&i| fs::read(&batch[i].path).map(|content| (i, content)))
+                        .collect::<io::Result<Vec<_>>>()
+                })
+            })
+            .collect();
+        readers
+            .into_iter()
+            .map(|reader| {
+                reader
+                    .join()
+                    .unwrap_or_else(|_| Err(io::Error::other("read-ahead thread panicked")))
+            })
+            .collect()
+    });
+    for result in results {
+        for (i, content) in result? {
+            contents[i] = Some(content);
+        }
+    }
+    Ok(contents)
+}
+
+fn append_entry<W: Write>(
+    tar: &mut tar::Builder<W>,
+    entry: &TreeEntry,
+    content: Option<Vec<u8>>,
+) -> io::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_metadata_in_mode(&entry.metadata, HeaderMode::Complete);
+    let file_type = entry.metadata.file_type();
+    if let Some(target) = &entry.link_target {
+        header.set_size(0);
+        tar.append_link(&mut header, &entry.archive_path, target)
+    } else if file_type.is_dir() {
+        header.set_size(0);
+        tar.append_data(&mut header, &entry.archive_path, io::empty())
+    } else if file_type.is_file() {
+        if let Some(content) = content {
+            header.set_size(content.len() as u64);
+            tar.append_data(&mut header, &entry.archive_path, content.as_slice())
+        } else {
+            let file = File::open(&entry.path)?;
+            tar.append_data(&mut header, &entry.archive_path, file)
+        }
+    } else {
+        // Sockets, fifos, and device files have no place in release artifacts.
+        Ok(())
+    }
+}
+
+fn append_manifest<W: Write>(
+    tar: &mut tar::Builder<W>,
+    entries: &[TreeEntry],
+    file_artifact: Option<&Path>,
+) -> io::Result<()> {
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        file_artifact: file_artifact.map(|path| path.to_string_lossy().to_string()),
+        entries: entries
+            .iter()
+            .filter_map(|entry| {
+                let file_type = entry.metadata.file_type();
+                let (kind, size) = if file_type.is_symlink() {
+                    (ArchiveEntryKind::Symlink, 0)
+                } else if file_type.is_dir() {
+                    (ArchiveEntryKind::Directory, 0)
+                } else if file_type.is_file() {
+                    (ArchiveEntryKind::File, entry.metadata.len())
+                } else {
+                    return None;
+                };
+                Some(ArchiveEntry {
+                    path: entry.archive_path.to_string_lossy().to_string(),
+                    size,
+                    mode: permissions::file_mode(&entry.metadata) & 0o7777,
+                    kind,
+                    link_target: entry
+                        .link_target
+                        .as_ref()
+                        .map(|target| target.to_string_lossy().to_string()),
+                })
+            })
+            .collect(),
+    };
+    let content = serde_json::to_vec(&manifest).map_err(io::Error::other)?;
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_size(content.len() as u64);
+    tar.append_data(&mut header, MANIFEST_PATH, content.as_slice())
+}
+
+/// Reads the manifest from the start of a compressed tar stream, returning `None` for archives saved
+/// without one. Fails when the stream ends before the manifest is complete, as with a ranged read.
+pub(crate) fn read_manifest<R: Read>(reader: R) -> io::Result<Option<Vec<ArchiveEntry>>> {
+    let mut archive = tar::Archive::new(Decoder::detect(reader)?);
+    let Some(entry) = archive.entries()?.next() else {
+        return Ok(None);
+    };
+    let mut entry = entry?;
+    if entry.path()? != Path::new(MANIFEST_PATH) {
+        return Ok(None);
+    }
+    let mut content = vec![];
+    entry.read_to_end(&mut content)?;
+    if content.len() as u64 != entry.header().size()? {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    let manifest: Manifest = serde_json::from_slice(&content).map_err(io::Error::other)?;
+    Ok(Some(manifest.entries))
+}
+
+/// Lists the entries of a compressed tar stream, reading headers and skipping over file content.
+pub(crate) fn list_entries<R: Read>(reader: R) -> io::Result<Vec<ArchiveEntry>> {
+    let mut archive = tar::Archive::new(Decoder::detect(reader)?);
+    let mut entries = vec![];
+    for (index, entry) in archive.entries()?.enumerate() {
+        let entry = entry?;
+        // Only the first entry is the manifest. Archives saved without one may have a file there.
+        if index == 0 && entry.path()? == Path::new(MANIFEST_PATH) {
+            continue;
+        }
+        let header = entry.header();
+        let kind = match header.entry_type() {
+            EntryType::Regular | EntryType::Continuous => ArchiveEntryKind::File,
+            EntryType::Directory => ArchiveEntryKind::Directory,
+            EntryType::Symlink => ArchiveEntryKind::Symlink,
+            _ => ArchiveEntryKind::Other,
+        };
+        entries.push(ArchiveEntry {
+            path: entry.path()?.to_string_lossy().to_string(),
+            size: header.size()?,
+            mode: header.mode()? & 0o7777,
+            kind,
+            link_target: entry
+                .link_name()?
+                .map(|target| target.to_string_lossy().to_string()),
+        });
+    }
+    Ok(entries)
+}
+
+/// Unpacks a compressed tar stream into the given directory, leaving out the manifest, the first
+/// entry.
+/// Single-file artifacts are instead unpacked to their original path within
+/// `file_artifact_root`, and that path is returned. Modes from the archive are masked with the
+/// process umask, and files that already exist are handled by the conflict policy.
+pub(crate) fn unpack<R: Read>(
+    reader: R,
+    destination: &Path,
+    file_artifact_root: &Path,
+    on_conflict: ConflictPolicy,
+) -> io::Result<Option<PathBuf>> {
+    let mut archive = tar::Archive::new(Decoder::detect(reader)?);
+    archive.set_mask(permissions::process_umask());
+    let mut file_artifact = None;
+    let mut destination = destination;
+    let mut is_destination_created = false;
+    // Like tar::Archive::unpack, directories are unpacked last, so that read-only
+    // directories do not prevent unpacking their contents.
+    let mut directories = vec![];
+    let mut folded_paths = HashMap::new();
+    for (index, entry) in archive.entries()?.enumerate() {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        // Only the first entry is the manifest. Archives saved without one may have a file there.
+        if index == 0 && path == Path::new(MANIFEST_PATH) {
+            let mut content = vec![];
+            entry.read_to_end(&mut content)?;
+            let manifest: Manifest = serde_json::from_slice(&content).map_err(io::Error::other)?;
+            if let Some(path) = manifest.file_artifact {
+                file_artifact = Some(file_artifact_root.join(path));
+                destination = file_artifact_root;
+            }
+            continue;
+        }
+        // Entries repeated with the same case, as tar allows, replace each other as they would on
+        // any filesystem.
+        let normal_path: PathBuf = path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        let folded_path = normal_path.to_string_lossy().to_lowercase();
+        let is_repeated = match folded_paths.insert(folded_path, normal_path.clone()) {
+            Some(other) if other != normal_path => {
+                return Err(ArchivePathError::CaseCollision(other, normal_path).into());
+            }
+            other => other.is_some(),
+        };
+        let is_directory = entry.header().entry_type() == EntryType::Directory;
+        if !is_repeated
+            && !is_directory
+            && on_conflict != ConflictPolicy::Overwrite
+            && fs::symlink_metadata(destination.join(&normal_path)).is_ok()
+        {
+            if on_conflict == ConflictPolicy::Fail {
+                return Err(ArchivePathError::Exists(normal_path).into());
+            }
+            continue;
+        }
+        if !is_destination_created {
+            fs::create_dir_all(destination)?;
+            is_destination_created = true;
+        }
+        if is_directory {
+            directories.push(entry);
+        } else if !entry.unpack_in(destination)? {
+            return Err(ArchivePathError::OutsideDestination(path).into());
+        }
+    }
+    if !is_destination_created && file_artifact.is_none() {
+        fs::create_dir_all(destination)?;
+    }
+    for mut directory in directories {
+        if !directory.unpack_in(destination)? {
+            return Err(
+                ArchivePathError::OutsideDestination(directory.path()?.to_path_buf()).into(),
+            );
+        }
+    }
+    Ok(file_artifact)
+}
+
+// The tests archive symlinks & Unix modes.
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        ffi::OsStr,
+        fs, io,
+        os::unix::{ffi::OsStrExt, fs::symlink},
+        path::{Path, PathBuf},
+    };
+
+    use flate2::{write::GzEncoder, Compression};
+    use tar::Archive;
+    use uuid::Uuid;
+
+    use super::{
+        append_file, append_tree, file_artifact_path, list_entries, read_manifest,
+        read_small_files, unpack, walk_tree, windows_unsafe_reason, ArchivePathError,
+        ConflictPolicy, MANIFEST_PATH,
+    };
+    use crate::{filter::ArtifactFilter, ArchiveEntry, ArchiveEntryKind};
+
+    fn create_test_archive(source_path: &Path) -> Vec<u8> {
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        append_tree(&mut tar, source_path, &ArtifactFilter::default()).unwrap();
+        tar.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn archive_path_error(error: &io::Error) -> Option<&ArchivePathError> {
+        error.get_ref()?.downcast_ref::<ArchivePathError>()
+    }
+
+    // Writes entries with raw names, which tar::Builder would otherwise validate.
+    fn create_raw_archive(names: &[&str]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for name in names {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_mode(0o644);
+            header.set_size(4);
+            header.set_cksum();
+            tar.append(&header, "data".as_bytes()).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn create_test_tree() -> PathBuf {
+        let unique = Uuid::new_v4();
+        let source_path = PathBuf::from(format!("append-tree-test-{unique}"));
+        fs::create_dir_all(source_path.join("b/nested")).unwrap();
+        fs::create_dir_all(source_path.join("a")).unwrap();
+        for i in 0..600 {
+            fs::write(
+                source_path.join(format!("a/{i:04}.txt")),
+                format!("file {i}"),
+            )
+            .unwrap();
+        }
+        fs::write(source_path.join("b/nested/large.bin"), vec![7_u8; 200_000]).unwrap();
+        fs::write(source_path.join("index.html"), "<html></html>").unwrap();
+        symlink("index.html", source_path.join("default.html")).unwrap();
+        source_path
+    }
+
+    #[test]
+    fn walk_tree_lists_directories_before_contents_in_sorted_order() {
+        let source_path = create_test_tree();
+        let entries = walk_tree(&source_path, &ArtifactFilter::default()).unwrap();
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+
+        let paths: Vec<&Path> = entries.iter().map(|e| e.archive_path.as_path()).collect();
+        assert_eq!(paths.len(), 606);
+        assert_eq!(
+            paths[0..4],
+            [
+                Path::new("a"),
+                Path::new("b"),
+                Path::new("default.html"),
+                Path::new("index.html")
+            ]
+        );
+        assert_eq!(paths[4], Path::new("a/0000.txt"));
+        assert_eq!(paths[604], Path::new("b/nested"));
+        assert_eq!(paths[605], Path::new("b/nested/large.bin"));
+    }
+
+    #[test]
+    fn walk_tree_lists_filtered_paths() {
+        let source_path = create_test_tree();
+        let filtered_paths = |include: &str, exclude: &str| {
+            let filter = ArtifactFilter::from_env(&HashMap::from([
+                ("STATIC_ARTIFACTS_INCLUDE".to_string(), include.to_string()),
+                ("STATIC_ARTIFACTS_EXCLUDE".to_string(), exclude.to_string()),
+            ]))
+            .unwrap();
+            walk_tree(&source_path, &filter)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.archive_path)
+                .collect::<Vec<_>>()
+        };
+        let included = filtered_paths("**/*.bin, index.html", "");
+        let excluded = filtered_paths("", "b, *.txt");
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+
+        assert_eq!(
+            included,
+            [
+                Path::new("b"),
+                Path::new("index.html"),
+                Path::new("b/nested"),
+                Path::new("b/nested/large.bin")
+            ]
+        );
+        assert_eq!(
+            excluded,
+            [
+                Path::new("a"),
+                Path::new("default.html"),
+                Path::new("index.html")
+            ]
+        );
+    }
+
+    #[test]
+    fn read_small_files_reads_only_small_regular_files() {
+        let source_path = create_test_tree();
+        let entries = walk_tree(&source_path, &ArtifactFilter::default()).unwrap();
+        let sequential = read_small_files(&entries, 1).unwrap();
+        let parallel = read_small_files(&entries, 4).unwrap();
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+
+        assert_eq!(sequential, parallel);
+        assert!(sequential[0].is_none(), "directories are not read");
+        assert!(sequential[2].is_none(), "symlinks are not read");
+        assert_eq!(sequential[4], Some(b"file 0".to_vec()));
+        assert!(sequential[605].is_none(), "large files are streamed");
+    }
+
+    #[test]
+    fn append_tree_roundtrips_through_tar() {
+        let source_path = create_test_tree();
+        let mut tar = tar::Builder::new(Vec::new());
+        append_tree(&mut tar, &source_path, &ArtifactFilter::default()).unwrap();
+        let archive_data = tar.into_inner().unwrap();
+
+        let output_path = source_path.with_extension("extracted");
+        Archive::new(archive_data.as_slice())
+            .unpack(&output_path)
+            .unwrap();
+        let small_file = fs::read_to_string(output_path.join("a/0599.txt"));
+        let large_file = fs::read(output_path.join("b/nested/large.bin"));
+        let link_target = fs::read_link(output_path.join("default.html"));
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+        fs::remove_dir_all(&output_path).unwrap_or_default();
+
+        assert_eq!(small_file.unwrap(), "file 599");
+        assert_eq!(large_file.unwrap(), vec![7_u8; 200_000]);
+        assert_eq!(link_target.unwrap(), Path::new("index.html"));
+    }
+
+    #[test]
+    fn append_tree_writes_manifest_as_first_entry() {
+        let source_path = create_test_tree();
+        let archive_data = create_test_archive(&source_path);
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+
+        let mut archive = Archive::new(flate2::read::GzDecoder::new(archive_data.as_slice()));
+        let first_entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(first_entry.path().unwrap(), Path::new(MANIFEST_PATH));
+    }
+
+    #[test]
+    fn read_manifest_lists_tree() {
+        let source_path = create_test_tree();
+        let archive_data = create_test_archive(&source_path);
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+
+        let entries = read_manifest(archive_data.as_slice())
+            .unwrap()
+            .expect("archive should have a manifest");
+        assert_eq!(entries.len(), 606);
+        assert_eq!(
+            entries[2],
+            ArchiveEntry {
+                path: "default.html".to_string(),
+                size: 0,
+                mode: 0o777,
+                kind: ArchiveEntryKind::Symlink,
+                link_target: Some("index.html".to_string()),
+            }
+        );
+        assert_eq!(entries[605].path, "b/nested/large.bin");
+        assert_eq!(entries[605].size, 200_000);
+        assert_eq!(
+            entries,
+            list_entries(archive_data.as_slice()).unwrap(),
+            "manifest should match the archive's entries"
+        );
+    }
+
+    #[test]
+    fn read_manifest_fails_for_truncated_archive() {
+        let source_path = create_test_tree();
+        let archive_data = create_test_archive(&source_path);
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+
+        assert!(read_manifest(&archive_data[..1024]).is_err());
+    }
+
+    #[test]
+    fn read_manifest_returns_none_without_manifest() {
+        let archive_data = fs::read("test/fixtures/static-artifacts.tgz").unwrap();
+        assert!(read_manifest(archive_data.as_slice()).unwrap().is_none());
+    }
+
+    #[test]
+    fn unpack_leaves_out_manifest() {
+        let source_path = create_test_tree();
+        let archive_data = create_test_archive(&source_path);
+        let output_path = source_path.with_extension("unpacked");
+        let result = unpack(
+            archive_data.as_slice(),
+            &output_path,
+            Path::new("."),
+            ConflictPolicy::Overwrite,
+        );
+        let manifest_exists = output_path.join(MANIFEST_PATH).exists();
+        let small_file = fs::read_to_string(output_path.join("a/0000.txt"));
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+        fs::remove_dir_all(&output_path).unwrap_or_default();
+
+        assert!(result.unwrap().is_none());
+        assert!(!manifest_exists);
+        assert_eq!(small_file.unwrap(), "file 0");
+    }
+
+    #[test]
+    fn append_file_roundtrips_to_original_path() {
+        let unique = Uuid::new_v4();
+        let source_root = PathBuf::from(format!("append-file-test-{unique}"));
+        let restore_root = source_root.with_extension("restored");
+        fs::create_dir_all(source_root.join("dist")).unwrap();
+        fs::write(source_root.join("dist/bundle.bin"), "bundle").unwrap();
+
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        append_file(&mut tar, &source_root.join("dist/bundle.bin")).unwrap();
+        let archive_data = tar.into_inner().unwrap().finish().unwrap();
+        let manifest = read_manifest(archive_data.as_slice()).unwrap();
+        let result = unpack(
+            archive_data.as_slice(),
+            &restore_root.join("static-artifacts"),
+            &restore_root,
+            ConflictPolicy::Overwrite,
+        );
+        let restored_path = restore_root.join(&source_root).join("dist/bundle.bin");
+        let restored = fs::read_to_string(&restored_path);
+        let destination_exists = restore_root.join("static-artifacts").exists();
+        fs::remove_dir_all(&source_root).unwrap_or_default();
+        fs::remove_dir_all(&restore_root).unwrap_or_default();
+
+        let entries = manifest.expect("archive should have a manifest");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].path,
+            format!("append-file-test-{unique}/dist/bundle.bin")
+        );
+        assert_eq!(result.unwrap(), Some(restored_path));
+        assert_eq!(restored.unwrap(), "bundle");
+        assert!(!destination_exists);
+    }
+
+    #[test]
+    fn file_artifact_path_must_stay_within_working_directory() {
+        assert_eq!(
+            file_artifact_path(Path::new("./dist/bundle.bin")).unwrap(),
+            Path::new("dist/bundle.bin")
+        );
+        assert!(file_artifact_path(Path::new("/tmp/bundle.bin")).is_err());
+        assert!(file_artifact_path(Path::new("dist/../../bundle.bin")).is_err());
+    }
+
+    #[test]
+    fn unpack_creates_destination_for_empty_tree() {
+        let unique = Uuid::new_v4();
+        let source_path = PathBuf::from(format!("unpack-empty-test-{unique}"));
+        fs::create_dir_all(&source_path).unwrap();
+        let archive_data = create_test_archive(&source_path);
+        let output_path = source_path.with_extension("unpacked");
+        let result = unpack(
+            archive_data.as_slice(),
+            &output_path,
+            Path::new("."),
+            ConflictPolicy::Overwrite,
+        );
+        let destination_exists = output_path.is_dir();
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+        fs::remove_dir_all(&output_path).unwrap_or_default();
+
+        assert!(result.unwrap().is_none());
+        assert!(destination_exists);
+    }
+
+    #[test]
+    fn append_tree_fails_for_missing_source_dir() {
+        let mut tar = tar::Builder::new(Vec::new());
+        assert!(append_tree(
+            &mut tar,
+            Path::new("non-existent-path"),
+            &ArtifactFilter::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn append_tree_roundtrips_long_paths() {
+        let unique = Uuid::new_v4();
+        let source_path = PathBuf::from(format!("long-path-test-{unique}"));
+        let long_dir: PathBuf = (0..300).map(|i| format!("d{i}")).collect();
+        let long_name = format!("{}.txt", "n".repeat(200));
+        fs::create_dir_all(source_path.join(&long_dir)).unwrap();
+        fs::write(source_path.join(&long_dir).join(&long_name), "deep").unwrap();
+        symlink(&long_name, source_path.join(&long_dir).join("link.txt")).unwrap();
+        let archive_data = create_test_archive(&source_path);
+        let output_path = source_path.with_extension("unpacked");
+        let result = unpack(
+            archive_data.as_slice(),
+            &output_path,
+            Path::new("."),
+            ConflictPolicy::Overwrite,
+        );
+        let deep_file = fs::read_to_string(output_path.join(&long_dir).join(&long_name));
+        let link_target = fs::read_link(output_path.join(&long_dir).join("link.txt"));
+        let manifest = read_manifest(archive_data.as_slice()).unwrap();
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+        fs::remove_dir_all(&output_path).unwrap_or_default();
+
+        assert!(long_dir.join(&long_name).as_os_str().len() > 1024);
+        assert!(result.is_ok());
+        assert_eq!(deep_file.unwrap(), "deep");
+        assert_eq!(link_target.unwrap(), Path::new(&long_name));
+        let entries = manifest.expect("archive should have a manifest");
+        assert!(entries
+            .iter()
+            .any(|e| e.path == long_dir.join(&long_name).to_string_lossy()));
+    }
+
+    #[test]
+    fn append_tree_fails_for_non_utf8_names() {
+        let unique = Uuid::new_v4();
+        let source_path = PathBuf::from(format!("non-utf8-test-{unique}"));
+        fs::create_dir_all(&source_path).unwrap();
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        fs::write(source_path.join(name), "latin-1").unwrap();
+        let mut tar = tar::Builder::new(Vec::new());
+        let result = append_tree(&mut tar, &source_path, &ArtifactFilter::default());
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+
+        let error = result.expect_err("non-UTF-8 names should fail");
+        assert_eq!(
+            archive_path_error(&error),
+            Some(&ArchivePathError::NotUtf8(PathBuf::from(name)))
+        );
+    }
+
+    #[test]
+    fn append_tree_fails_for_case_colliding_names() {
+        let unique = Uuid::new_v4();
+        let source_path = PathBuf::from(format!("case-collision-test-{unique}"));
+        fs::create_dir_all(source_path.join("assets")).unwrap();
+        fs::write(source_path.join("assets/Logo.png"), "upper").unwrap();
+        fs::write(source_path.join("assets/logo.png"), "lower").unwrap();
+        let mut tar = tar::Builder::new(Vec::new());
+        let result = append_tree(&mut tar, &source_path, &ArtifactFilter::default());
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+
+        let error = result.expect_err("case-colliding names should fail");
+        assert_eq!(
+            archive_path_error(&error),
+            Some(&ArchivePathError::CaseCollision(
+                PathBuf::from("assets/Logo.png"),
+                PathBuf::from("assets/logo.png")
+            ))
+        );
+    }
+
+    #[test]
+    fn append_tree_fails_for_manifest_path() {
+        let unique = Uuid::new_v4();
+        let source_path = PathBuf::from(format!("manifest-path-test-{unique}"));
+        fs::create_dir_all(source_path.join("nested")).unwrap();
+        fs::write(source_path.join("nested").join(MANIFEST_PATH), "{}").unwrap();
+        let mut tar = tar::Builder::new(Vec::new());
+        let nested_result = append_tree(&mut tar, &source_path, &ArtifactFilter::default());
+        fs::write(source_path.join(MANIFEST_PATH), "{}").unwrap();
+        let mut tar = tar::Builder::new(Vec::new());
+        let result = append_tree(&mut tar, &source_path, &ArtifactFilter::default());
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+
+        assert!(nested_result.is_ok(), "{nested_result:?}");
+        let error = result.expect_err("a file at the manifest's path should fail");
+        assert_eq!(
+            archive_path_error(&error),
+            Some(&ArchivePathError::ReservedForManifest(PathBuf::from(
+                MANIFEST_PATH
+            )))
+        );
+    }
+
+    #[test]
+    fn unpack_reads_only_first_entry_as_manifest() {
+        let archive_data = create_raw_archive(&["index.html", MANIFEST_PATH]);
+        let output_path = PathBuf::from(format!("unpack-manifest-test-{}", Uuid::new_v4()));
+        let result = unpack(
+            archive_data.as_slice(),
+            &output_path,
+            Path::new("."),
+            ConflictPolicy::Overwrite,
+        );
+        let content = fs::read_to_string(output_path.join(MANIFEST_PATH));
+        let entries = list_entries(archive_data.as_slice()).unwrap();
+        fs::remove_dir_all(&output_path).unwrap_or_default();
+
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(content.unwrap(), "data");
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[test]
+    fn unpack_fails_for_entries_outside_destination() {
+        let archive_data = create_raw_archive(&["index.html", "../escaped.txt"]);
+        let output_path = PathBuf::from(format!("unpack-outside-test-{}", Uuid::new_v4()));
+        let result = unpack(
+            archive_data.as_slice(),
+            &output_path,
+            Path::new("."),
+            ConflictPolicy::Overwrite,
+        );
+        fs::remove_dir_all(&output_path).unwrap_or_default();
+
+        let error = result.expect_err("entries outside the destination should fail");
+        assert_eq!(
+            archive_path_error(&error),
+            Some(&ArchivePathError::OutsideDestination(PathBuf::from(
+                "../escaped.txt"
+            )))
+        );
+    }
+
+    #[test]
+    fn unpack_fails_for_case_colliding_entries() {
+        let archive_data = create_raw_archive(&["./README.md", "README.md", "readme.md"]);
+        let output_path = PathBuf::from(format!("unpack-case-test-{}", Uuid::new_v4()));
+        let result = unpack(
+            archive_data.as_slice(),
+            &output_path,
+            Path::new("."),
+            ConflictPolicy::Overwrite,
+        );
+        fs::remove_dir_all(&output_path).unwrap_or_default();
+
+        let error = result.expect_err("case-colliding entries should fail");
+        assert_eq!(
+            archive_path_error(&error),
+            Some(&ArchivePathError::CaseCollision(
+                PathBuf::from("README.md"),
+                PathBuf::from("readme.md")
+            ))
+        );
+    }
+
+    #[test]
+    fn unpack_applies_conflict_policy() {
+        let archive_data = create_raw_archive(&["index.html", "robots.txt"]);
+        let output_path = PathBuf::from(format!("unpack-conflict-test-{}", Uuid::new_v4()));
+        let unpack_with = |on_conflict| {
+            fs::create_dir_all(&output_path).unwrap();
+            fs::write(output_path.join("index.html"), "checked in").unwrap();
+            let result = unpack(
+                archive_data.as_slice(),
+                &output_path,
+                Path::new("."),
+                on_conflict,
+            );
+            let index = fs::read_to_string(output_path.join("index.html")).unwrap();
+            let robots_exists = output_path.join("robots.txt").exists();
+            fs::remove_dir_all(&output_path).unwrap_or_default();
+            (result, index, robots_exists)
+        };
+
+        let (result, index, robots_exists) = unpack_with(ConflictPolicy::Overwrite);
+        assert!(result.is_ok());
+        assert_eq!(index, "data");
+        assert!(robots_exists);
+
+        let (result, index, robots_exists) = unpack_with(ConflictPolicy::KeepExisting);
+        assert!(result.is_ok());
+        assert_eq!(index, "checked in");
+        assert!(robots_exists);
+
+        let (result, index, _) = unpack_with(ConflictPolicy::Fail);
+        let error = result.expect_err("existing files should fail");
+        assert_eq!(
+            archive_path_error(&error),
+            Some(&ArchivePathError::Exists(PathBuf::from("index.html")))
+        );
+        assert_eq!(index, "checked in");
+
+        let test_env = HashMap::from([(
+            "STATIC_ARTIFACTS_ON_CONFLICT".to_string(),
+            "Keep-Existing".to_string(),
+        )]);
+        assert_eq!(
+            ConflictPolicy::from_env(&test_env).unwrap(),
+            ConflictPolicy::KeepExisting
+        );
+        assert_eq!(
+            ConflictPolicy::from_env(&HashMap::<String, String>::new()).unwrap(),
+            ConflictPolicy::Overwrite
+        );
+        assert!(ConflictPolicy::from_env(&HashMap::from([(
+            "STATIC_ARTIFACTS_ON_CONFLICT".to_string(),
+            "merge".to_string(),
+        )]))
+        .is_err());
+    }
+
+    #[test]
+    fn windows_unsafe_reason_finds_unportable_names() {
+        for name in [
+            "index.html",
+            ".well-known",
+            "[slug].js",
+            "console.log",
+            "COM10.txt",
+        ] {
+            assert_eq!(windows_unsafe_reason(name), None, "{name} should be safe");
+        }
+        for name in [
+            "a\\b.txt",
+            "12:00.json",
+            "what?.html",
+            "trailing.",
+            "trailing ",
+            "aux.css",
+            "Con",
+            "lpt1.tar.gz",
+            "tab\t.txt",
+        ] {
+            assert!(
+                windows_unsafe_reason(name).is_some(),
+                "{name} should be unsafe"
+            );
+        }
+    }
+
+    #[test]
+    fn append_tree_fails_for_windows_reserved_names() {
+        let unique = Uuid::new_v4();
+        let source_path = PathBuf::from(format!("windows-reserved-test-{unique}"));
+        fs::create_dir_all(source_path.join("nul")).unwrap();
+        fs::write(source_path.join("nul/index.html"), "").unwrap();
+        let mut tar = tar::Builder::new(Vec::new());
+        let result = append_tree(&mut tar, &source_path, &ArtifactFilter::default());
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+
+        let error = result.expect_err("reserved names should fail");
+        assert_eq!(
+            archive_path_error(&error),
+            Some(&ArchivePathError::NotWindowsSafe(
+                PathBuf::from("nul"),
+                "'nul' is a reserved device name".to_string()
+            ))
+        );
+    }
+}
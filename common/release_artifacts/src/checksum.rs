@@ -0,0 +1,206 @@
+//! Checksums each archive when saved, and verifies it when loaded, before anything is extracted.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use aws_sdk_s3::types::MetadataDirective;
+use ring::digest;
+
+use crate::errors::ReleaseArtifactsError;
+
+/// The S3 user metadata key, stored as the header `x-amz-meta-release-phase-sha256`.
+pub(crate) const CHECKSUM_METADATA_KEY: &str = "release-phase-sha256";
+
+/// Computes a hex-encoded SHA-256 digest from bytes as they are written or received, so that
+/// archives need not be read again.
+pub(crate) struct Checksum(digest::Context);
+
+impl Checksum {
+    pub(crate) fn new() -> Self {
+        Checksum(digest::Context::new(&digest::SHA256))
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    pub(crate) fn finish(self) -> String {
+        hex::encode(self.0.finish())
+    }
+}
+
+/// Computes the checksum of the bytes read through it, such as an archive that is extracted as
+/// it is received.
+pub(crate) struct ChecksumReader<R: Read> {
+    reader: R,
+    checksum: Checksum,
+    byte_count: usize,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        ChecksumReader {
+            reader,
+            checksum: Checksum::new(),
+            byte_count: 0,
+        }
+    }
+
+    /// Returns the count of bytes read, and their checksum.
+    pub(crate) fn finish(self) -> (usize, String) {
+        (self.byte_count, self.checksum.finish())
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.reader.read(buf)?;
+        self.checksum.update(&buf[..count]);
+        self.byte_count += count;
+        Ok(count)
+    }
+}
+
+/// Returns the hex-encoded SHA-256 digest of the file.
+pub(crate) fn file_checksum(path: &Path) -> Result<String, ReleaseArtifactsError> {
+    let read_error =
+        |e| ReleaseArtifactsError::ArchiveError(e, format!("reading archive {path:?} to digest"));
+    let mut file = File::open(path).map_err(read_error)?;
+    let mut checksum = Checksum::new();
+    let mut buffer = vec![0_u8; 64 * 1024];
+    loop {
+        let count = file.read(&mut buffer).map_err(read_error)?;
+        if count == 0 {
+            break;
+        }
+        checksum.update(&buffer[..count]);
+    }
+    Ok(checksum.finish())
+}
+
+/// Fails with `ChecksumMismatch` unless the actual checksum is the expected one, when there is
+/// one.
+pub(crate) fn verify_checksum(
+    archive: &str,
+    expected: Option<&str>,
+    actual: &str,
+) -> Result<(), ReleaseArtifactsError> {
+    match expected {
+        Some(expected) if !expected.trim().eq_ignore_ascii_case(actual) => {
+            Err(ReleaseArtifactsError::ChecksumMismatch(format!(
+                "archive {archive} has SHA-256 {actual}, but {} was saved; it may be truncated or corrupted",
+                expected.trim()
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn checksum_path(archive_path: &Path) -> PathBuf {
+    let mut path = archive_path.as_os_str().to_owned();
+    path.push(".sha256");
+    PathBuf::from(path)
+}
+
+pub(crate) fn write_checksum_file(
+    archive_path: &Path,
+    checksum: &str,
+) -> Result<(), ReleaseArtifactsError> {
+    let path = checksum_path(archive_path);
+    fs::write(&path, checksum)
+        .map_err(|e| ReleaseArtifactsError::ArchiveError(e, format!("writing checksum {path:?}")))
+}
+
+/// Verifies the archive against the `.sha256` file beside it, when there is one.
+pub(crate) fn verify_checksum_file(archive_path: &Path) -> Result<(), ReleaseArtifactsError> {
+    let Ok(expected) = fs::read_to_string(checksum_path(archive_path)) else {
+        return Ok(());
+    };
+    verify_checksum(
+        &format!("{archive_path:?}"),
+        Some(&expected),
+        &file_checksum(archive_path)?,
+    )
+}
+
+/// Adds the checksum to the metadata of an object already stored, by copying it onto itself,
+/// for multipart uploads, which start before the archive, and so its checksum, is complete.
+pub(crate) async fn put_checksum_metadata_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_key: &str,
+    checksum: &str,
+) -> Result<(), ReleaseArtifactsError> {
+    s3.copy_object()
+        .bucket(bucket_name)
+        .key(bucket_key)
+        .copy_source(format!("{bucket_name}/{bucket_key}"))
+        .metadata_directive(MetadataDirective::Replace)
+        .metadata(CHECKSUM_METADATA_KEY, checksum)
+        .send()
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use uuid::Uuid;
+
+    use super::{
+        file_checksum, verify_checksum, verify_checksum_file, write_checksum_file, ChecksumReader,
+    };
+    use crate::errors::ReleaseArtifactsError;
+
+    #[test]
+    fn checksum_reader_digests_bytes_read() {
+        let mut reader = ChecksumReader::new("".as_bytes());
+        std::io::copy(&mut reader, &mut std::io::sink()).unwrap();
+        assert_eq!(
+            reader.finish(),
+            (
+                0,
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string()
+            )
+        );
+        let mut reader = ChecksumReader::new("archive".as_bytes());
+        std::io::copy(&mut reader, &mut std::io::sink()).unwrap();
+        assert_eq!(reader.finish().0, 7);
+    }
+
+    #[test]
+    fn verify_checksum_rejects_mismatch() {
+        let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert!(verify_checksum("test.tgz", None, empty).is_ok());
+        assert!(verify_checksum("test.tgz", Some(empty), empty).is_ok());
+        assert!(verify_checksum("test.tgz", Some(&empty.to_uppercase()), empty).is_ok());
+        assert!(matches!(
+            verify_checksum("test.tgz", Some(&"0".repeat(64)), empty),
+            Err(ReleaseArtifactsError::ChecksumMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn verify_checksum_file_detects_corruption() {
+        let archive_path = PathBuf::from(format!("checksum-test-{}.tgz", Uuid::new_v4()));
+        fs::write(&archive_path, "archive").unwrap();
+        let unchecked = verify_checksum_file(&archive_path);
+        write_checksum_file(&archive_path, &file_checksum(&archive_path).unwrap()).unwrap();
+        let intact = verify_checksum_file(&archive_path);
+        fs::write(&archive_path, "archiv").unwrap();
+        let truncated = verify_checksum_file(&archive_path);
+        fs::remove_file(&archive_path).unwrap_or_default();
+        fs::remove_file(super::checksum_path(&archive_path)).unwrap_or_default();
+
+        assert!(unchecked.is_ok());
+        assert!(intact.is_ok());
+        assert!(matches!(
+            truncated,
+            Err(ReleaseArtifactsError::ChecksumMismatch(_))
+        ));
+    }
+}
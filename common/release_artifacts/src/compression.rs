@@ -0,0 +1,207 @@
+//! Compresses archives with gzip or Zstandard, selected by `STATIC_ARTIFACTS_COMPRESSION`, and
+//! detects the compression of an archive from its content when it is read.
+
+use std::{
+    collections::HashMap,
+    hash::BuildHasher,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+use flate2::{bufread::GzDecoder, write::GzEncoder, Compression, GzBuilder};
+
+use crate::errors::ReleaseArtifactsError;
+
+// The magic number that starts every Zstandard frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+// The Zstandard level for content that is already compressed, the fastest of the standard
+// levels, since Zstandard cannot store without compressing as gzip can.
+const ZSTD_FASTEST_LEVEL: i32 = 1;
+
+/// How archives are compressed, which also determines their file extension.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArchiveCompression {
+    /// A `.tgz` archive.
+    #[default]
+    Gzip,
+    /// A `.tzst` archive.
+    Zstd,
+}
+
+impl ArchiveCompression {
+    /// Reads `STATIC_ARTIFACTS_COMPRESSION`, `gzip` or `zstd`, defaulting to `gzip`.
+    pub fn from_env<S: BuildHasher>(
+        env: &HashMap<String, String, S>,
+    ) -> Result<Self, ReleaseArtifactsError> {
+        match env
+            .get("STATIC_ARTIFACTS_COMPRESSION")
+            .map(|v| v.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            None | Some("" | "gzip" | "gz") => Ok(ArchiveCompression::Gzip),
+            Some("zstd" | "zst") => Ok(ArchiveCompression::Zstd),
+            Some(value) => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+                "STATIC_ARTIFACTS_COMPRESSION '{value}' is not supported, use gzip or zstd"
+            ))),
+        }
+    }
+
+    /// The compression for an archive at the path, by its extension, `.tzst` for Zstandard,
+    /// otherwise gzip.
+    #[must_use]
+    pub fn from_archive_path(path: &Path) -> Self {
+        if path.extension().is_some_and(|e| e == "tzst") {
+            ArchiveCompression::Zstd
+        } else {
+            ArchiveCompression::Gzip
+        }
+    }
+
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveCompression::Gzip => "tgz",
+            ArchiveCompression::Zstd => "tzst",
+        }
+    }
+}
+
+/// Compresses what is written to it, finished with `finish`.
+pub(crate) enum Encoder<W: Write> {
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// `level` is that of gzip, of which `Compression::none()` is the fastest level of
+    /// Zstandard, and any other its default level.
+    pub(crate) fn new(
+        writer: W,
+        compression: ArchiveCompression,
+        level: Compression,
+    ) -> io::Result<Self> {
+        match compression {
+            ArchiveCompression::Gzip => Ok(Encoder::Gzip(GzBuilder::new().write(writer, level))),
+            ArchiveCompression::Zstd => {
+                let level = if level == Compression::none() {
+                    ZSTD_FASTEST_LEVEL
+                } else {
+                    zstd::DEFAULT_COMPRESSION_LEVEL
+                };
+                zstd::Encoder::new(writer, level).map(Encoder::Zstd)
+            }
+        }
+    }
+
+    /// Completes the compressed stream, returning the writer.
+    pub(crate) fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Decompresses an archive stream, gzip or Zstandard, detected from its first bytes.
+pub(crate) enum Decoder<R: BufRead> {
+    Gzip(GzDecoder<R>),
+    Zstd(zstd::Decoder<'static, R>),
+}
+
+impl<R: Read> Decoder<BufReader<R>> {
+    pub(crate) fn detect(reader: R) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        if reader.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+            zstd::Decoder::with_buffer(reader).map(Decoder::Zstd)
+        } else {
+            Ok(Decoder::Gzip(GzDecoder::new(reader)))
+        }
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decoder::Gzip(decoder) => decoder.read(buf),
+            Decoder::Zstd(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{Read, Write},
+        path::Path,
+    };
+
+    use flate2::Compression;
+
+    use super::{ArchiveCompression, Decoder, Encoder};
+
+    #[test]
+    fn archive_compression_from_env() {
+        let mut test_env: HashMap<String, String> = HashMap::new();
+        assert_eq!(
+            ArchiveCompression::from_env(&test_env).unwrap(),
+            ArchiveCompression::Gzip
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_COMPRESSION".to_string(),
+            "ZSTD".to_string(),
+        );
+        assert_eq!(
+            ArchiveCompression::from_env(&test_env).unwrap(),
+            ArchiveCompression::Zstd
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_COMPRESSION".to_string(),
+            "brotli".to_string(),
+        );
+        assert!(ArchiveCompression::from_env(&test_env).is_err());
+        assert_eq!(
+            ArchiveCompression::from_archive_path(Path::new("release-v102.tzst")),
+            ArchiveCompression::Zstd
+        );
+        assert_eq!(
+            ArchiveCompression::from_archive_path(Path::new("release-v102.tgz")),
+            ArchiveCompression::Gzip
+        );
+    }
+
+    #[test]
+    fn decoder_detects_compression() {
+        for compression in [ArchiveCompression::Gzip, ArchiveCompression::Zstd] {
+            for level in [Compression::none(), Compression::default()] {
+                let mut encoder = Encoder::new(Vec::new(), compression, level).unwrap();
+                encoder.write_all(b"release artifacts").unwrap();
+                let compressed = encoder.finish().unwrap();
+
+                let mut decompressed = String::new();
+                Decoder::detect(compressed.as_slice())
+                    .unwrap()
+                    .read_to_string(&mut decompressed)
+                    .unwrap();
+                assert_eq!(decompressed, "release artifacts", "{compression:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Selects the read-only or read-write storage credentials of each process, and falls back to the
+//! secondary credentials when storage rejects the primary ones.
+
+use std::{collections::HashMap, future::Future, hash::BuildHasher};
+
+use crate::errors::ReleaseArtifactsError;
+
+// The credential vars that a scope's prefixed vars replace, as `STATIC_ARTIFACTS_<suffix>`.
+const CREDENTIAL_SUFFIXES: [&str; 5] = [
+    "ACCESS_KEY_ID",
+    "SECRET_ACCESS_KEY",
+    "SESSION_TOKEN",
+    "ACCESS_KEY_ID_2",
+    "SECRET_ACCESS_KEY_2",
+];
+
+/// The access to storage that a process needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialScope {
+    /// Loading, inspecting, & listing artifacts, with `STATIC_ARTIFACTS_RO_*` credentials.
+    ReadOnly,
+    /// Saving, deleting, & tagging artifacts, with `STATIC_ARTIFACTS_RW_*` credentials.
+    ReadWrite,
+}
+
+impl CredentialScope {
+    fn prefix(self) -> &'static str {
+        match self {
+            CredentialScope::ReadOnly => "STATIC_ARTIFACTS_RO_",
+            CredentialScope::ReadWrite => "STATIC_ARTIFACTS_RW_",
+        }
+    }
+}
+
+/// Replaces the credentials in env with those of the scope, such as
+/// `STATIC_ARTIFACTS_RO_ACCESS_KEY_ID` for `STATIC_ARTIFACTS_ACCESS_KEY_ID`, when the scope's
+/// access key ID is set, returning whether they were. The other scope's credentials are
+/// removed, so that they are never used by mistake.
+pub fn select_credentials<S: BuildHasher>(
+    env: &mut HashMap<String, String, S>,
+    scope: CredentialScope,
+) -> bool {
+    let other_scope = match scope {
+        CredentialScope::ReadOnly => CredentialScope::ReadWrite,
+        CredentialScope::ReadWrite => CredentialScope::ReadOnly,
+    };
+    for suffix in CREDENTIAL_SUFFIXES {
+        env.remove(&format!("{}{suffix}", other_scope.prefix()));
+    }
+    if !env.contains_key(&format!("{}ACCESS_KEY_ID", scope.prefix())) {
+        return false;
+    }
+    for suffix in CREDENTIAL_SUFFIXES {
+        let var = format!("STATIC_ARTIFACTS_{suffix}");
+        match env.remove(&format!("{}{suffix}", scope.prefix())) {
+            Some(value) => env.insert(var, value),
+            None => env.remove(&var),
+        };
+    }
+    true
+}
+
+/// Runs the storage operation with the env's credentials, then once more with the secondary
+/// credentials, when set, if storage rejected the primary ones.
+pub(crate) async fn with_secondary_fallback<S, T, F, Fut>(
+    env: &HashMap<String, String, S>,
+    operation: F,
+) -> Result<T, ReleaseArtifactsError>
+where
+    S: BuildHasher,
+    F: Fn(HashMap<String, String>) -> Fut,
+    Fut: Future<Output = Result<T, ReleaseArtifactsError>>,
+{
+    let primary_env: HashMap<String, String> =
+        env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    match operation(primary_env).await {
+        Err(error) if error.is_auth_failure() => {
+            let Some(secondary_env) = secondary_credentials_env(env) else {
+                return Err(error);
+            };
+            eprintln!(
+                "release-phase warning: storage rejected STATIC_ARTIFACTS_ACCESS_KEY_ID, retrying with STATIC_ARTIFACTS_ACCESS_KEY_ID_2: {error:?}"
+            );
+            operation(secondary_env).await
+        }
+        result => result,
+    }
+}
+
+// The env with the secondary credentials in place of the primary ones, when both secondary
+// vars are set, and differ from the primary. The primary's session token is removed, as it is
+// only valid with the primary's temporary keys.
+fn secondary_credentials_env<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Option<HashMap<String, String>> {
+    let access_key_id = env
+        .get("STATIC_ARTIFACTS_ACCESS_KEY_ID_2")
+        .filter(|v| !v.trim().is_empty())?;
+    let secret_access_key = env
+        .get("STATIC_ARTIFACTS_SECRET_ACCESS_KEY_2")
+        .filter(|v| !v.trim().is_empty())?;
+    if env.get("STATIC_ARTIFACTS_ACCESS_KEY_ID") == Some(access_key_id)
+        && env.get("STATIC_ARTIFACTS_SECRET_ACCESS_KEY") == Some(secret_access_key)
+    {
+        return None;
+    }
+    let mut secondary_env: HashMap<String, String> =
+        env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    secondary_env.insert(
+        "STATIC_ARTIFACTS_ACCESS_KEY_ID".to_string(),
+        access_key_id.clone(),
+    );
+    secondary_env.insert(
+        "STATIC_ARTIFACTS_SECRET_ACCESS_KEY".to_string(),
+        secret_access_key.clone(),
+    );
+    secondary_env.remove("STATIC_ARTIFACTS_SESSION_TOKEN");
+    Some(secondary_env)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::{
+        secondary_credentials_env, select_credentials, with_secondary_fallback, CredentialScope,
+    };
+    use crate::errors::ReleaseArtifactsError;
+
+    fn test_env(secondary: bool) -> HashMap<String, String> {
+        let mut env = HashMap::from([
+            (
+                "STATIC_ARTIFACTS_ACCESS_KEY_ID".to_string(),
+                "old-key".to_string(),
+            ),
+            (
+                "STATIC_ARTIFACTS_SECRET_ACCESS_KEY".to_string(),
+                "old-secret".to_string(),
+            ),
+        ]);
+        if secondary {
+            env.insert(
+                "STATIC_ARTIFACTS_ACCESS_KEY_ID_2".to_string(),
+                "new-key".to_string(),
+            );
+            env.insert(
+                "STATIC_ARTIFACTS_SECRET_ACCESS_KEY_2".to_string(),
+                "new-secret".to_string(),
+            );
+        }
+        env
+    }
+
+    fn access_denied() -> ReleaseArtifactsError {
+        ReleaseArtifactsError::StorageError {
+            message: "InvalidAccessKeyId: The AWS Access Key Id you provided does not exist in our records.".to_string(),
+            request_id: None,
+            extended_request_id: None,
+            status: Some(403),
+        }
+    }
+
+    #[test]
+    fn select_credentials_uses_scope_credentials() {
+        let mut env = test_env(true);
+        env.insert(
+            "STATIC_ARTIFACTS_RO_ACCESS_KEY_ID".to_string(),
+            "ro-key".to_string(),
+        );
+        env.insert(
+            "STATIC_ARTIFACTS_RO_SECRET_ACCESS_KEY".to_string(),
+            "ro-secret".to_string(),
+        );
+        env.insert(
+            "STATIC_ARTIFACTS_RW_ACCESS_KEY_ID".to_string(),
+            "rw-key".to_string(),
+        );
+        env.insert(
+            "STATIC_ARTIFACTS_RW_SECRET_ACCESS_KEY".to_string(),
+            "rw-secret".to_string(),
+        );
+
+        let mut load_env = env.clone();
+        assert!(select_credentials(&mut load_env, CredentialScope::ReadOnly));
+        assert_eq!(load_env["STATIC_ARTIFACTS_ACCESS_KEY_ID"], "ro-key");
+        assert_eq!(load_env["STATIC_ARTIFACTS_SECRET_ACCESS_KEY"], "ro-secret");
+        assert_eq!(load_env.get("STATIC_ARTIFACTS_ACCESS_KEY_ID_2"), None);
+        assert_eq!(load_env.get("STATIC_ARTIFACTS_RW_ACCESS_KEY_ID"), None);
+
+        let mut save_env = env.clone();
+        assert!(select_credentials(
+            &mut save_env,
+            CredentialScope::ReadWrite
+        ));
+        assert_eq!(save_env["STATIC_ARTIFACTS_ACCESS_KEY_ID"], "rw-key");
+        assert_eq!(save_env.get("STATIC_ARTIFACTS_RO_ACCESS_KEY_ID"), None);
+
+        let mut unscoped_env = test_env(false);
+        assert!(!select_credentials(
+            &mut unscoped_env,
+            CredentialScope::ReadOnly
+        ));
+        assert_eq!(unscoped_env, test_env(false));
+    }
+
+    #[test]
+    fn secondary_credentials_env_replaces_primary() {
+        assert!(secondary_credentials_env(&test_env(false)).is_none());
+        let secondary_env = secondary_credentials_env(&test_env(true)).unwrap();
+        assert_eq!(secondary_env["STATIC_ARTIFACTS_ACCESS_KEY_ID"], "new-key");
+        assert_eq!(
+            secondary_env["STATIC_ARTIFACTS_SECRET_ACCESS_KEY"],
+            "new-secret"
+        );
+    }
+
+    #[test]
+    fn secondary_credentials_env_drops_primary_session_token() {
+        let mut env = test_env(true);
+        env.insert(
+            "STATIC_ARTIFACTS_SESSION_TOKEN".to_string(),
+            "old-session-token".to_string(),
+        );
+        let secondary_env = secondary_credentials_env(&env).unwrap();
+        assert_eq!(secondary_env["STATIC_ARTIFACTS_ACCESS_KEY_ID"], "new-key");
+        assert_eq!(secondary_env.get("STATIC_ARTIFACTS_SESSION_TOKEN"), None);
+    }
+
+    #[tokio::test]
+    async fn with_secondary_fallback_retries_auth_failure() {
+        let attempts = AtomicUsize::new(0);
+        let result = with_secondary_fallback(&test_env(true), |env| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if env["STATIC_ARTIFACTS_ACCESS_KEY_ID"] == "old-key" {
+                    Err(access_denied())
+                } else {
+                    Ok(env["STATIC_ARTIFACTS_ACCESS_KEY_ID"].clone())
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), "new-key");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn with_secondary_fallback_does_not_retry_other_failures() {
+        let attempts = AtomicUsize::new(0);
+        let result: Result<(), _> = with_secondary_fallback(&test_env(true), |_| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            async {
+                Err(ReleaseArtifactsError::StorageKeyNotFound(
+                    "Not Found".to_string(),
+                ))
+            }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let result: Result<(), _> =
+            with_secondary_fallback(&test_env(false), |_| async { Err(access_denied()) }).await;
+        assert!(result.is_err_and(|e| e.is_auth_failure()));
+    }
+}
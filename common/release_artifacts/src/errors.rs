@@ -1,9 +1,52 @@
+use std::time::SystemTime;
+
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::operation::{RequestId, RequestIdExt};
+use aws_sdk_s3::primitives::ByteStreamError;
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+
+const CLOCK_SKEW_ERROR_CODES: &[&str] = &["RequestTimeTooSkewed", "RequestExpired"];
+
+// Codes of S3 errors for credentials that are unknown, revoked, or not allowed the operation.
+const AUTH_ERROR_CODES: &[&str] = &[
+    "AccessDenied",
+    "ExpiredToken",
+    "InvalidAccessKeyId",
+    "InvalidToken",
+    "SignatureDoesNotMatch",
+];
+
 #[derive(Debug)]
 pub enum ReleaseArtifactsError {
     ArchiveError(std::io::Error, String),
     ArchiveStreamError(aws_sdk_s3::primitives::ByteStreamError),
+    CdnPurgeFailed(String),
+    // The archive's SHA-256 digest differs from the one saved with it.
+    ChecksumMismatch(String),
+    ConfigInvalid(String),
     ConfigMissing(String),
-    StorageError(String),
+    ExtractionVetoed(String),
+    NotifyFailed(String),
+    // The request IDs & HTTP status, when S3 responded, are what AWS support asks for.
+    StorageError {
+        message: String,
+        request_id: Option<String>,
+        extended_request_id: Option<String>,
+        status: Option<u16>,
+    },
+    SignatureInvalid(String),
+    // Keyless signing failed to get a certificate for the OIDC token, or to sign with its key.
+    SigningFailed(String),
+    // The storage service's clock differs from this container's, so request signatures are
+    // rejected. The SDK retries such requests signed at the service's time, when retries are
+    // enabled and the skew exceeds its 4 minute threshold, so this is reported when that fails.
+    StorageClockSkewed {
+        message: String,
+        skew_seconds: Option<i64>,
+        request_id: Option<String>,
+    },
     StorageKeyNotFound(String),
     StorageURLUnsupportedScheme(String),
     StorageURLInvalid(url::ParseError),
@@ -11,22 +54,91 @@ pub enum ReleaseArtifactsError {
     StorageURLHostMissing(String),
 }
 
-impl<T: std::error::Error + aws_sdk_s3::error::ProvideErrorMetadata> From<T>
-    for ReleaseArtifactsError
+impl ReleaseArtifactsError {
+    /// Whether storage rejected the credentials, so that other credentials may succeed.
+    #[must_use]
+    pub fn is_auth_failure(&self) -> bool {
+        match self {
+            ReleaseArtifactsError::StorageError {
+                message, status, ..
+            } => {
+                matches!(status, Some(401 | 403))
+                    || AUTH_ERROR_CODES
+                        .iter()
+                        .any(|code| message.starts_with(&format!("{code}:")))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the archive's body was interrupted while it was received, such as by a reset
+    /// connection, so that downloading it again may succeed.
+    #[must_use]
+    pub fn is_interrupted_transfer(&self) -> bool {
+        match self {
+            ReleaseArtifactsError::ArchiveStreamError(_) => true,
+            // Extracting as the archive is received wraps the body's errors in those of reading
+            // & unpacking it.
+            ReleaseArtifactsError::ArchiveError(error, _) => {
+                let mut source: Option<&(dyn std::error::Error + 'static)> =
+                    error.get_ref().map(|e| e as _);
+                while let Some(error) = source {
+                    if error.is::<ByteStreamError>() {
+                        return true;
+                    }
+                    source = match error.downcast_ref::<std::io::Error>() {
+                        Some(io_error) => io_error.get_ref().map(|e| e as _),
+                        None => error.source(),
+                    };
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<E> From<SdkError<E, HttpResponse>> for ReleaseArtifactsError
+where
+    E: std::error::Error + ProvideErrorMetadata + Send + Sync + 'static,
 {
-    fn from(value: T) -> Self {
-        match value.code() {
-            Some(code) => match code {
-                "NoSuchKey" => ReleaseArtifactsError::StorageKeyNotFound("Not Found".to_string()),
-                _ => ReleaseArtifactsError::StorageError(format!(
-                    "{code}: {}",
-                    value.message().map_or("(no message)".into(), String::from)
-                )),
-            },
-            _ => ReleaseArtifactsError::StorageError(format!(
+    fn from(value: SdkError<E, HttpResponse>) -> Self {
+        let message = match value.code() {
+            Some("NoSuchKey") => {
+                return ReleaseArtifactsError::StorageKeyNotFound("Not Found".to_string())
+            }
+            Some(code) if CLOCK_SKEW_ERROR_CODES.contains(&code) => {
+                return ReleaseArtifactsError::StorageClockSkewed {
+                    message: format!(
+                        "{code}: {}; the system clock of this container is out of sync with the storage service",
+                        value.message().map_or("(no message)".into(), String::from)
+                    ),
+                    skew_seconds: value.raw_response().and_then(clock_skew_seconds),
+                    request_id: value.request_id().map(String::from),
+                };
+            }
+            Some(code) => format!(
+                "{code}: {}",
+                value.message().map_or("(no message)".into(), String::from)
+            ),
+            None => format!(
                 "{}",
-                aws_smithy_types::error::display::DisplayErrorContext(value)
-            )),
+                aws_smithy_types::error::display::DisplayErrorContext(&value)
+            ),
+        };
+        ReleaseArtifactsError::StorageError {
+            message,
+            request_id: value.request_id().map(String::from),
+            extended_request_id: value.extended_request_id().map(String::from),
+            status: value.raw_response().map(|r| r.status().as_u16()),
         }
     }
 }
+
+// Seconds that the storage service's clock, from the response `Date`, is ahead of this system's.
+fn clock_skew_seconds(response: &HttpResponse) -> Option<i64> {
+    let date = response.headers().get("date")?;
+    let server_time = DateTime::from_str(date, Format::HttpDate).ok()?;
+    let now = DateTime::from(SystemTime::now());
+    Some(server_time.secs() - now.secs())
+}
@@ -0,0 +1,257 @@
+//! Records the files extracted by each load, with their size & SHA-256, in a report beside the
+//! artifacts.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    hash::BuildHasher,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{checksum::file_checksum, errors::ReleaseArtifactsError};
+
+/// The regular files & symlinks of an extracted artifacts directory, by their path within it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExtractionReport {
+    /// The key or name of the archive that was extracted.
+    pub loaded_key: String,
+    /// The total size of the regular files.
+    pub total_size: u64,
+    pub files: BTreeMap<String, ExtractedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExtractedFile {
+    pub size: u64,
+    /// The hex-encoded SHA-256 digest of a regular file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// The target of a symlink, which is not followed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+}
+
+/// The differences between an artifacts directory and its extraction report, each a sorted
+/// list of paths within the directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractionDiff {
+    pub modified: Vec<String>,
+    pub missing: Vec<String>,
+    pub added: Vec<String>,
+}
+
+impl ExtractionDiff {
+    /// Whether the directory is as it was extracted.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.added.is_empty()
+    }
+}
+
+impl fmt::Display for ExtractionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (change, paths) in [
+            ("modified", &self.modified),
+            ("missing", &self.missing),
+            ("added", &self.added),
+        ] {
+            for path in paths {
+                writeln!(f, "{change:>8}  {path}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ExtractionReport {
+    /// Records the contents of the directory, without following symlinks.
+    pub fn of_dir(dir: &Path, loaded_key: &str) -> Result<Self, ReleaseArtifactsError> {
+        let mut files = BTreeMap::new();
+        record_dir(dir, Path::new(""), &mut files)?;
+        Ok(ExtractionReport {
+            loaded_key: loaded_key.to_string(),
+            total_size: files
+                .values()
+                .filter(|file| file.sha256.is_some())
+                .map(|file| file.size)
+                .sum(),
+            files,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self, ReleaseArtifactsError> {
+        let content = fs::read(path).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(e, format!("reading extraction report {path:?}"))
+        })?;
+        serde_json::from_slice(&content).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e.into(),
+                format!("parsing extraction report {path:?}"),
+            )
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), ReleaseArtifactsError> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(e.into(), "serializing extraction report".into())
+        })?;
+        fs::write(path, content).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(e, format!("writing extraction report {path:?}"))
+        })
+    }
+
+    /// Compares the directory as it is now to this report of it.
+    pub fn verify(&self, dir: &Path) -> Result<ExtractionDiff, ReleaseArtifactsError> {
+        let current = ExtractionReport::of_dir(dir, &self.loaded_key)?;
+        let mut diff = ExtractionDiff::default();
+        for (path, file) in &self.files {
+            match current.files.get(path) {
+                Some(current_file) if current_file == file => {}
+                Some(_) => diff.modified.push(path.clone()),
+                None => diff.missing.push(path.clone()),
+            }
+        }
+        diff.added = current
+            .files
+            .into_keys()
+            .filter(|path| !self.files.contains_key(path))
+            .collect();
+        Ok(diff)
+    }
+}
+
+/// The path of the extraction report of the directory, or `None` when disabled.
+pub fn extraction_report_path<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    dir: &Path,
+) -> Option<PathBuf> {
+    match env
+        .get("STATIC_ARTIFACTS_EXTRACTION_REPORT")
+        .map(|v| v.trim())
+    {
+        Some("false") => None,
+        Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ => {
+            let name = dir.file_name().unwrap_or(dir.as_os_str()).to_string_lossy();
+            Some(dir.with_file_name(format!("{name}.extraction-report.json")))
+        }
+    }
+}
+
+fn record_dir(
+    dir: &Path,
+    relative_dir: &Path,
+    files: &mut BTreeMap<String, ExtractedFile>,
+) -> Result<(), ReleaseArtifactsError> {
+    let read_error = |e| {
+        ReleaseArtifactsError::ArchiveError(
+            e,
+            format!("reading {:?} for extraction report", dir.join(relative_dir)),
+        )
+    };
+    for dir_entry in fs::read_dir(dir.join(relative_dir)).map_err(read_error)? {
+        let dir_entry = dir_entry.map_err(read_error)?;
+        // DirEntry::metadata does not traverse symlinks.
+        let metadata = dir_entry.metadata().map_err(read_error)?;
+        let path = relative_dir.join(dir_entry.file_name());
+        if metadata.is_dir() {
+            record_dir(dir, &path, files)?;
+            continue;
+        }
+        let file = if metadata.is_symlink() {
+            let target = fs::read_link(dir_entry.path()).map_err(read_error)?;
+            ExtractedFile {
+                size: 0,
+                sha256: None,
+                link_target: Some(target.to_string_lossy().to_string()),
+            }
+        } else if metadata.is_file() {
+            ExtractedFile {
+                size: metadata.len(),
+                sha256: Some(file_checksum(&dir_entry.path())?),
+                link_target: None,
+            }
+        } else {
+            continue;
+        };
+        files.insert(path.to_string_lossy().to_string(), file);
+    }
+    Ok(())
+}
+
+// The tests extract symlinks, which are created with `std::os::unix`.
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use std::{collections::HashMap, fs, os::unix::fs::symlink, path::PathBuf};
+
+    use uuid::Uuid;
+
+    use super::{extraction_report_path, ExtractionReport};
+
+    #[test]
+    fn extraction_report_detects_changes() {
+        let dir = PathBuf::from(format!("extraction-report-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(dir.join("css")).unwrap();
+        fs::write(dir.join("index.html"), "<html></html>").unwrap();
+        fs::write(dir.join("css/site.css"), "body {}").unwrap();
+        fs::write(dir.join("robots.txt"), "").unwrap();
+        symlink("index.html", dir.join("default.html")).unwrap();
+        let report = ExtractionReport::of_dir(&dir, "release-v102.tgz").unwrap();
+        let unchanged = report.verify(&dir).unwrap();
+        fs::write(dir.join("index.html"), "<html>defaced</html>").unwrap();
+        fs::remove_file(dir.join("robots.txt")).unwrap();
+        fs::write(dir.join("css/extra.css"), "").unwrap();
+        let changed = report.verify(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap_or_default();
+
+        assert_eq!(report.total_size, 20);
+        assert_eq!(
+            report.files.keys().collect::<Vec<_>>(),
+            ["css/site.css", "default.html", "index.html", "robots.txt"]
+        );
+        assert_eq!(
+            report.files["default.html"].link_target.as_deref(),
+            Some("index.html")
+        );
+        assert!(unchanged.is_empty());
+        assert_eq!(changed.modified, ["index.html"]);
+        assert_eq!(changed.missing, ["robots.txt"]);
+        assert_eq!(changed.added, ["css/extra.css"]);
+        assert_eq!(
+            changed.to_string(),
+            "modified  index.html\n missing  robots.txt\n   added  css/extra.css\n"
+        );
+    }
+
+    #[test]
+    fn extraction_report_path_from_env() {
+        let mut test_env: HashMap<String, String> = HashMap::new();
+        assert_eq!(
+            extraction_report_path(&test_env, &PathBuf::from("/workspace/static-artifacts")),
+            Some(PathBuf::from(
+                "/workspace/static-artifacts.extraction-report.json"
+            ))
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_EXTRACTION_REPORT".to_string(),
+            "/tmp/report.json".to_string(),
+        );
+        assert_eq!(
+            extraction_report_path(&test_env, &PathBuf::from("static-artifacts")),
+            Some(PathBuf::from("/tmp/report.json"))
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_EXTRACTION_REPORT".to_string(),
+            "false".to_string(),
+        );
+        assert_eq!(
+            extraction_report_path(&test_env, &PathBuf::from("static-artifacts")),
+            None
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! Fault injection for S3 calls, to test how saving & loading withstand an unreliable network.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::BuildHasher,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use aws_sdk_s3::config::{
+    interceptors::BeforeDeserializationInterceptorContextMut, ConfigBag, Intercept,
+    RuntimeComponents,
+};
+use aws_smithy_types::body::SdkBody;
+use bytes::Bytes;
+use http_body::{Body, Frame};
+
+use crate::errors::ReleaseArtifactsError;
+
+const INJECTED_ERROR_BODY: &str =
+    "<Error><Code>InternalError</Code><Message>Injected fault</Message></Error>";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct FaultConfig {
+    pub(crate) error_rate: f64,
+    pub(crate) truncate_rate: f64,
+    pub(crate) slow_chunk: Option<Duration>,
+    pub(crate) seed: u64,
+}
+
+impl FaultConfig {
+    /// Returns the faults configured by `STATIC_ARTIFACTS_FAULTS`, or `None` when unset, from its
+    /// comma-separated `error=<rate>`, `truncate=<rate>`, `slow-ms=<millis>`, & `seed=<number>`.
+    pub(crate) fn from_env<S: BuildHasher>(
+        env: &HashMap<String, String, S>,
+    ) -> Result<Option<FaultConfig>, ReleaseArtifactsError> {
+        let Some(spec) = env
+            .get("STATIC_ARTIFACTS_FAULTS")
+            .filter(|spec| !spec.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let invalid = |item: &str| {
+            ReleaseArtifactsError::ConfigInvalid(format!(
+                "STATIC_ARTIFACTS_FAULTS '{item}' is not one of error=<rate>, truncate=<rate>, slow-ms=<milliseconds>, or seed=<number>"
+            ))
+        };
+        let mut config = FaultConfig {
+            seed: 1,
+            ..FaultConfig::default()
+        };
+        for item in spec.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            let (name, value) = item.split_once('=').ok_or_else(|| invalid(item))?;
+            let rate = || {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|rate| (0.0..=1.0).contains(rate))
+                    .ok_or_else(|| invalid(item))
+            };
+            match name.trim() {
+                "error" => config.error_rate = rate()?,
+                "truncate" => config.truncate_rate = rate()?,
+                "slow-ms" => {
+                    config.slow_chunk = Some(Duration::from_millis(
+                        value.parse().map_err(|_| invalid(item))?,
+                    ));
+                }
+                // Zero would leave the random state stuck at zero.
+                "seed" => config.seed = value.parse::<u64>().map_err(|_| invalid(item))?.max(1),
+                _ => return Err(invalid(item)),
+            }
+        }
+        Ok(Some(config))
+    }
+}
+
+/// Injects the configured faults into the responses of an S3 client.
+#[derive(Debug)]
+pub(crate) struct FaultInjector {
+    config: FaultConfig,
+    state: AtomicU64,
+}
+
+impl FaultInjector {
+    pub(crate) fn new(config: FaultConfig) -> Self {
+        let state = AtomicU64::new(config.seed);
+        FaultInjector { config, state }
+    }
+
+    // Whether a fault of the given rate happens this time, from a xorshift of the seed.
+    fn roll(&self, rate: f64) -> bool {
+        if rate <= 0.0 {
+            return false;
+        }
+        let next = |mut x: u64| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        };
+        let previous = self
+            .state
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| Some(next(x)))
+            .unwrap_or_default();
+        #[allow(clippy::cast_precision_loss)]
+        let sample = (next(previous) >> 11) as f64 / (1_u64 << 53) as f64;
+        sample < rate
+    }
+}
+
+impl Intercept for FaultInjector {
+    fn name(&self) -> &'static str {
+        "FaultInjector"
+    }
+
+    fn modify_before_deserialization(
+        &self,
+        context: &mut BeforeDeserializationInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), aws_sdk_s3::error::BoxError> {
+        let response = context.response_mut();
+        if self.roll(self.config.error_rate) {
+            *response.status_mut() = 500.try_into()?;
+            *response.body_mut() = SdkBody::from(INJECTED_ERROR_BODY);
+            return Ok(());
+        }
+        let truncate_after = self.roll(self.config.truncate_rate).then(|| {
+            response
+                .headers()
+                .get("content-length")
+                .and_then(|length| length.parse::<u64>().ok())
+                .map_or(0, |length| length / 2)
+        });
+        if truncate_after.is_some() || self.config.slow_chunk.is_some() {
+            let inner = std::mem::replace(response.body_mut(), SdkBody::taken());
+            *response.body_mut() = SdkBody::from_body_1_x(FaultyBody {
+                inner,
+                remaining: truncate_after,
+                slow_chunk: self.config.slow_chunk,
+                delay: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+// A response body that is delayed before each chunk, or that fails after some bytes.
+struct FaultyBody {
+    inner: SdkBody,
+    // Bytes to pass before failing, when truncated.
+    remaining: Option<u64>,
+    slow_chunk: Option<Duration>,
+    delay: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl Body for FaultyBody {
+    type Data = Bytes;
+    type Error = aws_smithy_types::body::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        // Not delayed at the end, when the SDK may no longer poll for the end of the body.
+        if let Some(slow_chunk) = self.slow_chunk.filter(|_| !self.inner.is_end_stream()) {
+            let delay = self
+                .delay
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(slow_chunk)));
+            ready!(delay.as_mut().poll(cx));
+        }
+        if self.remaining == Some(0) {
+            return Poll::Ready(Some(Err("injected fault: response body truncated".into())));
+        }
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        self.delay = None;
+        let Some(Ok(frame)) = frame else {
+            return Poll::Ready(frame);
+        };
+        match (frame.into_data(), self.remaining) {
+            (Ok(mut data), Some(remaining)) => {
+                let passed = data
+                    .len()
+                    .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+                data.truncate(passed);
+                self.remaining = Some(remaining - passed as u64);
+                Poll::Ready(Some(Ok(Frame::data(data))))
+            }
+            (Ok(data), None) => Poll::Ready(Some(Ok(Frame::data(data)))),
+            (Err(frame), _) => Poll::Ready(Some(Ok(frame))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining != Some(0) && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs, path::Path, time::Duration};
+
+    use aws_config::BehaviorVersion;
+    use aws_sdk_s3::config::retry::RetryConfig;
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+    use uuid::Uuid;
+
+    use super::{FaultConfig, FaultInjector};
+    use crate::{
+        download_with_client, errors::ReleaseArtifactsError, list_with_client,
+        make_s3_test_credentials, ArchiveVerifier, ConflictPolicy,
+    };
+
+    const ARCHIVE_URI: &str =
+        "https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=GetObject";
+
+    fn make_faulty_client(
+        replay_client: &StaticReplayClient,
+        config: FaultConfig,
+    ) -> aws_sdk_s3::Client {
+        aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .retry_config(
+                    RetryConfig::standard()
+                        .with_max_attempts(3)
+                        .with_initial_backoff(Duration::from_millis(1)),
+                )
+                .interceptor(FaultInjector::new(config))
+                .http_client(replay_client.clone())
+                .build(),
+        )
+    }
+
+    fn archive_event() -> ReplayEvent {
+        let archive_data =
+            fs::read("test/fixtures/static-artifacts.tgz").expect("test fixture file should exist");
+        ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri(ARCHIVE_URI)
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .header("content-length", archive_data.len())
+                .body(SdkBody::from(archive_data))
+                .unwrap(),
+        )
+    }
+
+    async fn download(s3: &aws_sdk_s3::Client) -> (Result<(), ReleaseArtifactsError>, bool) {
+        let output_dir_name = format!("test-output-faults-{}", Uuid::new_v4());
+        let output_dir = Path::new(output_dir_name.as_str());
+        let result = download_with_client(
+            s3,
+            &"test-bucket".to_string(),
+            &"sub/path/static-artifacts.tgz".to_string(),
+            output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
+        )
+        .await;
+        let extracted = output_dir.join("index.html").is_file();
+        fs::remove_dir_all(output_dir).unwrap_or_default();
+        (result, extracted)
+    }
+
+    #[test]
+    fn fault_config_from_env() {
+        let env = |spec: &str| {
+            FaultConfig::from_env(&HashMap::from([(
+                "STATIC_ARTIFACTS_FAULTS".to_string(),
+                spec.to_string(),
+            )]))
+        };
+        assert_eq!(FaultConfig::from_env(&HashMap::new()).unwrap(), None);
+        assert_eq!(
+            env("error=0.25, truncate=0.5,slow-ms=20,seed=7").unwrap(),
+            Some(FaultConfig {
+                error_rate: 0.25,
+                truncate_rate: 0.5,
+                slow_chunk: Some(Duration::from_millis(20)),
+                seed: 7,
+            })
+        );
+        assert!(env("error=2").is_err());
+        assert!(env("drop=0.5").is_err());
+        assert!(env("slow-ms").is_err());
+    }
+
+    #[test]
+    fn fault_injector_rolls_at_rate() {
+        let injector = FaultInjector::new(FaultConfig {
+            seed: 42,
+            ..FaultConfig::default()
+        });
+        let faults = (0..10_000).filter(|_| injector.roll(0.2)).count();
+        assert!((1_700..2_300).contains(&faults), "{faults} faults");
+        assert!(!(0..100).any(|_| injector.roll(0.0)));
+        assert!((0..100).all(|_| injector.roll(1.0)));
+    }
+
+    #[tokio::test]
+    async fn injected_errors_are_retried() {
+        let list_event = || {
+            ReplayEvent::new(
+                http::Request::builder()
+                    .method("GET")
+                    .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F")
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(200)
+                    .body(SdkBody::from(
+                        "<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>",
+                    ))
+                    .unwrap(),
+            )
+        };
+        let replay_client = StaticReplayClient::new(vec![list_event(), list_event(), list_event()]);
+        let s3 = make_faulty_client(
+            &replay_client,
+            FaultConfig {
+                error_rate: 1.0,
+                seed: 1,
+                ..FaultConfig::default()
+            },
+        );
+
+        let result =
+            list_with_client(&s3, &"test-bucket".to_string(), &"sub/path/".to_string()).await;
+
+        assert_eq!(
+            replay_client.actual_requests().count(),
+            3,
+            "every attempt was made"
+        );
+        assert!(matches!(
+            result,
+            Err(ReleaseArtifactsError::StorageError {
+                status: Some(500),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn truncated_download_fails_after_retries() {
+        let replay_client =
+            StaticReplayClient::new(vec![archive_event(), archive_event(), archive_event()]);
+        let s3 = make_faulty_client(
+            &replay_client,
+            FaultConfig {
+                truncate_rate: 1.0,
+                seed: 1,
+                ..FaultConfig::default()
+            },
+        );
+
+        let (result, extracted) = download(&s3).await;
+
+        assert!(
+            result
+                .as_ref()
+                .is_err_and(ReleaseArtifactsError::is_interrupted_transfer),
+            "{result:?}"
+        );
+        assert_eq!(replay_client.actual_requests().count(), 3);
+        assert!(!extracted, "a partial archive is never extracted");
+    }
+
+    #[tokio::test]
+    async fn slow_download_succeeds() {
+        let replay_client = StaticReplayClient::new(vec![archive_event()]);
+        let s3 = make_faulty_client(
+            &replay_client,
+            FaultConfig {
+                slow_chunk: Some(Duration::from_millis(5)),
+                seed: 1,
+                ..FaultConfig::default()
+            },
+        );
+
+        let (result, extracted) = download(&s3).await;
+
+        assert!(result.is_ok(), "{result:?}");
+        assert!(extracted);
+    }
+}
@@ -0,0 +1,140 @@
+//! Selects the paths of an artifacts directory to archive, with the glob patterns of
+//! `STATIC_ARTIFACTS_INCLUDE` & `STATIC_ARTIFACTS_EXCLUDE`.
+
+use std::{collections::HashMap, hash::BuildHasher, path::Path};
+
+use regex::Regex;
+
+use crate::errors::ReleaseArtifactsError;
+
+/// The patterns of paths to archive. The default archives every path.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ArtifactFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl ArtifactFilter {
+    pub(crate) fn from_env<S: BuildHasher>(
+        env: &HashMap<String, String, S>,
+    ) -> Result<Self, ReleaseArtifactsError> {
+        Ok(ArtifactFilter {
+            include: glob_patterns(env, "STATIC_ARTIFACTS_INCLUDE")?,
+            exclude: glob_patterns(env, "STATIC_ARTIFACTS_EXCLUDE")?,
+        })
+    }
+
+    /// Whether every path is archived.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether every path is archived, unless excluded.
+    pub(crate) fn includes_all(&self) -> bool {
+        self.include.is_empty()
+    }
+
+    /// Whether the path, and so everything in it, is left out of the archive.
+    pub(crate) fn excludes(&self, path: &Path) -> bool {
+        matches_any(&self.exclude, path)
+    }
+
+    /// Whether the path, and so everything in it, is archived, unless excluded. Directories
+    /// that are not included are still searched for included paths.
+    pub(crate) fn includes(&self, path: &Path) -> bool {
+        self.includes_all() || matches_any(&self.include, path)
+    }
+}
+
+fn matches_any(patterns: &[Regex], path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    patterns.iter().any(|pattern| pattern.is_match(&path))
+}
+
+fn glob_patterns<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    var: &str,
+) -> Result<Vec<Regex>, ReleaseArtifactsError> {
+    env.get(var)
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|glob| !glob.is_empty())
+        .map(|glob| {
+            glob_regex(glob).map_err(|e| {
+                ReleaseArtifactsError::ConfigInvalid(format!(
+                    "{var} pattern '{glob}' is not valid: {e}"
+                ))
+            })
+        })
+        .collect()
+}
+
+// Translates the glob to a regex matching the whole of a relative path.
+fn glob_regex(glob: &str) -> Result<Regex, regex::Error> {
+    let glob = glob.trim_start_matches("./").trim_end_matches('/');
+    let mut regex = if glob.contains('/') {
+        String::from("^")
+    } else {
+        String::from("^(?:.*/)?")
+    };
+    let mut chars = glob.trim_start_matches('/').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use super::ArtifactFilter;
+
+    fn test_filter(include: &str, exclude: &str) -> ArtifactFilter {
+        ArtifactFilter::from_env(&HashMap::from([
+            ("STATIC_ARTIFACTS_INCLUDE".to_string(), include.to_string()),
+            ("STATIC_ARTIFACTS_EXCLUDE".to_string(), exclude.to_string()),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn artifact_filter_matches_globs() {
+        let filter = test_filter("", "node_modules, *.map, /assets/**/draft-?.png");
+        assert!(!filter.is_empty());
+        assert!(filter.includes(Path::new("index.html")));
+        assert!(filter.excludes(Path::new("node_modules")));
+        assert!(filter.excludes(Path::new("packages/ui/node_modules")));
+        assert!(!filter.excludes(Path::new("node_modules_backup")));
+        assert!(filter.excludes(Path::new("js/app.js.map")));
+        assert!(!filter.excludes(Path::new("js/app.js")));
+        assert!(filter.excludes(Path::new("assets/draft-1.png")));
+        assert!(filter.excludes(Path::new("assets/blog/2024/draft-2.png")));
+        assert!(!filter.excludes(Path::new("public/assets/draft-1.png")));
+
+        let filter = test_filter("/dist/, public/*.html", "");
+        assert!(filter.includes(Path::new("dist")));
+        assert!(!filter.includes(Path::new("src/dist")));
+        assert!(filter.includes(Path::new("public/index.html")));
+        assert!(!filter.includes(Path::new("public/js/index.html")));
+        assert!(!filter.includes(Path::new("README.md")));
+
+        assert!(ArtifactFilter::default().is_empty());
+        assert!(ArtifactFilter::default().includes(Path::new("README.md")));
+    }
+}
@@ -0,0 +1,1315 @@
+//! Deletes the archives in storage beyond those kept by the retention, with the completion markers
+//! of releases older than those kept, and aborts stale multipart uploads.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    hash::BuildHasher,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use aws_sdk_s3::{
+    error::ProvideErrorMetadata,
+    types::{Delete, ObjectIdentifier},
+};
+use tokio::task::JoinSet;
+
+use crate::{
+    checksum, detect_storage_scheme,
+    errors::ReleaseArtifactsError,
+    generate_file_storage_location, generate_s3_client, generate_s3_storage_prefix,
+    guard_s3_credentials, list_file_storage, list_with_client, log_info, markers, signature,
+    tags::{self, Tags, PINNED_TAG, STAGE_TAG},
+    StoredArchive,
+};
+
+// The most keys that S3 accepts in one DeleteObjects request.
+const DELETE_BATCH_SIZE: usize = 1000;
+
+// DeleteObjects requests in flight at once.
+const DELETE_CONCURRENCY: usize = 4;
+
+// GetObjectTagging requests in flight at once.
+const TAG_CONCURRENCY: usize = 16;
+
+/// What garbage collection kept & deleted.
+#[derive(Debug, Default, PartialEq)]
+pub struct GcReport {
+    pub kept: usize,
+    pub deleted: Vec<String>,
+    /// Release IDs whose completion markers were deleted, recorded before the oldest archive kept.
+    pub pruned_releases: Vec<String>,
+    /// Keys of the archives whose stale multipart uploads were aborted.
+    pub aborted_uploads: Vec<String>,
+}
+
+/// How many of the newest archives to keep, for each pipeline stage & for all the rest, and for
+/// how long.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Retention {
+    /// Archives to keep that are untagged or of a stage without its own count, all when `None`.
+    pub retain: Option<usize>,
+    /// Archives to keep per stage, by the stage that saved them.
+    pub stages: BTreeMap<String, usize>,
+    /// Age within which archives are kept, even beyond the counts. When set without a count,
+    /// older archives are deleted, except for the newest of each stage.
+    pub max_age: Option<Duration>,
+    /// Age after which multipart uploads of archives are aborted, none when `None`.
+    pub abort_uploads_after: Option<Duration>,
+}
+
+impl Retention {
+    /// Returns the retention from `STATIC_ARTIFACTS_RETAIN_COUNT`, `STATIC_ARTIFACTS_RETAIN_DAYS`,
+    /// `STATIC_ARTIFACTS_RETAIN_STAGES`, & `STATIC_ARTIFACTS_ABORT_UPLOADS_HOURS`, or `None`
+    /// when garbage collection is not configured.
+    pub fn from_env<S: BuildHasher>(
+        env: &HashMap<String, String, S>,
+    ) -> Result<Option<Retention>, ReleaseArtifactsError> {
+        let retain = env
+            .get("STATIC_ARTIFACTS_RETAIN_COUNT")
+            .map(|value| parse_count("STATIC_ARTIFACTS_RETAIN_COUNT", value))
+            .transpose()?;
+        let max_age = env
+            .get("STATIC_ARTIFACTS_RETAIN_DAYS")
+            .map(|value| parse_days("STATIC_ARTIFACTS_RETAIN_DAYS", value))
+            .transpose()?;
+        let mut stages = BTreeMap::new();
+        for rule in env
+            .get("STATIC_ARTIFACTS_RETAIN_STAGES")
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+        {
+            let (stage, count) = rule
+                .split_once('=')
+                .filter(|(stage, _)| !stage.trim().is_empty())
+                .ok_or_else(|| {
+                    ReleaseArtifactsError::ConfigInvalid(format!(
+                        "STATIC_ARTIFACTS_RETAIN_STAGES '{rule}' is not like 'production=10'"
+                    ))
+                })?;
+            stages.insert(
+                stage.trim().to_string(),
+                parse_count("STATIC_ARTIFACTS_RETAIN_STAGES", count)?,
+            );
+        }
+        let abort_uploads_after = env
+            .get("STATIC_ARTIFACTS_ABORT_UPLOADS_HOURS")
+            .map(|value| parse_hours("STATIC_ARTIFACTS_ABORT_UPLOADS_HOURS", value))
+            .transpose()?;
+        if retain.is_none()
+            && max_age.is_none()
+            && stages.is_empty()
+            && abort_uploads_after.is_none()
+        {
+            return Ok(None);
+        }
+        Ok(Some(Retention {
+            retain,
+            stages,
+            max_age,
+            abort_uploads_after,
+        }))
+    }
+
+    // The number of archives of the given stage to keep, or `None` to keep them all.
+    fn retain_for(&self, stage: Option<&str>) -> Option<usize> {
+        stage
+            .and_then(|stage| self.stages.get(stage).copied())
+            .or(self.retain)
+    }
+
+    // Whether the archive, the `nth` newest of its stage, from 1, is deleted, unless pinned.
+    fn expires(&self, stage: Option<&str>, nth: usize, archive: &StoredArchive) -> bool {
+        let beyond_count = match self.retain_for(stage) {
+            Some(retain) => nth > retain,
+            // Age alone never deletes the newest archive, which would leave nothing to load.
+            None => self.max_age.is_some() && nth > 1,
+        };
+        let too_old = self.max_age.is_none_or(|max_age| {
+            SystemTime::now()
+                .duration_since(archive.last_modified)
+                .is_ok_and(|age| age > max_age)
+        });
+        beyond_count && too_old
+    }
+}
+
+fn parse_count(name: &str, value: &str) -> Result<usize, ReleaseArtifactsError> {
+    match value.trim().parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+            "{name} '{value}' is not a number of archives, at least 1"
+        ))),
+    }
+}
+
+fn parse_days(name: &str, value: &str) -> Result<Duration, ReleaseArtifactsError> {
+    match value.trim().parse::<u64>() {
+        Ok(days) if days > 0 => Ok(Duration::from_secs(days.saturating_mul(24 * 60 * 60))),
+        _ => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+            "{name} '{value}' is not a number of days, at least 1"
+        ))),
+    }
+}
+
+fn parse_hours(name: &str, value: &str) -> Result<Duration, ReleaseArtifactsError> {
+    match value.trim().parse::<u64>() {
+        Ok(hours) if hours > 0 => Ok(Duration::from_secs(hours.saturating_mul(60 * 60))),
+        _ => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+            "{name} '{value}' is not a number of hours, at least 1"
+        ))),
+    }
+}
+
+/// Deletes archives in the configured storage location beyond those kept by the retention, and
+/// the completion markers of releases older than every archive kept, except `RELEASE_ID`'s.
+pub async fn gc<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    retention: &Retention,
+) -> Result<GcReport, ReleaseArtifactsError> {
+    let release_id = env
+        .get("RELEASE_ID")
+        .map(String::as_str)
+        .filter(|id| !id.is_empty());
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"file" => {
+            let storage_path = generate_file_storage_location(env, &String::new())?;
+            gc_file(&storage_path, retention, release_id)
+        }
+        Ok(scheme) if scheme == *"s3" => {
+            guard_s3_credentials(env)?;
+            let (bucket_name, bucket_region, bucket_key_prefix) = generate_s3_storage_prefix(env)?;
+            let s3 = generate_s3_client(env, bucket_region).await;
+            gc_s3(&s3, &bucket_name, &bucket_key_prefix, retention, release_id).await
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+// Whether the key or file name is that of an archive saved by this buildpack, named like
+// `generate_archive_name`, so that other objects sharing the prefix, such as manifests,
+// reports, or audit logs, are never deleted.
+pub(crate) fn is_archive_name(key: &str) -> bool {
+    let name = key.rsplit('/').next().unwrap_or(key);
+    let stem = name
+        .strip_prefix("release-")
+        .or_else(|| name.strip_prefix("artifact-"))
+        .and_then(|rest| {
+            rest.strip_suffix(".tgz")
+                .or_else(|| rest.strip_suffix(".tzst"))
+        });
+    stem.is_some_and(|stem| !stem.is_empty())
+}
+
+// Splits archives, listed oldest first, into those to keep & those to delete, ignoring
+// anything that is not an archive. Archives are counted per stage, kept while within the max
+// age, and pinned archives are kept even when expired, by their tags, if any, in `tags` by key.
+fn select_expired(
+    archives: &[StoredArchive],
+    retention: &Retention,
+    tags: &HashMap<String, Tags>,
+) -> (usize, Vec<String>) {
+    let mut seen: HashMap<Option<&str>, usize> = HashMap::new();
+    let mut kept = 0;
+    let mut expired = vec![];
+    for archive in archives
+        .iter()
+        .rev()
+        .filter(|archive| is_archive_name(&archive.key))
+    {
+        let archive_tags = tags.get(&archive.key);
+        let stage = archive_tags
+            .and_then(|tags| tags.get(STAGE_TAG))
+            .map(String::as_str);
+        let pinned = archive_tags.is_some_and(|tags| tags.contains_key(PINNED_TAG));
+        let count = seen.entry(stage).or_default();
+        *count += 1;
+        if !pinned && retention.expires(stage, *count, archive) {
+            expired.push(archive.key.clone());
+        } else {
+            kept += 1;
+        }
+    }
+    expired.reverse();
+    (kept, expired)
+}
+
+// When the oldest archive kept was saved. Rerun protection only matters while a release may be
+// retried, so the completion markers of releases before it are pruned.
+fn oldest_kept(archives: &[StoredArchive], expired: &[String]) -> Option<SystemTime> {
+    let expired: HashSet<&str> = expired.iter().map(String::as_str).collect();
+    archives
+        .iter()
+        .filter(|archive| is_archive_name(&archive.key) && !expired.contains(archive.key.as_str()))
+        .map(|archive| archive.last_modified)
+        .min()
+}
+
+fn gc_file(
+    storage_path: &Path,
+    retention: &Retention,
+    release_id: Option<&str>,
+) -> Result<GcReport, ReleaseArtifactsError> {
+    let archives = list_file_storage(storage_path)?;
+    let tags = archives
+        .iter()
+        .map(|archive| {
+            let tags = tags::read_file_tags(&storage_path.join(&archive.key));
+            (archive.key.clone(), tags)
+        })
+        .collect();
+    let (kept, expired) = select_expired(&archives, retention, &tags);
+    for name in &expired {
+        let path = storage_path.join(name);
+        fs::remove_file(&path).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(e, format!("during gc fs::remove_file({path:?})"))
+        })?;
+        fs::remove_file(signature::signature_path(&path)).unwrap_or_default();
+        fs::remove_file(checksum::checksum_path(&path)).unwrap_or_default();
+        fs::remove_file(tags::tags_path(&path)).unwrap_or_default();
+    }
+    let pruned_releases = match oldest_kept(&archives, &expired) {
+        Some(cutoff) => markers::prune_file_markers(storage_path, cutoff, release_id)?,
+        None => vec![],
+    };
+    log_info!(
+        "release-phase gc kept {kept} archives, deleted {}, pruned the markers of {} releases",
+        expired.len(),
+        pruned_releases.len()
+    );
+    Ok(GcReport {
+        kept,
+        deleted: expired,
+        pruned_releases,
+        aborted_uploads: vec![],
+    })
+}
+
+/// Deletes expired archives & the markers of releases before those kept with batched
+/// `DeleteObjects` requests, several at once, then aborts stale multipart uploads, when configured.
+pub(crate) async fn gc_s3(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key_prefix: &String,
+    retention: &Retention,
+    release_id: Option<&str>,
+) -> Result<GcReport, ReleaseArtifactsError> {
+    let archives = list_with_client(s3, bucket_name, bucket_key_prefix).await?;
+    // Stages are needed to count every archive, but without them, pins only matter for the
+    // archives that would otherwise be deleted, so only those are read.
+    let (kept, expired) = if retention.stages.is_empty() {
+        let (_, candidates) = select_expired(&archives, retention, &HashMap::new());
+        let tags = fetch_s3_tags(s3, bucket_name, candidates).await?;
+        select_expired(&archives, retention, &tags)
+    } else {
+        let keys = archives
+            .iter()
+            .map(|archive| archive.key.clone())
+            .filter(|key| is_archive_name(key))
+            .collect();
+        let tags = fetch_s3_tags(s3, bucket_name, keys).await?;
+        select_expired(&archives, retention, &tags)
+    };
+    // Keyless signatures are stored beside their archives.
+    let listed: HashSet<&str> = archives
+        .iter()
+        .map(|archive| archive.key.as_str())
+        .collect();
+    let mut deleted_keys: Vec<String> = expired
+        .iter()
+        .flat_map(|key| [key.clone(), signature::signature_key(key)])
+        .filter(|key| listed.contains(key.as_str()))
+        .collect();
+    let expired_markers = match oldest_kept(&archives, &expired) {
+        Some(cutoff) => {
+            markers::expired_s3_markers(s3, bucket_name, bucket_key_prefix, cutoff, release_id)
+                .await?
+        }
+        None => BTreeMap::new(),
+    };
+    deleted_keys.extend(expired_markers.values().flatten().cloned());
+    let mut batches = deleted_keys
+        .chunks(DELETE_BATCH_SIZE)
+        .map(<[String]>::to_vec);
+    let mut requests = JoinSet::new();
+    let mut failures = vec![];
+    loop {
+        while requests.len() < DELETE_CONCURRENCY {
+            let Some(batch) = batches.next() else {
+                break;
+            };
+            requests.spawn(delete_batch(s3.clone(), bucket_name.clone(), batch));
+        }
+        let Some(result) = requests.join_next().await else {
+            break;
+        };
+        match result {
+            Ok(Ok(batch_failures)) => failures.extend(batch_failures),
+            Ok(Err(error)) => {
+                requests.abort_all();
+                return Err(error);
+            }
+            Err(error) => {
+                requests.abort_all();
+                return Err(ReleaseArtifactsError::StorageError {
+                    message: format!("gc delete task failed: {error}"),
+                    request_id: None,
+                    extended_request_id: None,
+                    status: None,
+                });
+            }
+        }
+    }
+    if !failures.is_empty() {
+        return Err(ReleaseArtifactsError::StorageError {
+            message: format!(
+                "gc failed to delete {} objects: {}",
+                failures.len(),
+                failures.join("; ")
+            ),
+            request_id: None,
+            extended_request_id: None,
+            status: None,
+        });
+    }
+    let aborted_uploads = match retention.abort_uploads_after {
+        Some(age) => {
+            let started_before = SystemTime::now()
+                .checked_sub(age)
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            abort_multipart_uploads(s3, bucket_name, bucket_key_prefix, started_before).await?
+        }
+        None => vec![],
+    };
+    let pruned_releases: Vec<String> = expired_markers.into_keys().collect();
+    log_info!(
+        "release-phase gc kept {kept} archives, deleted {}, pruned the markers of {} releases, aborted {} stale uploads",
+        expired.len(),
+        pruned_releases.len(),
+        aborted_uploads.len()
+    );
+    Ok(GcReport {
+        kept,
+        deleted: expired,
+        pruned_releases,
+        aborted_uploads,
+    })
+}
+
+// Aborts the multipart uploads of archives under the prefix that were started before the given
+// time, returning their keys. ListMultipartUploads has no paginator, so pages are followed by
+// their markers.
+async fn abort_multipart_uploads(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_key_prefix: &str,
+    started_before: SystemTime,
+) -> Result<Vec<String>, ReleaseArtifactsError> {
+    let mut stale = vec![];
+    let mut key_marker = None;
+    let mut upload_id_marker = None;
+    loop {
+        let page = s3
+            .list_multipart_uploads()
+            .bucket(bucket_name)
+            .prefix(bucket_key_prefix)
+            .set_key_marker(key_marker.take())
+            .set_upload_id_marker(upload_id_marker.take())
+            .send()
+            .await?;
+        for upload in page.uploads() {
+            let initiated = upload
+                .initiated()
+                .and_then(|t| SystemTime::try_from(*t).ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            if let (Some(key), Some(upload_id)) = (upload.key(), upload.upload_id()) {
+                if is_archive_name(key) && initiated < started_before {
+                    stale.push((key.to_string(), upload_id.to_string()));
+                }
+            }
+        }
+        key_marker = page.next_key_marker().map(String::from);
+        upload_id_marker = page.next_upload_id_marker().map(String::from);
+        if !page.is_truncated().unwrap_or_default() || key_marker.is_none() {
+            break;
+        }
+    }
+    for (key, upload_id) in &stale {
+        match s3
+            .abort_multipart_upload()
+            .bucket(bucket_name)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+        {
+            // Completed or aborted since it was listed.
+            Err(error) if error.code() == Some("NoSuchUpload") => {}
+            Err(error) => return Err(error.into()),
+            Ok(_) => {}
+        }
+    }
+    Ok(stale.into_iter().map(|(key, _)| key).collect())
+}
+
+// Reads the tags of each archive, several at once, returning tags by key.
+async fn fetch_s3_tags(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    keys: Vec<String>,
+) -> Result<HashMap<String, Tags>, ReleaseArtifactsError> {
+    let mut keys = keys.into_iter();
+    let mut requests = JoinSet::new();
+    let mut all_tags = HashMap::new();
+    loop {
+        while requests.len() < TAG_CONCURRENCY {
+            let Some(key) = keys.next() else {
+                break;
+            };
+            let s3 = s3.clone();
+            let bucket_name = bucket_name.to_string();
+            requests.spawn(async move {
+                let tags = tags::get_s3_tags(&s3, &bucket_name, &key).await;
+                (key, tags)
+            });
+        }
+        let Some(result) = requests.join_next().await else {
+            break;
+        };
+        match result {
+            Ok((key, Ok(tags))) => {
+                all_tags.insert(key, tags);
+            }
+            Ok((_, Err(error))) => {
+                requests.abort_all();
+                return Err(error);
+            }
+            Err(error) => {
+                requests.abort_all();
+                return Err(ReleaseArtifactsError::StorageError {
+                    message: format!("gc tagging task failed: {error}"),
+                    request_id: None,
+                    extended_request_id: None,
+                    status: None,
+                });
+            }
+        }
+    }
+    Ok(all_tags)
+}
+
+// Deletes up to 1000 keys in one request, returning the keys that S3 failed to delete.
+async fn delete_batch(
+    s3: aws_sdk_s3::Client,
+    bucket_name: String,
+    keys: Vec<String>,
+) -> Result<Vec<String>, ReleaseArtifactsError> {
+    let objects = keys
+        .into_iter()
+        .map(|key| ObjectIdentifier::builder().key(key).build())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ReleaseArtifactsError::StorageError {
+            message: format!("building gc delete request: {e}"),
+            request_id: None,
+            extended_request_id: None,
+            status: None,
+        })?;
+    let delete = Delete::builder()
+        .set_objects(Some(objects))
+        .quiet(true)
+        .build()
+        .map_err(|e| ReleaseArtifactsError::StorageError {
+            message: format!("building gc delete request: {e}"),
+            request_id: None,
+            extended_request_id: None,
+            status: None,
+        })?;
+    let output = s3
+        .delete_objects()
+        .bucket(bucket_name)
+        .delete(delete)
+        .send()
+        .await?;
+    Ok(output
+        .errors()
+        .iter()
+        .map(|error| {
+            format!(
+                "{}: {}",
+                error.key().unwrap_or_default(),
+                error.code().unwrap_or_default()
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fmt::Write,
+        fs,
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
+
+    use aws_config::BehaviorVersion;
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+    use uuid::Uuid;
+
+    use super::{gc_file, gc_s3, is_archive_name, select_expired, GcReport, Retention};
+    use crate::{
+        make_s3_test_credentials,
+        tags::{write_file_tags, Tags, PINNED_TAG, STAGE_TAG},
+        StoredArchive,
+    };
+
+    fn retain(count: usize) -> Retention {
+        Retention {
+            retain: Some(count),
+            ..Retention::default()
+        }
+    }
+
+    #[test]
+    fn retention_from_env_parses_positive_counts() {
+        let mut test_env: HashMap<String, String> = HashMap::new();
+        assert_eq!(Retention::from_env(&test_env).unwrap(), None);
+        test_env.insert(
+            "STATIC_ARTIFACTS_RETAIN_COUNT".to_string(),
+            "10".to_string(),
+        );
+        assert_eq!(Retention::from_env(&test_env).unwrap(), Some(retain(10)));
+        test_env.insert("STATIC_ARTIFACTS_RETAIN_COUNT".to_string(), "0".to_string());
+        assert!(Retention::from_env(&test_env).is_err());
+    }
+
+    #[test]
+    fn retention_from_env_parses_count_and_days() {
+        let mut test_env: HashMap<String, String> = HashMap::new();
+        test_env.insert("STATIC_ARTIFACTS_RETAIN_COUNT".to_string(), "5".to_string());
+        assert_eq!(Retention::from_env(&test_env).unwrap(), Some(retain(5)));
+        test_env.clear();
+        test_env.insert("STATIC_ARTIFACTS_RETAIN_DAYS".to_string(), "30".to_string());
+        assert_eq!(
+            Retention::from_env(&test_env).unwrap(),
+            Some(Retention {
+                max_age: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+                ..Retention::default()
+            })
+        );
+        test_env.insert("STATIC_ARTIFACTS_RETAIN_DAYS".to_string(), "0".to_string());
+        assert!(Retention::from_env(&test_env).is_err());
+        test_env.insert(
+            "STATIC_ARTIFACTS_RETAIN_DAYS".to_string(),
+            "a month".to_string(),
+        );
+        assert!(Retention::from_env(&test_env).is_err());
+    }
+
+    #[test]
+    fn retention_from_env_parses_stages() {
+        let mut test_env: HashMap<String, String> = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_RETAIN_STAGES".to_string(),
+            "production=10, review-app=2".to_string(),
+        );
+        let retention = Retention::from_env(&test_env).unwrap().unwrap();
+        assert_eq!(retention.retain, None);
+        assert_eq!(retention.stages.get("production"), Some(&10));
+        assert_eq!(retention.stages.get("review-app"), Some(&2));
+        test_env.insert(
+            "STATIC_ARTIFACTS_RETAIN_STAGES".to_string(),
+            "production".to_string(),
+        );
+        assert!(Retention::from_env(&test_env).is_err());
+        test_env.insert(
+            "STATIC_ARTIFACTS_RETAIN_STAGES".to_string(),
+            "=2".to_string(),
+        );
+        assert!(Retention::from_env(&test_env).is_err());
+    }
+
+    #[test]
+    fn retention_from_env_parses_abort_uploads_hours() {
+        let mut test_env: HashMap<String, String> = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_ABORT_UPLOADS_HOURS".to_string(),
+            "24".to_string(),
+        );
+        assert_eq!(
+            Retention::from_env(&test_env).unwrap(),
+            Some(Retention {
+                abort_uploads_after: Some(Duration::from_secs(24 * 60 * 60)),
+                ..Retention::default()
+            })
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_ABORT_UPLOADS_HOURS".to_string(),
+            "0".to_string(),
+        );
+        assert!(Retention::from_env(&test_env).is_err());
+    }
+
+    #[test]
+    fn is_archive_name_matches_saved_archives_only() {
+        assert!(is_archive_name("release-v102.tgz"));
+        assert!(is_archive_name("release-v102.tzst"));
+        assert!(is_archive_name("sub/path/release-v102.tgz"));
+        assert!(is_archive_name(
+            "artifact-0b3a1c7e-52a4-4e4f-9f37-4d2f5b0d7b5e.tgz"
+        ));
+        assert!(!is_archive_name("release-.tgz"));
+        assert!(!is_archive_name("sub/path/manifest.json"));
+        assert!(!is_archive_name("release-v102.tgz.sig"));
+        assert!(!is_archive_name("reports/release-v102.json"));
+        assert!(!is_archive_name("audit.log"));
+    }
+
+    #[test]
+    fn select_expired_keeps_newest() {
+        let archives = |keys: &[&str]| {
+            keys.iter()
+                .map(|key| StoredArchive {
+                    key: (*key).to_string(),
+                    size: 1,
+                    last_modified: SystemTime::UNIX_EPOCH,
+                })
+                .collect::<Vec<_>>()
+        };
+        let no_tags = HashMap::new();
+        assert_eq!(
+            select_expired(
+                &archives(&["release-v1.tgz", "release-v2.tgz", "release-v3.tgz"]),
+                &retain(2),
+                &no_tags
+            ),
+            (2, vec!["release-v1.tgz".to_string()])
+        );
+        assert_eq!(
+            select_expired(&archives(&["release-v1.tgz"]), &retain(2), &no_tags),
+            (1, vec![])
+        );
+        assert_eq!(
+            select_expired(
+                &archives(&[
+                    "audit.log",
+                    "release-v1.tgz",
+                    "manifest.json",
+                    "release-v2.tgz"
+                ]),
+                &retain(1),
+                &no_tags
+            ),
+            (1, vec!["release-v1.tgz".to_string()])
+        );
+    }
+
+    #[test]
+    fn select_expired_retains_per_stage() {
+        let keys = (1..=6)
+            .map(|i| format!("release-v{i}.tgz"))
+            .collect::<Vec<_>>();
+        let archives = keys
+            .iter()
+            .map(|key| StoredArchive {
+                key: key.clone(),
+                size: 1,
+                last_modified: SystemTime::UNIX_EPOCH,
+            })
+            .collect::<Vec<_>>();
+        // v1, v3, v5 are production; v2, v4 are a review app; v6 is untagged.
+        let stage = |stage: &str| Tags::from([(STAGE_TAG.to_string(), stage.to_string())]);
+        let tags = HashMap::from([
+            (keys[0].clone(), stage("production")),
+            (keys[1].clone(), stage("review-app")),
+            (keys[2].clone(), stage("production")),
+            (keys[3].clone(), stage("review-app")),
+            (keys[4].clone(), stage("production")),
+        ]);
+        let retention = Retention {
+            retain: None,
+            stages: [("production".to_string(), 2), ("review-app".to_string(), 1)].into(),
+            ..Retention::default()
+        };
+        assert_eq!(
+            select_expired(&archives, &retention, &tags),
+            (
+                4,
+                vec!["release-v1.tgz".to_string(), "release-v2.tgz".to_string()]
+            )
+        );
+        let retention = Retention {
+            retain: Some(1),
+            stages: [("production".to_string(), 3)].into(),
+            ..Retention::default()
+        };
+        assert_eq!(
+            select_expired(&archives, &retention, &tags),
+            (5, vec!["release-v2.tgz".to_string()])
+        );
+    }
+
+    #[test]
+    fn select_expired_keeps_archives_within_max_age() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        // v1 & v2 were saved 10 & 5 days ago, v3 & v4 today.
+        let archives = [(1, 10), (2, 5), (3, 0), (4, 0)]
+            .iter()
+            .map(|(version, days_ago)| StoredArchive {
+                key: format!("release-v{version}.tgz"),
+                size: 1,
+                last_modified: SystemTime::now() - day * *days_ago,
+            })
+            .collect::<Vec<_>>();
+        let no_tags = HashMap::new();
+        let within = |days: u32, retain: Option<usize>| Retention {
+            retain,
+            max_age: Some(day * days),
+            ..Retention::default()
+        };
+        assert_eq!(
+            select_expired(&archives, &within(7, None), &no_tags),
+            (3, vec!["release-v1.tgz".to_string()])
+        );
+        assert_eq!(
+            select_expired(&archives, &within(7, Some(1)), &no_tags),
+            (3, vec!["release-v1.tgz".to_string()])
+        );
+        assert_eq!(
+            select_expired(&archives, &within(1, Some(3)), &no_tags),
+            (3, vec!["release-v1.tgz".to_string()])
+        );
+        assert_eq!(
+            select_expired(&archives[..2], &within(1, None), &no_tags),
+            (1, vec!["release-v1.tgz".to_string()]),
+            "the newest archive should be kept however old"
+        );
+    }
+
+    #[test]
+    fn select_expired_keeps_pinned() {
+        let archives = ["release-v1.tgz", "release-v2.tgz", "release-v3.tgz"]
+            .iter()
+            .map(|key| StoredArchive {
+                key: (*key).to_string(),
+                size: 1,
+                last_modified: SystemTime::UNIX_EPOCH,
+            })
+            .collect::<Vec<_>>();
+        let tags = HashMap::from([(
+            "release-v1.tgz".to_string(),
+            Tags::from([(PINNED_TAG.to_string(), "true".to_string())]),
+        )]);
+        assert_eq!(
+            select_expired(&archives, &retain(1), &tags),
+            (2, vec!["release-v2.tgz".to_string()])
+        );
+    }
+
+    #[test]
+    fn gc_file_deletes_old_archives_and_signatures() {
+        let storage_path = PathBuf::from(format!("gc-file-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&storage_path).unwrap();
+        for (i, name) in ["release-v1.tgz", "release-v2.tgz", "release-v3.tgz"]
+            .iter()
+            .enumerate()
+        {
+            let path = storage_path.join(name);
+            fs::write(&path, "archive").unwrap();
+            fs::write(storage_path.join(format!("{name}.sig")), "sig").unwrap();
+            let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(i as u64 + 1);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(modified))
+                .unwrap();
+        }
+
+        fs::write(storage_path.join("audit.log"), "log").unwrap();
+
+        let result = gc_file(&storage_path, &retain(2), None);
+        let audit_log_exists = storage_path.join("audit.log").exists();
+        let v1_exists = storage_path.join("release-v1.tgz").exists();
+        let v1_sig_exists = storage_path.join("release-v1.tgz.sig").exists();
+        let v3_exists = storage_path.join("release-v3.tgz").exists();
+        fs::remove_dir_all(&storage_path).unwrap_or_default();
+
+        assert_eq!(
+            result.unwrap(),
+            GcReport {
+                kept: 2,
+                deleted: vec!["release-v1.tgz".to_string()],
+                ..GcReport::default()
+            }
+        );
+        assert!(audit_log_exists);
+        assert!(!v1_exists);
+        assert!(!v1_sig_exists);
+        assert!(v3_exists);
+    }
+
+    #[test]
+    fn gc_file_prunes_markers_of_releases_before_kept_archives() {
+        let storage_path = PathBuf::from(format!("gc-file-markers-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&storage_path).unwrap();
+        let set_modified = |path: &PathBuf, secs: u64| {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .and_then(|file| {
+                    file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                })
+                .unwrap();
+        };
+        for (name, secs) in [
+            ("release-v1.tgz", 10),
+            ("release-v2.tgz", 20),
+            ("release-v3.tgz", 30),
+        ] {
+            let path = storage_path.join(name);
+            fs::write(&path, "archive").unwrap();
+            set_modified(&path, secs);
+        }
+        for (marker, secs) in [
+            ("v1/complete", 15),
+            ("v1/steps/build", 12),
+            ("v2/complete", 25),
+            ("v4/steps/build", 5),
+        ] {
+            let path = storage_path.join("release-phase-markers").join(marker);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "").unwrap();
+            set_modified(&path, secs);
+        }
+
+        let result = gc_file(&storage_path, &retain(2), Some("v4"));
+        let v1_markers_exist = storage_path.join("release-phase-markers/v1").exists();
+        let v2_marker_exists = storage_path
+            .join("release-phase-markers/v2/complete")
+            .exists();
+        let v4_marker_exists = storage_path
+            .join("release-phase-markers/v4/steps/build")
+            .exists();
+        fs::remove_dir_all(&storage_path).unwrap_or_default();
+
+        assert_eq!(
+            result.unwrap(),
+            GcReport {
+                kept: 2,
+                deleted: vec!["release-v1.tgz".to_string()],
+                pruned_releases: vec!["v1".to_string()],
+                ..GcReport::default()
+            }
+        );
+        assert!(!v1_markers_exist);
+        assert!(v2_marker_exists);
+        assert!(v4_marker_exists, "the current release's markers are kept");
+    }
+
+    #[test]
+    fn gc_file_retains_per_stage_tag() {
+        let storage_path = PathBuf::from(format!("gc-file-stage-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&storage_path).unwrap();
+        for (i, (name, stage)) in [
+            ("release-v1.tgz", "review-app"),
+            ("release-v2.tgz", "production"),
+            ("release-v3.tgz", "review-app"),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let path = storage_path.join(name);
+            fs::write(&path, "archive").unwrap();
+            write_file_tags(
+                &path,
+                &Tags::from([(STAGE_TAG.to_string(), (*stage).to_string())]),
+            )
+            .unwrap();
+            let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(i as u64 + 1);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(modified))
+                .unwrap();
+        }
+
+        let retention = Retention {
+            retain: None,
+            stages: [("review-app".to_string(), 1)].into(),
+            ..Retention::default()
+        };
+        let result = gc_file(&storage_path, &retention, None);
+        let v1_tags_exists = storage_path.join("release-v1.tgz.tags").exists();
+        let v3_tags_exists = storage_path.join("release-v3.tgz.tags").exists();
+        fs::remove_dir_all(&storage_path).unwrap_or_default();
+
+        assert_eq!(
+            result.unwrap(),
+            GcReport {
+                kept: 2,
+                deleted: vec!["release-v1.tgz".to_string()],
+                ..GcReport::default()
+            }
+        );
+        assert!(!v1_tags_exists);
+        assert!(v3_tags_exists);
+    }
+
+    #[tokio::test]
+    async fn gc_s3_deletes_in_batches() {
+        let contents = (0..2501).fold(String::new(), |mut contents, i| {
+            write!(
+                contents,
+                "<Contents><Key>sub/path/release-v{i}.tgz</Key><LastModified>2024-07-01T{:02}:{:02}:{:02}.000Z</LastModified><Size>1</Size></Contents>",
+                i / 3600,
+                i / 60 % 60,
+                i % 60
+            )
+            .unwrap();
+            contents
+        });
+        let list_objects = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(format!(
+                    "<ListBucketResult><IsTruncated>false</IsTruncated>{contents}</ListBucketResult>"
+                )))
+                .unwrap(),
+        );
+        let delete_objects = || {
+            ReplayEvent::new(
+                http::Request::builder()
+                    .method("POST")
+                    .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?delete")
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(200)
+                    .body(SdkBody::from("<DeleteResult></DeleteResult>"))
+                    .unwrap(),
+            )
+        };
+        // Expired archives are checked for pins before deleting.
+        let get_tagging = || {
+            ReplayEvent::new(
+                http::Request::builder()
+                    .method("GET")
+                    .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?tagging")
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(200)
+                    .body(SdkBody::from("<Tagging><TagSet></TagSet></Tagging>"))
+                    .unwrap(),
+            )
+        };
+        let list_markers = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2Frelease-phase-markers%2F")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(
+                    "<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>",
+                ))
+                .unwrap(),
+        );
+        let mut events = vec![list_objects];
+        events.extend((0..2500).map(|_| get_tagging()));
+        events.extend([
+            list_markers,
+            delete_objects(),
+            delete_objects(),
+            delete_objects(),
+        ]);
+        let replay_client = StaticReplayClient::new(events);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = gc_s3(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/".to_string(),
+            &retain(1),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.kept, 1);
+        assert_eq!(result.deleted.len(), 2500);
+        assert_eq!(result.deleted[0], "sub/path/release-v0.tgz");
+        assert!(!result
+            .deleted
+            .contains(&"sub/path/release-v2500.tgz".to_string()));
+        let delete_requests = replay_client
+            .actual_requests()
+            .filter(|r| r.uri().contains("?delete"))
+            .count();
+        assert_eq!(delete_requests, 3);
+    }
+
+    #[tokio::test]
+    async fn gc_s3_deletes_keyless_signatures_of_expired_archives() {
+        let replay_event = |method: &str, uri: &str, response_body: &str| {
+            ReplayEvent::new(
+                http::Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(200)
+                    .body(SdkBody::from(response_body.to_string()))
+                    .unwrap(),
+            )
+        };
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event(
+                "GET",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F",
+                "<ListBucketResult><IsTruncated>false</IsTruncated>\
+                <Contents><Key>sub/path/release-v1.tgz</Key><LastModified>2024-07-01T12:00:00.000Z</LastModified><Size>1</Size></Contents>\
+                <Contents><Key>sub/path/release-v1.tgz.sig</Key><LastModified>2024-07-01T11:59:59.000Z</LastModified><Size>1</Size></Contents>\
+                <Contents><Key>sub/path/release-v2.tgz</Key><LastModified>2024-07-02T12:00:00.000Z</LastModified><Size>1</Size></Contents>\
+                </ListBucketResult>",
+            ),
+            replay_event(
+                "GET",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/release-v1.tgz?tagging",
+                "<Tagging><TagSet></TagSet></Tagging>",
+            ),
+            replay_event(
+                "GET",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2Frelease-phase-markers%2F",
+                "<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>",
+            ),
+            replay_event(
+                "POST",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/?delete",
+                "<DeleteResult></DeleteResult>",
+            ),
+        ]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = gc_s3(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/".to_string(),
+            &retain(1),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.deleted, vec!["sub/path/release-v1.tgz".to_string()]);
+        let delete_body = replay_client
+            .actual_requests()
+            .find(|r| r.uri().contains("?delete"))
+            .and_then(|r| {
+                r.body()
+                    .bytes()
+                    .map(|b| String::from_utf8_lossy(b).to_string())
+            })
+            .unwrap();
+        assert!(delete_body.contains("<Key>sub/path/release-v1.tgz</Key>"));
+        assert!(delete_body.contains("<Key>sub/path/release-v1.tgz.sig</Key>"));
+        assert!(!delete_body.contains("release-v2"));
+    }
+
+    #[tokio::test]
+    async fn gc_s3_prunes_markers_of_releases_before_kept_archives() {
+        let replay_event = |method: &str, uri: &str, response_body: &str| {
+            ReplayEvent::new(
+                http::Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(200)
+                    .body(SdkBody::from(response_body.to_string()))
+                    .unwrap(),
+            )
+        };
+        let object = |key: &str, last_modified: &str| {
+            format!("<Contents><Key>sub/path/{key}</Key><LastModified>{last_modified}</LastModified><Size>1</Size></Contents>")
+        };
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event(
+                "GET",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F",
+                &format!(
+                    "<ListBucketResult><IsTruncated>false</IsTruncated>{}{}</ListBucketResult>",
+                    object("release-v1.tgz", "2024-07-01T12:00:00.000Z"),
+                    object("release-v2.tgz", "2024-07-02T12:00:00.000Z"),
+                ),
+            ),
+            replay_event(
+                "GET",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/release-v1.tgz?tagging",
+                "<Tagging><TagSet></TagSet></Tagging>",
+            ),
+            replay_event(
+                "GET",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2Frelease-phase-markers%2F",
+                &format!(
+                    "<ListBucketResult><IsTruncated>false</IsTruncated>{}{}{}{}</ListBucketResult>",
+                    object("release-phase-markers/v1/complete", "2024-07-01T13:00:00.000Z"),
+                    object("release-phase-markers/v1/steps/build", "2024-07-01T12:30:00.000Z"),
+                    object("release-phase-markers/v2/steps/build", "2024-07-02T11:00:00.000Z"),
+                    object("release-phase-markers/v3/complete", "2024-07-03T12:00:00.000Z"),
+                ),
+            ),
+            replay_event(
+                "POST",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/?delete",
+                "<DeleteResult></DeleteResult>",
+            ),
+        ]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = gc_s3(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/".to_string(),
+            &retain(1),
+            Some("v2"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.deleted, vec!["sub/path/release-v1.tgz".to_string()]);
+        assert_eq!(result.pruned_releases, vec!["v1".to_string()]);
+        let delete_body = replay_client
+            .actual_requests()
+            .find(|r| r.uri().contains("?delete"))
+            .and_then(|r| {
+                r.body()
+                    .bytes()
+                    .map(|b| String::from_utf8_lossy(b).to_string())
+            })
+            .unwrap();
+        assert!(delete_body.contains("<Key>sub/path/release-phase-markers/v1/complete</Key>"));
+        assert!(delete_body.contains("<Key>sub/path/release-phase-markers/v1/steps/build</Key>"));
+        assert!(!delete_body.contains("release-phase-markers/v2"));
+        assert!(!delete_body.contains("release-phase-markers/v3"));
+    }
+
+    #[tokio::test]
+    async fn gc_s3_aborts_stale_multipart_uploads() {
+        let replay_event = |method: &str, uri: &str, response_body: String| {
+            ReplayEvent::new(
+                http::Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(200)
+                    .body(SdkBody::from(response_body))
+                    .unwrap(),
+            )
+        };
+        let upload = |key: &str, upload_id: &str, initiated: &str| {
+            format!("<Upload><Key>{key}</Key><UploadId>{upload_id}</UploadId><Initiated>{initiated}</Initiated></Upload>")
+        };
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event(
+                "GET",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F",
+                "<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>".to_string(),
+            ),
+            replay_event(
+                "GET",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/?uploads&prefix=sub%2Fpath%2F",
+                format!(
+                    "<ListMultipartUploadsResult><IsTruncated>true</IsTruncated><NextKeyMarker>sub/path/release-v2.tgz</NextKeyMarker><NextUploadIdMarker>UPLOAD2</NextUploadIdMarker>{}{}</ListMultipartUploadsResult>",
+                    upload("sub/path/release-v1.tgz", "UPLOAD1", "2024-07-01T12:00:00.000Z"),
+                    upload("sub/path/release-v2.tgz", "UPLOAD2", "2999-01-01T00:00:00.000Z"),
+                ),
+            ),
+            replay_event(
+                "GET",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/?uploads&key-marker=sub%2Fpath%2Frelease-v2.tgz&prefix=sub%2Fpath%2F&upload-id-marker=UPLOAD2",
+                format!(
+                    "<ListMultipartUploadsResult><IsTruncated>false</IsTruncated>{}</ListMultipartUploadsResult>",
+                    upload("sub/path/reports/audit.log", "UPLOAD3", "2024-07-01T12:00:00.000Z"),
+                ),
+            ),
+            replay_event(
+                "DELETE",
+                "https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/release-v1.tgz?x-id=AbortMultipartUpload&uploadId=UPLOAD1",
+                String::new(),
+            ),
+        ]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+        let retention = Retention {
+            abort_uploads_after: Some(Duration::from_secs(24 * 60 * 60)),
+            ..Retention::default()
+        };
+
+        let result = gc_s3(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/".to_string(),
+            &retention,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            result.aborted_uploads,
+            vec!["sub/path/release-v1.tgz".to_string()]
+        );
+        let requests: Vec<String> = replay_client
+            .actual_requests()
+            .map(|r| format!("{} {}", r.method(), r.uri()))
+            .collect();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[2].contains("key-marker=sub%2Fpath%2Frelease-v2.tgz"));
+        assert!(requests[3].starts_with("DELETE") && requests[3].contains("uploadId=UPLOAD1"));
+    }
+}
@@ -0,0 +1,518 @@
+//! Save journals, recorded under `release-phase-journal/` while an archive is uploaded in parts, to
+//! complete or abort the uploads of crashed release processes.
+
+use std::{
+    collections::HashMap,
+    hash::BuildHasher,
+    io,
+    time::{Duration, SystemTime},
+};
+
+use aws_sdk_s3::{
+    error::ProvideErrorMetadata,
+    operation::get_object::GetObjectError,
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    detect_storage_scheme, errors::ReleaseArtifactsError, generate_s3_client,
+    generate_s3_storage_prefix, guard_s3_credentials, log_info,
+};
+
+const JOURNAL_DIR: &str = "release-phase-journal";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SaveJournal {
+    pub(crate) bucket_key: String,
+    pub(crate) upload_id: String,
+    pub(crate) parts: Vec<JournalPart>,
+    // Set once every part was uploaded, so that only completing the upload remains.
+    pub(crate) uploaded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct JournalPart {
+    pub(crate) part_number: i32,
+    pub(crate) e_tag: Option<String>,
+}
+
+/// A multipart upload, interrupted while saving, that was recovered from its journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveredUpload {
+    /// Every part had been uploaded, so the upload was completed, storing the archive at the key.
+    Completed(String),
+    /// The upload was aborted, deleting the parts stored for the key.
+    Aborted(String),
+}
+
+/// The name of the journal for an archive, relative to the storage location.
+pub(crate) fn journal_name(archive_name: &str) -> String {
+    format!("{JOURNAL_DIR}/{archive_name}.json")
+}
+
+/// Completes or aborts the uploads of the save journals in the configured storage location that
+/// are older than `min_age`, so that saves still in progress are left alone. Only S3 storage has
+/// multipart uploads, so nothing is recovered from other storage.
+pub async fn abort_stale_uploads<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    min_age: Duration,
+) -> Result<Vec<RecoveredUpload>, ReleaseArtifactsError> {
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"file" => Ok(vec![]),
+        Ok(scheme) if scheme == *"s3" => {
+            guard_s3_credentials(env)?;
+            let (bucket_name, bucket_region, bucket_key_prefix) = generate_s3_storage_prefix(env)?;
+            let s3 = generate_s3_client(env, bucket_region).await;
+            let older_than = SystemTime::now()
+                .checked_sub(min_age)
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            abort_stale_uploads_with_client(&s3, &bucket_name, &bucket_key_prefix, older_than).await
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) async fn abort_stale_uploads_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_key_prefix: &str,
+    older_than: SystemTime,
+) -> Result<Vec<RecoveredUpload>, ReleaseArtifactsError> {
+    let mut journal_keys = vec![];
+    let mut pages = s3
+        .list_objects_v2()
+        .bucket(bucket_name)
+        .prefix(format!("{bucket_key_prefix}{JOURNAL_DIR}/"))
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(ReleaseArtifactsError::from)?;
+        for object in page.contents() {
+            let last_modified = object
+                .last_modified()
+                .and_then(|t| SystemTime::try_from(*t).ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            if let Some(key) = object.key().filter(|_| last_modified <= older_than) {
+                journal_keys.push(key.to_string());
+            }
+        }
+    }
+    let mut recovered = vec![];
+    for journal_key in journal_keys {
+        if let Some(upload) = recover_upload_with_client(s3, bucket_name, &journal_key).await? {
+            recovered.push(upload);
+        }
+    }
+    Ok(recovered)
+}
+
+/// Finishes the upload recorded in the journal, if any, then deletes the journal. An upload
+/// whose parts were all uploaded is completed, falling back to aborting it, such as when the
+/// parts recorded no longer match those stored.
+pub(crate) async fn recover_upload_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    journal_key: &str,
+) -> Result<Option<RecoveredUpload>, ReleaseArtifactsError> {
+    let Some(journal) = read_journal_with_client(s3, bucket_name, journal_key).await? else {
+        return Ok(None);
+    };
+    if journal.uploaded {
+        let parts = journal
+            .parts
+            .iter()
+            .map(|part| {
+                CompletedPart::builder()
+                    .part_number(part.part_number)
+                    .set_e_tag(part.e_tag.clone())
+                    .build()
+            })
+            .collect();
+        match s3
+            .complete_multipart_upload()
+            .bucket(bucket_name)
+            .key(&journal.bucket_key)
+            .upload_id(&journal.upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+        {
+            Ok(_) => {
+                delete_journal_with_client(s3, bucket_name, journal_key).await?;
+                return Ok(Some(RecoveredUpload::Completed(journal.bucket_key)));
+            }
+            Err(error) => log_info!(
+                "save-release-artifacts cannot complete interrupted upload of '{}', aborting: {:?}",
+                journal.bucket_key,
+                ReleaseArtifactsError::from(error)
+            ),
+        }
+    }
+    match s3
+        .abort_multipart_upload()
+        .bucket(bucket_name)
+        .key(&journal.bucket_key)
+        .upload_id(&journal.upload_id)
+        .send()
+        .await
+    {
+        // The upload was already finished, but its journal was not deleted.
+        Err(error) if error.code() == Some("NoSuchUpload") => {}
+        Err(error) => return Err(error.into()),
+        Ok(_) => {}
+    }
+    delete_journal_with_client(s3, bucket_name, journal_key).await?;
+    Ok(Some(RecoveredUpload::Aborted(journal.bucket_key)))
+}
+
+pub(crate) async fn write_journal_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    journal_key: &str,
+    journal: &SaveJournal,
+) -> Result<(), ReleaseArtifactsError> {
+    let content = serde_json::to_vec(journal).map_err(|e| {
+        ReleaseArtifactsError::ArchiveError(
+            io::Error::other(e),
+            format!("serializing save journal '{journal_key}'"),
+        )
+    })?;
+    s3.put_object()
+        .bucket(bucket_name)
+        .key(journal_key)
+        .content_type("application/json")
+        .body(ByteStream::from(content))
+        .send()
+        .await?;
+    Ok(())
+}
+
+pub(crate) async fn delete_journal_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    journal_key: &str,
+) -> Result<(), ReleaseArtifactsError> {
+    s3.delete_object()
+        .bucket(bucket_name)
+        .key(journal_key)
+        .send()
+        .await?;
+    Ok(())
+}
+
+async fn read_journal_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    journal_key: &str,
+) -> Result<Option<SaveJournal>, ReleaseArtifactsError> {
+    let object = match s3
+        .get_object()
+        .bucket(bucket_name)
+        .key(journal_key)
+        .send()
+        .await
+    {
+        Ok(object) => object,
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(GetObjectError::is_no_such_key) =>
+        {
+            return Ok(None)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let content = object
+        .body
+        .collect()
+        .await
+        .map_err(ReleaseArtifactsError::ArchiveStreamError)?
+        .into_bytes();
+    serde_json::from_slice(&content).map(Some).map_err(|e| {
+        ReleaseArtifactsError::ArchiveError(
+            io::Error::other(e),
+            format!("reading save journal '{journal_key}'"),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use aws_config::BehaviorVersion;
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+
+    use super::{
+        abort_stale_uploads_with_client, journal_name, recover_upload_with_client, JournalPart,
+        RecoveredUpload, SaveJournal,
+    };
+    use crate::make_s3_test_credentials;
+
+    const BUCKET_URI: &str = "https://test-bucket.s3.us-east-1.amazonaws.com";
+
+    fn test_s3_client(replay_client: &StaticReplayClient) -> aws_sdk_s3::Client {
+        aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        )
+    }
+
+    fn replay_event(method: &str, uri: &str, status: u16, response_body: &str) -> ReplayEvent {
+        ReplayEvent::new(
+            http::Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(status)
+                .body(SdkBody::from(response_body))
+                .unwrap(),
+        )
+    }
+
+    fn journal_body(uploaded: bool) -> String {
+        serde_json::to_string(&SaveJournal {
+            bucket_key: "release-v102.tgz".to_string(),
+            upload_id: "TESTUPLOADID".to_string(),
+            parts: vec![JournalPart {
+                part_number: 1,
+                e_tag: Some("\"test-etag\"".to_string()),
+            }],
+            uploaded,
+        })
+        .unwrap()
+    }
+
+    fn request_lines(replay_client: &StaticReplayClient) -> Vec<String> {
+        replay_client
+            .actual_requests()
+            .map(|r| format!("{} {}", r.method(), r.uri()))
+            .collect()
+    }
+
+    #[test]
+    fn journal_name_is_beneath_archives() {
+        assert_eq!(
+            journal_name("release-v102.tgz"),
+            "release-phase-journal/release-v102.tgz.json"
+        );
+    }
+
+    #[tokio::test]
+    async fn recover_upload_aborts_partial_upload() {
+        let journal_uri = format!("{BUCKET_URI}/release-phase-journal/release-v102.tgz.json");
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event("GET", &journal_uri, 200, &journal_body(false)),
+            replay_event(
+                "DELETE",
+                &format!(
+                    "{BUCKET_URI}/release-v102.tgz?x-id=AbortMultipartUpload&uploadId=TESTUPLOADID"
+                ),
+                204,
+                "",
+            ),
+            replay_event(
+                "DELETE",
+                &format!("{journal_uri}?x-id=DeleteObject"),
+                204,
+                "",
+            ),
+        ]);
+        let s3 = test_s3_client(&replay_client);
+
+        let result = recover_upload_with_client(
+            &s3,
+            "test-bucket",
+            "release-phase-journal/release-v102.tgz.json",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            result,
+            Some(RecoveredUpload::Aborted("release-v102.tgz".to_string()))
+        );
+        let requests = request_lines(&replay_client);
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].contains("AbortMultipartUpload"));
+        assert!(requests[2].starts_with("DELETE") && requests[2].contains("journal"));
+    }
+
+    #[tokio::test]
+    async fn recover_upload_completes_uploaded_parts() {
+        let journal_uri = format!("{BUCKET_URI}/release-phase-journal/release-v102.tgz.json");
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event("GET", &journal_uri, 200, &journal_body(true)),
+            replay_event(
+                "POST",
+                &format!("{BUCKET_URI}/release-v102.tgz?uploadId=TESTUPLOADID"),
+                200,
+                "<CompleteMultipartUploadResult><Bucket>test-bucket</Bucket><Key>release-v102.tgz</Key><ETag>\"test-etag\"</ETag></CompleteMultipartUploadResult>",
+            ),
+            replay_event(
+                "DELETE",
+                &format!("{journal_uri}?x-id=DeleteObject"),
+                204,
+                "",
+            ),
+        ]);
+        let s3 = test_s3_client(&replay_client);
+
+        let result = recover_upload_with_client(
+            &s3,
+            "test-bucket",
+            "release-phase-journal/release-v102.tgz.json",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            result,
+            Some(RecoveredUpload::Completed("release-v102.tgz".to_string()))
+        );
+        let requests = request_lines(&replay_client);
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].starts_with("POST") && requests[1].contains("uploadId=TESTUPLOADID"));
+        let completion = replay_client
+            .actual_requests()
+            .nth(1)
+            .and_then(|r| {
+                r.body()
+                    .bytes()
+                    .map(|b| String::from_utf8_lossy(b).to_string())
+            })
+            .unwrap();
+        assert!(
+            completion.contains("<PartNumber>1</PartNumber>"),
+            "{completion}"
+        );
+    }
+
+    #[tokio::test]
+    async fn recover_upload_without_journal_does_nothing() {
+        let replay_client = StaticReplayClient::new(vec![replay_event(
+            "GET",
+            &format!("{BUCKET_URI}/release-phase-journal/release-v102.tgz.json"),
+            404,
+            "<Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message></Error>",
+        )]);
+        let s3 = test_s3_client(&replay_client);
+
+        let result = recover_upload_with_client(
+            &s3,
+            "test-bucket",
+            "release-phase-journal/release-v102.tgz.json",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result, None);
+        assert_eq!(replay_client.actual_requests().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn recover_upload_deletes_journal_of_finished_upload() {
+        let journal_uri = format!("{BUCKET_URI}/release-phase-journal/release-v102.tgz.json");
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event("GET", &journal_uri, 200, &journal_body(false)),
+            replay_event(
+                "DELETE",
+                &format!("{BUCKET_URI}/release-v102.tgz?x-id=AbortMultipartUpload&uploadId=TESTUPLOADID"),
+                404,
+                "<Error><Code>NoSuchUpload</Code><Message>The specified upload does not exist.</Message></Error>",
+            ),
+            replay_event(
+                "DELETE",
+                &format!("{journal_uri}?x-id=DeleteObject"),
+                204,
+                "",
+            ),
+        ]);
+        let s3 = test_s3_client(&replay_client);
+
+        let result = recover_upload_with_client(
+            &s3,
+            "test-bucket",
+            "release-phase-journal/release-v102.tgz.json",
+        )
+        .await;
+
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(replay_client.actual_requests().count(), 3);
+    }
+
+    #[tokio::test]
+    async fn abort_stale_uploads_skips_recent_journals() {
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event(
+                "GET",
+                &format!("{BUCKET_URI}/?list-type=2&prefix=sub%2Fpath%2Frelease-phase-journal%2F"),
+                200,
+                r"
+                <ListBucketResult>
+                    <IsTruncated>false</IsTruncated>
+                    <Contents>
+                        <Key>sub/path/release-phase-journal/release-v101.tgz.json</Key>
+                        <LastModified>2024-07-01T12:20:47.000Z</LastModified>
+                        <Size>100</Size>
+                    </Contents>
+                    <Contents>
+                        <Key>sub/path/release-phase-journal/release-v102.tgz.json</Key>
+                        <LastModified>2024-07-04T04:51:50.000Z</LastModified>
+                        <Size>100</Size>
+                    </Contents>
+                </ListBucketResult>",
+            ),
+            replay_event(
+                "GET",
+                &format!("{BUCKET_URI}/sub/path/release-phase-journal/release-v101.tgz.json"),
+                200,
+                &journal_body(false).replace("release-v102", "sub/path/release-v101"),
+            ),
+            replay_event(
+                "DELETE",
+                &format!("{BUCKET_URI}/sub/path/release-v101.tgz?x-id=AbortMultipartUpload&uploadId=TESTUPLOADID"),
+                204,
+                "",
+            ),
+            replay_event(
+                "DELETE",
+                &format!(
+                    "{BUCKET_URI}/sub/path/release-phase-journal/release-v101.tgz.json?x-id=DeleteObject"
+                ),
+                204,
+                "",
+            ),
+        ]);
+        let s3 = test_s3_client(&replay_client);
+        // Between the last modified times of the two journals.
+        let older_than = SystemTime::UNIX_EPOCH + Duration::from_secs(1_719_921_600);
+
+        let result = abort_stale_uploads_with_client(&s3, "test-bucket", "sub/path/", older_than)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result,
+            vec![RecoveredUpload::Aborted(
+                "sub/path/release-v101.tgz".to_string()
+            )]
+        );
+        let requests = request_lines(&replay_client);
+        assert_eq!(requests.len(), 4);
+        assert!(requests.iter().all(|r| !r.contains("release-v102")));
+    }
+}
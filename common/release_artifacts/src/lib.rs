@@ -1,34 +1,122 @@
+mod access_check;
+mod archive;
+mod checksum;
+mod compression;
+mod credentials;
 mod errors;
-
+mod extraction_report;
+#[cfg(feature = "fault-injection")]
+mod faults;
+mod filter;
+mod gc;
+mod journal;
+mod load_credentials;
+mod local_gc;
+mod markers;
+mod notify;
+mod permissions;
+mod pre_extract;
+mod provision;
+mod public_access;
+mod purge;
+mod retry;
+mod signature;
+mod single_flight;
+mod storage_setup;
+mod tags;
+mod tee_upload;
+mod usage;
+
+pub use access_check::{check_storage_access, Access, S3Action};
+pub use archive::{ArchivePathError, ConflictPolicy};
 use aws_smithy_types::DateTime;
+pub use compression::ArchiveCompression;
+pub use credentials::{select_credentials, CredentialScope};
 use errors::ReleaseArtifactsError;
-use flate2::{read::GzDecoder, Compression, GzBuilder};
+pub use extraction_report::{
+    extraction_report_path, ExtractedFile, ExtractionDiff, ExtractionReport,
+};
+use filter::ArtifactFilter;
+use flate2::Compression;
+pub use gc::{gc, GcReport, Retention};
+pub use journal::{abort_stale_uploads, RecoveredUpload};
+pub use load_credentials::apply_load_credentials;
+pub use markers::{has_completion_marker, put_completion_marker};
+pub use notify::{notify, Notifier, ReleaseNotification};
 use regex::Regex;
+pub use release_log::{self as log, log_debug, log_info};
+use serde::{Deserialize, Serialize};
+pub use signature::ArchiveVerifier;
 use std::{
     collections::HashMap,
     env,
     fs::{self, File},
+    future::Future,
     hash::BuildHasher,
-    io::{Read, Write},
+    io::{BufWriter, Read, Write},
     path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime},
 };
-use tar::Archive;
+pub use storage_setup::{storage_setup, SetupFormat};
+pub use tags::{pin, unpin};
+use tokio_util::io::SyncIoBridge;
+pub use usage::SaveReport;
 
 use aws_config::meta::region::RegionProviderChain;
-use aws_sdk_s3::{config::Credentials, config::Region, Client};
+use aws_sdk_s3::config::SharedHttpClient;
+use aws_sdk_s3::{
+    config::Credentials, config::Region, operation::head_object::HeadObjectError, Client,
+};
+use aws_smithy_http_client::tls;
 use url::Url;
 
+#[cfg(test)]
+use criterion as _;
 use tokio as _;
 use uuid::{self as _, Uuid};
 
+/// The dyno metadata file, such as `/etc/heroku/dyno.json`, of which only these fields are
+/// captured, each into the env var it names.
+#[derive(Deserialize, Debug, Default)]
+struct DynoMetadata {
+    // RELEASE_ID
+    release_id: Option<String>,
+    // HEROKU_APP_ID
+    app_id: Option<String>,
+    // DYNO
+    dyno_name: Option<String>,
+}
+
+/// Captures the env vars used by release artifacts from the process env, overridden by the
+/// dyno metadata in the directory: the fields of `dyno.json`, then the plain `release_id` file,
+/// which takes precedence for `RELEASE_ID`.
 #[must_use]
 pub fn capture_env(dyno_metadata_dir: &Path) -> HashMap<String, String> {
     let mut env = HashMap::new();
     for (key, value) in env::vars() {
-        if key.starts_with("STATIC_ARTIFACTS_") || key == "RELEASE_ID" {
+        if key.starts_with("STATIC_ARTIFACTS_")
+            || key == "RELEASE_ID"
+            || key == "HEROKU_APP_ID"
+            || key == "DYNO"
+        {
             env.insert(key, value);
         }
     }
+    // Override with values from the dyno metadata JSON, when present.
+    let dyno_metadata = read_dyno_metadata(&dyno_metadata_dir.join("dyno.json"));
+    for (key, value) in [
+        ("RELEASE_ID", dyno_metadata.release_id),
+        ("HEROKU_APP_ID", dyno_metadata.app_id),
+        ("DYNO", dyno_metadata.dyno_name),
+    ] {
+        if let Some(value) = value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+        {
+            env.insert(key.to_owned(), value);
+        }
+    }
     // Override RELEASE_ID with value from the dyno filesystem, when present.
     File::open(dyno_metadata_dir.join("release_id"))
         .map_or(None, |mut file| {
@@ -43,79 +131,481 @@ pub fn capture_env(dyno_metadata_dir: &Path) -> HashMap<String, String> {
     env
 }
 
+// A missing file is empty metadata, while an invalid one is warned about, so that a dyno still
+// starts with the env it was given.
+fn read_dyno_metadata(path: &Path) -> DynoMetadata {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return DynoMetadata::default();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|error| {
+        eprintln!(
+            "release-phase warning: ignoring dyno metadata {path:?}, which is invalid: {error}"
+        );
+        DynoMetadata::default()
+    })
+}
+
+/// Also stores the archive at `STATIC_ARTIFACTS_REPLICA_URL`, when set, purges the CDN
+/// configured by `STATIC_ARTIFACTS_CDN`, and deletes old archives according to the `Retention`
+/// from env, each on a best-effort basis. Archives are tagged with `STATIC_ARTIFACTS_STAGE`.
+/// Returns, and logs, the storage usage of the save, warning when the archive grew more than
+/// `STATIC_ARTIFACTS_GROWTH_WARNING_PERCENT` since the previous release's. Saves again with the
+/// secondary credentials, when set, if storage rejects the primary ones.
 pub async fn save<S: BuildHasher>(
     env: &HashMap<String, String, S>,
     dir: &Path,
+) -> Result<SaveReport, ReleaseArtifactsError> {
+    with_storage_clients(credentials::with_secondary_fallback(
+        env,
+        |env| async move { save_with(&env, dir).await },
+    ))
+    .await
+}
+
+async fn save_with<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    dir: &Path,
+) -> Result<SaveReport, ReleaseArtifactsError> {
+    let retention = Retention::from_env(env)?;
+    let growth_warning_percent = usage::growth_warning_percent(env)?;
+    let started = Instant::now();
+    let archive_path = save_to_storage(env, dir).await?;
+    let mut report = SaveReport::new(
+        fs::metadata(&archive_path).map_or(0, |m| m.len()),
+        usage::source_size(dir),
+        started.elapsed(),
+    );
+    tags::tag_stored_archive(env).await?;
+    load_credentials::write_load_credentials(env).await?;
+    if let Some(replica_url) = env
+        .get("STATIC_ARTIFACTS_REPLICA_URL")
+        .filter(|url| Some(url.as_str()) != storage_urls(env).first().copied())
+    {
+        let mut replica_env: HashMap<String, String> =
+            env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        replica_env.insert("STATIC_ARTIFACTS_URL".to_string(), replica_url.clone());
+        let stored = match store_archive(&replica_env, &archive_path).await {
+            Ok(()) => tags::tag_stored_archive(&replica_env).await,
+            Err(error) => Err(error),
+        };
+        match stored {
+            Ok(()) => log_info!("save-release-artifacts replicated archive to '{replica_url}'"),
+            Err(error) => eprintln!(
+                "save-release-artifacts failed to replicate archive to '{replica_url}', continuing: {error:?}"
+            ),
+        }
+    }
+    let key_prefix = match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"s3" => generate_s3_storage_prefix(env)?.2,
+        _ => String::new(),
+    };
+    match purge::purge_cdn(env, &key_prefix).await {
+        Ok(true) => log_info!("save-release-artifacts purged CDN"),
+        Ok(false) => {}
+        Err(error) => {
+            eprintln!("save-release-artifacts failed to purge CDN, continuing: {error:?}");
+        }
+    }
+    // Listed before garbage collection, which may delete the previous archive.
+    let stored_archives = match list_stored_archives(env).await {
+        Ok(archives) => {
+            let saved_name = archive_path.file_name().unwrap_or_default();
+            report.previous_archive_size =
+                usage::previous_archive_size(&archives, &saved_name.to_string_lossy());
+            Some(archives.len())
+        }
+        Err(error) => {
+            log_debug!("save-release-artifacts failed to list stored archives: {error:?}");
+            None
+        }
+    };
+    let mut deleted = 0;
+    if let Some(retention) = retention {
+        match gc(env, &retention).await {
+            Ok(gc_report) => deleted = gc_report.deleted.len(),
+            Err(error) => eprintln!(
+                "save-release-artifacts failed to delete old archives, continuing: {error:?}"
+            ),
+        }
+    }
+    report.stored_archives = stored_archives.map(|count| count.saturating_sub(deleted));
+    log_info!("save-release-artifacts {report}");
+    if let Some(warning) = growth_warning_percent.and_then(|p| report.growth_warning(p)) {
+        eprintln!("release-phase warning: {warning}");
+    }
+    Ok(report)
+}
+
+// Returns the path of the archive, which remains on the local filesystem.
+async fn save_to_storage<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    dir: &Path,
+) -> Result<PathBuf, ReleaseArtifactsError> {
+    let filter = ArtifactFilter::from_env(env)?;
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"file" => {
+            guard_file(env)?;
+            let archive_name = generate_archive_name::<S>(env)?;
+            log_info!("save-release-artifacts writing archive: {archive_name}");
+            let destination_path = generate_file_storage_location(env, &archive_name)?;
+            create_filtered_archive(dir, &destination_path, &filter)?;
+            checksum::write_checksum_file(
+                &destination_path,
+                &checksum::file_checksum(&destination_path)?,
+            )?;
+            let signature = signature::sign_archive(env, &destination_path).await?;
+            signature::write_signature_file(&destination_path, signature.as_deref())?;
+            Ok(destination_path)
+        }
+        Ok(scheme) if scheme == *"s3" => {
+            guard_s3(env)?;
+            let archive_name = generate_archive_name::<S>(env)?;
+            log_info!("save-release-artifacts uploading archive: {archive_name}");
+            let (bucket_name, bucket_region, bucket_key) =
+                generate_s3_storage_location(env, &archive_name)?;
+            log_debug!("save-release-artifacts bucket '{bucket_name}', region {bucket_region:?}, key '{bucket_key}'");
+            let s3 = generate_s3_client(env, bucket_region.clone()).await;
+            provision::create_bucket_if_missing(
+                env,
+                &s3,
+                &bucket_name,
+                bucket_region.as_deref(),
+                &generate_s3_storage_prefix(env)?.2,
+            )
+            .await?;
+            public_access::warn_if_public(env, &s3, &bucket_name).await;
+            let (_, _, journal_key) =
+                generate_s3_storage_location(env, &journal::journal_name(&archive_name))?;
+            match journal::recover_upload_with_client(&s3, &bucket_name, &journal_key).await {
+                Ok(Some(recovered)) => {
+                    log_info!("save-release-artifacts recovered interrupted upload: {recovered:?}");
+                }
+                Ok(None) => {}
+                Err(error) => eprintln!(
+                    "save-release-artifacts failed to recover interrupted upload, continuing: {error:?}"
+                ),
+            }
+            if signature::signs_archives(env) {
+                // The signature is metadata sent when the upload starts, so it must be computed
+                // from the complete archive.
+                create_filtered_archive(dir, Path::new(archive_name.as_str()), &filter)?;
+                let signature =
+                    signature::sign_archive(env, Path::new(archive_name.as_str())).await?;
+                put_archive_with_client(
+                    &s3,
+                    &bucket_name,
+                    &bucket_key,
+                    &archive_name,
+                    signature.as_deref(),
+                )
+                .await?;
+            } else {
+                let uploaded = tee_upload::archive_and_upload_with_client(
+                    &s3,
+                    &bucket_name,
+                    &bucket_key,
+                    Some(&journal_key),
+                    dir,
+                    Path::new(archive_name.as_str()),
+                    &filter,
+                )
+                .await?;
+                if !uploaded.checksum_stored {
+                    if let Err(error) = checksum::put_checksum_metadata_with_client(
+                        &s3,
+                        &bucket_name,
+                        &bucket_key,
+                        &uploaded.checksum,
+                    )
+                    .await
+                    {
+                        eprintln!(
+                            "release-phase warning: archive saved without its checksum, so loading cannot verify it: {error:?}"
+                        );
+                    }
+                }
+            }
+            Ok(PathBuf::from(archive_name))
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+// Stores an archive that was already created, such as for a replica.
+async fn store_archive<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    archive_path: &Path,
 ) -> Result<(), ReleaseArtifactsError> {
+    let archive_name = generate_archive_name::<S>(env)?;
+    let signature = signature::sign_archive(env, archive_path).await?;
     match detect_storage_scheme(env) {
         Ok(scheme) if scheme == *"file" => {
             guard_file(env)?;
-            let archive_name = generate_archive_name::<S>(env);
-            eprintln!("save-release-artifacts writing archive: {archive_name}");
             let destination_path = generate_file_storage_location(env, &archive_name)?;
-            create_archive(dir, &destination_path)?;
-            Ok(())
+            fs::copy(archive_path, &destination_path).map_err(|e| {
+                ReleaseArtifactsError::ArchiveError(
+                    e,
+                    format!("copying archive {archive_path:?} to {destination_path:?}"),
+                )
+            })?;
+            checksum::write_checksum_file(
+                &destination_path,
+                &checksum::file_checksum(archive_path)?,
+            )?;
+            signature::write_signature_file(&destination_path, signature.as_deref())
         }
         Ok(scheme) if scheme == *"s3" => {
             guard_s3(env)?;
-            let archive_name = generate_archive_name::<S>(env);
-            eprintln!("save-release-artifacts uploading archive: {archive_name}");
-            create_archive(dir, Path::new(archive_name.as_str()))?;
             let (bucket_name, bucket_region, bucket_key) =
                 generate_s3_storage_location(env, &archive_name)?;
             let s3 = generate_s3_client(env, bucket_region).await;
-            upload_with_client(&s3, &bucket_name, &bucket_key, &archive_name).await
+            public_access::warn_if_public(env, &s3, &bucket_name).await;
+            put_archive_with_client(
+                &s3,
+                &bucket_name,
+                &bucket_key,
+                &archive_path.to_string_lossy().to_string(),
+                signature.as_deref(),
+            )
+            .await
         }
         Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
         Err(e) => Err(e),
     }
 }
 
+/// Loads from each of the comma-separated `STATIC_ARTIFACTS_URL`s in order, such as a fallback
+/// bucket in another region, until one succeeds, with the secondary credentials, when set, if
+/// storage rejects the primary ones. Then applies `STATIC_ARTIFACTS_CHMOD`, if set, to the
+/// extracted directory, and writes its extraction report. The partial local copies left beside the
+/// dir by interrupted loads are removed first, by the load holding the dir's lock.
+///
+/// When other processes load into the same dir at the same time, only one of them does, and the
+/// others wait for its loaded key.
 pub async fn load<S: BuildHasher>(
     env: &HashMap<String, String, S>,
     dir: &Path,
+) -> Result<String, ReleaseArtifactsError> {
+    let chmod = env
+        .get("STATIC_ARTIFACTS_CHMOD")
+        .filter(|spec| !spec.trim().is_empty())
+        .map(|spec| permissions::ChmodSpec::parse(spec))
+        .transpose()?;
+    with_storage_clients(single_flight::load_once(env, dir, |holds_lock| {
+        load_into_dir(env, dir, chmod, holds_lock)
+    }))
+    .await
+}
+
+async fn load_into_dir<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    dir: &Path,
+    chmod: Option<permissions::ChmodSpec>,
+    holds_lock: bool,
+) -> Result<String, ReleaseArtifactsError> {
+    // Without the lock, another load of the dir may still be writing its copies.
+    if holds_lock {
+        match local_gc::prune_local_copies(dir) {
+            Ok(removed) => {
+                for path in removed {
+                    log_info!("load-release-artifacts removed stale local copy {path:?}");
+                }
+            }
+            Err(error) => eprintln!(
+                "release-phase warning: failed to prune local copies of artifacts for {dir:?}: {error:?}"
+            ),
+        }
+    }
+    let loaded_key = load_from_any_storage(env, dir).await?;
+    if let Some(chmod) = chmod {
+        if dir.exists() {
+            log_info!("load-release-artifacts applying STATIC_ARTIFACTS_CHMOD to {dir:?}");
+            chmod.apply(dir).map_err(|e| {
+                ReleaseArtifactsError::ArchiveError(e, format!("during load chmod({dir:?})"))
+            })?;
+        }
+    }
+    if let Some(report_path) = extraction_report_path(env, dir).filter(|_| dir.is_dir()) {
+        match ExtractionReport::of_dir(dir, &loaded_key).and_then(|r| r.write(&report_path)) {
+            Ok(()) => log_info!("load-release-artifacts wrote extraction report {report_path:?}"),
+            Err(error) => eprintln!(
+                "release-phase warning: failed to write extraction report {report_path:?}: {error:?}"
+            ),
+        }
+    }
+    Ok(loaded_key)
+}
+
+async fn load_from_any_storage<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    dir: &Path,
+) -> Result<String, ReleaseArtifactsError> {
+    let urls = storage_urls(env);
+    if urls.len() < 2 {
+        return credentials::with_secondary_fallback(env, |env| async move {
+            load_from_storage(&env, dir).await
+        })
+        .await;
+    }
+    let mut last_error = None;
+    for url in urls {
+        let mut storage_env: HashMap<String, String> =
+            env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        storage_env.insert("STATIC_ARTIFACTS_URL".to_string(), url.to_string());
+        let loaded = credentials::with_secondary_fallback(&storage_env, |env| async move {
+            load_from_storage(&env, dir).await
+        })
+        .await;
+        match loaded {
+            Ok(loaded_key) => return Ok(loaded_key),
+            Err(error) => {
+                log_info!("load-release-artifacts failed to load from '{url}', trying the next storage URL: {error:?}");
+                last_error = Some(error);
+            }
+        }
+    }
+    Err(last_error.expect("should have tried at least two storage URLs"))
+}
+
+async fn load_from_storage<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    dir: &Path,
 ) -> Result<String, ReleaseArtifactsError> {
     match detect_storage_scheme(env) {
         Ok(scheme) if scheme == *"file" => {
             guard_file(env)?;
-            let archive_name = generate_archive_name::<S>(env);
-            eprintln!("load-release-artifacts reading archive: {archive_name}");
+            let archive_name = generate_archive_name::<S>(env)?;
+            log_info!("load-release-artifacts reading archive: {archive_name}");
             // This file scheme does not currently find latest if the specific release ID is missing.
             let source_path = generate_file_storage_location(env, &archive_name)?;
-            extract_archive(&source_path, dir)?;
-            Ok(archive_name.to_string())
+            checksum::verify_checksum_file(&source_path)?;
+            ArchiveVerifier::from_env(env)?.verify(
+                &source_path,
+                signature::read_signature_file(&source_path).as_deref(),
+            )?;
+            extract_archive_with_policy(&source_path, dir, ConflictPolicy::from_env(env)?)?;
+            Ok(archive_name)
         }
         Ok(scheme) if scheme == *"s3" => {
             guard_s3(env)?;
-            let archive_name = generate_archive_name::<S>(env);
-            eprintln!("load-release-artifacts downloading archive: {archive_name}");
+            let verifier = ArchiveVerifier::from_env(env)?;
+            let on_conflict = ConflictPolicy::from_env(env)?;
+            let archive_name = generate_archive_name::<S>(env)?;
+            log_info!("load-release-artifacts downloading archive: {archive_name}");
             let (bucket_name, bucket_region, bucket_key) =
                 generate_s3_storage_location(env, &archive_name)?;
+            log_debug!("load-release-artifacts bucket '{bucket_name}', region {bucket_region:?}, key '{bucket_key}'");
             let s3 = generate_s3_client(env, bucket_region).await;
-            download_specific_or_latest_with_client(&s3, &bucket_name, &bucket_key, dir).await
+            // Use the archive prefetched during build, if it is still the one to load.
+            if let (Some(prefetched_key), Some(prefetched_archive)) = (
+                env.get("STATIC_ARTIFACTS_PREFETCHED_KEY"),
+                env.get("STATIC_ARTIFACTS_PREFETCHED_ARCHIVE"),
+            ) {
+                if let Some(loaded_key) = load_prefetched_with_client(
+                    &s3,
+                    &bucket_name,
+                    &bucket_key,
+                    prefetched_key,
+                    Path::new(prefetched_archive),
+                    dir,
+                    &verifier,
+                    on_conflict,
+                )
+                .await?
+                {
+                    return Ok(loaded_key);
+                }
+            }
+            download_specific_or_latest_with_client(
+                &s3,
+                &bucket_name,
+                &bucket_key,
+                dir,
+                &verifier,
+                on_conflict,
+            )
+            .await
         }
         Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
         Err(e) => Err(e),
     }
 }
 
+// Extracts the prefetched archive when its key is still the one to load, returning that key,
+// or `None` when the archive to load has changed since it was prefetched.
+#[allow(clippy::too_many_arguments)]
+async fn load_prefetched_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key: &String,
+    prefetched_key: &String,
+    prefetched_archive: &Path,
+    dir: &Path,
+    verifier: &ArchiveVerifier,
+    on_conflict: ConflictPolicy,
+) -> Result<Option<String>, ReleaseArtifactsError> {
+    let current_key =
+        resolve_specific_or_latest_key_with_client(s3, bucket_name, bucket_key).await?;
+    if current_key.as_ref() != Some(prefetched_key) {
+        return Ok(None);
+    }
+    log_info!("load-release-artifacts using prefetched archive: {prefetched_key}");
+    checksum::verify_checksum_file(prefetched_archive)?;
+    verifier.verify(
+        prefetched_archive,
+        signature::read_signature_file(prefetched_archive).as_deref(),
+    )?;
+    extract_archive_with_policy(prefetched_archive, dir, on_conflict)?;
+    Ok(Some(prefetched_key.clone()))
+}
+
 pub async fn upload_with_client(
     s3: &aws_sdk_s3::Client,
     bucket_name: &String,
     bucket_key: &String,
     archive_name: &String,
 ) -> Result<(), ReleaseArtifactsError> {
+    put_archive_with_client(s3, bucket_name, bucket_key, archive_name, None).await
+}
+
+// Keyless signatures are put beside the archive, before it, as they exceed the size of metadata.
+async fn put_archive_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key: &String,
+    archive_name: &String,
+    signature: Option<&str>,
+) -> Result<(), ReleaseArtifactsError> {
+    let checksum = checksum::file_checksum(Path::new(&archive_name))?;
     let archive_data =
         aws_sdk_s3::primitives::ByteStream::from_path(std::path::Path::new(&archive_name))
             .await
             .map_err(ReleaseArtifactsError::ArchiveStreamError)?;
-    s3.put_object()
+    let mut request = s3
+        .put_object()
         .bucket(bucket_name)
         .key(bucket_key)
-        .body(archive_data)
-        .send()
-        .await
-        .map_err(ReleaseArtifactsError::from)?;
+        .metadata(checksum::CHECKSUM_METADATA_KEY, checksum)
+        .body(archive_data);
+    if let Some(signature) = signature {
+        let metadata = if signature::is_keyless(signature) {
+            s3.put_object()
+                .bucket(bucket_name)
+                .key(signature::signature_key(bucket_key))
+                .body(aws_sdk_s3::primitives::ByteStream::from(
+                    signature.as_bytes().to_vec(),
+                ))
+                .send()
+                .await
+                .map_err(ReleaseArtifactsError::from)?;
+            signature::KEYLESS_SIGNATURE_METADATA
+        } else {
+            signature
+        };
+        request = request.metadata(signature::SIGNATURE_METADATA_KEY, metadata);
+    }
+    request.send().await.map_err(ReleaseArtifactsError::from)?;
     Ok(())
 }
 
@@ -124,30 +614,39 @@ pub async fn download_specific_or_latest_with_client(
     bucket_name: &String,
     bucket_key: &String,
     destination_dir: &Path,
+    verifier: &ArchiveVerifier,
+    on_conflict: ConflictPolicy,
 ) -> Result<String, ReleaseArtifactsError> {
-    match download_with_client(s3, bucket_name, bucket_key, destination_dir).await {
+    match download_with_client(
+        s3,
+        bucket_name,
+        bucket_key,
+        destination_dir,
+        verifier,
+        on_conflict,
+    )
+    .await
+    {
         Ok(()) => Ok(bucket_key.clone()),
         Err(e) => match e {
             ReleaseArtifactsError::StorageKeyNotFound(_) => {
-                eprintln!("load-release-artifacts specific artifact not found '{bucket_key}', instead getting latest artifact");
-                let key_parts = bucket_key.split('/');
-                let key_prefix_size = key_parts.clone().count() - 1;
-                let key_prefix_parts: Vec<&str> = key_parts.clone().take(key_prefix_size).collect();
-                let key_prefix = if key_prefix_parts.is_empty() {
-                    String::new()
-                } else {
-                    key_prefix_parts.join("/") + "/"
-                };
-                let latest_result = find_latest_with_client(s3, bucket_name, &key_prefix)
-                    .await
-                    .map_err(ReleaseArtifactsError::from)?;
+                log_info!("load-release-artifacts specific artifact not found '{bucket_key}', instead getting latest artifact");
+                let key_prefix = parent_key_prefix(bucket_key);
+                let latest_result = find_latest_with_client(s3, bucket_name, &key_prefix).await?;
                 match latest_result {
                     Some(latest_bucket_key) => {
-                        eprintln!(
+                        log_info!(
                             "load-release-artifacts getting latest artifact '{latest_bucket_key}'"
                         );
-                        download_with_client(s3, bucket_name, &latest_bucket_key, destination_dir)
-                            .await?;
+                        download_with_client(
+                            s3,
+                            bucket_name,
+                            &latest_bucket_key,
+                            destination_dir,
+                            verifier,
+                            on_conflict,
+                        )
+                        .await?;
                         Ok(latest_bucket_key.clone())
                     }
                     None => Err(ReleaseArtifactsError::StorageKeyNotFound(format!(
@@ -165,30 +664,250 @@ pub async fn download_with_client(
     bucket_name: &String,
     bucket_key: &String,
     destination_dir: &Path,
+    verifier: &ArchiveVerifier,
+    on_conflict: ConflictPolicy,
 ) -> Result<(), ReleaseArtifactsError> {
-    let mut output = s3
+    // Signatures and the pre-extract command verify the whole archive before it is extracted, so
+    // it is first downloaded to a file.
+    if !verifier.needs_archive_file() {
+        if let Some(staging_dir) = streaming_staging_dir(destination_dir) {
+            return retry::retry_interrupted(s3, || {
+                stream_extract_with_client(
+                    s3,
+                    bucket_name,
+                    bucket_key,
+                    destination_dir,
+                    &staging_dir,
+                    on_conflict,
+                )
+            })
+            .await;
+        }
+    }
+    let temp_archive_path = local_gc::local_copy_path(destination_dir, ".tgz")
+        .unwrap_or_else(|| PathBuf::from(format!("static-artifacts-temp--{}", Uuid::new_v4())));
+    let temp_archive_path = temp_archive_path.as_path();
+    if let Some(parent) = temp_archive_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                format!("during download_with_client fs::create_dir_all({parent:?})"),
+            )
+        })?;
+    }
+
+    let downloaded =
+        match get_archive_with_client(s3, bucket_name, bucket_key, temp_archive_path).await {
+            Ok(downloaded) => downloaded,
+            Err(e) => {
+                // A partially received archive is never used.
+                fs::remove_file(temp_archive_path).unwrap_or_default();
+                return Err(e);
+            }
+        };
+    log_info!(
+        "load-release-artifacts received {}-bytes",
+        downloaded.byte_count
+    );
+
+    if let Err(e) = verifier.verify(temp_archive_path, downloaded.signature.as_deref()) {
+        fs::remove_file(temp_archive_path).unwrap_or_default();
+        return Err(e);
+    }
+    let extracted = extract_archive_with_policy(temp_archive_path, destination_dir, on_conflict);
+    if extracted.is_err() {
+        fs::remove_file(temp_archive_path).unwrap_or_default();
+    }
+    extracted?;
+    fs::remove_file(temp_archive_path).map_err(|e| {
+        ReleaseArtifactsError::ArchiveError(
+            e,
+            format!("during download_with_client fs::remove_file({temp_archive_path:?})"),
+        )
+    })?;
+
+    Ok(())
+}
+
+// The dir beside the destination to extract into as the archive is received, when the
+// destination is a new dir, which the staging dir can be renamed to.
+fn streaming_staging_dir(destination_dir: &Path) -> Option<PathBuf> {
+    if destination_dir.exists() {
+        return None;
+    }
+    local_gc::local_copy_path(destination_dir, "")
+}
+
+// Extracts the archive as it is received, rather than after it is downloaded, into the staging
+// dir, which is moved into place once the whole archive matches its checksum, so that a
+// truncated or corrupted download never leaves partial artifacts behind.
+async fn stream_extract_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key: &String,
+    destination_dir: &Path,
+    staging_dir: &Path,
+    on_conflict: ConflictPolicy,
+) -> Result<(), ReleaseArtifactsError> {
+    let output = s3
         .get_object()
         .bucket(bucket_name)
         .key(bucket_key)
         .send()
         .await
         .map_err(ReleaseArtifactsError::from)?;
+    let expected_checksum = output
+        .metadata()
+        .and_then(|m| m.get(checksum::CHECKSUM_METADATA_KEY))
+        .cloned();
+    let mut reader =
+        checksum::ChecksumReader::new(SyncIoBridge::new(output.body.into_async_read()));
+    let unpack_dir = staging_dir.to_path_buf();
+    let unpacked = tokio::task::spawn_blocking(move || {
+        // The staging dir is new, so conflicts are only with a single-file artifact's original
+        // path, when it is moved into place.
+        let file_artifact = archive::unpack(
+            &mut reader,
+            &unpack_dir,
+            &unpack_dir,
+            ConflictPolicy::Overwrite,
+        )?;
+        // The archive may end before the compressed stream, such as with padding.
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        Ok::<_, std::io::Error>((file_artifact, reader.finish()))
+    })
+    .await
+    .map_err(|e| {
+        ReleaseArtifactsError::ArchiveError(
+            std::io::Error::other(e),
+            "during download_with_client spawn_blocking".to_string(),
+        )
+    })?;
+    let result = unpacked
+        .map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                format!("during download_with_client archive::unpack({staging_dir:?})"),
+            )
+        })
+        .and_then(|(file_artifact, (byte_count, checksum))| {
+            log_info!("load-release-artifacts received {byte_count}-bytes");
+            checksum::verify_checksum(bucket_key, expected_checksum.as_deref(), &checksum)?;
+            move_staged_artifacts(staging_dir, destination_dir, file_artifact, on_conflict)
+        });
+    if result.is_err() {
+        fs::remove_dir_all(staging_dir).unwrap_or_default();
+    }
+    result
+}
 
-    let unique = Uuid::new_v4();
-    let temp_archive_name = format!(
-        "static-artifacts-temp--{}--{}",
-        bucket_key.replace('/', "-"),
-        unique
-    );
-    let temp_archive_path = Path::new(&temp_archive_name);
+// Moves the artifacts extracted into the staging dir to the destination, or a single-file
+// artifact to its original path, relative to the working directory, unless a file is already
+// there and the conflict policy keeps it.
+fn move_staged_artifacts(
+    staging_dir: &Path,
+    destination_dir: &Path,
+    file_artifact: Option<PathBuf>,
+    on_conflict: ConflictPolicy,
+) -> Result<(), ReleaseArtifactsError> {
+    let move_error = |e| {
+        ReleaseArtifactsError::ArchiveError(
+            e,
+            format!("during download_with_client moving {staging_dir:?} into place"),
+        )
+    };
+    let Some(staged_path) = file_artifact else {
+        return fs::rename(staging_dir, destination_dir).map_err(move_error);
+    };
+    let path = staged_path
+        .strip_prefix(staging_dir)
+        .unwrap_or(&staged_path)
+        .to_path_buf();
+    if fs::symlink_metadata(&path).is_ok() {
+        match on_conflict {
+            ConflictPolicy::Overwrite => {}
+            ConflictPolicy::KeepExisting => {
+                fs::remove_dir_all(staging_dir).map_err(move_error)?;
+                log_info!("load-release-artifacts kept existing file: {path:?}");
+                return Ok(());
+            }
+            ConflictPolicy::Fail => {
+                return Err(move_error(ArchivePathError::Exists(path).into()));
+            }
+        }
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(move_error)?;
+    }
+    // The working directory may be on another filesystem than the staging dir.
+    fs::rename(&staged_path, &path)
+        .or_else(|_| fs::copy(&staged_path, &path).map(|_| ()))
+        .map_err(move_error)?;
+    fs::remove_dir_all(staging_dir).map_err(move_error)?;
+    log_info!("load-release-artifacts restored single file: {path:?}");
+    Ok(())
+}
+
+/// Downloads an archive to the given path, without extracting it, returning its size.
+pub async fn download_archive_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key: &String,
+    archive_path: &Path,
+) -> Result<usize, ReleaseArtifactsError> {
+    get_archive_with_client(s3, bucket_name, bucket_key, archive_path)
+        .await
+        .map(|downloaded| downloaded.byte_count)
+}
+
+// An archive downloaded by `get_archive_with_client`.
+struct DownloadedArchive {
+    byte_count: usize,
+    // From the archive's metadata.
+    signature: Option<String>,
+    checksum: String,
+}
+
+// Downloads the archive again when its body is interrupted.
+async fn get_archive_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key: &String,
+    archive_path: &Path,
+) -> Result<DownloadedArchive, ReleaseArtifactsError> {
+    retry::retry_interrupted(s3, || {
+        get_archive_once_with_client(s3, bucket_name, bucket_key, archive_path)
+    })
+    .await
+}
+
+// Verifies the archive against the checksum in its metadata, if any, as it is received, deleting
+// it when it does not match.
+async fn get_archive_once_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key: &String,
+    archive_path: &Path,
+) -> Result<DownloadedArchive, ReleaseArtifactsError> {
+    let mut output = s3
+        .get_object()
+        .bucket(bucket_name)
+        .key(bucket_key)
+        .send()
+        .await
+        .map_err(ReleaseArtifactsError::from)?;
 
-    let mut archive = File::create(temp_archive_path).map_err(|e| {
+    let mut archive = File::create(archive_path).map_err(|e| {
         ReleaseArtifactsError::ArchiveError(
             e,
-            format!("during download_with_client File::create({temp_archive_path:?})"),
+            format!("during download_archive_with_client File::create({archive_path:?})"),
         )
     })?;
 
+    let mut checksum = checksum::Checksum::new();
     let mut byte_count = 0_usize;
     while let Some(bytes) = output
         .body
@@ -200,64 +919,545 @@ pub async fn download_with_client(
         archive.write_all(&bytes).map_err(|e| {
             ReleaseArtifactsError::ArchiveError(
                 e,
-                "during download_with_client archive.write_all".to_string(),
+                "during download_archive_with_client archive.write_all".to_string(),
             )
         })?;
+        checksum.update(&bytes);
         byte_count += bytes_len;
     }
-    eprintln!("load-release-artifacts received {byte_count}-bytes");
-
-    extract_archive(temp_archive_path, destination_dir)?;
-    fs::remove_file(temp_archive_path).map_err(|e| {
-        ReleaseArtifactsError::ArchiveError(
-            e,
-            format!("during download_with_client fs::remove_file({temp_archive_path:?})"),
-        )
-    })?;
-
-    Ok(())
+    let metadata = |key: &str| output.metadata().and_then(|m| m.get(key)).cloned();
+    let checksum = checksum.finish();
+    if let Err(error) = checksum::verify_checksum(
+        bucket_key,
+        metadata(checksum::CHECKSUM_METADATA_KEY).as_deref(),
+        &checksum,
+    ) {
+        fs::remove_file(archive_path).unwrap_or_default();
+        return Err(error);
+    }
+    let signature = match metadata(signature::SIGNATURE_METADATA_KEY) {
+        Some(signature) if signature == signature::KEYLESS_SIGNATURE_METADATA => {
+            Some(get_signature_with_client(s3, bucket_name, bucket_key).await?)
+        }
+        signature => signature,
+    };
+    Ok(DownloadedArchive {
+        byte_count,
+        signature,
+        checksum,
+    })
 }
 
-pub async fn find_latest_with_client(
+// The keyless signature stored beside the archive.
+async fn get_signature_with_client(
     s3: &aws_sdk_s3::Client,
     bucket_name: &String,
-    bucket_key_prefix: &String,
-) -> Result<Option<String>, ReleaseArtifactsError> {
+    bucket_key: &str,
+) -> Result<String, ReleaseArtifactsError> {
     let output = s3
-        .list_objects_v2()
+        .get_object()
         .bucket(bucket_name)
-        .prefix(bucket_key_prefix)
+        .key(signature::signature_key(bucket_key))
         .send()
         .await
         .map_err(ReleaseArtifactsError::from)?;
-    let latest_key = output.contents.and_then(|mut c| {
-        if c.is_empty() {
-            return None;
+    let bytes = output
+        .body
+        .collect()
+        .await
+        .map_err(ReleaseArtifactsError::ArchiveStreamError)?
+        .into_bytes();
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+/// Resolves the key that loading would download: the specific key when it exists, otherwise
+/// the latest archive alongside it.
+pub async fn resolve_specific_or_latest_key_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key: &String,
+) -> Result<Option<String>, ReleaseArtifactsError> {
+    match s3
+        .head_object()
+        .bucket(bucket_name)
+        .key(bucket_key)
+        .send()
+        .await
+    {
+        Ok(_) => Ok(Some(bucket_key.clone())),
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(HeadObjectError::is_not_found) =>
+        {
+            find_latest_with_client(s3, bucket_name, &parent_key_prefix(bucket_key)).await
         }
-        c.sort_by_key(|k| {
-            k.last_modified()
-                .map_or_else(|| DateTime::from_secs(0), std::borrow::ToOwned::to_owned)
-        });
-        c.last()
-            .expect("should have at least one sorted object")
-            .key()
-            .map(std::string::ToString::to_string)
-    });
-    Ok(latest_key)
+        Err(e) => Err(ReleaseArtifactsError::from(e)),
+    }
 }
 
-fn detect_storage_scheme<S: BuildHasher>(
+/// Downloads the latest stored archive, without extracting it, such as during build, so that
+/// loading it when first booting skips the download. Returns the key of the prefetched archive,
+/// or `None` when nothing is stored yet.
+pub async fn prefetch<S: BuildHasher>(
     env: &HashMap<String, String, S>,
-) -> Result<String, ReleaseArtifactsError> {
-    match env.get("STATIC_ARTIFACTS_URL") {
-        Some(url) => {
-            let result = Url::parse(url).map_err(ReleaseArtifactsError::StorageURLInvalid)?;
-            Ok(result.scheme().to_string())
+    archive_path: &Path,
+    cached_key: Option<&str>,
+) -> Result<Option<String>, ReleaseArtifactsError> {
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"s3" => {
+            guard_s3_credentials(env)?;
+            let (bucket_name, bucket_region, bucket_key_prefix) = generate_s3_storage_prefix(env)?;
+            let s3 = generate_s3_client(env, bucket_region).await;
+            prefetch_with_client(
+                &s3,
+                &bucket_name,
+                &bucket_key_prefix,
+                archive_path,
+                cached_key,
+            )
+            .await
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+/// The download is skipped when `cached_key` is still the latest and its archive is present.
+pub async fn prefetch_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key_prefix: &String,
+    archive_path: &Path,
+    cached_key: Option<&str>,
+) -> Result<Option<String>, ReleaseArtifactsError> {
+    let Some(latest_key) = find_latest_with_client(s3, bucket_name, bucket_key_prefix).await?
+    else {
+        return Ok(None);
+    };
+    if cached_key == Some(latest_key.as_str()) && archive_path.is_file() {
+        return Ok(Some(latest_key));
+    }
+    let downloaded = get_archive_with_client(s3, bucket_name, &latest_key, archive_path).await?;
+    checksum::write_checksum_file(archive_path, &downloaded.checksum)?;
+    signature::write_signature_file(archive_path, downloaded.signature.as_deref())?;
+    Ok(Some(latest_key))
+}
+
+// The prefix of the key's "directory", including its trailing slash.
+fn parent_key_prefix(bucket_key: &str) -> String {
+    bucket_key
+        .rsplit_once('/')
+        .map_or_else(String::new, |(prefix, _)| format!("{prefix}/"))
+}
+
+pub async fn find_latest_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key_prefix: &String,
+) -> Result<Option<String>, ReleaseArtifactsError> {
+    let output = s3
+        .list_objects_v2()
+        .bucket(bucket_name)
+        .prefix(bucket_key_prefix)
+        .send()
+        .await
+        .map_err(ReleaseArtifactsError::from)?;
+    let latest_key = output.contents.and_then(|mut c| {
+        // Archives of artifact channels are nested under their own prefix, and keyless
+        // signatures are beside their archives.
+        c.retain(|o| {
+            is_direct_child_key(o.key(), bucket_key_prefix)
+                && !o.key().is_some_and(|key| key.ends_with(".sig"))
+        });
+        if c.is_empty() {
+            return None;
+        }
+        c.sort_by_key(|k| {
+            k.last_modified()
+                .map_or_else(|| DateTime::from_secs(0), std::borrow::ToOwned::to_owned)
+        });
+        c.last()
+            .expect("should have at least one sorted object")
+            .key()
+            .map(std::string::ToString::to_string)
+    });
+    Ok(latest_key)
+}
+
+/// Scopes artifact storage to a key prefix within `STATIC_ARTIFACTS_URL`, such as for an
+/// artifact channel, so that its archives are saved, loaded, & listed separately.
+pub fn scope_storage_to_prefix<S: BuildHasher>(
+    env: &mut HashMap<String, String, S>,
+    key_prefix: &str,
+) -> Result<(), ReleaseArtifactsError> {
+    let urls = storage_urls(env);
+    if urls.is_empty() {
+        return Err(ReleaseArtifactsError::StorageURLMissing);
+    }
+    let scoped_urls = scope_urls_to_prefix(&urls, key_prefix)?;
+    env.insert("STATIC_ARTIFACTS_URL".to_string(), scoped_urls);
+    if let Some(replica_url) = env.get("STATIC_ARTIFACTS_REPLICA_URL") {
+        let scoped_replica_url = scope_urls_to_prefix(&[replica_url.as_str()], key_prefix)?;
+        env.insert(
+            "STATIC_ARTIFACTS_REPLICA_URL".to_string(),
+            scoped_replica_url,
+        );
+    }
+    Ok(())
+}
+
+fn scope_urls_to_prefix(urls: &[&str], key_prefix: &str) -> Result<String, ReleaseArtifactsError> {
+    let mut scoped_urls = vec![];
+    for url in urls {
+        let mut url = Url::parse(url).map_err(ReleaseArtifactsError::StorageURLInvalid)?;
+        let scoped_path = format!(
+            "{}/{}",
+            url.path().trim_end_matches('/'),
+            key_prefix.trim_matches('/')
+        );
+        url.set_path(&scoped_path);
+        scoped_urls.push(url.to_string());
+    }
+    Ok(scoped_urls.join(","))
+}
+
+// `STATIC_ARTIFACTS_URL` may list fallbacks after the primary URL, separated by commas.
+fn storage_urls<S: BuildHasher>(env: &HashMap<String, String, S>) -> Vec<&str> {
+    env.get("STATIC_ARTIFACTS_URL")
+        .map(|urls| {
+            urls.split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Only loading falls back, so everything else uses the primary URL.
+fn primary_storage_url<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<&str, ReleaseArtifactsError> {
+    storage_urls(env)
+        .first()
+        .copied()
+        .ok_or(ReleaseArtifactsError::StorageURLMissing)
+}
+
+fn is_direct_child_key(key: Option<&str>, key_prefix: &str) -> bool {
+    key.is_some_and(|k| !k.strip_prefix(key_prefix).unwrap_or(k).contains('/'))
+}
+
+/// An archive found in artifact storage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredArchive {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: SystemTime,
+}
+
+/// Lists every archive in the configured storage location, oldest first.
+pub async fn list_stored_archives<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<Vec<StoredArchive>, ReleaseArtifactsError> {
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"file" => {
+            let storage_path = generate_file_storage_location(env, &String::new())?;
+            list_file_storage(&storage_path)
+        }
+        Ok(scheme) if scheme == *"s3" => {
+            guard_s3_credentials(env)?;
+            let (bucket_name, bucket_region, bucket_key_prefix) = generate_s3_storage_prefix(env)?;
+            let s3 = generate_s3_client(env, bucket_region).await;
+            list_with_client(&s3, &bucket_name, &bucket_key_prefix).await
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn list_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key_prefix: &String,
+) -> Result<Vec<StoredArchive>, ReleaseArtifactsError> {
+    let mut archives = vec![];
+    let mut pages = s3
+        .list_objects_v2()
+        .bucket(bucket_name)
+        .prefix(bucket_key_prefix)
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(ReleaseArtifactsError::from)?;
+        for object in page.contents() {
+            if !is_direct_child_key(object.key(), bucket_key_prefix) {
+                continue;
+            }
+            if let Some(key) = object.key() {
+                archives.push(StoredArchive {
+                    key: key.to_string(),
+                    size: object
+                        .size()
+                        .map_or(0, |s| u64::try_from(s).unwrap_or_default()),
+                    last_modified: object
+                        .last_modified()
+                        .and_then(|t| SystemTime::try_from(*t).ok())
+                        .unwrap_or(SystemTime::UNIX_EPOCH),
+                });
+            }
+        }
+    }
+    archives.sort_by_key(|a| a.last_modified);
+    Ok(archives)
+}
+
+fn list_file_storage(storage_path: &Path) -> Result<Vec<StoredArchive>, ReleaseArtifactsError> {
+    let mut archives = vec![];
+    let entries = fs::read_dir(storage_path).map_err(|e| {
+        ReleaseArtifactsError::ArchiveError(
+            e,
+            format!("during list_file_storage fs::read_dir({storage_path:?})"),
+        )
+    })?;
+    for entry in entries {
+        let entry = entry.map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                "during list_file_storage reading directory entry".to_string(),
+            )
+        })?;
+        let metadata = entry.metadata().map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                format!(
+                    "during list_file_storage entry.metadata({:?})",
+                    entry.path()
+                ),
+            )
+        })?;
+        if metadata.is_file()
+            && !entry
+                .path()
+                .extension()
+                .is_some_and(|e| e == "sig" || e == "sha256" || e == "tags")
+        {
+            archives.push(StoredArchive {
+                key: entry.file_name().to_string_lossy().to_string(),
+                size: metadata.len(),
+                last_modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+    }
+    archives.sort_by(|a, b| {
+        a.last_modified
+            .cmp(&b.last_modified)
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    Ok(archives)
+}
+
+/// Formats a byte count for humans, e.g. `1.5 MiB`.
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// The kind of an entry listed from an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveEntryKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+/// An entry listed from an archive, without extracting it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub size: u64,
+    pub mode: u32,
+    pub kind: ArchiveEntryKind,
+    pub link_target: Option<String>,
+}
+
+/// Lists the contents of the given release's archive, from its embedded manifest when present,
+/// otherwise streaming through the tar index instead of downloading & extracting it.
+pub async fn inspect<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    release_id: &str,
+) -> Result<Vec<ArchiveEntry>, ReleaseArtifactsError> {
+    let mut release_env: HashMap<String, String> =
+        env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    release_env.insert("RELEASE_ID".to_string(), release_id.to_string());
+    let archive_name = generate_archive_name(&release_env)?;
+    match detect_storage_scheme(&release_env) {
+        Ok(scheme) if scheme == *"file" => {
+            guard_file(&release_env)?;
+            log_info!("inspect-release-artifacts reading archive: {archive_name}");
+            let source_path = generate_file_storage_location(&release_env, &archive_name)?;
+            let source = File::open(&source_path).map_err(|e| {
+                ReleaseArtifactsError::ArchiveError(
+                    e,
+                    format!("during inspect File::open({source_path:?})"),
+                )
+            })?;
+            let manifest = archive::read_manifest(&source).map_err(|e| {
+                ReleaseArtifactsError::ArchiveError(e, "during inspect read_manifest".to_string())
+            })?;
+            if let Some(entries) = manifest {
+                return Ok(entries);
+            }
+            let source = File::open(&source_path).map_err(|e| {
+                ReleaseArtifactsError::ArchiveError(
+                    e,
+                    format!("during inspect File::open({source_path:?})"),
+                )
+            })?;
+            archive::list_entries(source).map_err(|e| {
+                ReleaseArtifactsError::ArchiveError(e, "during inspect list_entries".to_string())
+            })
+        }
+        Ok(scheme) if scheme == *"s3" => {
+            guard_s3(&release_env)?;
+            log_info!("inspect-release-artifacts streaming archive: {archive_name}");
+            let (bucket_name, bucket_region, bucket_key) =
+                generate_s3_storage_location(&release_env, &archive_name)?;
+            let s3 = generate_s3_client(&release_env, bucket_region).await;
+            inspect_with_client(&s3, &bucket_name, &bucket_key).await
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn inspect_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key: &String,
+) -> Result<Vec<ArchiveEntry>, ReleaseArtifactsError> {
+    if let Some(entries) = fetch_manifest_with_client(s3, bucket_name, bucket_key).await? {
+        return Ok(entries);
+    }
+    log_info!("inspect-release-artifacts archive has no manifest, listing its entries instead");
+    let output = s3
+        .get_object()
+        .bucket(bucket_name)
+        .key(bucket_key)
+        .send()
+        .await
+        .map_err(ReleaseArtifactsError::from)?;
+    let reader = SyncIoBridge::new(output.body.into_async_read());
+    tokio::task::spawn_blocking(move || archive::list_entries(reader))
+        .await
+        .map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                std::io::Error::other(e),
+                "during inspect_with_client spawn_blocking".to_string(),
+            )
+        })?
+        .map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                "during inspect_with_client list_entries".to_string(),
+            )
+        })
+}
+
+// The first ranged read of an archive, which holds the manifest of most archives.
+const MANIFEST_RANGE_INITIAL_BYTES: u64 = 256 * 1024;
+
+// Beyond this, reading the whole archive index is not much slower than finding its manifest.
+const MANIFEST_RANGE_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Fetches the manifest embedded at the start of an archive with ranged reads, growing the range
+/// until the manifest is complete. Returns `None` for archives saved without a manifest.
+pub async fn fetch_manifest_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key: &String,
+) -> Result<Option<Vec<ArchiveEntry>>, ReleaseArtifactsError> {
+    fetch_manifest_in_ranges(s3, bucket_name, bucket_key, MANIFEST_RANGE_INITIAL_BYTES).await
+}
+
+async fn fetch_manifest_in_ranges(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &String,
+    bucket_key: &String,
+    initial_range_bytes: u64,
+) -> Result<Option<Vec<ArchiveEntry>>, ReleaseArtifactsError> {
+    let mut range_bytes = initial_range_bytes;
+    loop {
+        let output = s3
+            .get_object()
+            .bucket(bucket_name)
+            .key(bucket_key)
+            .range(format!("bytes=0-{}", range_bytes - 1))
+            .send()
+            .await
+            .map_err(ReleaseArtifactsError::from)?;
+        let data = output
+            .body
+            .collect()
+            .await
+            .map_err(ReleaseArtifactsError::ArchiveStreamError)?
+            .into_bytes();
+        let is_whole_archive = (data.len() as u64) < range_bytes;
+        match archive::read_manifest(data.as_ref()) {
+            Ok(manifest) => return Ok(manifest),
+            Err(_) if !is_whole_archive && range_bytes < MANIFEST_RANGE_MAX_BYTES => {
+                range_bytes *= 4;
+            }
+            Err(_) if !is_whole_archive => return Ok(None),
+            Err(e) => {
+                return Err(ReleaseArtifactsError::ArchiveError(
+                    e,
+                    "during fetch_manifest_with_client read_manifest".to_string(),
+                ))
+            }
         }
-        None => Err(ReleaseArtifactsError::StorageURLMissing),
     }
 }
 
+/// Formats an entry's kind & permissions like `ls -l`, e.g. `drwxr-xr-x`.
+#[must_use]
+pub fn format_mode(kind: ArchiveEntryKind, mode: u32) -> String {
+    let kind_char = match kind {
+        ArchiveEntryKind::File => '-',
+        ArchiveEntryKind::Directory => 'd',
+        ArchiveEntryKind::Symlink => 'l',
+        ArchiveEntryKind::Other => '?',
+    };
+    let permissions: String = ["r", "w", "x"]
+        .iter()
+        .cycle()
+        .take(9)
+        .enumerate()
+        .map(|(i, c)| if mode & (0o400 >> i) == 0 { "-" } else { c })
+        .collect();
+    format!("{kind_char}{permissions}")
+}
+
+fn detect_storage_scheme<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<String, ReleaseArtifactsError> {
+    let url = primary_storage_url(env)?;
+    let result = Url::parse(url).map_err(ReleaseArtifactsError::StorageURLInvalid)?;
+    Ok(result.scheme().to_string())
+}
+
 fn guard_s3<S: ::std::hash::BuildHasher>(
     env: &HashMap<String, String, S>,
 ) -> Result<(), ReleaseArtifactsError> {
@@ -277,7 +1477,39 @@ fn guard_s3<S: ::std::hash::BuildHasher>(
     if !messages.is_empty() {
         return Err(ReleaseArtifactsError::ConfigMissing(messages.join(". ")));
     }
-    Ok(())
+    guard_s3_endpoint(env)
+}
+
+fn guard_s3_credentials<S: ::std::hash::BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<(), ReleaseArtifactsError> {
+    let mut messages: Vec<String> = vec![];
+    if !env.contains_key("STATIC_ARTIFACTS_ACCESS_KEY_ID") {
+        messages.push("STATIC_ARTIFACTS_ACCESS_KEY_ID is required".to_string());
+    }
+    if !env.contains_key("STATIC_ARTIFACTS_SECRET_ACCESS_KEY") {
+        messages.push("STATIC_ARTIFACTS_SECRET_ACCESS_KEY is required".to_string());
+    }
+    if !messages.is_empty() {
+        return Err(ReleaseArtifactsError::ConfigMissing(messages.join(". ")));
+    }
+    guard_s3_endpoint(env)
+}
+
+// `STATIC_ARTIFACTS_ENDPOINT_URL` is checked before the client is created, since the SDK only
+// fails on an invalid endpoint when a request is made, with a less helpful error.
+fn guard_s3_endpoint<S: ::std::hash::BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<(), ReleaseArtifactsError> {
+    let Some(endpoint_url) = env.get("STATIC_ARTIFACTS_ENDPOINT_URL") else {
+        return Ok(());
+    };
+    match Url::parse(endpoint_url) {
+        Ok(url) if (url.scheme() == "https" || url.scheme() == "http") && url.has_host() => Ok(()),
+        _ => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+            "STATIC_ARTIFACTS_ENDPOINT_URL should be an http or https URL, like https://minio.example.com:9000, not '{endpoint_url}'"
+        ))),
+    }
 }
 
 fn guard_file<S: ::std::hash::BuildHasher>(
@@ -296,15 +1528,19 @@ fn guard_file<S: ::std::hash::BuildHasher>(
     Ok(())
 }
 
-fn generate_archive_name<S: BuildHasher>(env: &HashMap<String, String, S>) -> String {
+// Named with the extension of `STATIC_ARTIFACTS_COMPRESSION`.
+fn generate_archive_name<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<String, ReleaseArtifactsError> {
+    let extension = ArchiveCompression::from_env(env)?.extension();
     let release_id = env
         .get("RELEASE_ID")
         .map_or(String::default(), std::borrow::ToOwned::to_owned);
     if release_id.is_empty() {
         let unique = Uuid::new_v4();
-        format!("artifact-{unique}.tgz")
+        Ok(format!("artifact-{unique}.{extension}"))
     } else {
-        format!("release-{release_id}.tgz")
+        Ok(format!("release-{release_id}.{extension}"))
     }
 }
 
@@ -313,7 +1549,7 @@ fn generate_s3_storage_location<S: BuildHasher>(
     archive_name: &String,
 ) -> Result<(String, Option<String>, String), ReleaseArtifactsError> {
     let (bucket_name, bucket_region_from_url, bucket_path) =
-        parse_s3_url(&env["STATIC_ARTIFACTS_URL"])?;
+        parse_s3_url(primary_storage_url(env)?)?;
     let bucket_region =
         bucket_region_from_url.or_else(|| env.get("STATIC_ARTIFACTS_REGION").cloned());
     let bucket_key =
@@ -321,31 +1557,128 @@ fn generate_s3_storage_location<S: BuildHasher>(
     Ok((bucket_name, bucket_region, bucket_key))
 }
 
+fn generate_s3_storage_prefix<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<(String, Option<String>, String), ReleaseArtifactsError> {
+    let (bucket_name, bucket_region_from_url, bucket_path) =
+        parse_s3_url(primary_storage_url(env)?)?;
+    let bucket_region =
+        bucket_region_from_url.or_else(|| env.get("STATIC_ARTIFACTS_REGION").cloned());
+    let bucket_key_prefix = bucket_path
+        .filter(|p| !p.is_empty())
+        .map_or_else(String::new, |p| format!("{p}/"));
+    Ok((bucket_name, bucket_region, bucket_key_prefix))
+}
+
 fn generate_file_storage_location<S: BuildHasher>(
     env: &HashMap<String, String, S>,
     archive_name: &String,
 ) -> Result<PathBuf, ReleaseArtifactsError> {
-    let url = Url::parse(&env["STATIC_ARTIFACTS_URL"])
-        .map_err(ReleaseArtifactsError::StorageURLInvalid)?;
-    let dest_path = url.path();
-    fs::create_dir_all(dest_path).map_err(|e| {
+    let url =
+        Url::parse(primary_storage_url(env)?).map_err(ReleaseArtifactsError::StorageURLInvalid)?;
+    let dest_path = file_storage_dir(&url);
+    fs::create_dir_all(&dest_path).map_err(|e| {
         ReleaseArtifactsError::ArchiveError(
             e,
-            format!("creating filesystem destination directory '{dest_path}'"),
+            format!(
+                "creating filesystem destination directory '{}'",
+                dest_path.display()
+            ),
         )
     })?;
-    let result = Path::new(dest_path).join(archive_name);
-    Ok(result.clone())
+    Ok(dest_path.join(archive_name))
+}
+
+// The dir of `file://` storage. On Windows, the path of a URL like `file:///C:/storage` has a
+// slash before its drive, so it is converted to a file path instead.
+fn file_storage_dir(url: &Url) -> PathBuf {
+    #[cfg(windows)]
+    if let Ok(path) = url.to_file_path() {
+        return path;
+    }
+    PathBuf::from(url.path())
+}
+
+// The env vars that configure an S3 client, so that a cached client is only reused for the same
+// config.
+const S3_CLIENT_VARS: &[&str] = &[
+    "STATIC_ARTIFACTS_ACCESS_KEY_ID",
+    "STATIC_ARTIFACTS_SECRET_ACCESS_KEY",
+    "STATIC_ARTIFACTS_SESSION_TOKEN",
+    "STATIC_ARTIFACTS_ENDPOINT_URL",
+    "STATIC_ARTIFACTS_FORCE_PATH_STYLE",
+    "STATIC_ARTIFACTS_MAX_RETRIES",
+    "STATIC_ARTIFACTS_RETRY_BACKOFF_MS",
+    "STATIC_ARTIFACTS_MAX_IDLE_CONNECTIONS",
+    "STATIC_ARTIFACTS_KEEPALIVE_SECONDS",
+    "STATIC_ARTIFACTS_FAULTS",
+];
+
+// The region, & the values of `S3_CLIENT_VARS`, that a cached S3 client was created for.
+type S3ClientKey = (Option<String>, Vec<Option<String>>);
+
+// The idle connections per host & keepalive seconds that a shared HTTP client was tuned with.
+type S3HttpClientKey = (Option<usize>, Option<u64>);
+
+// The clients created during one top-level operation, such as `save`. They are never cached
+// beyond it, because a pooled connection is driven by a task on the runtime that opened it, and
+// the executables create & drop a runtime for each operation.
+#[derive(Default)]
+struct StorageClients {
+    s3: tokio::sync::Mutex<HashMap<S3ClientKey, Client>>,
+    http: Mutex<HashMap<S3HttpClientKey, SharedHttpClient>>,
 }
 
+tokio::task_local! {
+    static STORAGE_CLIENTS: Arc<StorageClients>;
+}
+
+/// Runs the operation with its own cache of storage clients, unless it is part of an operation
+/// that already has one, such as garbage collection during a save.
+async fn with_storage_clients<F: Future>(operation: F) -> F::Output {
+    if STORAGE_CLIENTS.try_with(|_| ()).is_ok() {
+        operation.await
+    } else {
+        STORAGE_CLIENTS
+            .scope(Arc::new(StorageClients::default()), operation)
+            .await
+    }
+}
+
+/// Returns the S3 client for the credentials & region, created once per top-level operation, so
+/// that config & credential resolution is not repeated by each step, such as saving then
+/// replicating. Clients are cheap to clone, sharing their config.
 async fn generate_s3_client<S: BuildHasher>(
     env: &HashMap<String, String, S>,
     bucket_region: Option<String>,
+) -> Client {
+    let Ok(clients) = STORAGE_CLIENTS.try_with(Arc::clone) else {
+        return new_s3_client(env, bucket_region).await;
+    };
+    let key = (
+        bucket_region.clone(),
+        S3_CLIENT_VARS
+            .iter()
+            .map(|var| env.get(*var).cloned())
+            .collect(),
+    );
+    let mut s3_clients = clients.s3.lock().await;
+    if let Some(client) = s3_clients.get(&key) {
+        return client.clone();
+    }
+    let client = new_s3_client(env, bucket_region).await;
+    s3_clients.insert(key, client.clone());
+    client
+}
+
+async fn new_s3_client<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    bucket_region: Option<String>,
 ) -> Client {
     let credentials = Credentials::new(
         env["STATIC_ARTIFACTS_ACCESS_KEY_ID"].clone(),
         env["STATIC_ARTIFACTS_SECRET_ACCESS_KEY"].clone(),
-        None,
+        env.get("STATIC_ARTIFACTS_SESSION_TOKEN").cloned(),
         None,
         "Static Artifacts storage",
     );
@@ -354,9 +1687,103 @@ async fn generate_s3_client<S: BuildHasher>(
     let shared_config = aws_config::from_env()
         .region(region_provider)
         .credentials_provider(credentials)
+        .http_client(s3_http_client(env))
         .load()
         .await;
-    Client::new(&shared_config)
+    let mut s3_config =
+        aws_sdk_s3::config::Builder::from(&shared_config).retry_config(retry::retry_config(env));
+    if let Some(endpoint_url) = env.get("STATIC_ARTIFACTS_ENDPOINT_URL") {
+        // S3-compatible services, such as MinIO & Ceph, often cannot resolve the bucket as a
+        // subdomain of their host, so the bucket is addressed in the path, unless disabled.
+        let force_path_style = !env
+            .get("STATIC_ARTIFACTS_FORCE_PATH_STYLE")
+            .is_some_and(|v| v == "0" || v == "false");
+        log_debug!("release-phase S3 endpoint {endpoint_url}, path-style {force_path_style}");
+        s3_config = s3_config
+            .endpoint_url(endpoint_url)
+            .force_path_style(force_path_style);
+    }
+    Client::from_conf(with_faults(env, s3_config).build())
+}
+
+// Injects the faults of `STATIC_ARTIFACTS_FAULTS` into S3 responses, only when built with the
+// `fault-injection` feature for testing, so that production builds never inject them.
+#[cfg(feature = "fault-injection")]
+fn with_faults<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    s3_config: aws_sdk_s3::config::Builder,
+) -> aws_sdk_s3::config::Builder {
+    match faults::FaultConfig::from_env(env) {
+        Ok(Some(faults)) => {
+            eprintln!("release-phase warning: injecting S3 faults, from STATIC_ARTIFACTS_FAULTS");
+            s3_config.interceptor(faults::FaultInjector::new(faults))
+        }
+        Ok(None) => s3_config,
+        Err(error) => {
+            eprintln!("release-phase warning: not injecting S3 faults, {error:?}");
+            s3_config
+        }
+    }
+}
+
+#[cfg(not(feature = "fault-injection"))]
+fn with_faults<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    s3_config: aws_sdk_s3::config::Builder,
+) -> aws_sdk_s3::config::Builder {
+    if env.contains_key("STATIC_ARTIFACTS_FAULTS") {
+        eprintln!("release-phase warning: not injecting S3 faults, STATIC_ARTIFACTS_FAULTS requires the fault-injection feature");
+    }
+    s3_config
+}
+
+/// Returns the HTTP client shared by every S3 client of the top-level operation with the same
+/// tuning, so that its steps on the same bucket, such as saving then listing, reuse pooled
+/// connections & TLS sessions. Tuned by:
+/// * `STATIC_ARTIFACTS_MAX_IDLE_CONNECTIONS`: idle connections kept open per host, `0` to
+///   open a new connection for every request
+/// * `STATIC_ARTIFACTS_KEEPALIVE_SECONDS`: how long an idle connection is kept open
+fn s3_http_client<S: BuildHasher>(env: &HashMap<String, String, S>) -> SharedHttpClient {
+    let key = (
+        parse_tuning_var::<usize, S>(env, "STATIC_ARTIFACTS_MAX_IDLE_CONNECTIONS"),
+        parse_tuning_var::<u64, S>(env, "STATIC_ARTIFACTS_KEEPALIVE_SECONDS"),
+    );
+    let Ok(clients) = STORAGE_CLIENTS.try_with(Arc::clone) else {
+        return new_s3_http_client(key);
+    };
+    let mut http_clients = clients.http.lock().unwrap_or_else(PoisonError::into_inner);
+    http_clients
+        .entry(key)
+        .or_insert_with(|| new_s3_http_client(key))
+        .clone()
+}
+
+fn new_s3_http_client((max_idle, keepalive): S3HttpClientKey) -> SharedHttpClient {
+    let mut builder = aws_smithy_http_client::Builder::new();
+    if let Some(max_idle) = max_idle {
+        builder.set_pool_max_idle_per_host(Some(max_idle));
+    }
+    if let Some(keepalive) = keepalive {
+        builder.set_pool_idle_timeout(Some(Some(Duration::from_secs(keepalive))));
+    }
+    builder
+        .tls_provider(tls::Provider::Rustls(
+            tls::rustls_provider::CryptoMode::Ring,
+        ))
+        .build_https()
+}
+
+// Invalid tuning values fall back to the defaults, rather than failing the operation.
+fn parse_tuning_var<T: std::str::FromStr, S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    name: &str,
+) -> Option<T> {
+    let value = env.get(name)?;
+    let parsed = value.trim().parse().ok();
+    if parsed.is_none() {
+        eprintln!("release-phase ignoring {name}, not a whole number: '{value}'");
+    }
+    parsed
 }
 
 pub fn parse_s3_url(
@@ -403,33 +1830,165 @@ pub fn parse_s3_url(
     Ok((bucket_name, bucket_region, bucket_path))
 }
 
-/// Tars & compresses contents of the given directory to a .tar.gz file.
-pub fn create_archive(source_dir: &Path, destination: &Path) -> Result<(), ReleaseArtifactsError> {
-    let output_file: File = File::create(destination).map_err(|e| {
+const ARCHIVE_WRITE_BUFFER_BYTES: usize = 256 * 1024;
+
+/// Tars & compresses contents of the given directory to a .tar.gz file, or to a .tar.zst file
+/// when the destination's extension is `.tzst`.
+///
+/// When given a single file instead, its path is kept in the archive, relative to the working
+/// directory, so that `extract_archive` can restore it to the same place.
+pub fn create_archive(source: &Path, destination: &Path) -> Result<(), ReleaseArtifactsError> {
+    create_filtered_archive(source, destination, &ArtifactFilter::default())
+}
+
+// Like `create_archive`, of only the paths of a source directory selected by the filter.
+fn create_filtered_archive(
+    source: &Path,
+    destination: &Path,
+    filter: &ArtifactFilter,
+) -> Result<(), ReleaseArtifactsError> {
+    let output_file: File = File::create(destination).map_err(|e| {
         ReleaseArtifactsError::ArchiveError(
             e,
             format!("during create_archive File::create({destination:?})"),
         )
     })?;
-    let gz = GzBuilder::new().write(output_file, Compression::default());
-    let mut tar = tar::Builder::new(gz);
-    tar.follow_symlinks(false);
-    // add to root of archive
-    tar.append_dir_all("", source_dir).map_err(|e| {
-        ReleaseArtifactsError::ArchiveError(
-            e,
-            format!("during create_archive tar.append_dir_all({source_dir:?})"),
-        )
+    let writer = BufWriter::with_capacity(ARCHIVE_WRITE_BUFFER_BYTES, output_file);
+    let compression = ArchiveCompression::from_archive_path(destination);
+    write_archive(source, writer, compression, filter)?
+        .flush()
+        .map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(e, "during create_archive finish".to_string())
+        })
+}
+
+// Writes the compressed tar of the given directory, filtered, or single file, returning the
+// writer once the archive is complete.
+fn write_archive<W: Write>(
+    source: &Path,
+    writer: W,
+    compression: ArchiveCompression,
+    filter: &ArtifactFilter,
+) -> Result<W, ReleaseArtifactsError> {
+    let level = select_compression(source);
+    if level == Compression::none() {
+        match compression {
+            ArchiveCompression::Gzip => {
+                log_info!("save-release-artifacts storing without compression, content is already compressed");
+            }
+            ArchiveCompression::Zstd => {
+                log_info!("save-release-artifacts compressing at the fastest level, content is already compressed");
+            }
+        }
+    }
+    let encoder = compression::Encoder::new(writer, compression, level).map_err(|e| {
+        ReleaseArtifactsError::ArchiveError(e, "during create_archive Encoder::new".to_string())
     })?;
-    tar.finish().map_err(|e| {
-        ReleaseArtifactsError::ArchiveError(e, "during create_archive tar.finish()".to_string())
+    let mut tar = tar::Builder::new(encoder);
+    if source.is_file() {
+        log_info!("save-release-artifacts storing single file: {source:?}");
+        archive::append_file(&mut tar, source).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                format!("during create_archive append_file({source:?})"),
+            )
+        })?;
+    } else {
+        if !filter.is_empty() {
+            log_info!("save-release-artifacts archiving paths selected by STATIC_ARTIFACTS_INCLUDE & STATIC_ARTIFACTS_EXCLUDE");
+        }
+        // add to root of archive
+        archive::append_tree(&mut tar, source, filter).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                format!("during create_archive append_tree({source:?})"),
+            )
+        })?;
+    }
+    tar.into_inner()
+        .and_then(compression::Encoder::finish)
+        .map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(e, "during create_archive finish".to_string())
+        })
+}
+
+// File extensions of formats that are already compressed, so gzip cannot shrink them further.
+const INCOMPRESSIBLE_EXTENSIONS: [&str; 24] = [
+    "7z", "avif", "br", "bz2", "gif", "gz", "heic", "ico", "jpeg", "jpg", "m4a", "mp3", "mp4",
+    "ogg", "png", "tgz", "tzst", "webm", "webp", "woff", "woff2", "xz", "zip", "zst",
+];
+
+// When at least this share of the content bytes is already compressed, store it as-is.
+const INCOMPRESSIBLE_THRESHOLD_PERCENT: u64 = 90;
+
+/// Chooses store-only gzip when the content is dominated by already-compressed files,
+/// avoiding CPU spent recompressing incompressible assets.
+fn select_compression(source: &Path) -> Compression {
+    if source.is_file() {
+        return if is_incompressible(source) {
+            Compression::none()
+        } else {
+            Compression::default()
+        };
+    }
+    let mut total_bytes = 0_u64;
+    let mut incompressible_bytes = 0_u64;
+    measure_compressibility(source, &mut total_bytes, &mut incompressible_bytes)
+        .unwrap_or_default();
+    if total_bytes > 0
+        && incompressible_bytes * 100 >= total_bytes * INCOMPRESSIBLE_THRESHOLD_PERCENT
+    {
+        Compression::none()
+    } else {
+        Compression::default()
+    }
+}
+
+fn measure_compressibility(
+    dir: &Path,
+    total_bytes: &mut u64,
+    incompressible_bytes: &mut u64,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            measure_compressibility(&entry.path(), total_bytes, incompressible_bytes)?;
+        } else if file_type.is_file() {
+            let size = entry.metadata()?.len();
+            *total_bytes += size;
+            if is_incompressible(&entry.path()) {
+                *incompressible_bytes += size;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_incompressible(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        let extension = extension.to_string_lossy().to_ascii_lowercase();
+        INCOMPRESSIBLE_EXTENSIONS.contains(&extension.as_str())
     })
 }
 
-/// Decompresses and untars a given .tar.gz file to the given directory.
+/// Decompresses and untars a given .tar.gz or .tar.zst file to the given directory, detecting
+/// the compression from its content.
+///
+/// Single-file artifacts are instead restored to their original path, relative to the working
+/// directory.
 pub fn extract_archive(
     source_file: &Path,
     destination: &Path,
+) -> Result<(), ReleaseArtifactsError> {
+    extract_archive_with_policy(source_file, destination, ConflictPolicy::default())
+}
+
+// Extracts like `extract_archive`, handling files that already exist with the conflict policy.
+fn extract_archive_with_policy(
+    source_file: &Path,
+    destination: &Path,
+    on_conflict: ConflictPolicy,
 ) -> Result<(), ReleaseArtifactsError> {
     let source = File::open(source_file).map_err(|e| {
         ReleaseArtifactsError::ArchiveError(
@@ -437,13 +1996,17 @@ pub fn extract_archive(
             format!("during extract_archive File::open({source_file:?})"),
         )
     })?;
-    let mut archive = Archive::new(GzDecoder::new(source));
-    archive.unpack(destination).map_err(|e| {
-        ReleaseArtifactsError::ArchiveError(
-            e,
-            format!("during extract_archive archive.unpack({destination:?})"),
-        )
-    })
+    let file_artifact =
+        archive::unpack(source, destination, Path::new("."), on_conflict).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                format!("during extract_archive archive::unpack({destination:?})"),
+            )
+        })?;
+    if let Some(path) = file_artifact {
+        log_info!("load-release-artifacts restored single file: {path:?}");
+    }
+    Ok(())
 }
 
 #[allow(dead_code)]
@@ -464,11 +2027,12 @@ mod tests {
         env,
         fs::{self, File},
         io::{Read, Write},
-        path::Path,
+        path::{Path, PathBuf},
+        sync::Arc,
     };
 
     use aws_config::BehaviorVersion;
-    use flate2::read::GzDecoder;
+    use flate2::{read::GzDecoder, Compression};
     use tar::Archive;
     use uuid::Uuid;
 
@@ -476,12 +2040,20 @@ mod tests {
     use aws_smithy_types::body::SdkBody;
 
     use crate::{
-        capture_env, create_archive, detect_storage_scheme,
+        archive, capture_env, checksum, create_archive, detect_storage_scheme,
         download_specific_or_latest_with_client, download_with_client,
-        errors::ReleaseArtifactsError, extract_archive, find_latest_with_client,
-        generate_archive_name, generate_file_storage_location, generate_s3_client,
-        generate_s3_storage_location, guard_file, guard_s3, load, make_s3_test_credentials,
-        parse_s3_url, save, upload_with_client,
+        errors::ReleaseArtifactsError,
+        extract_archive, extraction_report_path, fetch_manifest_in_ranges, find_latest_with_client,
+        format_mode, format_size, generate_archive_name, generate_file_storage_location,
+        generate_s3_client, generate_s3_storage_location, generate_s3_storage_prefix, guard_file,
+        guard_s3, guard_s3_credentials, inspect, inspect_with_client, list_stored_archives,
+        list_with_client, load, load_prefetched_with_client, make_s3_test_credentials,
+        parent_key_prefix, parse_s3_url, parse_tuning_var, prefetch_with_client,
+        resolve_specific_or_latest_key_with_client, s3_http_client, save, scope_storage_to_prefix,
+        select_compression,
+        single_flight::{load_lock_path, loaded_key_path},
+        storage_urls, upload_with_client, with_storage_clients, ArchiveEntry, ArchiveEntryKind,
+        ArchiveVerifier, ConflictPolicy, ExtractionReport, STORAGE_CLIENTS,
     };
 
     #[test]
@@ -516,6 +2088,53 @@ mod tests {
         fs::remove_dir_all(dyno_metadata_path).unwrap_or_default();
     }
 
+    #[test]
+    fn capture_env_with_dyno_json_succeeds() {
+        let unique = Uuid::new_v4();
+        let dyno_metadata_dir = format!("dyno-metadata-for-test-{unique}");
+        let dyno_metadata_path = Path::new(&dyno_metadata_dir);
+        fs::create_dir_all(dyno_metadata_path).expect("dyno metadata dir should be created");
+        fs::write(
+            dyno_metadata_path.join("dyno.json"),
+            r#"{"release_id": "v102", "app_id": "test-app-id", "dyno_name": "web.1", "size": "standard-1x"}"#,
+        )
+        .expect("dyno metadata file shoud be written");
+
+        env::set_var("DYNO", "web.2");
+        let with_json = capture_env(dyno_metadata_path);
+        env::remove_var("DYNO");
+        fs::write(dyno_metadata_path.join("release_id"), "v103")
+            .expect("dyno metadata file shoud be written");
+        let with_release_id_file = capture_env(dyno_metadata_path);
+        fs::remove_dir_all(dyno_metadata_path).unwrap_or_default();
+
+        assert_eq!(with_json.get("RELEASE_ID"), Some(&"v102".to_string()));
+        assert_eq!(
+            with_json.get("HEROKU_APP_ID"),
+            Some(&"test-app-id".to_string())
+        );
+        assert_eq!(with_json.get("DYNO"), Some(&"web.1".to_string()));
+        assert_eq!(
+            with_release_id_file.get("RELEASE_ID"),
+            Some(&"v103".to_string())
+        );
+    }
+
+    #[test]
+    fn capture_env_ignores_invalid_dyno_json() {
+        let unique = Uuid::new_v4();
+        let dyno_metadata_dir = format!("dyno-metadata-for-test-{unique}");
+        let dyno_metadata_path = Path::new(&dyno_metadata_dir);
+        fs::create_dir_all(dyno_metadata_path).expect("dyno metadata dir should be created");
+        fs::write(dyno_metadata_path.join("dyno.json"), "{\"release_id\": ")
+            .expect("dyno metadata file shoud be written");
+
+        let result = capture_env(dyno_metadata_path);
+        fs::remove_dir_all(dyno_metadata_path).unwrap_or_default();
+
+        assert_eq!(result.get("HEROKU_APP_ID"), None);
+    }
+
     #[tokio::test]
     async fn save_file_url_succeeds() {
         let unique = Uuid::new_v4();
@@ -534,7 +2153,11 @@ mod tests {
         let result = save(&test_env, Path::new("test/fixtures/static-artifacts")).await;
 
         eprintln!("{result:?}");
-        assert!(result.is_ok());
+        let report = result.expect("save should succeed");
+        assert!(report.archive_size > 0);
+        assert!(report.source_size > 0);
+        assert_eq!(report.stored_archives, Some(1));
+        assert_eq!(report.previous_archive_size, None);
         eprintln!("{:#?}", fs::metadata(&output_archive_dir_path));
         assert!(fs::metadata(&output_archive_dir_path).is_ok());
         assert!(
@@ -544,85 +2167,196 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn upload_with_client_succeeds() {
-        let put_object_1 = ReplayEvent::new(
-            http::Request::builder()
-                .method("PUT")
-                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=PutObject")
-                .body(SdkBody::empty()) // body must be empty here, because it uses a streamer impl
-                .unwrap(),
-            http::Response::builder()
-                .status(200)
-                .body(SdkBody::empty())
-                .unwrap(),
+    async fn save_reports_previous_archive_size() {
+        let unique = Uuid::new_v4();
+        let abs_root = env::current_dir().expect("should have a current working directory");
+        let output_archive_dir_path =
+            Path::new(&abs_root).join(format!("test-saved-static-artifacts-{unique}"));
+
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", output_archive_dir_path.to_string_lossy()),
         );
-        let replay_client = StaticReplayClient::new(vec![put_object_1]);
-        let s3 = aws_sdk_s3::Client::from_conf(
-            aws_sdk_s3::Config::builder()
-                .behavior_version(BehaviorVersion::latest())
-                .credentials_provider(make_s3_test_credentials())
-                .region(aws_sdk_s3::config::Region::new("us-east-1"))
-                .http_client(replay_client.clone())
-                .build(),
+        test_env.insert("RELEASE_ID".to_string(), "v1".to_string());
+        let first = save(&test_env, Path::new("test/fixtures/static-artifacts")).await;
+        test_env.insert("RELEASE_ID".to_string(), "v2".to_string());
+        let second = save(&test_env, Path::new("test/fixtures/static-artifacts")).await;
+        fs::remove_dir_all(output_archive_dir_path).expect("temporary directory should be deleted");
+
+        let first = first.expect("first save should succeed");
+        let second = second.expect("second save should succeed");
+        assert_eq!(second.previous_archive_size, Some(first.archive_size));
+        assert_eq!(second.stored_archives, Some(2));
+        assert_eq!(second.growth_warning(50), None);
+    }
+
+    #[tokio::test]
+    async fn save_file_url_with_replica_url_succeeds() {
+        let unique = Uuid::new_v4();
+        let abs_root = env::current_dir().expect("should have a current working directory");
+        let output_archive_dir_path =
+            Path::new(&abs_root).join(format!("test-saved-static-artifacts-{unique}"));
+        let replica_archive_dir_path =
+            Path::new(&abs_root).join(format!("test-replicated-static-artifacts-{unique}"));
+
+        let mut test_env = HashMap::new();
+        test_env.insert("RELEASE_ID".to_string(), unique.to_string());
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", output_archive_dir_path.to_string_lossy()),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_REPLICA_URL".to_string(),
+            format!("file://{}", replica_archive_dir_path.to_string_lossy()),
         );
 
-        let result = upload_with_client(
-            &s3,
-            &"test-bucket".to_string(),
-            &"sub/path/static-artifacts.tgz".to_string(),
-            &"test/fixtures/static-artifacts.tgz".to_string(),
-        )
-        .await;
+        let result = save(&test_env, Path::new("test/fixtures/static-artifacts")).await;
 
+        eprintln!("{result:?}");
         assert!(result.is_ok());
-        replay_client.assert_requests_match(&[]);
+        let archive_name = format!("release-{unique}.tgz");
+        assert!(fs::metadata(output_archive_dir_path.join(&archive_name)).is_ok());
+        assert_eq!(
+            fs::read(output_archive_dir_path.join(&archive_name)).unwrap(),
+            fs::read(replica_archive_dir_path.join(&archive_name)).unwrap()
+        );
+        fs::remove_dir_all(output_archive_dir_path).expect("temporary directory should be deleted");
+        fs::remove_dir_all(replica_archive_dir_path)
+            .expect("temporary directory should be deleted");
     }
 
     #[tokio::test]
-    async fn load_file_url_succeeds() {
+    async fn save_succeeds_when_replica_fails() {
         let unique = Uuid::new_v4();
         let abs_root = env::current_dir().expect("should have a current working directory");
-        let source_archive_dir_path = Path::new(&abs_root).join("test/fixtures");
+        let output_archive_dir_path =
+            Path::new(&abs_root).join(format!("test-saved-static-artifacts-{unique}"));
+
+        let mut test_env = HashMap::new();
+        test_env.insert("RELEASE_ID".to_string(), unique.to_string());
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", output_archive_dir_path.to_string_lossy()),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_REPLICA_URL".to_string(),
+            "ftp://replica/static-artifacts".to_string(),
+        );
+
+        let result = save(&test_env, Path::new("test/fixtures/static-artifacts")).await;
+
+        eprintln!("{result:?}");
+        assert!(result.is_ok());
+        assert!(
+            fs::metadata(output_archive_dir_path.join(format!("release-{unique}.tgz"))).is_ok()
+        );
+        fs::remove_dir_all(output_archive_dir_path).expect("temporary directory should be deleted");
+    }
+
+    #[tokio::test]
+    async fn save_and_load_signed_file_url() {
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+
+        let unique = Uuid::new_v4();
+        let abs_root = env::current_dir().expect("should have a current working directory");
+        let output_archive_dir_path =
+            Path::new(&abs_root).join(format!("test-signed-static-artifacts-{unique}"));
         let destination_dir_path =
-            Path::new(&abs_root).join(format!("static-artifacts-test-{unique}"));
+            Path::new(&abs_root).join(format!("static-artifacts-signed-test-{unique}"));
+        let seed = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&hex::decode(seed).unwrap()).unwrap();
 
         let mut test_env = HashMap::new();
-        test_env.insert("RELEASE_ID".to_string(), "xxxxx".to_string());
+        test_env.insert("RELEASE_ID".to_string(), unique.to_string());
         test_env.insert(
             "STATIC_ARTIFACTS_URL".to_string(),
-            format!("file://{}", source_archive_dir_path.to_string_lossy()).to_string(),
+            format!("file://{}", output_archive_dir_path.to_string_lossy()),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_REQUIRE_SIGNATURE".to_string(),
+            "1".to_string(),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_VERIFY_KEY".to_string(),
+            hex::encode(key_pair.public_key().as_ref()),
         );
 
+        save(&test_env, Path::new("test/fixtures/static-artifacts"))
+            .await
+            .unwrap();
         let result = load(&test_env, &destination_dir_path).await;
+        assert!(matches!(
+            result,
+            Err(ReleaseArtifactsError::SignatureInvalid(_))
+        ));
 
+        test_env.insert("STATIC_ARTIFACTS_SIGNING_KEY".to_string(), seed.to_string());
+        save(&test_env, Path::new("test/fixtures/static-artifacts"))
+            .await
+            .unwrap();
+        let result = load(&test_env, &destination_dir_path).await;
         eprintln!("{result:?}");
         assert!(result.is_ok());
-        assert!(fs::metadata(&destination_dir_path).is_ok());
         assert!(fs::metadata(destination_dir_path.join("index.html")).is_ok());
-        assert!(fs::metadata(destination_dir_path.join("images")).is_ok());
-        assert!(fs::metadata(destination_dir_path.join("images/desktop-heroku-pride.jpg")).is_ok());
+
+        fs::remove_dir_all(output_archive_dir_path).expect("temporary directory should be deleted");
+        fs::remove_file(extraction_report_path(&test_env, &destination_dir_path).unwrap())
+            .unwrap_or_default();
+        fs::remove_file(loaded_key_path(&destination_dir_path).unwrap()).unwrap_or_default();
+        fs::remove_file(load_lock_path(&destination_dir_path).unwrap()).unwrap_or_default();
         fs::remove_dir_all(destination_dir_path).expect("temporary directory should be deleted");
     }
 
     #[tokio::test]
-    async fn download_specific_or_latest_with_client_specific_succeeds() {
+    async fn load_file_url_rejects_corrupted_archive() {
         let unique = Uuid::new_v4();
-        let output_dir_name = format!("test-output-static-artifacts-{unique}");
-        let output_dir = Path::new(output_dir_name.as_str());
-        fs::remove_dir_all(output_dir).unwrap_or_default();
+        let abs_root = env::current_dir().expect("should have a current working directory");
+        let output_archive_dir_path =
+            Path::new(&abs_root).join(format!("test-checksum-static-artifacts-{unique}"));
+        let destination_dir_path =
+            Path::new(&abs_root).join(format!("static-artifacts-checksum-test-{unique}"));
 
-        let get_object_1 = ReplayEvent::new(
+        let mut test_env = HashMap::new();
+        test_env.insert("RELEASE_ID".to_string(), unique.to_string());
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", output_archive_dir_path.to_string_lossy()),
+        );
+
+        save(&test_env, Path::new("test/fixtures/static-artifacts"))
+            .await
+            .unwrap();
+        let archive_path = output_archive_dir_path.join(format!("release-{unique}.tgz"));
+        let archive = fs::read(&archive_path).unwrap();
+        fs::write(&archive_path, &archive[..archive.len() / 2]).unwrap();
+        let result = load(&test_env, &destination_dir_path).await;
+        let destination_exists = destination_dir_path.exists();
+        fs::remove_dir_all(output_archive_dir_path).expect("temporary directory should be deleted");
+        fs::remove_dir_all(&destination_dir_path).unwrap_or_default();
+        fs::remove_file(load_lock_path(&destination_dir_path).unwrap()).unwrap_or_default();
+
+        assert!(matches!(
+            result,
+            Err(ReleaseArtifactsError::ChecksumMismatch(_))
+        ));
+        assert!(!destination_exists);
+    }
+
+    #[tokio::test]
+    async fn upload_with_client_succeeds() {
+        let put_object_1 = ReplayEvent::new(
             http::Request::builder()
-                .method("GET")
-                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=GetObject")
-                .body(SdkBody::empty())
+                .method("PUT")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=PutObject")
+                .body(SdkBody::empty()) // body must be empty here, because it uses a streamer impl
                 .unwrap(),
             http::Response::builder()
                 .status(200)
-                .body(SdkBody::from(read_fixture_archive_data()))
+                .body(SdkBody::empty())
                 .unwrap(),
         );
-        let replay_client = StaticReplayClient::new(vec![get_object_1]);
+        let replay_client = StaticReplayClient::new(vec![put_object_1]);
         let s3 = aws_sdk_s3::Client::from_conf(
             aws_sdk_s3::Config::builder()
                 .behavior_version(BehaviorVersion::latest())
@@ -632,43 +2366,36 @@ mod tests {
                 .build(),
         );
 
-        let result = download_specific_or_latest_with_client(
+        let result = upload_with_client(
             &s3,
             &"test-bucket".to_string(),
             &"sub/path/static-artifacts.tgz".to_string(),
-            output_dir,
+            &"test/fixtures/static-artifacts.tgz".to_string(),
         )
         .await;
 
-        println!("{result:#?}");
         assert!(result.is_ok());
         replay_client.assert_requests_match(&[]);
-        assert!(fs::metadata(output_dir).is_ok());
-        assert!(fs::metadata(output_dir.join("index.html")).is_ok());
-        assert!(fs::metadata(output_dir.join("images")).is_ok());
-        assert!(fs::metadata(output_dir.join("images/desktop-heroku-pride.jpg")).is_ok());
-        fs::remove_dir_all(output_dir).expect("temporary directory should be deleted");
     }
 
     #[tokio::test]
-    async fn download_specific_or_latest_with_client_specific_no_prefix_succeeds() {
-        let unique = Uuid::new_v4();
-        let output_dir_name = format!("test-output-static-artifacts-{unique}");
-        let output_dir = Path::new(output_dir_name.as_str());
-        fs::remove_dir_all(output_dir).unwrap_or_default();
-
-        let get_object_1 = ReplayEvent::new(
+    async fn upload_with_client_fails_with_request_ids() {
+        let put_object_1 = ReplayEvent::new(
             http::Request::builder()
-                .method("GET")
-                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/static-artifacts.tgz?x-id=GetObject")
+                .method("PUT")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=PutObject")
                 .body(SdkBody::empty())
                 .unwrap(),
             http::Response::builder()
-                .status(200)
-                .body(SdkBody::from(read_fixture_archive_data()))
+                .status(403)
+                .header("x-amz-request-id", "TESTREQUESTID")
+                .header("x-amz-id-2", "TESTEXTENDEDREQUESTID")
+                .body(SdkBody::from(
+                    "<Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>",
+                ))
                 .unwrap(),
         );
-        let replay_client = StaticReplayClient::new(vec![get_object_1]);
+        let replay_client = StaticReplayClient::new(vec![put_object_1]);
         let s3 = aws_sdk_s3::Client::from_conf(
             aws_sdk_s3::Config::builder()
                 .behavior_version(BehaviorVersion::latest())
@@ -678,15 +2405,306 @@ mod tests {
                 .build(),
         );
 
-        let result = download_specific_or_latest_with_client(
+        let result = upload_with_client(
             &s3,
             &"test-bucket".to_string(),
-            &"static-artifacts.tgz".to_string(),
-            output_dir,
+            &"sub/path/static-artifacts.tgz".to_string(),
+            &"test/fixtures/static-artifacts.tgz".to_string(),
         )
         .await;
 
-        println!("{result:#?}");
+        match result {
+            Err(ReleaseArtifactsError::StorageError {
+                message,
+                request_id,
+                extended_request_id,
+                status,
+            }) => {
+                assert_eq!(message, "AccessDenied: Access Denied");
+                assert_eq!(request_id.as_deref(), Some("TESTREQUESTID"));
+                assert_eq!(
+                    extended_request_id.as_deref(),
+                    Some("TESTEXTENDEDREQUESTID")
+                );
+                assert_eq!(status, Some(403));
+            }
+            other => panic!("expected a storage error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn upload_with_client_fails_with_clock_skew() {
+        let server_date = aws_smithy_types::DateTime::from_secs(
+            aws_smithy_types::DateTime::from(std::time::SystemTime::now()).secs() - 3600,
+        )
+        .fmt(aws_smithy_types::date_time::Format::HttpDate)
+        .unwrap();
+        let put_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("PUT")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=PutObject")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(403)
+                .header("date", server_date)
+                .header("x-amz-request-id", "TESTREQUESTID")
+                .body(SdkBody::from(
+                    "<Error><Code>RequestTimeTooSkewed</Code><Message>The difference between the request time and the current time is too large.</Message></Error>",
+                ))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![put_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .retry_config(aws_sdk_s3::config::retry::RetryConfig::disabled())
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = upload_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/static-artifacts.tgz".to_string(),
+            &"test/fixtures/static-artifacts.tgz".to_string(),
+        )
+        .await;
+
+        match result {
+            Err(ReleaseArtifactsError::StorageClockSkewed {
+                message,
+                skew_seconds,
+                request_id,
+            }) => {
+                assert!(message.starts_with("RequestTimeTooSkewed: "));
+                assert!(skew_seconds.is_some_and(|s| (-3610..=-3590).contains(&s)));
+                assert_eq!(request_id.as_deref(), Some("TESTREQUESTID"));
+            }
+            other => panic!("expected a clock skew error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn save_and_load_single_file_file_url_succeeds() {
+        let unique = Uuid::new_v4();
+        let abs_root = env::current_dir().expect("should have a current working directory");
+        let source_root = format!("single-file-artifact-test-{unique}");
+        let source_file = Path::new(&source_root).join("dist/bundle.bin");
+        let storage_dir_path = Path::new(&abs_root).join(format!("{source_root}-storage"));
+        fs::create_dir_all(Path::new(&source_root).join("dist")).unwrap();
+        fs::write(&source_file, "bundle").unwrap();
+
+        let mut test_env = HashMap::new();
+        test_env.insert("RELEASE_ID".to_string(), format!("{unique}"));
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", storage_dir_path.to_string_lossy()).to_string(),
+        );
+
+        let save_result = save(&test_env, &source_file).await;
+        fs::remove_dir_all(&source_root).unwrap_or_default();
+        let destination_dir_path = Path::new(&source_root).join("static-artifacts");
+        let load_result = load(&test_env, &destination_dir_path).await;
+        let restored = fs::read_to_string(&source_file);
+        let destination_exists = destination_dir_path.exists();
+        fs::remove_dir_all(&source_root).unwrap_or_default();
+        fs::remove_dir_all(&storage_dir_path).unwrap_or_default();
+
+        assert!(save_result.is_ok());
+        assert!(load_result.is_ok());
+        assert_eq!(restored.unwrap(), "bundle");
+        assert!(!destination_exists);
+    }
+
+    #[tokio::test]
+    async fn load_file_url_succeeds() {
+        let unique = Uuid::new_v4();
+        let abs_root = env::current_dir().expect("should have a current working directory");
+        let source_archive_dir_path = Path::new(&abs_root).join("test/fixtures");
+        let destination_dir_path =
+            Path::new(&abs_root).join(format!("static-artifacts-test-{unique}"));
+
+        let mut test_env = HashMap::new();
+        test_env.insert("RELEASE_ID".to_string(), "xxxxx".to_string());
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", source_archive_dir_path.to_string_lossy()).to_string(),
+        );
+
+        let result = load(&test_env, &destination_dir_path).await;
+
+        eprintln!("{result:?}");
+        assert!(result.is_ok());
+        assert!(fs::metadata(&destination_dir_path).is_ok());
+        assert!(fs::metadata(destination_dir_path.join("index.html")).is_ok());
+        assert!(fs::metadata(destination_dir_path.join("images")).is_ok());
+        assert!(fs::metadata(destination_dir_path.join("images/desktop-heroku-pride.jpg")).is_ok());
+        let report_path = extraction_report_path(&test_env, &destination_dir_path).unwrap();
+        let report = ExtractionReport::read(&report_path).unwrap();
+        assert_eq!(report.loaded_key, "release-xxxxx.tgz");
+        assert!(report.files.contains_key("images/desktop-heroku-pride.jpg"));
+        assert!(report.verify(&destination_dir_path).unwrap().is_empty());
+        fs::remove_file(report_path).unwrap_or_default();
+        fs::remove_file(loaded_key_path(&destination_dir_path).unwrap()).unwrap_or_default();
+        fs::remove_file(load_lock_path(&destination_dir_path).unwrap()).unwrap_or_default();
+        fs::remove_dir_all(destination_dir_path).expect("temporary directory should be deleted");
+    }
+
+    #[tokio::test]
+    async fn load_file_url_falls_back_to_next_url() {
+        let unique = Uuid::new_v4();
+        let abs_root = env::current_dir().expect("should have a current working directory");
+        let missing_archive_dir_path =
+            Path::new(&abs_root).join(format!("static-artifacts-missing-{unique}"));
+        let source_archive_dir_path = Path::new(&abs_root).join("test/fixtures");
+        let destination_dir_path =
+            Path::new(&abs_root).join(format!("static-artifacts-test-{unique}"));
+
+        let mut test_env = HashMap::new();
+        test_env.insert("RELEASE_ID".to_string(), "xxxxx".to_string());
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!(
+                "file://{}, file://{}",
+                missing_archive_dir_path.to_string_lossy(),
+                source_archive_dir_path.to_string_lossy()
+            ),
+        );
+
+        let result = load(&test_env, &destination_dir_path).await;
+
+        eprintln!("{result:?}");
+        assert!(result.is_ok());
+        assert!(fs::metadata(destination_dir_path.join("index.html")).is_ok());
+        fs::remove_dir_all(missing_archive_dir_path).unwrap_or_default();
+        fs::remove_file(extraction_report_path(&test_env, &destination_dir_path).unwrap())
+            .unwrap_or_default();
+        fs::remove_file(loaded_key_path(&destination_dir_path).unwrap()).unwrap_or_default();
+        fs::remove_file(load_lock_path(&destination_dir_path).unwrap()).unwrap_or_default();
+        fs::remove_dir_all(destination_dir_path).expect("temporary directory should be deleted");
+    }
+
+    #[test]
+    fn storage_urls_splits_primary_and_fallbacks() {
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            "s3://primary-bucket.s3.us-east-1.amazonaws.com, s3://fallback-bucket.s3.us-west-2.amazonaws.com,".to_string(),
+        );
+        assert_eq!(
+            storage_urls(&test_env),
+            vec![
+                "s3://primary-bucket.s3.us-east-1.amazonaws.com",
+                "s3://fallback-bucket.s3.us-west-2.amazonaws.com"
+            ]
+        );
+        assert_eq!(
+            detect_storage_scheme(&test_env).expect("should parse the primary URL"),
+            "s3"
+        );
+        let (bucket_name, bucket_region, _) =
+            generate_s3_storage_location(&test_env, &"test-name.tgz".to_string())
+                .expect("should use the primary URL");
+        assert_eq!(bucket_name, "primary-bucket");
+        assert_eq!(bucket_region, Some("us-east-1".to_string()));
+
+        scope_storage_to_prefix(&mut test_env, "docs").unwrap();
+        assert_eq!(
+            test_env["STATIC_ARTIFACTS_URL"],
+            "s3://primary-bucket.s3.us-east-1.amazonaws.com/docs,s3://fallback-bucket.s3.us-west-2.amazonaws.com/docs"
+        );
+    }
+
+    #[tokio::test]
+    async fn download_specific_or_latest_with_client_specific_succeeds() {
+        let unique = Uuid::new_v4();
+        let output_dir_name = format!("test-output-static-artifacts-{unique}");
+        let output_dir = Path::new(output_dir_name.as_str());
+        fs::remove_dir_all(output_dir).unwrap_or_default();
+
+        let get_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=GetObject")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(read_fixture_archive_data()))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![get_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = download_specific_or_latest_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/static-artifacts.tgz".to_string(),
+            output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
+        )
+        .await;
+
+        println!("{result:#?}");
+        assert!(result.is_ok());
+        replay_client.assert_requests_match(&[]);
+        assert!(fs::metadata(output_dir).is_ok());
+        assert!(fs::metadata(output_dir.join("index.html")).is_ok());
+        assert!(fs::metadata(output_dir.join("images")).is_ok());
+        assert!(fs::metadata(output_dir.join("images/desktop-heroku-pride.jpg")).is_ok());
+        fs::remove_dir_all(output_dir).expect("temporary directory should be deleted");
+    }
+
+    #[tokio::test]
+    async fn download_specific_or_latest_with_client_specific_no_prefix_succeeds() {
+        let unique = Uuid::new_v4();
+        let output_dir_name = format!("test-output-static-artifacts-{unique}");
+        let output_dir = Path::new(output_dir_name.as_str());
+        fs::remove_dir_all(output_dir).unwrap_or_default();
+
+        let get_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/static-artifacts.tgz?x-id=GetObject")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(read_fixture_archive_data()))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![get_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = download_specific_or_latest_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"static-artifacts.tgz".to_string(),
+            output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
+        )
+        .await;
+
+        println!("{result:#?}");
         assert!(result.is_ok());
         replay_client.assert_requests_match(&[]);
         assert!(fs::metadata(output_dir).is_ok());
@@ -772,6 +2790,8 @@ mod tests {
             &"test-bucket".to_string(),
             &"sub/path/static-artifacts.tgz".to_string(),
             output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
         )
         .await;
 
@@ -862,6 +2882,8 @@ mod tests {
             &"test-bucket".to_string(),
             &"static-artifacts.tgz".to_string(),
             output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
         )
         .await;
 
@@ -927,6 +2949,8 @@ mod tests {
             &"test-bucket".to_string(),
             &"sub/path/static-artifacts.tgz".to_string(),
             output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
         )
         .await;
 
@@ -946,35 +2970,840 @@ mod tests {
         let get_object_1 = ReplayEvent::new(
             http::Request::builder()
                 .method("GET")
-                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/static-artifacts.tgz?x-id=GetObject")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/static-artifacts.tgz?x-id=GetObject")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(404)
+                .body(SdkBody::from(r"
+                    <Error>
+                        <Code>NoSuchKey</Code>
+                    </Error>",
+                ))
+                .unwrap(),
+        );
+        let list_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(
+                    r"
+                    <ListBucketResult>
+                        <IsTruncated>false</IsTruncated>
+                    </ListBucketResult>",
+                ))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![get_object_1, list_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = download_specific_or_latest_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"static-artifacts.tgz".to_string(),
+            output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
+        )
+        .await;
+
+        println!("{result:#?}");
+        assert!(result.is_err());
+        replay_client.assert_requests_match(&[]);
+        assert!(fs::metadata(output_dir).is_err());
+    }
+
+    #[tokio::test]
+    async fn download_with_client_succeeds() {
+        let unique = Uuid::new_v4();
+        let output_dir_name = format!("test-output-static-artifacts-{unique}");
+        let output_dir = Path::new(output_dir_name.as_str());
+        fs::remove_dir_all(output_dir).unwrap_or_default();
+
+        let get_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=GetObject")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(read_fixture_archive_data()))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![get_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = download_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/static-artifacts.tgz".to_string(),
+            output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
+        )
+        .await;
+
+        assert!(result.is_ok());
+        replay_client.assert_requests_match(&[]);
+        assert!(fs::metadata(output_dir).is_ok());
+        assert!(fs::metadata(output_dir.join("index.html")).is_ok());
+        assert!(fs::metadata(output_dir.join("images")).is_ok());
+        assert!(fs::metadata(output_dir.join("images/desktop-heroku-pride.jpg")).is_ok());
+        fs::remove_dir_all(output_dir).expect("temporary directory should be deleted");
+    }
+
+    #[tokio::test]
+    async fn download_with_client_rejects_corrupted_archive_without_extracting() {
+        let unique = Uuid::new_v4();
+        let output_dir_name = format!("test-output-static-artifacts-{unique}");
+        let output_dir = Path::new(output_dir_name.as_str());
+
+        let get_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=GetObject")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .header("x-amz-meta-release-phase-sha256", "0".repeat(64))
+                .body(SdkBody::from(read_fixture_archive_data()))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![get_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = download_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/static-artifacts.tgz".to_string(),
+            output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
+        )
+        .await;
+
+        assert!(
+            matches!(result, Err(ReleaseArtifactsError::ChecksumMismatch(_))),
+            "{result:?}"
+        );
+        assert!(!output_dir.exists());
+        let staging_prefix = format!(".{output_dir_name}.loading-");
+        assert!(!fs::read_dir(".").unwrap().any(|entry| entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&staging_prefix)));
+    }
+
+    #[tokio::test]
+    async fn prefetch_with_client_downloads_latest() {
+        let unique = Uuid::new_v4();
+        let archive_path = env::temp_dir().join(format!("test-prefetch-{unique}.tgz"));
+
+        let list_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(r"
+                    <ListBucketResult>
+                        <IsTruncated>false</IsTruncated>
+                        <Contents>
+                            <Key>sub/path/v100.tgz</Key>
+                            <LastModified>2024-07-01T12:20:47.000Z</LastModified>
+                        </Contents>
+                        <Contents>
+                            <Key>sub/path/v101.tgz</Key>
+                            <LastModified>2024-07-01T19:40:05.000Z</LastModified>
+                        </Contents>
+                    </ListBucketResult>",
+                ))
+                .unwrap(),
+        );
+        let get_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/v101.tgz?x-id=GetObject")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(read_fixture_archive_data()))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![list_object_1, get_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = prefetch_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/".to_string(),
+            &archive_path,
+            Some("sub/path/v100.tgz"),
+        )
+        .await;
+
+        replay_client.assert_requests_match(&[]);
+        assert_eq!(result.unwrap(), Some("sub/path/v101.tgz".to_string()));
+        assert_eq!(
+            fs::metadata(&archive_path).unwrap().len(),
+            read_fixture_archive_data().len() as u64
+        );
+        fs::remove_file(&archive_path).expect("prefetched archive should be deleted");
+    }
+
+    #[tokio::test]
+    async fn prefetch_with_client_skips_cached_latest() {
+        let unique = Uuid::new_v4();
+        let archive_path = env::temp_dir().join(format!("test-prefetch-{unique}.tgz"));
+        fs::write(&archive_path, read_fixture_archive_data()).unwrap();
+
+        let list_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(
+                    r"
+                    <ListBucketResult>
+                        <IsTruncated>false</IsTruncated>
+                        <Contents>
+                            <Key>v100.tgz</Key>
+                            <LastModified>2024-07-01T12:20:47.000Z</LastModified>
+                        </Contents>
+                    </ListBucketResult>",
+                ))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![list_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = prefetch_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &String::new(),
+            &archive_path,
+            Some("v100.tgz"),
+        )
+        .await;
+
+        replay_client.assert_requests_match(&[]);
+        assert_eq!(result.unwrap(), Some("v100.tgz".to_string()));
+        fs::remove_file(&archive_path).expect("prefetched archive should be deleted");
+    }
+
+    #[tokio::test]
+    async fn load_prefetched_with_client_extracts_current_archive() {
+        let unique = Uuid::new_v4();
+        let archive_path = env::temp_dir().join(format!("test-prefetched-{unique}.tgz"));
+        fs::write(&archive_path, read_fixture_archive_data()).unwrap();
+        checksum::write_checksum_file(
+            &archive_path,
+            &checksum::file_checksum(&archive_path).unwrap(),
+        )
+        .unwrap();
+        let output_dir_name = format!("test-output-static-artifacts-{unique}");
+        let output_dir = Path::new(output_dir_name.as_str());
+
+        let head_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("HEAD")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/release-v103.tgz")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::empty())
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![head_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = load_prefetched_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/release-v103.tgz".to_string(),
+            &"sub/path/release-v103.tgz".to_string(),
+            &archive_path,
+            output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
+        )
+        .await;
+
+        replay_client.assert_requests_match(&[]);
+        assert_eq!(
+            result.unwrap(),
+            Some("sub/path/release-v103.tgz".to_string())
+        );
+        assert!(fs::metadata(output_dir.join("index.html")).is_ok());
+        assert!(fs::metadata(output_dir.join("images/desktop-heroku-pride.jpg")).is_ok());
+        fs::remove_dir_all(output_dir).expect("temporary directory should be deleted");
+        fs::remove_file(checksum::checksum_path(&archive_path)).unwrap();
+        fs::remove_file(&archive_path).expect("prefetched archive should be deleted");
+    }
+
+    #[tokio::test]
+    async fn load_prefetched_with_client_skips_previous_archive() {
+        let unique = Uuid::new_v4();
+        let archive_path = env::temp_dir().join(format!("test-prefetched-{unique}.tgz"));
+        fs::write(&archive_path, read_fixture_archive_data()).unwrap();
+        let output_dir_name = format!("test-output-static-artifacts-{unique}");
+        let output_dir = Path::new(output_dir_name.as_str());
+
+        let head_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("HEAD")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/release-v103.tgz")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::empty())
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![head_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = load_prefetched_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/release-v103.tgz".to_string(),
+            &"sub/path/release-v102.tgz".to_string(),
+            &archive_path,
+            output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
+        )
+        .await;
+
+        replay_client.assert_requests_match(&[]);
+        assert_eq!(result.unwrap(), None);
+        assert!(fs::metadata(output_dir).is_err());
+        fs::remove_file(&archive_path).expect("prefetched archive should be deleted");
+    }
+
+    #[tokio::test]
+    async fn resolve_specific_or_latest_key_with_client_falls_back_to_latest() {
+        let head_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("HEAD")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/release-v103.tgz")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(404)
+                .body(SdkBody::empty())
+                .unwrap(),
+        );
+        let list_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(r"
+                    <ListBucketResult>
+                        <IsTruncated>false</IsTruncated>
+                        <Contents>
+                            <Key>sub/path/release-v102.tgz</Key>
+                            <LastModified>2024-07-04T04:51:50.000Z</LastModified>
+                        </Contents>
+                    </ListBucketResult>",
+                ))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![head_object_1, list_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = resolve_specific_or_latest_key_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/release-v103.tgz".to_string(),
+        )
+        .await;
+
+        replay_client.assert_requests_match(&[]);
+        assert_eq!(
+            result.unwrap(),
+            Some("sub/path/release-v102.tgz".to_string())
+        );
+    }
+
+    #[test]
+    fn parent_key_prefix_includes_trailing_slash() {
+        assert_eq!(parent_key_prefix("sub/path/release-v1.tgz"), "sub/path/");
+        assert_eq!(parent_key_prefix("release-v1.tgz"), "");
+    }
+
+    #[tokio::test]
+    async fn find_latest_with_client_succeeds() {
+        let list_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(r"
+                    <ListBucketResult>
+                        <IsTruncated>false</IsTruncated>
+                        <Contents>
+                            <Key>v100.tgz</Key>
+                            <LastModified>2024-07-01T12:20:47.000Z</LastModified>
+                        </Contents>
+                        <Contents>
+                            <Key>v102.tgz</Key>
+                            <LastModified>2024-07-04T04:51:50.000Z</LastModified>
+                        </Contents>
+                        <Contents>
+                            <Key>v101.tgz</Key>
+                            <LastModified>2024-07-01T19:40:05.000Z</LastModified>
+                        </Contents>
+                    </ListBucketResult>",
+                ))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![list_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result =
+            find_latest_with_client(&s3, &"test-bucket".to_string(), &"sub/path/".to_string())
+                .await;
+
+        println!("find_latest_with_client_succeeds result {result:#?}");
+        assert!(result.is_ok());
+        replay_client.assert_requests_match(&[]);
+        assert!(result
+            .expect("should be ok")
+            .is_some_and(|f| f == "v102.tgz"));
+    }
+
+    #[tokio::test]
+    async fn find_latest_with_client_skips_artifact_channels() {
+        let list_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(r"
+                    <ListBucketResult>
+                        <IsTruncated>false</IsTruncated>
+                        <Contents>
+                            <Key>sub/path/release-v100.tgz</Key>
+                            <LastModified>2024-07-01T12:20:47.000Z</LastModified>
+                        </Contents>
+                        <Contents>
+                            <Key>sub/path/docs/release-v101.tgz</Key>
+                            <LastModified>2024-07-01T19:40:05.000Z</LastModified>
+                        </Contents>
+                    </ListBucketResult>",
+                ))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![list_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result =
+            find_latest_with_client(&s3, &"test-bucket".to_string(), &"sub/path/".to_string())
+                .await;
+
+        replay_client.assert_requests_match(&[]);
+        assert_eq!(
+            result.expect("should be ok"),
+            Some("sub/path/release-v100.tgz".to_string())
+        );
+    }
+
+    #[test]
+    fn scope_storage_to_prefix_appends_to_url_path() {
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            "s3://test-bucket/sub/path/".to_string(),
+        );
+        scope_storage_to_prefix(&mut test_env, "docs").unwrap();
+        assert_eq!(
+            test_env["STATIC_ARTIFACTS_URL"],
+            "s3://test-bucket/sub/path/docs"
+        );
+
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            "s3://test-bucket".to_string(),
+        );
+        scope_storage_to_prefix(&mut test_env, "/docs/").unwrap();
+        assert_eq!(test_env["STATIC_ARTIFACTS_URL"], "s3://test-bucket/docs");
+
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            "file:///tmp/artifacts".to_string(),
+        );
+        scope_storage_to_prefix(&mut test_env, "docs").unwrap();
+        assert_eq!(
+            test_env["STATIC_ARTIFACTS_URL"],
+            "file:///tmp/artifacts/docs"
+        );
+    }
+
+    #[test]
+    fn scope_storage_to_prefix_requires_url() {
+        let mut test_env: HashMap<String, String> = HashMap::new();
+        assert!(matches!(
+            scope_storage_to_prefix(&mut test_env, "docs"),
+            Err(ReleaseArtifactsError::StorageURLMissing)
+        ));
+    }
+
+    #[tokio::test]
+    async fn find_latest_with_client_empty() {
+        let list_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(r"
+                    <ListBucketResult>
+                        <IsTruncated>false</IsTruncated>
+                    </ListBucketResult>",
+                ))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![list_object_1]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result =
+            find_latest_with_client(&s3, &"test-bucket".to_string(), &"sub/path/".to_string())
+                .await;
+
+        println!("find_latest_with_client_succeeds result {result:#?}");
+        assert!(result.is_ok());
+        replay_client.assert_requests_match(&[]);
+        assert!(result.expect("should be ok").is_none());
+    }
+
+    #[tokio::test]
+    async fn list_with_client_succeeds_across_pages() {
+        let list_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(r"
+                    <ListBucketResult>
+                        <IsTruncated>true</IsTruncated>
+                        <NextContinuationToken>page-2</NextContinuationToken>
+                        <Contents>
+                            <Key>sub/path/release-v102.tgz</Key>
+                            <LastModified>2024-07-04T04:51:50.000Z</LastModified>
+                            <Size>300</Size>
+                        </Contents>
+                        <Contents>
+                            <Key>sub/path/release-v100.tgz</Key>
+                            <LastModified>2024-07-01T12:20:47.000Z</LastModified>
+                            <Size>100</Size>
+                        </Contents>
+                    </ListBucketResult>",
+                ))
+                .unwrap(),
+        );
+        let list_object_2 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F&continuation-token=page-2")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(r"
+                    <ListBucketResult>
+                        <IsTruncated>false</IsTruncated>
+                        <Contents>
+                            <Key>sub/path/release-v101.tgz</Key>
+                            <LastModified>2024-07-01T19:40:05.000Z</LastModified>
+                            <Size>200</Size>
+                        </Contents>
+                    </ListBucketResult>",
+                ))
+                .unwrap(),
+        );
+        let replay_client = StaticReplayClient::new(vec![list_object_1, list_object_2]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+
+        let result = list_with_client(&s3, &"test-bucket".to_string(), &"sub/path/".to_string())
+            .await
+            .expect("should be ok");
+
+        replay_client.assert_requests_match(&[]);
+        let keys: Vec<&str> = result.iter().map(|a| a.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "sub/path/release-v100.tgz",
+                "sub/path/release-v101.tgz",
+                "sub/path/release-v102.tgz"
+            ]
+        );
+        assert_eq!(result.iter().map(|a| a.size).sum::<u64>(), 600);
+    }
+
+    #[tokio::test]
+    async fn list_stored_archives_file_url_succeeds() {
+        let unique = Uuid::new_v4();
+        let abs_root = env::current_dir().expect("should have a current working directory");
+        let storage_dir_path = Path::new(&abs_root).join(format!("test-list-storage-{unique}"));
+        fs::create_dir_all(&storage_dir_path).expect("storage directory should be created");
+        fs::write(storage_dir_path.join("release-v1.tgz"), b"12345")
+            .expect("archive should be written");
+        fs::write(storage_dir_path.join("release-v2.tgz"), b"1234567890")
+            .expect("archive should be written");
+        fs::create_dir_all(storage_dir_path.join("nested"))
+            .expect("nested directory should be created");
+
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", storage_dir_path.to_string_lossy()),
+        );
+
+        let result = list_stored_archives(&test_env).await;
+        fs::remove_dir_all(&storage_dir_path).expect("temporary directory should be deleted");
+
+        let archives = result.expect("should be ok");
+        assert_eq!(archives.len(), 2);
+        assert_eq!(archives.iter().map(|a| a.size).sum::<u64>(), 15);
+        assert!(archives.iter().any(|a| a.key == "release-v1.tgz"));
+        assert!(archives.iter().any(|a| a.key == "release-v2.tgz"));
+    }
+
+    #[tokio::test]
+    async fn list_stored_archives_s3_requires_credentials() {
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            "s3://test-bucket/sub/path".to_string(),
+        );
+
+        let result = list_stored_archives(&test_env).await;
+        assert!(matches!(
+            result,
+            Err(ReleaseArtifactsError::ConfigMissing(_))
+        ));
+    }
+
+    #[test]
+    fn generate_s3_storage_prefix_with_and_without_path() {
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            "s3://xxxxx.s3.us-west-2.amazonaws.com/yyyyy/zzzzz/".to_string(),
+        );
+        let result = generate_s3_storage_prefix(&test_env).expect("result is ok");
+        assert_eq!(
+            result,
+            (
+                "xxxxx".to_string(),
+                Some("us-west-2".to_string()),
+                "yyyyy/zzzzz/".to_string()
+            )
+        );
+
+        test_env.insert("STATIC_ARTIFACTS_URL".to_string(), "s3://xxxxx".to_string());
+        let result = generate_s3_storage_prefix(&test_env).expect("result is ok");
+        assert_eq!(result, ("xxxxx".to_string(), None, String::new()));
+    }
+
+    #[test]
+    fn format_size_uses_binary_units() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[tokio::test]
+    async fn inspect_file_url_succeeds() {
+        let abs_root = env::current_dir().expect("should have a current working directory");
+        let source_archive_dir_path = Path::new(&abs_root).join("test/fixtures");
+
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", source_archive_dir_path.to_string_lossy()).to_string(),
+        );
+
+        let result = inspect(&test_env, "xxxxx").await;
+
+        eprintln!("{result:?}");
+        let entries = result.expect("archive should be listed");
+        assert_eq!(entries.len(), 6);
+        assert!(entries.contains(&ArchiveEntry {
+            path: "./images/desktop-heroku-pride.jpg".to_string(),
+            size: 515_230,
+            mode: 0o644,
+            kind: ArchiveEntryKind::File,
+            link_target: None,
+        }));
+        assert!(entries
+            .iter()
+            .any(|e| e.path == "./images/" && e.kind == ArchiveEntryKind::Directory));
+    }
+
+    #[tokio::test]
+    async fn inspect_file_url_fails_for_missing_release() {
+        let abs_root = env::current_dir().expect("should have a current working directory");
+        let source_archive_dir_path = Path::new(&abs_root).join("test/fixtures");
+
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", source_archive_dir_path.to_string_lossy()).to_string(),
+        );
+
+        let result = inspect(&test_env, "does-not-exist").await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn inspect_with_client_without_manifest_succeeds() {
+        let get_object_1 = ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=GetObject")
+                .header("range", "bytes=0-262143")
                 .body(SdkBody::empty())
                 .unwrap(),
             http::Response::builder()
-                .status(404)
-                .body(SdkBody::from(r"
-                    <Error>
-                        <Code>NoSuchKey</Code>
-                    </Error>",
+                .status(206)
+                .body(SdkBody::from(
+                    read_fixture_archive_data()[..262_144].to_vec(),
                 ))
                 .unwrap(),
         );
-        let list_object_1 = ReplayEvent::new(
+        let get_object_2 = ReplayEvent::new(
             http::Request::builder()
                 .method("GET")
-                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=")
+                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=GetObject")
                 .body(SdkBody::empty())
                 .unwrap(),
             http::Response::builder()
                 .status(200)
-                .body(SdkBody::from(
-                    r"
-                    <ListBucketResult>
-                        <IsTruncated>false</IsTruncated>
-                    </ListBucketResult>",
-                ))
+                .body(SdkBody::from(read_fixture_archive_data()))
                 .unwrap(),
         );
-        let replay_client = StaticReplayClient::new(vec![get_object_1, list_object_1]);
+        let replay_client = StaticReplayClient::new(vec![get_object_1, get_object_2]);
         let s3 = aws_sdk_s3::Client::from_conf(
             aws_sdk_s3::Config::builder()
                 .behavior_version(BehaviorVersion::latest())
@@ -984,39 +3813,71 @@ mod tests {
                 .build(),
         );
 
-        let result = download_specific_or_latest_with_client(
+        let result = inspect_with_client(
             &s3,
             &"test-bucket".to_string(),
-            &"static-artifacts.tgz".to_string(),
-            output_dir,
+            &"sub/path/static-artifacts.tgz".to_string(),
         )
         .await;
 
-        println!("{result:#?}");
-        assert!(result.is_err());
         replay_client.assert_requests_match(&[]);
-        assert!(fs::metadata(output_dir).is_err());
+        let entries = result.expect("archive should be listed");
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "./",
+                "./index.html",
+                "./.DS_Store",
+                "./images/",
+                "./images/.DS_Store",
+                "./images/desktop-heroku-pride.jpg"
+            ]
+        );
     }
 
     #[tokio::test]
-    async fn download_with_client_succeeds() {
+    async fn fetch_manifest_in_ranges_grows_range_until_complete() {
         let unique = Uuid::new_v4();
-        let output_dir_name = format!("test-output-static-artifacts-{unique}");
-        let output_dir = Path::new(output_dir_name.as_str());
-        fs::remove_dir_all(output_dir).unwrap_or_default();
-
-        let get_object_1 = ReplayEvent::new(
-            http::Request::builder()
-                .method("GET")
-                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/sub/path/static-artifacts.tgz?x-id=GetObject")
-                .body(SdkBody::empty())
-                .unwrap(),
-            http::Response::builder()
-                .status(200)
-                .body(SdkBody::from(read_fixture_archive_data()))
-                .unwrap(),
+        let source_path = PathBuf::from(format!("manifest-range-test-{unique}"));
+        let archive_path = source_path.with_extension("tgz");
+        fs::create_dir_all(&source_path).unwrap();
+        for i in 0..2000 {
+            fs::write(source_path.join(format!("page-{i}.html")), format!("{i}")).unwrap();
+        }
+        create_archive(&source_path, &archive_path).unwrap();
+        let archive_data = fs::read(&archive_path).unwrap();
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+        fs::remove_file(&archive_path).unwrap_or_default();
+
+        let mut events = vec![];
+        let mut range_bytes = 1024_usize;
+        loop {
+            let body = archive_data[..range_bytes.min(archive_data.len())].to_vec();
+            let is_complete = archive::read_manifest(body.as_slice()).is_ok();
+            events.push(ReplayEvent::new(
+                http::Request::builder()
+                    .method("GET")
+                    .uri("https://test-bucket.s3.us-east-1.amazonaws.com/release-v1.tgz?x-id=GetObject")
+                    .header("range", format!("bytes=0-{}", range_bytes - 1))
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(206)
+                    .body(SdkBody::from(body))
+                    .unwrap(),
+            ));
+            if is_complete {
+                break;
+            }
+            range_bytes *= 4;
+        }
+        assert!(events.len() > 1, "manifest should not fit the first range");
+        assert!(
+            range_bytes < archive_data.len(),
+            "archive should not be read whole"
         );
-        let replay_client = StaticReplayClient::new(vec![get_object_1]);
+        let replay_client = StaticReplayClient::new(events);
         let s3 = aws_sdk_s3::Client::from_conf(
             aws_sdk_s3::Config::builder()
                 .behavior_version(BehaviorVersion::latest())
@@ -1026,109 +3887,29 @@ mod tests {
                 .build(),
         );
 
-        let result = download_with_client(
+        let result = fetch_manifest_in_ranges(
             &s3,
             &"test-bucket".to_string(),
-            &"sub/path/static-artifacts.tgz".to_string(),
-            output_dir,
+            &"release-v1.tgz".to_string(),
+            1024,
         )
         .await;
 
-        assert!(result.is_ok());
-        replay_client.assert_requests_match(&[]);
-        assert!(fs::metadata(output_dir).is_ok());
-        assert!(fs::metadata(output_dir.join("index.html")).is_ok());
-        assert!(fs::metadata(output_dir.join("images")).is_ok());
-        assert!(fs::metadata(output_dir.join("images/desktop-heroku-pride.jpg")).is_ok());
-        fs::remove_dir_all(output_dir).expect("temporary directory should be deleted");
-    }
-
-    #[tokio::test]
-    async fn find_latest_with_client_succeeds() {
-        let list_object_1 = ReplayEvent::new(
-            http::Request::builder()
-                .method("GET")
-                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F")
-                .body(SdkBody::empty())
-                .unwrap(),
-            http::Response::builder()
-                .status(200)
-                .body(SdkBody::from(r"
-                    <ListBucketResult>
-                        <IsTruncated>false</IsTruncated>
-                        <Contents>
-                            <Key>v100.tgz</Key>
-                            <LastModified>2024-07-01T12:20:47.000Z</LastModified>
-                        </Contents>
-                        <Contents>
-                            <Key>v102.tgz</Key>
-                            <LastModified>2024-07-04T04:51:50.000Z</LastModified>
-                        </Contents>
-                        <Contents>
-                            <Key>v101.tgz</Key>
-                            <LastModified>2024-07-01T19:40:05.000Z</LastModified>
-                        </Contents>
-                    </ListBucketResult>",
-                ))
-                .unwrap(),
-        );
-        let replay_client = StaticReplayClient::new(vec![list_object_1]);
-        let s3 = aws_sdk_s3::Client::from_conf(
-            aws_sdk_s3::Config::builder()
-                .behavior_version(BehaviorVersion::latest())
-                .credentials_provider(make_s3_test_credentials())
-                .region(aws_sdk_s3::config::Region::new("us-east-1"))
-                .http_client(replay_client.clone())
-                .build(),
-        );
-
-        let result =
-            find_latest_with_client(&s3, &"test-bucket".to_string(), &"sub/path/".to_string())
-                .await;
-
-        println!("find_latest_with_client_succeeds result {result:#?}");
-        assert!(result.is_ok());
         replay_client.assert_requests_match(&[]);
-        assert!(result
-            .expect("should be ok")
-            .is_some_and(|f| f == "v102.tgz"));
+        let entries = result.unwrap().expect("archive should have a manifest");
+        assert_eq!(entries.len(), 2000);
+        assert_eq!(entries[0].path, "page-0.html");
     }
 
-    #[tokio::test]
-    async fn find_latest_with_client_empty() {
-        let list_object_1 = ReplayEvent::new(
-            http::Request::builder()
-                .method("GET")
-                .uri("https://test-bucket.s3.us-east-1.amazonaws.com/?list-type=2&prefix=sub%2Fpath%2F")
-                .body(SdkBody::empty())
-                .unwrap(),
-            http::Response::builder()
-                .status(200)
-                .body(SdkBody::from(r"
-                    <ListBucketResult>
-                        <IsTruncated>false</IsTruncated>
-                    </ListBucketResult>",
-                ))
-                .unwrap(),
-        );
-        let replay_client = StaticReplayClient::new(vec![list_object_1]);
-        let s3 = aws_sdk_s3::Client::from_conf(
-            aws_sdk_s3::Config::builder()
-                .behavior_version(BehaviorVersion::latest())
-                .credentials_provider(make_s3_test_credentials())
-                .region(aws_sdk_s3::config::Region::new("us-east-1"))
-                .http_client(replay_client.clone())
-                .build(),
+    #[test]
+    fn format_mode_matches_ls() {
+        assert_eq!(format_mode(ArchiveEntryKind::File, 0o644), "-rw-r--r--");
+        assert_eq!(
+            format_mode(ArchiveEntryKind::Directory, 0o755),
+            "drwxr-xr-x"
         );
-
-        let result =
-            find_latest_with_client(&s3, &"test-bucket".to_string(), &"sub/path/".to_string())
-                .await;
-
-        println!("find_latest_with_client_succeeds result {result:#?}");
-        assert!(result.is_ok());
-        replay_client.assert_requests_match(&[]);
-        assert!(result.expect("should be ok").is_none());
+        assert_eq!(format_mode(ArchiveEntryKind::Symlink, 0o777), "lrwxrwxrwx");
+        assert_eq!(format_mode(ArchiveEntryKind::Other, 0o4750), "?rwxr-x---");
     }
 
     fn read_fixture_archive_data() -> std::vec::Vec<u8> {
@@ -1162,6 +3943,42 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn guard_s3_checks_endpoint_url() {
+        let mut test_env = HashMap::new();
+        test_env.insert("RELEASE_ID".to_string(), "test-release-id".to_string());
+        test_env.insert(
+            "STATIC_ARTIFACTS_ACCESS_KEY_ID".to_string(),
+            "test-key".to_string(),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_SECRET_ACCESS_KEY".to_string(),
+            "test-secret".to_string(),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            "s3://test-bucket/sub/path".to_string(),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_ENDPOINT_URL".to_string(),
+            "http://minio.local:9000".to_string(),
+        );
+        assert!(guard_s3(&test_env).is_ok());
+
+        test_env.insert(
+            "STATIC_ARTIFACTS_ENDPOINT_URL".to_string(),
+            "minio.local:9000".to_string(),
+        );
+        assert!(matches!(
+            guard_s3(&test_env),
+            Err(ReleaseArtifactsError::ConfigInvalid(_))
+        ));
+        assert!(matches!(
+            guard_s3_credentials(&test_env),
+            Err(ReleaseArtifactsError::ConfigInvalid(_))
+        ));
+    }
+
     #[test]
     fn guard_s3_should_fail_missing_requirements() {
         let mut test_env = HashMap::new();
@@ -1259,7 +4076,7 @@ mod tests {
     fn generate_archive_name_with_release_id() {
         let mut test_env = HashMap::new();
         test_env.insert("RELEASE_ID".to_string(), "xxxxx".to_string());
-        let result = generate_archive_name(&test_env);
+        let result = generate_archive_name(&test_env).unwrap();
         assert_eq!(result, "release-xxxxx.tgz".to_string());
     }
 
@@ -1268,7 +4085,7 @@ mod tests {
     fn generate_archive_name_without_release_id() {
         let test_env = HashMap::new();
 
-        let result = generate_archive_name(&test_env);
+        let result = generate_archive_name(&test_env).unwrap();
         assert!(result.starts_with("artifact-"));
         assert!(result.ends_with(".tgz"));
     }
@@ -1445,6 +4262,154 @@ mod tests {
             .is_some_and(|r| r.to_string() == "us-east-1"));
     }
 
+    #[tokio::test]
+    async fn generate_s3_client_reuses_client_for_same_credentials_and_region() {
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_ACCESS_KEY_ID".to_string(),
+            "test-key-id".to_string(),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_SECRET_ACCESS_KEY".to_string(),
+            "test-key-secret".to_string(),
+        );
+        let cached_count = || async {
+            STORAGE_CLIENTS
+                .try_with(Arc::clone)
+                .expect("clients should be cached")
+                .s3
+                .lock()
+                .await
+                .len()
+        };
+
+        with_storage_clients(async {
+            generate_s3_client(&test_env, Some("us-west-1".to_string())).await;
+            generate_s3_client(&test_env, Some("us-west-1".to_string())).await;
+            assert_eq!(cached_count().await, 1);
+            generate_s3_client(&test_env, Some("eu-west-1".to_string())).await;
+            assert_eq!(cached_count().await, 2);
+            test_env.insert(
+                "STATIC_ARTIFACTS_FAULTS".to_string(),
+                "error=0.5".to_string(),
+            );
+            generate_s3_client(&test_env, Some("eu-west-1".to_string())).await;
+            assert_eq!(cached_count().await, 3);
+            test_env.insert(
+                "STATIC_ARTIFACTS_ENDPOINT_URL".to_string(),
+                "http://minio.local:9000".to_string(),
+            );
+            generate_s3_client(&test_env, Some("eu-west-1".to_string())).await;
+            assert_eq!(cached_count().await, 4);
+            test_env.insert(
+                "STATIC_ARTIFACTS_FORCE_PATH_STYLE".to_string(),
+                "0".to_string(),
+            );
+            generate_s3_client(&test_env, Some("eu-west-1".to_string())).await;
+            assert_eq!(cached_count().await, 5);
+            test_env.insert(
+                "STATIC_ARTIFACTS_SESSION_TOKEN".to_string(),
+                "test-session-token".to_string(),
+            );
+            generate_s3_client(&test_env, Some("eu-west-1".to_string())).await;
+            assert_eq!(cached_count().await, 6);
+            test_env.insert("STATIC_ARTIFACTS_MAX_RETRIES".to_string(), "5".to_string());
+            generate_s3_client(&test_env, Some("eu-west-1".to_string())).await;
+            assert_eq!(cached_count().await, 7);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn storage_clients_are_not_cached_beyond_the_operation() {
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_ACCESS_KEY_ID".to_string(),
+            "test-key-id".to_string(),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_SECRET_ACCESS_KEY".to_string(),
+            "test-key-secret".to_string(),
+        );
+
+        generate_s3_client(&test_env, None).await;
+        assert!(STORAGE_CLIENTS.try_with(|_| ()).is_err());
+        let outer = with_storage_clients(async {
+            generate_s3_client(&test_env, None).await;
+            let outer = STORAGE_CLIENTS
+                .try_with(Arc::clone)
+                .expect("should be scoped");
+            // A nested operation shares the clients of the one it is part of.
+            with_storage_clients(async {
+                let inner = STORAGE_CLIENTS
+                    .try_with(Arc::clone)
+                    .expect("should be scoped");
+                assert!(Arc::ptr_eq(&outer, &inner));
+            })
+            .await;
+            outer
+        })
+        .await;
+        assert_eq!(outer.s3.lock().await.len(), 1);
+        assert!(STORAGE_CLIENTS.try_with(|_| ()).is_err());
+    }
+
+    #[tokio::test]
+    async fn s3_http_client_is_shared_per_tuning() {
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_KEEPALIVE_SECONDS".to_string(),
+            "86401".to_string(),
+        );
+        let tuned_count = || {
+            STORAGE_CLIENTS
+                .try_with(Arc::clone)
+                .expect("HTTP clients should be shared")
+                .http
+                .lock()
+                .unwrap()
+                .len()
+        };
+
+        with_storage_clients(async {
+            s3_http_client(&test_env);
+            s3_http_client(&test_env);
+            assert_eq!(tuned_count(), 1);
+            test_env.insert(
+                "STATIC_ARTIFACTS_MAX_IDLE_CONNECTIONS".to_string(),
+                "0".to_string(),
+            );
+            s3_http_client(&test_env);
+            assert_eq!(tuned_count(), 2);
+        })
+        .await;
+    }
+
+    #[test]
+    fn parse_tuning_var_ignores_invalid_values() {
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_MAX_IDLE_CONNECTIONS".to_string(),
+            " 4 ".to_string(),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_KEEPALIVE_SECONDS".to_string(),
+            "forever".to_string(),
+        );
+        assert_eq!(
+            parse_tuning_var::<usize, _>(&test_env, "STATIC_ARTIFACTS_MAX_IDLE_CONNECTIONS"),
+            Some(4)
+        );
+        assert_eq!(
+            parse_tuning_var::<u64, _>(&test_env, "STATIC_ARTIFACTS_KEEPALIVE_SECONDS"),
+            None
+        );
+        assert_eq!(
+            parse_tuning_var::<u64, _>(&test_env, "STATIC_ARTIFACTS_UNSET"),
+            None
+        );
+    }
+
     #[test]
     fn detect_storage_scheme_return_scheme() {
         let mut test_env = HashMap::new();
@@ -1530,6 +4495,73 @@ mod tests {
         fs::remove_dir_all(output_path).unwrap_or_default();
     }
 
+    #[test]
+    fn create_and_extract_zstd_archive() {
+        let unique = Uuid::new_v4();
+        let output_file = format!("artifact-from-test-succeeds-{unique}.tzst");
+        let output_dir = format!("artifact-from-test-{unique}");
+        let output_path = Path::new(&output_dir);
+
+        create_archive(
+            Path::new("test/fixtures/static-artifacts"),
+            Path::new(output_file.as_str()),
+        )
+        .unwrap();
+        let mut magic = [0_u8; 4];
+        File::open(&output_file)
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .unwrap();
+        let result = extract_archive(Path::new(output_file.as_str()), output_path);
+        let index_exists = output_path.join("index.html").is_file();
+        fs::remove_file(&output_file).unwrap_or_default();
+        fs::remove_dir_all(output_path).unwrap_or_default();
+
+        assert_eq!(
+            magic,
+            [0x28, 0xb5, 0x2f, 0xfd],
+            "should be a Zstandard frame"
+        );
+        assert!(result.is_ok(), "{result:?}");
+        assert!(index_exists);
+    }
+
+    #[test]
+    fn select_compression_stores_already_compressed_content() {
+        let unique = Uuid::new_v4();
+        let source_dir = format!("compression-test-{unique}");
+        let source_path = Path::new(&source_dir);
+        fs::create_dir_all(source_path.join("fonts")).unwrap();
+        fs::write(source_path.join("hero.JPG"), vec![0_u8; 9_000]).unwrap();
+        fs::write(source_path.join("fonts/body.woff2"), vec![0_u8; 1_000]).unwrap();
+        fs::write(source_path.join("index.html"), vec![b'a'; 100]).unwrap();
+
+        let result = select_compression(source_path);
+        fs::remove_dir_all(source_path).unwrap_or_default();
+        assert_eq!(result, Compression::none());
+    }
+
+    #[test]
+    fn select_compression_compresses_mixed_content() {
+        let unique = Uuid::new_v4();
+        let source_dir = format!("compression-test-{unique}");
+        let source_path = Path::new(&source_dir);
+        fs::create_dir_all(source_path).unwrap();
+        fs::write(source_path.join("hero.png"), vec![0_u8; 5_000]).unwrap();
+        fs::write(source_path.join("app.js"), vec![b'a'; 5_000]).unwrap();
+
+        let result = select_compression(source_path);
+        fs::remove_dir_all(source_path).unwrap_or_default();
+        assert_eq!(result, Compression::default());
+    }
+
+    #[test]
+    fn select_compression_defaults_for_missing_or_empty_dir() {
+        assert_eq!(
+            select_compression(Path::new("non-existent-path")),
+            Compression::default()
+        );
+    }
+
     #[test]
     fn create_archive_should_fail_for_missing_source_dir() {
         let unique = Uuid::new_v4();
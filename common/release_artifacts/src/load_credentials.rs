@@ -0,0 +1,322 @@
+//! Mints short-lived, read-only credentials from STS for loading artifacts, written to
+//! `STATIC_ARTIFACTS_LOAD_CREDENTIALS_FILE` when saving.
+
+use std::{
+    collections::HashMap,
+    fs,
+    hash::BuildHasher,
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use aws_sdk_s3::config::{Credentials, Region};
+use aws_smithy_types::{date_time::Format, DateTime};
+use serde_json::{json, Value};
+
+use crate::{
+    detect_storage_scheme, errors::ReleaseArtifactsError, generate_s3_storage_prefix, log_info,
+    parse_tuning_var, s3_http_client,
+};
+
+const SESSION_NAME: &str = "release-phase-load";
+
+// 12 hours, the default of `GetFederationToken`.
+const FEDERATION_TOKEN_DURATION_SECONDS: i32 = 43_200;
+
+// 1 hour, the default max session of a role, which `AssumeRole` cannot exceed unless it is raised.
+const ASSUME_ROLE_DURATION_SECONDS: i32 = 3_600;
+
+// The vars of the credentials file, which are the only ones that loading takes from it.
+const ACCESS_KEY_ID_VAR: &str = "STATIC_ARTIFACTS_ACCESS_KEY_ID";
+const SECRET_ACCESS_KEY_VAR: &str = "STATIC_ARTIFACTS_SECRET_ACCESS_KEY";
+const SESSION_TOKEN_VAR: &str = "STATIC_ARTIFACTS_SESSION_TOKEN";
+const EXPIRATION_VAR: &str = "STATIC_ARTIFACTS_LOAD_CREDENTIALS_EXPIRATION";
+
+/// Temporary credentials for loading.
+#[derive(Debug)]
+struct LoadCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: String,
+    expiration: DateTime,
+}
+
+/// Writes read-only credentials to `STATIC_ARTIFACTS_LOAD_CREDENTIALS_FILE`, when set, returning
+/// its path.
+pub(crate) async fn write_load_credentials<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<Option<PathBuf>, ReleaseArtifactsError> {
+    let Some(path) = env
+        .get("STATIC_ARTIFACTS_LOAD_CREDENTIALS_FILE")
+        .filter(|p| !p.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"s3" => {
+            let (bucket_name, bucket_region, bucket_key_prefix) = generate_s3_storage_prefix(env)?;
+            let policy = load_policy(&bucket_name, &bucket_key_prefix).to_string();
+            let credentials = mint_credentials(env, bucket_region, &policy).await?;
+            let path = PathBuf::from(path);
+            write_credentials_file(&path, &credentials)?;
+            log_info!(
+                "save-release-artifacts wrote read-only load credentials, expiring {}, to {path:?}",
+                format_expiration(&credentials.expiration)
+            );
+            Ok(Some(path))
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+            "STATIC_ARTIFACTS_LOAD_CREDENTIALS_FILE requires s3 storage, not {scheme}"
+        ))),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replaces the credentials in env with those of `STATIC_ARTIFACTS_LOAD_CREDENTIALS_FILE`, when
+/// it is set & the file exists, returning whether they were. Expired credentials are still
+/// used, with a warning, so that loading fails with the storage's own error.
+pub fn apply_load_credentials<S: BuildHasher>(
+    env: &mut HashMap<String, String, S>,
+) -> Result<bool, ReleaseArtifactsError> {
+    let Some(path) = env
+        .get("STATIC_ARTIFACTS_LOAD_CREDENTIALS_FILE")
+        .filter(|p| !p.trim().is_empty())
+        .map(PathBuf::from)
+    else {
+        return Ok(false);
+    };
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return Ok(false);
+    };
+    let vars = parse_credentials_file(&contents);
+    for var in [ACCESS_KEY_ID_VAR, SECRET_ACCESS_KEY_VAR, SESSION_TOKEN_VAR] {
+        let value = vars.get(var).ok_or_else(|| {
+            ReleaseArtifactsError::ConfigInvalid(format!(
+                "load credentials file {path:?} is missing {var}"
+            ))
+        })?;
+        env.insert(var.to_string(), value.clone());
+    }
+    if let Some(expiration) = vars
+        .get(EXPIRATION_VAR)
+        .and_then(|e| DateTime::from_str(e, Format::DateTime).ok())
+    {
+        if expiration.secs() <= DateTime::from(SystemTime::now()).secs() {
+            eprintln!(
+                "release-phase warning: load credentials in {path:?} expired {}; rerun the release phase to mint new ones",
+                format_expiration(&expiration)
+            );
+        }
+    }
+    Ok(true)
+}
+
+// Allows only reading & listing the archives under the prefix.
+fn load_policy(bucket_name: &str, bucket_key_prefix: &str) -> Value {
+    let bucket_arn = format!("arn:aws:s3:::{bucket_name}");
+    let mut list_statement = json!({
+        "Sid": "ListArtifacts",
+        "Effect": "Allow",
+        "Action": ["s3:ListBucket"],
+        "Resource": bucket_arn,
+    });
+    if !bucket_key_prefix.is_empty() {
+        list_statement["Condition"] = json!({
+            "StringLike": { "s3:prefix": [format!("{bucket_key_prefix}*")] }
+        });
+    }
+    json!({
+        "Version": "2012-10-17",
+        "Statement": [
+            {
+                "Sid": "ReadArtifacts",
+                "Effect": "Allow",
+                "Action": ["s3:GetObject"],
+                "Resource": format!("{bucket_arn}/{bucket_key_prefix}*"),
+            },
+            list_statement,
+        ],
+    })
+}
+
+async fn mint_credentials<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    bucket_region: Option<String>,
+    policy: &str,
+) -> Result<LoadCredentials, ReleaseArtifactsError> {
+    let duration_seconds =
+        parse_tuning_var::<i32, S>(env, "STATIC_ARTIFACTS_LOAD_CREDENTIALS_SECONDS");
+    let shared_config = aws_config::from_env()
+        .region(Region::new(
+            bucket_region.unwrap_or_else(|| "us-east-1".to_string()),
+        ))
+        .credentials_provider(Credentials::new(
+            env[ACCESS_KEY_ID_VAR].clone(),
+            env[SECRET_ACCESS_KEY_VAR].clone(),
+            env.get(SESSION_TOKEN_VAR).cloned(),
+            None,
+            "Static Artifacts storage",
+        ))
+        .http_client(s3_http_client(env))
+        .load()
+        .await;
+    let mut sts_config = aws_sdk_sts::config::Builder::from(&shared_config);
+    // S3-compatible services, such as MinIO, serve STS from the same endpoint.
+    if let Some(endpoint_url) = env.get("STATIC_ARTIFACTS_ENDPOINT_URL") {
+        sts_config = sts_config.endpoint_url(endpoint_url);
+    }
+    let sts = aws_sdk_sts::Client::from_conf(sts_config.build());
+    let credentials = if let Some(role_arn) = env.get("STATIC_ARTIFACTS_LOAD_ROLE_ARN") {
+        sts.assume_role()
+            .role_arn(role_arn)
+            .role_session_name(SESSION_NAME)
+            .policy(policy)
+            .duration_seconds(duration_seconds.unwrap_or(ASSUME_ROLE_DURATION_SECONDS))
+            .send()
+            .await?
+            .credentials
+    } else {
+        sts.get_federation_token()
+            .name(SESSION_NAME)
+            .policy(policy)
+            .duration_seconds(duration_seconds.unwrap_or(FEDERATION_TOKEN_DURATION_SECONDS))
+            .send()
+            .await?
+            .credentials
+    };
+    let credentials = credentials.ok_or_else(|| ReleaseArtifactsError::StorageError {
+        message: "STS responded without credentials".to_string(),
+        request_id: None,
+        extended_request_id: None,
+        status: None,
+    })?;
+    Ok(LoadCredentials {
+        access_key_id: credentials.access_key_id,
+        secret_access_key: credentials.secret_access_key,
+        session_token: credentials.session_token,
+        expiration: credentials.expiration,
+    })
+}
+
+// Readable only by its owner, since it holds secrets.
+fn write_credentials_file(
+    path: &Path,
+    credentials: &LoadCredentials,
+) -> Result<(), ReleaseArtifactsError> {
+    let write_error =
+        |e| ReleaseArtifactsError::ArchiveError(e, format!("writing load credentials {path:?}"));
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(write_error)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // Readable by the owner only, where there are Unix modes.
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(write_error)?;
+    write!(
+        file,
+        "{ACCESS_KEY_ID_VAR}={}\n{SECRET_ACCESS_KEY_VAR}={}\n{SESSION_TOKEN_VAR}={}\n{EXPIRATION_VAR}={}\n",
+        credentials.access_key_id,
+        credentials.secret_access_key,
+        credentials.session_token,
+        format_expiration(&credentials.expiration),
+    )
+    .map_err(write_error)
+}
+
+// `KEY=value` lines, ignoring blank lines & comments.
+fn parse_credentials_file(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+fn format_expiration(expiration: &DateTime) -> String {
+    expiration
+        .fmt(Format::DateTime)
+        .unwrap_or_else(|_| expiration.secs().to_string())
+}
+
+// The tests check that the credentials file is owner-only, with its Unix mode.
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use std::{collections::HashMap, fs, os::unix::fs::PermissionsExt, path::PathBuf};
+
+    use aws_smithy_types::DateTime;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{apply_load_credentials, load_policy, write_credentials_file, LoadCredentials};
+
+    #[test]
+    fn load_policy_allows_only_reading_prefix() {
+        assert_eq!(
+            load_policy("test-bucket", "sub/path/"),
+            json!({
+                "Version": "2012-10-17",
+                "Statement": [
+                    {
+                        "Sid": "ReadArtifacts",
+                        "Effect": "Allow",
+                        "Action": ["s3:GetObject"],
+                        "Resource": "arn:aws:s3:::test-bucket/sub/path/*",
+                    },
+                    {
+                        "Sid": "ListArtifacts",
+                        "Effect": "Allow",
+                        "Action": ["s3:ListBucket"],
+                        "Resource": "arn:aws:s3:::test-bucket",
+                        "Condition": { "StringLike": { "s3:prefix": ["sub/path/*"] } },
+                    },
+                ],
+            })
+        );
+        assert!(load_policy("test-bucket", "")["Statement"][1]
+            .get("Condition")
+            .is_none());
+    }
+
+    #[test]
+    fn apply_load_credentials_replaces_env_credentials() {
+        let path = PathBuf::from(format!("load-credentials-test-{}.env", Uuid::new_v4()));
+        let mut env = HashMap::from([
+            (
+                "STATIC_ARTIFACTS_LOAD_CREDENTIALS_FILE".to_string(),
+                path.to_string_lossy().to_string(),
+            ),
+            (
+                "STATIC_ARTIFACTS_ACCESS_KEY_ID".to_string(),
+                "write-key".to_string(),
+            ),
+        ]);
+        let without_file = apply_load_credentials(&mut env);
+
+        write_credentials_file(
+            &path,
+            &LoadCredentials {
+                access_key_id: "ASIAREADONLY".to_string(),
+                secret_access_key: "read-secret".to_string(),
+                session_token: "read-token".to_string(),
+                expiration: DateTime::from_secs(4_102_444_800),
+            },
+        )
+        .unwrap();
+        let mode = fs::metadata(&path).map(|m| m.permissions().mode() & 0o777);
+        let with_file = apply_load_credentials(&mut env);
+        fs::remove_file(&path).unwrap_or_default();
+
+        assert!(!without_file.unwrap());
+        assert!(with_file.unwrap());
+        assert_eq!(mode.unwrap(), 0o600);
+        assert_eq!(env["STATIC_ARTIFACTS_ACCESS_KEY_ID"], "ASIAREADONLY");
+        assert_eq!(env["STATIC_ARTIFACTS_SECRET_ACCESS_KEY"], "read-secret");
+        assert_eq!(env["STATIC_ARTIFACTS_SESSION_TOKEN"], "read-token");
+    }
+}
@@ -0,0 +1,91 @@
+//! Prunes the staging dirs & downloaded archives that interrupted loads leave beside the loaded
+//! dir on the dyno's disk.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+/// A new path beside the destination dir for a partial local copy of its artifacts, ending with
+/// the suffix, or None for a dir without a name, such as `.`.
+pub(crate) fn local_copy_path(destination_dir: &Path, suffix: &str) -> Option<PathBuf> {
+    let name = destination_dir.file_name()?.to_string_lossy();
+    Some(destination_dir.with_file_name(format!(".{name}.loading-{}{suffix}", Uuid::new_v4())))
+}
+
+/// Removes every partial local copy beside the destination dir, returning the paths removed. Only
+/// the load holding the dir's lock prunes them, so none are still being written.
+pub(crate) fn prune_local_copies(destination_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let Some(name) = destination_dir.file_name() else {
+        return Ok(vec![]);
+    };
+    let parent = destination_dir
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let prefix = format!(".{}.loading-", name.to_string_lossy());
+    let entries = match fs::read_dir(parent) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        entries => entries?,
+    };
+    let mut removed = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with(&prefix) {
+            continue;
+        }
+        // DirEntry::file_type does not traverse symlinks.
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+        removed.push(path);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use uuid::Uuid;
+
+    use super::{local_copy_path, prune_local_copies};
+
+    #[test]
+    fn prune_local_copies_removes_only_the_dirs_copies() {
+        let root = PathBuf::from(format!("local-gc-test-{}", Uuid::new_v4()));
+        let destination_dir = root.join("static-artifacts");
+        fs::create_dir_all(&destination_dir).unwrap();
+        let staging_dir = local_copy_path(&destination_dir, "").unwrap();
+        fs::create_dir_all(staging_dir.join("css")).unwrap();
+        let archive = local_copy_path(&destination_dir, ".tgz").unwrap();
+        fs::write(&archive, "archive").unwrap();
+        let other_dirs_archive = local_copy_path(&root.join("public"), ".tgz").unwrap();
+        fs::write(&other_dirs_archive, "another dir's").unwrap();
+
+        let result = prune_local_copies(&destination_dir);
+        let copies_remain = staging_dir.exists() || archive.exists();
+        let others_remain = destination_dir.exists() && other_dirs_archive.exists();
+        fs::remove_dir_all(&root).unwrap_or_default();
+
+        assert_eq!(result.unwrap().len(), 2);
+        assert!(!copies_remain);
+        assert!(others_remain);
+    }
+
+    #[test]
+    fn local_copy_path_is_beside_the_dir() {
+        let path = local_copy_path(&PathBuf::from("public/assets"), ".tgz").unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+
+        assert_eq!(path.parent().unwrap(), PathBuf::from("public"));
+        assert!(name.starts_with(".assets.loading-"), "{name}");
+        assert!(name.ends_with(".tgz"), "{name}");
+        assert!(local_copy_path(&PathBuf::from("."), ".tgz").is_none());
+    }
+}
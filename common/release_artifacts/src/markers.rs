@@ -0,0 +1,378 @@
+//! Completion markers of release sequences, stored under `release-phase-markers/`, apart from the
+//! archives.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    hash::BuildHasher,
+    io,
+    path::Path,
+    time::SystemTime,
+};
+
+use aws_sdk_s3::operation::head_object::HeadObjectError;
+
+use crate::{
+    detect_storage_scheme, errors::ReleaseArtifactsError, generate_file_storage_location,
+    generate_s3_client, generate_s3_storage_location, guard_s3_credentials,
+};
+
+const MARKERS_DIR: &str = "release-phase-markers";
+
+/// Returns whether the given marker, a relative path like `<release-id>/complete`, was recorded
+/// in the configured storage location.
+pub async fn has_completion_marker<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    marker: &str,
+) -> Result<bool, ReleaseArtifactsError> {
+    let marker_name = marker_name(marker)?;
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"file" => {
+            let marker_path = generate_file_storage_location(env, &marker_name)?;
+            Ok(marker_path.is_file())
+        }
+        Ok(scheme) if scheme == *"s3" => {
+            guard_s3_credentials(env)?;
+            let (bucket_name, bucket_region, bucket_key) =
+                generate_s3_storage_location(env, &marker_name)?;
+            let s3 = generate_s3_client(env, bucket_region).await;
+            has_marker_with_client(&s3, &bucket_name, &bucket_key).await
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Records the given marker, a relative path like `<release-id>/complete`, in the configured
+/// storage location.
+pub async fn put_completion_marker<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    marker: &str,
+) -> Result<(), ReleaseArtifactsError> {
+    let marker_name = marker_name(marker)?;
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"file" => {
+            let marker_path = generate_file_storage_location(env, &marker_name)?;
+            write_file_marker(&marker_path)
+        }
+        Ok(scheme) if scheme == *"s3" => {
+            guard_s3_credentials(env)?;
+            let (bucket_name, bucket_region, bucket_key) =
+                generate_s3_storage_location(env, &marker_name)?;
+            let s3 = generate_s3_client(env, bucket_region).await;
+            put_marker_with_client(&s3, &bucket_name, &bucket_key).await
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+// The name of the marker, relative to the storage location, rejecting markers that would
+// escape the markers dir.
+fn marker_name(marker: &str) -> Result<String, ReleaseArtifactsError> {
+    if marker
+        .split('/')
+        .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return Err(ReleaseArtifactsError::ConfigInvalid(format!(
+            "completion marker `{marker}` must be a relative path"
+        )));
+    }
+    Ok(format!("{MARKERS_DIR}/{marker}"))
+}
+
+fn write_file_marker(marker_path: &Path) -> Result<(), ReleaseArtifactsError> {
+    marker_path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(marker_path, ""))
+        .map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                format!("writing completion marker {marker_path:?}"),
+            )
+        })
+}
+
+pub(crate) async fn has_marker_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_key: &str,
+) -> Result<bool, ReleaseArtifactsError> {
+    match s3
+        .head_object()
+        .bucket(bucket_name)
+        .key(bucket_key)
+        .send()
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(HeadObjectError::is_not_found) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(ReleaseArtifactsError::from(e)),
+    }
+}
+
+pub(crate) async fn put_marker_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_key: &str,
+) -> Result<(), ReleaseArtifactsError> {
+    s3.put_object()
+        .bucket(bucket_name)
+        .key(bucket_key)
+        .body(aws_sdk_s3::primitives::ByteStream::from_static(b""))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Deletes the marker directories of releases whose every marker was recorded before `cutoff`,
+/// other than the current release's, returning their release IDs.
+pub(crate) fn prune_file_markers(
+    storage_path: &Path,
+    cutoff: SystemTime,
+    current_release_id: Option<&str>,
+) -> Result<Vec<String>, ReleaseArtifactsError> {
+    let mut markers = vec![];
+    list_file_markers(storage_path, &storage_path.join(MARKERS_DIR), &mut markers)?;
+    let expired = markers_recorded_before(markers, "", cutoff, current_release_id);
+    for release_id in expired.keys() {
+        let release_path = storage_path.join(MARKERS_DIR).join(release_id);
+        fs::remove_dir_all(&release_path).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                format!("during gc fs::remove_dir_all({release_path:?})"),
+            )
+        })?;
+    }
+    Ok(expired.into_keys().collect())
+}
+
+// Lists the marker files under the dir, recursively, by their path relative to the storage
+// path, like S3 keys, and when each was recorded.
+fn list_file_markers(
+    storage_path: &Path,
+    dir: &Path,
+    markers: &mut Vec<(String, SystemTime)>,
+) -> Result<(), ReleaseArtifactsError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(ReleaseArtifactsError::ArchiveError(
+                e,
+                format!("during gc fs::read_dir({dir:?})"),
+            ))
+        }
+    };
+    for entry in entries {
+        let (path, metadata) = entry
+            .and_then(|entry| entry.metadata().map(|metadata| (entry.path(), metadata)))
+            .map_err(|e| {
+                ReleaseArtifactsError::ArchiveError(e, format!("during gc listing {dir:?}"))
+            })?;
+        if metadata.is_dir() {
+            list_file_markers(storage_path, &path, markers)?;
+            continue;
+        }
+        let key = path
+            .strip_prefix(storage_path)
+            .unwrap_or(&path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        markers.push((key, metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)));
+    }
+    Ok(())
+}
+
+/// Returns the keys of the markers in S3 of releases whose every marker was recorded before
+/// `cutoff`, other than the current release's, by release ID.
+pub(crate) async fn expired_s3_markers(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_key_prefix: &str,
+    cutoff: SystemTime,
+    current_release_id: Option<&str>,
+) -> Result<BTreeMap<String, Vec<String>>, ReleaseArtifactsError> {
+    let markers_prefix = format!("{bucket_key_prefix}{MARKERS_DIR}/");
+    let mut markers = vec![];
+    let mut pages = s3
+        .list_objects_v2()
+        .bucket(bucket_name)
+        .prefix(&markers_prefix)
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(ReleaseArtifactsError::from)?;
+        for object in page.contents() {
+            if let Some(key) = object.key() {
+                let recorded = object
+                    .last_modified()
+                    .and_then(|t| SystemTime::try_from(*t).ok())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                markers.push((key.to_string(), recorded));
+            }
+        }
+    }
+    Ok(markers_recorded_before(
+        markers,
+        bucket_key_prefix,
+        cutoff,
+        current_release_id,
+    ))
+}
+
+// Groups the markers, listed by key & when each was recorded, by release ID, keeping those of
+// releases whose every marker was recorded before `cutoff`. The current release's are kept, as
+// it may still be recording them.
+fn markers_recorded_before(
+    markers: Vec<(String, SystemTime)>,
+    bucket_key_prefix: &str,
+    cutoff: SystemTime,
+    current_release_id: Option<&str>,
+) -> BTreeMap<String, Vec<String>> {
+    let markers_prefix = format!("{bucket_key_prefix}{MARKERS_DIR}/");
+    let mut releases: BTreeMap<String, (SystemTime, Vec<String>)> = BTreeMap::new();
+    for (key, recorded) in markers {
+        let Some(release_id) = key
+            .strip_prefix(&markers_prefix)
+            .and_then(|marker| marker.split_once('/'))
+            .map(|(release_id, _)| release_id.to_string())
+        else {
+            continue;
+        };
+        let release = releases
+            .entry(release_id)
+            .or_insert((SystemTime::UNIX_EPOCH, vec![]));
+        release.0 = release.0.max(recorded);
+        release.1.push(key);
+    }
+    releases
+        .into_iter()
+        .filter(|(release_id, (newest, _))| {
+            *newest < cutoff && Some(release_id.as_str()) != current_release_id
+        })
+        .map(|(release_id, (_, keys))| (release_id, keys))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use aws_config::BehaviorVersion;
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+    use uuid::Uuid;
+
+    use super::{
+        has_completion_marker, has_marker_with_client, put_completion_marker,
+        put_marker_with_client,
+    };
+    use crate::{list_stored_archives, make_s3_test_credentials};
+
+    #[tokio::test]
+    async fn completion_marker_file_roundtrip() {
+        let storage_path = std::env::temp_dir().join(format!("marker-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&storage_path).unwrap();
+        fs::write(storage_path.join("release-v102.tgz"), "archive").unwrap();
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", storage_path.to_string_lossy()),
+        );
+
+        let before = has_completion_marker(&test_env, "v102/complete")
+            .await
+            .unwrap();
+        put_completion_marker(&test_env, "v102/complete")
+            .await
+            .unwrap();
+        let after = has_completion_marker(&test_env, "v102/complete")
+            .await
+            .unwrap();
+        let other = has_completion_marker(&test_env, "v103/complete")
+            .await
+            .unwrap();
+        let archives = list_stored_archives(&test_env).await.unwrap();
+        let invalid = put_completion_marker(&test_env, "../v102").await;
+        fs::remove_dir_all(&storage_path).unwrap_or_default();
+
+        assert!(!before);
+        assert!(after);
+        assert!(!other);
+        assert_eq!(archives.len(), 1, "markers are not listed as archives");
+        assert!(invalid.is_err());
+    }
+
+    #[tokio::test]
+    async fn completion_marker_s3_roundtrip() {
+        let marker_uri =
+            "https://test-bucket.s3.us-east-1.amazonaws.com/release-phase-markers/v102/complete";
+        let replay_client = StaticReplayClient::new(vec![
+            ReplayEvent::new(
+                http::Request::builder()
+                    .method("HEAD")
+                    .uri(marker_uri)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(404)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+            ),
+            ReplayEvent::new(
+                http::Request::builder()
+                    .method("PUT")
+                    .uri(format!("{marker_uri}?x-id=PutObject"))
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(200)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+            ),
+            ReplayEvent::new(
+                http::Request::builder()
+                    .method("HEAD")
+                    .uri(marker_uri)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(200)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+            ),
+        ]);
+        let s3 = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        );
+        let key = "release-phase-markers/v102/complete";
+
+        let before = has_marker_with_client(&s3, "test-bucket", key)
+            .await
+            .unwrap();
+        put_marker_with_client(&s3, "test-bucket", key)
+            .await
+            .unwrap();
+        let after = has_marker_with_client(&s3, "test-bucket", key)
+            .await
+            .unwrap();
+
+        assert!(!before);
+        assert!(after);
+        replay_client.assert_requests_match(&[]);
+    }
+}
@@ -0,0 +1,407 @@
+//! Notifies the webhook, Slack, or SNS target of `RELEASE_PHASE_NOTIFY_URL` of the outcome of each
+//! release.
+
+use std::{collections::HashMap, hash::BuildHasher};
+
+use aws_sdk_sns::config::{Credentials, Region};
+use serde_json::json;
+use url::Url;
+
+use crate::{
+    errors::ReleaseArtifactsError,
+    purge::{required, send_request},
+    s3_http_client,
+};
+
+const SLACK_WEBHOOK_HOST: &str = "hooks.slack.com";
+
+/// The outcome of a release, to notify of.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReleaseNotification {
+    pub succeeded: bool,
+    pub release_id: Option<String>,
+    pub app: Option<String>,
+    /// Why the release failed.
+    pub detail: Option<String>,
+}
+
+impl ReleaseNotification {
+    /// A sentence describing the outcome, like `Release v102 of my-app failed: …`.
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let release = self
+            .release_id
+            .as_ref()
+            .map_or("Release".to_string(), |id| format!("Release {id}"));
+        let app = self
+            .app
+            .as_ref()
+            .map_or(String::new(), |app| format!(" of {app}"));
+        let outcome = if self.succeeded {
+            "succeeded"
+        } else {
+            "failed"
+        };
+        let detail = self
+            .detail
+            .as_ref()
+            .map_or(String::new(), |detail| format!(": {detail}"));
+        format!("{release}{app} {outcome}{detail}")
+    }
+}
+
+/// An HTTP target of release notifications, which are sent as the request it builds.
+pub trait Notifier {
+    fn request(
+        &self,
+        notification: &ReleaseNotification,
+    ) -> Result<http::Request<String>, ReleaseArtifactsError>;
+}
+
+/// Notifies the target of `RELEASE_PHASE_NOTIFY_URL`, returning whether one is configured.
+pub async fn notify<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    notification: &ReleaseNotification,
+) -> Result<bool, ReleaseArtifactsError> {
+    match notify_target_from_env(env)? {
+        None => return Ok(false),
+        Some(NotifyTarget::Http(notifier)) => {
+            let request = notifier.request(notification)?;
+            send_request(request)
+                .await
+                .map_err(ReleaseArtifactsError::NotifyFailed)?;
+        }
+        Some(NotifyTarget::Sns(topic)) => {
+            let sns = sns_client(env, &topic.region).await?;
+            publish_sns(&sns, &topic.topic_arn, notification).await?;
+        }
+    }
+    Ok(true)
+}
+
+// HTTP targets are sent the request they build, while SNS topics are published to with the SDK.
+enum NotifyTarget {
+    Http(Box<dyn Notifier>),
+    Sns(SnsTopic),
+}
+
+fn notify_target_from_env<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<Option<NotifyTarget>, ReleaseArtifactsError> {
+    let Some(notify_url) = env
+        .get("RELEASE_PHASE_NOTIFY_URL")
+        .filter(|url| !url.is_empty())
+    else {
+        return Ok(None);
+    };
+    let url = Url::parse(notify_url).map_err(|e| {
+        ReleaseArtifactsError::ConfigInvalid(format!("RELEASE_PHASE_NOTIFY_URL is invalid, {e}"))
+    })?;
+    match url.scheme() {
+        "https" if url.host_str() == Some(SLACK_WEBHOOK_HOST) => {
+            Ok(Some(NotifyTarget::Http(Box::new(SlackNotifier { url }))))
+        }
+        "https" => Ok(Some(NotifyTarget::Http(Box::new(WebhookNotifier { url })))),
+        "arn" => Ok(Some(NotifyTarget::Sns(SnsTopic::parse(notify_url)?))),
+        scheme => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+            "RELEASE_PHASE_NOTIFY_URL scheme '{scheme}' is not supported, expected 'https' or 'arn'"
+        ))),
+    }
+}
+
+// Posts the notification as JSON.
+struct WebhookNotifier {
+    url: Url,
+}
+
+impl Notifier for WebhookNotifier {
+    fn request(
+        &self,
+        notification: &ReleaseNotification,
+    ) -> Result<http::Request<String>, ReleaseArtifactsError> {
+        let body = json!({
+            "status": if notification.succeeded { "succeeded" } else { "failed" },
+            "release_id": notification.release_id,
+            "app": notification.app,
+            "detail": notification.detail,
+            "summary": notification.summary(),
+        });
+        json_request(&self.url, body.to_string())
+    }
+}
+
+// Posts the summary as the text of a Slack message.
+struct SlackNotifier {
+    url: Url,
+}
+
+impl Notifier for SlackNotifier {
+    fn request(
+        &self,
+        notification: &ReleaseNotification,
+    ) -> Result<http::Request<String>, ReleaseArtifactsError> {
+        let icon = if notification.succeeded { "✅" } else { "❌" };
+        let body = json!({ "text": format!("{icon} {}", notification.summary()) });
+        json_request(&self.url, body.to_string())
+    }
+}
+
+fn json_request(url: &Url, body: String) -> Result<http::Request<String>, ReleaseArtifactsError> {
+    http::Request::builder()
+        .method("POST")
+        .uri(url.as_str())
+        .header("content-type", "application/json")
+        .body(body)
+        .map_err(|e| {
+            ReleaseArtifactsError::NotifyFailed(format!("building notification request: {e}"))
+        })
+}
+
+// The SNS topic, whose region is that of its ARN, so that the SDK resolves its endpoint in any
+// partition, such as `sns.cn-north-1.amazonaws.com.cn`.
+struct SnsTopic {
+    topic_arn: String,
+    region: String,
+}
+
+impl SnsTopic {
+    fn parse(topic_arn: &str) -> Result<Self, ReleaseArtifactsError> {
+        // arn:<partition>:sns:<region>:<account>:<topic>
+        match topic_arn.split(':').collect::<Vec<_>>().as_slice() {
+            ["arn", _, "sns", region, _, _] if !region.is_empty() => Ok(SnsTopic {
+                topic_arn: topic_arn.to_string(),
+                region: (*region).to_string(),
+            }),
+            _ => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+                "RELEASE_PHASE_NOTIFY_URL '{topic_arn}' is not an SNS topic ARN"
+            ))),
+        }
+    }
+}
+
+async fn sns_client<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    region: &str,
+) -> Result<aws_sdk_sns::Client, ReleaseArtifactsError> {
+    let credentials = Credentials::new(
+        required(env, "STATIC_ARTIFACTS_ACCESS_KEY_ID")?,
+        required(env, "STATIC_ARTIFACTS_SECRET_ACCESS_KEY")?,
+        env.get("STATIC_ARTIFACTS_SESSION_TOKEN").cloned(),
+        None,
+        "Static Artifacts storage",
+    );
+    let shared_config = aws_config::from_env()
+        .region(Region::new(region.to_string()))
+        .credentials_provider(credentials)
+        .http_client(s3_http_client(env))
+        .load()
+        .await;
+    Ok(aws_sdk_sns::Client::new(&shared_config))
+}
+
+// Publishes the summary to the topic.
+async fn publish_sns(
+    sns: &aws_sdk_sns::Client,
+    topic_arn: &str,
+    notification: &ReleaseNotification,
+) -> Result<(), ReleaseArtifactsError> {
+    let summary = notification.summary();
+    // SNS subjects are limited to a line of 100 characters.
+    let subject: String = summary
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .take(100)
+        .collect();
+    sns.publish()
+        .topic_arn(topic_arn)
+        .subject(subject)
+        .message(summary)
+        .send()
+        .await
+        .map_err(|e| {
+            ReleaseArtifactsError::NotifyFailed(format!(
+                "publishing to {topic_arn}: {}",
+                aws_smithy_types::error::display::DisplayErrorContext(&e)
+            ))
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aws_config::BehaviorVersion;
+    use aws_sdk_sns::config::{Credentials, Region};
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+    use serde_json::Value;
+
+    use super::{
+        notify_target_from_env, publish_sns, Notifier, NotifyTarget, ReleaseNotification, SnsTopic,
+    };
+
+    fn failed_release() -> ReleaseNotification {
+        ReleaseNotification {
+            succeeded: false,
+            release_id: Some("v102".to_string()),
+            app: Some("my-app".to_string()),
+            detail: Some("command exited with status code 1".to_string()),
+        }
+    }
+
+    fn notify_env(url: &str) -> HashMap<String, String> {
+        HashMap::from([("RELEASE_PHASE_NOTIFY_URL".to_string(), url.to_string())])
+    }
+
+    fn http_notifier(url: &str) -> Box<dyn Notifier> {
+        match notify_target_from_env(&notify_env(url)).unwrap() {
+            Some(NotifyTarget::Http(notifier)) => notifier,
+            _ => panic!("{url} is not an HTTP target"),
+        }
+    }
+
+    fn make_sns_client(replay_client: &StaticReplayClient, region: &str) -> aws_sdk_sns::Client {
+        aws_sdk_sns::Client::from_conf(
+            aws_sdk_sns::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(Credentials::new(
+                    "test-key",
+                    "test-secret",
+                    Some("test-session-token".to_string()),
+                    None,
+                    "test",
+                ))
+                .region(Region::new(region.to_string()))
+                .http_client(replay_client.clone())
+                .build(),
+        )
+    }
+
+    fn publish_event(uri: &str) -> ReplayEvent {
+        ReplayEvent::new(
+            http::Request::builder()
+                .method("POST")
+                .uri(uri)
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(
+                    "<PublishResponse><PublishResult><MessageId>567910cd-659e-55d4-8ccb-5aaf14679dc0</MessageId></PublishResult></PublishResponse>",
+                ))
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn summary_describes_outcome() {
+        assert_eq!(
+            failed_release().summary(),
+            "Release v102 of my-app failed: command exited with status code 1"
+        );
+        assert_eq!(
+            ReleaseNotification {
+                succeeded: true,
+                ..ReleaseNotification::default()
+            }
+            .summary(),
+            "Release succeeded"
+        );
+    }
+
+    #[test]
+    fn notify_target_from_env_without_url() {
+        assert!(notify_target_from_env(&HashMap::<String, String>::new())
+            .unwrap()
+            .is_none());
+        assert!(notify_target_from_env(&notify_env("ftp://example.com")).is_err());
+        assert!(notify_target_from_env(&notify_env("arn:aws:s3:::bucket")).is_err());
+    }
+
+    #[test]
+    fn webhook_posts_notification_json() {
+        let request = http_notifier("https://example.com/releases")
+            .request(&failed_release())
+            .unwrap();
+        let body: Value = serde_json::from_str(request.body()).unwrap();
+
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.uri(), "https://example.com/releases");
+        assert_eq!(body["status"], "failed");
+        assert_eq!(body["release_id"], "v102");
+        assert_eq!(body["app"], "my-app");
+    }
+
+    #[test]
+    fn slack_posts_summary_text() {
+        let request = http_notifier("https://hooks.slack.com/services/T0/B0/x")
+            .request(&failed_release())
+            .unwrap();
+        let body: Value = serde_json::from_str(request.body()).unwrap();
+
+        assert_eq!(
+            body["text"],
+            "❌ Release v102 of my-app failed: command exited with status code 1"
+        );
+    }
+
+    #[test]
+    fn sns_topic_region_from_arn() {
+        let topic = SnsTopic::parse("arn:aws-cn:sns:cn-north-1:123456789012:releases").unwrap();
+        assert_eq!(topic.region, "cn-north-1");
+        assert!(SnsTopic::parse("arn:aws:sns::123456789012:releases").is_err());
+    }
+
+    #[tokio::test]
+    async fn sns_publishes_signed_summary() {
+        let replay_client =
+            StaticReplayClient::new(vec![publish_event("https://sns.eu-west-1.amazonaws.com/")]);
+        let sns = make_sns_client(&replay_client, "eu-west-1");
+
+        let result = publish_sns(
+            &sns,
+            "arn:aws:sns:eu-west-1:123456789012:releases",
+            &failed_release(),
+        )
+        .await;
+
+        assert!(result.is_ok(), "{result:?}");
+        let request = replay_client.actual_requests().next().unwrap();
+        assert_eq!(request.uri(), "https://sns.eu-west-1.amazonaws.com/");
+        assert!(request
+            .headers()
+            .get("authorization")
+            .unwrap()
+            .starts_with("AWS4-HMAC-SHA256 Credential=test-key/"));
+        assert_eq!(
+            request.headers().get("x-amz-security-token"),
+            Some("test-session-token")
+        );
+        let body = std::str::from_utf8(request.body().bytes().unwrap()).unwrap();
+        assert!(body.contains("Action=Publish"));
+        assert!(body.contains("TopicArn=arn%3Aaws%3Asns%3Aeu-west-1%3A123456789012%3Areleases"));
+        assert!(body.contains("Message=Release%20v102%20of%20my-app%20failed"));
+    }
+
+    #[tokio::test]
+    async fn sns_publishes_outside_standard_partition() {
+        let replay_client = StaticReplayClient::new(vec![publish_event(
+            "https://sns.cn-north-1.amazonaws.com.cn/",
+        )]);
+        let sns = make_sns_client(&replay_client, "cn-north-1");
+
+        let result = publish_sns(
+            &sns,
+            "arn:aws-cn:sns:cn-north-1:123456789012:releases",
+            &failed_release(),
+        )
+        .await;
+
+        assert!(result.is_ok(), "{result:?}");
+        let request = replay_client.actual_requests().next().unwrap();
+        assert_eq!(request.uri(), "https://sns.cn-north-1.amazonaws.com.cn/");
+    }
+}
@@ -0,0 +1,252 @@
+//! Normalizes the permissions of extracted artifacts with the `chmod`-style mode of
+//! `STATIC_ARTIFACTS_CHMOD`.
+
+use std::{
+    fs::{self, Metadata},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::errors::ReleaseArtifactsError;
+
+const DEFAULT_UMASK: u32 = 0o022;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Add,
+    Remove,
+    Set,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Clause {
+    Octal(u32),
+    Symbolic {
+        who: u32,
+        op: Op,
+        perms: u32,
+        x_if_executable: bool,
+    },
+}
+
+/// A parsed `chmod`-style mode, applied to every extracted file and directory.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ChmodSpec {
+    clauses: Vec<Clause>,
+}
+
+impl ChmodSpec {
+    pub(crate) fn parse(spec: &str) -> Result<ChmodSpec, ReleaseArtifactsError> {
+        let invalid = || {
+            ReleaseArtifactsError::ConfigInvalid(format!(
+                "STATIC_ARTIFACTS_CHMOD '{spec}' is not a mode like 'a+rX' or '755'"
+            ))
+        };
+        let spec = spec.trim();
+        if !spec.is_empty() && spec.chars().all(|c| c.is_ascii_digit()) {
+            let mode = u32::from_str_radix(spec, 8).map_err(|_| invalid())?;
+            if mode > 0o7777 {
+                return Err(invalid());
+            }
+            return Ok(ChmodSpec {
+                clauses: vec![Clause::Octal(mode)],
+            });
+        }
+        let mut clauses = vec![];
+        for clause in spec.split(',') {
+            let mut chars = clause.chars().peekable();
+            let mut who = 0;
+            while let Some(c) = chars.next_if(|c| "ugoa".contains(*c)) {
+                who |= match c {
+                    'u' => 0o4700,
+                    'g' => 0o2070,
+                    'o' => 0o0007,
+                    _ => 0o6777,
+                };
+            }
+            if who == 0 {
+                who = 0o6777;
+            }
+            let mut has_op = false;
+            while let Some(c) = chars.next() {
+                let op = match c {
+                    '+' => Op::Add,
+                    '-' => Op::Remove,
+                    '=' => Op::Set,
+                    _ => return Err(invalid()),
+                };
+                let mut perms = 0;
+                let mut x_if_executable = false;
+                while let Some(c) = chars.next_if(|c| "rwxXs".contains(*c)) {
+                    match c {
+                        'r' => perms |= 0o444,
+                        'w' => perms |= 0o222,
+                        'x' => perms |= 0o111,
+                        'X' => x_if_executable = true,
+                        _ => perms |= 0o6000,
+                    }
+                }
+                clauses.push(Clause::Symbolic {
+                    who,
+                    op,
+                    perms,
+                    x_if_executable,
+                });
+                has_op = true;
+            }
+            if !has_op {
+                return Err(invalid());
+            }
+        }
+        Ok(ChmodSpec { clauses })
+    }
+
+    /// Returns the permission bits of `mode` after applying every clause in order.
+    fn apply_to_mode(&self, mode: u32, is_dir: bool) -> u32 {
+        self.clauses
+            .iter()
+            .fold(mode & 0o7777, |mode, clause| match *clause {
+                Clause::Octal(octal) => octal,
+                Clause::Symbolic {
+                    who,
+                    op,
+                    perms,
+                    x_if_executable,
+                } => {
+                    let mut perms = perms;
+                    if x_if_executable && (is_dir || mode & 0o111 != 0) {
+                        perms |= 0o111;
+                    }
+                    let bits = perms & who;
+                    match op {
+                        Op::Add => mode | bits,
+                        Op::Remove => mode & !bits,
+                        Op::Set => (mode & !who) | bits,
+                    }
+                }
+            })
+    }
+
+    /// Applies the mode to the given path and, if it is a directory, to everything within it.
+    /// Symlinks are left as is, never followed.
+    pub(crate) fn apply(&self, root: &Path) -> io::Result<()> {
+        let mut pending: Vec<PathBuf> = vec![root.to_path_buf()];
+        while let Some(path) = pending.pop() {
+            let metadata = fs::symlink_metadata(&path)?;
+            if metadata.file_type().is_symlink() {
+                continue;
+            }
+            let is_dir = metadata.is_dir();
+            let mode = file_mode(&metadata);
+            let new_mode = self.apply_to_mode(mode, is_dir);
+            // Directories are made traversable before listing them, in case the mode allows it.
+            if new_mode != mode & 0o7777 {
+                set_file_mode(&path, new_mode)?;
+            }
+            if is_dir {
+                for entry in fs::read_dir(&path)? {
+                    pending.push(entry?.path());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The Unix mode of the file. Windows has none, so there, it is the mode that `tar` gives files
+/// archived on Windows, read-only when the file is.
+#[cfg(unix)]
+pub(crate) fn file_mode(metadata: &Metadata) -> u32 {
+    std::os::unix::fs::PermissionsExt::mode(&metadata.permissions())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_mode(metadata: &Metadata) -> u32 {
+    let mode = if metadata.is_dir() { 0o755 } else { 0o644 };
+    if metadata.permissions().readonly() {
+        mode & !0o222
+    } else {
+        mode
+    }
+}
+
+// Sets the Unix mode of the file, or on Windows, only whether it is read-only.
+#[cfg(unix)]
+fn set_file_mode(path: &Path, mode: u32) -> io::Result<()> {
+    fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+/// Returns the umask of this process, which extracted modes are masked with, like `tar` does
+/// for users other than root. Read from `/proc` to avoid changing it while reading it.
+pub(crate) fn process_umask() -> u32 {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Umask:"))
+                .and_then(|umask| u32::from_str_radix(umask.trim(), 8).ok())
+        })
+        .unwrap_or(DEFAULT_UMASK)
+}
+
+// The tests apply Unix modes, which Windows has none of.
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+    use uuid::Uuid;
+
+    use super::ChmodSpec;
+
+    #[test]
+    fn parse_and_apply_to_mode() {
+        let spec = ChmodSpec::parse("a+rX").unwrap();
+        assert_eq!(spec.apply_to_mode(0o600, false), 0o644);
+        assert_eq!(spec.apply_to_mode(0o700, true), 0o755);
+        assert_eq!(spec.apply_to_mode(0o700, false), 0o755);
+
+        let spec = ChmodSpec::parse("u=rwX,go=rX").unwrap();
+        assert_eq!(spec.apply_to_mode(0o777, false), 0o755);
+        assert_eq!(spec.apply_to_mode(0o666, false), 0o644);
+
+        let spec = ChmodSpec::parse("go-w").unwrap();
+        assert_eq!(spec.apply_to_mode(0o777, false), 0o755);
+
+        assert_eq!(
+            ChmodSpec::parse("644").unwrap().apply_to_mode(0o600, false),
+            0o644
+        );
+        assert!(ChmodSpec::parse("a+q").is_err());
+        assert!(ChmodSpec::parse("u").is_err());
+        assert!(ChmodSpec::parse("99999").is_err());
+    }
+
+    #[test]
+    fn apply_normalizes_a_tree() {
+        let root = std::env::temp_dir().join(format!("chmod-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(root.join("assets")).unwrap();
+        fs::write(root.join("assets/app.js"), "").unwrap();
+        fs::set_permissions(
+            root.join("assets/app.js"),
+            fs::Permissions::from_mode(0o600),
+        )
+        .unwrap();
+        fs::set_permissions(root.join("assets"), fs::Permissions::from_mode(0o700)).unwrap();
+
+        ChmodSpec::parse("a+rX").unwrap().apply(&root).unwrap();
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&root.join("assets")), 0o755);
+        assert_eq!(mode(&root.join("assets/app.js")), 0o644);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
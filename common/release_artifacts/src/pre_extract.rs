@@ -0,0 +1,56 @@
+//! Runs the command `STATIC_ARTIFACTS_PRE_EXTRACT_COMMAND`, such as a malware scan or size
+//! policy, with the path of each archive before it is extracted. Exiting non-zero vetoes it.
+
+use std::{path::Path, process::Command};
+
+use crate::{errors::ReleaseArtifactsError, log_info};
+
+pub(crate) fn run_pre_extract_command(
+    command: &str,
+    archive_path: &Path,
+) -> Result<(), ReleaseArtifactsError> {
+    log_info!("load-release-artifacts running pre-extract command: {command}");
+    // The archive path is passed as `$0`, so that it is appended to the command, unquoted.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{command} \"$0\""))
+        .arg(archive_path)
+        .env("STATIC_ARTIFACTS_ARCHIVE_PATH", archive_path)
+        .status()
+        .map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(
+                e,
+                format!("running pre-extract command '{command}'"),
+            )
+        })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(ReleaseArtifactsError::ExtractionVetoed(format!(
+            "pre-extract command '{command}' rejected archive {archive_path:?}, {status}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::run_pre_extract_command;
+    use crate::errors::ReleaseArtifactsError;
+
+    #[test]
+    fn pre_extract_command_receives_archive_path() {
+        let archive_path = Path::new("test/fixtures/static-artifacts.tgz");
+        assert!(run_pre_extract_command("test -s", archive_path).is_ok());
+        assert!(run_pre_extract_command(
+            "test \"$STATIC_ARTIFACTS_ARCHIVE_PATH\" = ",
+            archive_path
+        )
+        .is_ok());
+        assert!(matches!(
+            run_pre_extract_command("test -d", archive_path),
+            Err(ReleaseArtifactsError::ExtractionVetoed(_))
+        ));
+    }
+}
@@ -0,0 +1,293 @@
+//! Creates the artifact bucket when saving, if it does not exist, with
+//! `STATIC_ARTIFACTS_CREATE_BUCKET=1`.
+
+use std::{collections::HashMap, hash::BuildHasher, time::Duration};
+
+use aws_sdk_s3::{
+    error::BuildError,
+    operation::{create_bucket::CreateBucketError, head_bucket::HeadBucketError},
+    types::{
+        AbortIncompleteMultipartUpload, BucketCannedAcl, BucketLifecycleConfiguration,
+        BucketLocationConstraint, CreateBucketConfiguration, ExpirationStatus, LifecycleRule,
+        LifecycleRuleFilter, PublicAccessBlockConfiguration, ServerSideEncryption,
+        ServerSideEncryptionByDefault, ServerSideEncryptionConfiguration, ServerSideEncryptionRule,
+    },
+};
+
+use crate::{errors::ReleaseArtifactsError, gc::Retention, log_info};
+
+pub(crate) const LIFECYCLE_RULE_ID: &str = "release-phase-abort-incomplete-uploads";
+
+const DEFAULT_ABORT_UPLOADS_DAYS: i32 = 7;
+
+/// Creates the bucket, when `STATIC_ARTIFACTS_CREATE_BUCKET` is set and it does not exist.
+pub(crate) async fn create_bucket_if_missing<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_region: Option<&str>,
+    bucket_key_prefix: &str,
+) -> Result<(), ReleaseArtifactsError> {
+    if !env
+        .get("STATIC_ARTIFACTS_CREATE_BUCKET")
+        .is_some_and(|v| v == "1" || v == "true")
+    {
+        return Ok(());
+    }
+    create_bucket_with_client(
+        s3,
+        bucket_name,
+        bucket_region,
+        bucket_key_prefix,
+        abort_uploads_days(env)?,
+    )
+    .await
+}
+
+/// The days after which the lifecycle rule aborts incomplete uploads, from
+/// `STATIC_ARTIFACTS_ABORT_UPLOADS_HOURS`, rounded up, or else a week.
+pub(crate) fn abort_uploads_days<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<i32, ReleaseArtifactsError> {
+    Ok(Retention::from_env(env)?
+        .and_then(|retention| retention.abort_uploads_after)
+        .map_or(DEFAULT_ABORT_UPLOADS_DAYS, days_rounded_up))
+}
+
+// Creates & configures the bucket, unless it exists.
+async fn create_bucket_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_region: Option<&str>,
+    bucket_key_prefix: &str,
+    abort_uploads_days: i32,
+) -> Result<(), ReleaseArtifactsError> {
+    match s3.head_bucket().bucket(bucket_name).send().await {
+        Ok(_) => return Ok(()),
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(HeadBucketError::is_not_found) => {}
+        Err(e) => return Err(e.into()),
+    }
+    log_info!("save-release-artifacts creating bucket '{bucket_name}'");
+    let mut request = s3
+        .create_bucket()
+        .bucket(bucket_name)
+        .acl(BucketCannedAcl::Private);
+    // us-east-1 is the default location, which S3 rejects as a constraint.
+    if let Some(region) = bucket_region.filter(|region| *region != "us-east-1") {
+        request = request.create_bucket_configuration(
+            CreateBucketConfiguration::builder()
+                .location_constraint(BucketLocationConstraint::from(region))
+                .build(),
+        );
+    }
+    match request.send().await {
+        Ok(_) => {}
+        // Created by another save since it was checked.
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(CreateBucketError::is_bucket_already_owned_by_you) =>
+        {
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    s3.put_public_access_block()
+        .bucket(bucket_name)
+        .public_access_block_configuration(
+            PublicAccessBlockConfiguration::builder()
+                .block_public_acls(true)
+                .ignore_public_acls(true)
+                .block_public_policy(true)
+                .restrict_public_buckets(true)
+                .build(),
+        )
+        .send()
+        .await?;
+    s3.put_bucket_encryption()
+        .bucket(bucket_name)
+        .server_side_encryption_configuration(
+            ServerSideEncryptionConfiguration::builder()
+                .rules(
+                    ServerSideEncryptionRule::builder()
+                        .apply_server_side_encryption_by_default(
+                            ServerSideEncryptionByDefault::builder()
+                                .sse_algorithm(ServerSideEncryption::Aes256)
+                                .build()
+                                .map_err(|e| build_error(&e))?,
+                        )
+                        .build(),
+                )
+                .build()
+                .map_err(|e| build_error(&e))?,
+        )
+        .send()
+        .await?;
+    s3.put_bucket_lifecycle_configuration()
+        .bucket(bucket_name)
+        .lifecycle_configuration(
+            BucketLifecycleConfiguration::builder()
+                .rules(
+                    LifecycleRule::builder()
+                        .id(LIFECYCLE_RULE_ID)
+                        .status(ExpirationStatus::Enabled)
+                        .filter(
+                            LifecycleRuleFilter::builder()
+                                .prefix(bucket_key_prefix)
+                                .build(),
+                        )
+                        .abort_incomplete_multipart_upload(
+                            AbortIncompleteMultipartUpload::builder()
+                                .days_after_initiation(abort_uploads_days)
+                                .build(),
+                        )
+                        .build()
+                        .map_err(|e| build_error(&e))?,
+                )
+                .build()
+                .map_err(|e| build_error(&e))?,
+        )
+        .send()
+        .await?;
+    log_info!("save-release-artifacts created bucket '{bucket_name}'");
+    Ok(())
+}
+
+fn days_rounded_up(age: Duration) -> i32 {
+    let days = age.as_secs().div_ceil(24 * 60 * 60).max(1);
+    i32::try_from(days).unwrap_or(i32::MAX)
+}
+
+fn build_error(e: &BuildError) -> ReleaseArtifactsError {
+    ReleaseArtifactsError::StorageError {
+        message: format!("building create bucket request: {e}"),
+        request_id: None,
+        extended_request_id: None,
+        status: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use aws_config::BehaviorVersion;
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+
+    use super::{create_bucket_if_missing, create_bucket_with_client, days_rounded_up};
+    use crate::make_s3_test_credentials;
+
+    fn test_s3_client(replay_client: &StaticReplayClient) -> aws_sdk_s3::Client {
+        aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("eu-west-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        )
+    }
+
+    fn replay_event(method: &str, query: &str, status: u16) -> ReplayEvent {
+        ReplayEvent::new(
+            http::Request::builder()
+                .method(method)
+                .uri(format!(
+                    "https://test-bucket.s3.eu-west-1.amazonaws.com/{query}"
+                ))
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(status)
+                .body(SdkBody::empty())
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn days_rounded_up_is_at_least_one() {
+        assert_eq!(days_rounded_up(Duration::from_secs(60 * 60)), 1);
+        assert_eq!(days_rounded_up(Duration::from_secs(24 * 60 * 60)), 1);
+        assert_eq!(days_rounded_up(Duration::from_secs(25 * 60 * 60)), 2);
+    }
+
+    #[tokio::test]
+    async fn create_bucket_configures_new_bucket() {
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event("HEAD", "", 404),
+            replay_event("PUT", "", 200),
+            replay_event("PUT", "?publicAccessBlock", 200),
+            replay_event("PUT", "?encryption", 200),
+            replay_event("PUT", "?lifecycle", 200),
+        ]);
+        let s3 = test_s3_client(&replay_client);
+
+        let result =
+            create_bucket_with_client(&s3, "test-bucket", Some("eu-west-1"), "sub/path/", 2).await;
+        let requests: Vec<(String, String)> = replay_client
+            .actual_requests()
+            .map(|r| {
+                (
+                    format!("{} {}", r.method(), r.uri()),
+                    r.body()
+                        .bytes()
+                        .map(|b| String::from_utf8_lossy(b).to_string())
+                        .unwrap_or_default(),
+                )
+            })
+            .collect();
+
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(requests.len(), 5);
+        assert!(requests[1].0.starts_with("PUT"));
+        assert!(
+            requests[1]
+                .1
+                .contains("<LocationConstraint>eu-west-1</LocationConstraint>"),
+            "{}",
+            requests[1].1
+        );
+        assert!(requests[2].0.contains("publicAccessBlock"));
+        assert!(requests[2]
+            .1
+            .contains("<RestrictPublicBuckets>true</RestrictPublicBuckets>"));
+        assert!(requests[3].0.contains("encryption"));
+        assert!(requests[3]
+            .1
+            .contains("<SSEAlgorithm>AES256</SSEAlgorithm>"));
+        assert!(requests[4].0.contains("lifecycle"));
+        assert!(
+            requests[4].1.contains("<Prefix>sub/path/</Prefix>"),
+            "{}",
+            requests[4].1
+        );
+        assert!(requests[4]
+            .1
+            .contains("<DaysAfterInitiation>2</DaysAfterInitiation>"));
+    }
+
+    #[tokio::test]
+    async fn create_bucket_leaves_existing_bucket() {
+        let replay_client = StaticReplayClient::new(vec![replay_event("HEAD", "", 200)]);
+        let s3 = test_s3_client(&replay_client);
+
+        let result = create_bucket_with_client(&s3, "test-bucket", Some("eu-west-1"), "", 7).await;
+
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(replay_client.actual_requests().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn create_bucket_if_missing_requires_opt_in() {
+        let replay_client = StaticReplayClient::new(vec![]);
+        let s3 = test_s3_client(&replay_client);
+
+        let result = create_bucket_if_missing(&HashMap::new(), &s3, "test-bucket", None, "").await;
+
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(replay_client.actual_requests().count(), 0);
+    }
+}
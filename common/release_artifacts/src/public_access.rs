@@ -0,0 +1,241 @@
+//! Warns when an artifact bucket is publicly readable, since archives may contain compiled
+//! secrets.
+
+use std::{collections::HashMap, hash::BuildHasher};
+
+use aws_sdk_s3::types::{Permission, PolicyStatus, PublicAccessBlockConfiguration};
+
+use crate::{errors::ReleaseArtifactsError, log_debug};
+
+// Grantees of ACLs that make a bucket readable by anyone.
+const PUBLIC_GRANTEES: [(&str, &str); 2] = [
+    (
+        "http://acs.amazonaws.com/groups/global/AllUsers",
+        "everyone",
+    ),
+    (
+        "http://acs.amazonaws.com/groups/global/AuthenticatedUsers",
+        "any AWS account",
+    ),
+];
+
+/// Prints a prominent warning when the bucket is publicly readable, unless allowed by
+/// `STATIC_ARTIFACTS_ALLOW_PUBLIC`.
+pub(crate) async fn warn_if_public<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+) {
+    if env
+        .get("STATIC_ARTIFACTS_ALLOW_PUBLIC")
+        .is_some_and(|v| v == "1" || v == "true")
+    {
+        return;
+    }
+    let reasons = public_access_with_client(s3, bucket_name).await;
+    if reasons.is_empty() {
+        return;
+    }
+    eprintln!(
+        "release-phase warning: !!! bucket '{bucket_name}' is publicly readable: {}",
+        reasons.join("; ")
+    );
+    eprintln!(
+        "release-phase warning: !!! release artifacts may contain compiled secrets. Block public access to the bucket, or set STATIC_ARTIFACTS_ALLOW_PUBLIC=1 if it is public by design."
+    );
+}
+
+// Returns why the bucket is publicly readable, or nothing when it is not, as far as the access
+// key may tell.
+pub(crate) async fn public_access_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+) -> Vec<String> {
+    let block = match s3
+        .get_public_access_block()
+        .bucket(bucket_name)
+        .send()
+        .await
+    {
+        Ok(output) => output
+            .public_access_block_configuration()
+            .cloned()
+            .unwrap_or_else(|| PublicAccessBlockConfiguration::builder().build()),
+        // Including when the bucket has no public access block.
+        Err(error) => {
+            log_debug!(
+                "release-phase cannot read public access block of bucket '{bucket_name}': {:?}",
+                ReleaseArtifactsError::from(error)
+            );
+            PublicAccessBlockConfiguration::builder().build()
+        }
+    };
+    let mut reasons = vec![];
+    if !block.restrict_public_buckets().unwrap_or_default() {
+        match s3
+            .get_bucket_policy_status()
+            .bucket(bucket_name)
+            .send()
+            .await
+        {
+            Ok(output) => {
+                if output
+                    .policy_status()
+                    .and_then(PolicyStatus::is_public)
+                    .unwrap_or_default()
+                {
+                    reasons.push("its bucket policy allows public access".to_string());
+                }
+            }
+            Err(error) => log_debug!(
+                "release-phase cannot read policy status of bucket '{bucket_name}': {:?}",
+                ReleaseArtifactsError::from(error)
+            ),
+        }
+    }
+    if !block.ignore_public_acls().unwrap_or_default() {
+        match s3.get_bucket_acl().bucket(bucket_name).send().await {
+            Ok(output) => {
+                for grant in output.grants() {
+                    let readable = matches!(
+                        grant.permission(),
+                        Some(Permission::Read | Permission::FullControl)
+                    );
+                    let public_grantee = grant
+                        .grantee()
+                        .and_then(|grantee| grantee.uri())
+                        .and_then(|uri| {
+                            PUBLIC_GRANTEES
+                                .iter()
+                                .find(|(grantee_uri, _)| *grantee_uri == uri)
+                        });
+                    if let (true, Some((_, grantee))) = (readable, public_grantee) {
+                        reasons.push(format!("its ACL grants read access to {grantee}"));
+                    }
+                }
+            }
+            Err(error) => log_debug!(
+                "release-phase cannot read ACL of bucket '{bucket_name}': {:?}",
+                ReleaseArtifactsError::from(error)
+            ),
+        }
+    }
+    reasons
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aws_config::BehaviorVersion;
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+
+    use super::{public_access_with_client, warn_if_public};
+    use crate::make_s3_test_credentials;
+
+    fn test_s3_client(replay_client: &StaticReplayClient) -> aws_sdk_s3::Client {
+        aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        )
+    }
+
+    fn replay_event(query: &str, status: u16, response_body: &str) -> ReplayEvent {
+        ReplayEvent::new(
+            http::Request::builder()
+                .method("GET")
+                .uri(format!(
+                    "https://test-bucket.s3.us-east-1.amazonaws.com/?{query}"
+                ))
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(status)
+                .body(SdkBody::from(response_body))
+                .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn public_access_detects_public_policy_and_acl() {
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event(
+                "publicAccessBlock",
+                404,
+                "<Error><Code>NoSuchPublicAccessBlockConfiguration</Code></Error>",
+            ),
+            replay_event(
+                "policyStatus",
+                200,
+                "<PolicyStatus><IsPublic>true</IsPublic></PolicyStatus>",
+            ),
+            replay_event(
+                "acl",
+                200,
+                r#"<AccessControlPolicy><Owner><ID>owner</ID></Owner><AccessControlList>
+                    <Grant><Grantee xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="CanonicalUser"><ID>owner</ID></Grantee><Permission>FULL_CONTROL</Permission></Grant>
+                    <Grant><Grantee xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="Group"><URI>http://acs.amazonaws.com/groups/global/AllUsers</URI></Grantee><Permission>READ</Permission></Grant>
+                </AccessControlList></AccessControlPolicy>"#,
+            ),
+        ]);
+        let s3 = test_s3_client(&replay_client);
+
+        let reasons = public_access_with_client(&s3, "test-bucket").await;
+
+        assert_eq!(
+            reasons,
+            vec![
+                "its bucket policy allows public access".to_string(),
+                "its ACL grants read access to everyone".to_string(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn public_access_trusts_public_access_block() {
+        let replay_client = StaticReplayClient::new(vec![replay_event(
+            "publicAccessBlock",
+            200,
+            "<PublicAccessBlockConfiguration><BlockPublicAcls>true</BlockPublicAcls><IgnorePublicAcls>true</IgnorePublicAcls><BlockPublicPolicy>true</BlockPublicPolicy><RestrictPublicBuckets>true</RestrictPublicBuckets></PublicAccessBlockConfiguration>",
+        )]);
+        let s3 = test_s3_client(&replay_client);
+
+        let reasons = public_access_with_client(&s3, "test-bucket").await;
+
+        assert!(reasons.is_empty(), "{reasons:?}");
+        assert_eq!(replay_client.actual_requests().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn public_access_skips_checks_that_are_denied() {
+        let denied = "<Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>";
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event("publicAccessBlock", 403, denied),
+            replay_event("policyStatus", 403, denied),
+            replay_event("acl", 403, denied),
+        ]);
+        let s3 = test_s3_client(&replay_client);
+
+        let reasons = public_access_with_client(&s3, "test-bucket").await;
+
+        assert!(reasons.is_empty(), "{reasons:?}");
+        assert_eq!(replay_client.actual_requests().count(), 3);
+    }
+
+    #[tokio::test]
+    async fn warn_if_public_is_silenced_by_allow_public() {
+        let replay_client = StaticReplayClient::new(vec![]);
+        let s3 = test_s3_client(&replay_client);
+        let test_env =
+            HashMap::from([("STATIC_ARTIFACTS_ALLOW_PUBLIC".to_string(), "1".to_string())]);
+
+        warn_if_public(&test_env, &s3, "test-bucket").await;
+
+        assert_eq!(replay_client.actual_requests().count(), 0);
+    }
+}
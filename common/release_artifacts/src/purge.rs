@@ -0,0 +1,370 @@
+//! Purges the Fastly or `CloudFront` CDN of `STATIC_ARTIFACTS_CDN` after saving.
+
+use std::{
+    collections::HashMap,
+    hash::BuildHasher,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use aws_sdk_cloudfront::{
+    config::{Credentials, Region},
+    types::{InvalidationBatch, Paths},
+};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use url::Url;
+
+use crate::{errors::ReleaseArtifactsError, s3_http_client};
+
+// Bounds each purge or notification request, so that an unresponsive API cannot hang the release.
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns whether a CDN was purged, which is `false` when none is configured.
+pub(crate) async fn purge_cdn<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    key_prefix: &str,
+) -> Result<bool, ReleaseArtifactsError> {
+    match env.get("STATIC_ARTIFACTS_CDN").map(String::as_str) {
+        None | Some("") => Ok(false),
+        Some("fastly") => {
+            send_request(fastly_request(env)?)
+                .await
+                .map_err(ReleaseArtifactsError::CdnPurgeFailed)?;
+            Ok(true)
+        }
+        Some("cloudfront") => {
+            let cloudfront = cloudfront_client(env).await?;
+            invalidate_cloudfront(&cloudfront, env, key_prefix, SystemTime::now()).await?;
+            Ok(true)
+        }
+        Some(other) => Err(ReleaseArtifactsError::CdnPurgeFailed(format!(
+            "STATIC_ARTIFACTS_CDN '{other}' is not supported, expected 'fastly' or 'cloudfront'"
+        ))),
+    }
+}
+
+fn fastly_request<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<http::Request<String>, ReleaseArtifactsError> {
+    let service_id = required(env, "STATIC_ARTIFACTS_FASTLY_SERVICE_ID")?;
+    let api_token = required(env, "STATIC_ARTIFACTS_FASTLY_API_TOKEN")?;
+    let mut uri = Url::parse("https://api.fastly.com/service").expect("Fastly API URL is valid");
+    {
+        // Each segment is percent-encoded, so that any surrogate key stays in its segment.
+        let mut segments = uri.path_segments_mut().expect("Fastly API URL has a path");
+        segments.push(service_id);
+        match env.get("STATIC_ARTIFACTS_FASTLY_SURROGATE_KEY") {
+            Some(surrogate_key) => segments.extend(["purge", surrogate_key.as_str()]),
+            None => segments.push("purge_all"),
+        };
+    }
+    http::Request::builder()
+        .method("POST")
+        .uri(uri.as_str())
+        .header("fastly-key", api_token)
+        .header("accept", "application/json")
+        .body(String::new())
+        .map_err(|e| {
+            ReleaseArtifactsError::CdnPurgeFailed(format!("building Fastly purge request: {e}"))
+        })
+}
+
+// CloudFront is a global service, signed for `us-east-1`, with the storage credentials,
+// including the session token of temporary credentials.
+async fn cloudfront_client<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<aws_sdk_cloudfront::Client, ReleaseArtifactsError> {
+    let credentials = Credentials::new(
+        required(env, "STATIC_ARTIFACTS_ACCESS_KEY_ID")?,
+        required(env, "STATIC_ARTIFACTS_SECRET_ACCESS_KEY")?,
+        env.get("STATIC_ARTIFACTS_SESSION_TOKEN").cloned(),
+        None,
+        "Static Artifacts storage",
+    );
+    let shared_config = aws_config::from_env()
+        .region(Region::new("us-east-1"))
+        .credentials_provider(credentials)
+        .http_client(s3_http_client(env))
+        .load()
+        .await;
+    Ok(aws_sdk_cloudfront::Client::new(&shared_config))
+}
+
+async fn invalidate_cloudfront<S: BuildHasher>(
+    cloudfront: &aws_sdk_cloudfront::Client,
+    env: &HashMap<String, String, S>,
+    key_prefix: &str,
+    time: SystemTime,
+) -> Result<(), ReleaseArtifactsError> {
+    let distribution_id = required(env, "STATIC_ARTIFACTS_CLOUDFRONT_DISTRIBUTION_ID")?;
+    let paths: Vec<String> = match env.get("STATIC_ARTIFACTS_CLOUDFRONT_PATHS") {
+        Some(paths) => paths
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect(),
+        None => vec![format!("/{key_prefix}*")],
+    };
+    // CloudFront rejects a repeated caller reference, so it must be unique to each purge.
+    let caller_reference = format!(
+        "release-phase-{}",
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+    );
+    let build_error = |e: aws_sdk_cloudfront::error::BuildError| {
+        ReleaseArtifactsError::CdnPurgeFailed(format!(
+            "building CloudFront invalidation request: {e}"
+        ))
+    };
+    let invalidation_batch = InvalidationBatch::builder()
+        .caller_reference(caller_reference)
+        .paths(
+            Paths::builder()
+                .quantity(i32::try_from(paths.len()).unwrap_or(i32::MAX))
+                .set_items(Some(paths))
+                .build()
+                .map_err(build_error)?,
+        )
+        .build()
+        .map_err(build_error)?;
+    cloudfront
+        .create_invalidation()
+        .distribution_id(distribution_id)
+        .invalidation_batch(invalidation_batch)
+        .send()
+        .await
+        .map_err(|e| {
+            ReleaseArtifactsError::CdnPurgeFailed(format!(
+                "invalidating CloudFront distribution {distribution_id}: {}",
+                aws_smithy_types::error::display::DisplayErrorContext(&e)
+            ))
+        })?;
+    Ok(())
+}
+
+/// Sends the request over HTTPS, failing unless it responds successfully within 30 seconds.
+pub(crate) async fn send_request(request: http::Request<String>) -> Result<(), String> {
+    send_request_within(request, SEND_TIMEOUT).await.map(drop)
+}
+
+/// Sends the request like `send_request`, returning the body of its response.
+pub(crate) async fn fetch_response(request: http::Request<String>) -> Result<String, String> {
+    send_request_within(request, SEND_TIMEOUT).await
+}
+
+async fn send_request_within(
+    request: http::Request<String>,
+    timeout: Duration,
+) -> Result<String, String> {
+    let uri = request.uri().clone();
+    tokio::time::timeout(timeout, exchange(request))
+        .await
+        .map_err(|_| format!("requesting {uri}: timed out after {timeout:?}"))?
+}
+
+async fn exchange(request: http::Request<String>) -> Result<String, String> {
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_provider_and_native_roots(rustls::crypto::ring::default_provider())
+        .map_err(|e| format!("loading root certificates: {e}"))?
+        .https_only()
+        .enable_http1()
+        .build();
+    let client: Client<_, Full<Bytes>> = Client::builder(TokioExecutor::new()).build(connector);
+    let uri = request.uri().clone();
+    let response = client
+        .request(request.map(|body| Full::new(Bytes::from(body))))
+        .await
+        .map_err(|e| format!("requesting {uri}: {e}"))?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map(|b| String::from_utf8_lossy(&b.to_bytes()).to_string());
+    if !status.is_success() {
+        return Err(format!(
+            "{uri} responded {status}: {}",
+            body.unwrap_or_default()
+        ));
+    }
+    body.map_err(|e| format!("reading response of {uri}: {e}"))
+}
+
+pub(crate) fn required<'a, S: BuildHasher>(
+    env: &'a HashMap<String, String, S>,
+    name: &str,
+) -> Result<&'a String, ReleaseArtifactsError> {
+    env.get(name)
+        .ok_or_else(|| ReleaseArtifactsError::ConfigMissing(format!("{name} is required")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, SystemTime},
+    };
+
+    use aws_config::BehaviorVersion;
+    use aws_sdk_cloudfront::config::{Credentials, Region};
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+
+    use super::{fastly_request, invalidate_cloudfront, purge_cdn, send_request_within};
+
+    const INVALIDATION_URI: &str =
+        "https://cloudfront.amazonaws.com/2020-05-31/distribution/EDFDVBD6EXAMPLE/invalidation";
+
+    fn cloudfront_env() -> HashMap<String, String> {
+        HashMap::from([
+            ("STATIC_ARTIFACTS_CDN".to_string(), "cloudfront".to_string()),
+            (
+                "STATIC_ARTIFACTS_CLOUDFRONT_DISTRIBUTION_ID".to_string(),
+                "EDFDVBD6EXAMPLE".to_string(),
+            ),
+        ])
+    }
+
+    fn make_cloudfront_client(replay_client: &StaticReplayClient) -> aws_sdk_cloudfront::Client {
+        aws_sdk_cloudfront::Client::from_conf(
+            aws_sdk_cloudfront::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(Credentials::new(
+                    "test-key",
+                    "test-secret",
+                    Some("test-session-token".to_string()),
+                    None,
+                    "test",
+                ))
+                .region(Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        )
+    }
+
+    fn invalidation_event() -> ReplayEvent {
+        ReplayEvent::new(
+            http::Request::builder()
+                .method("POST")
+                .uri(INVALIDATION_URI)
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(201)
+                .body(SdkBody::from(
+                    "<Invalidation><Id>I2J0I21PCUYOIK</Id><Status>InProgress</Status></Invalidation>",
+                ))
+                .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn purge_cdn_without_cdn() {
+        let test_env: HashMap<String, String> = HashMap::new();
+        let result = purge_cdn(&test_env, "").await;
+        assert!(matches!(result, Ok(false)));
+
+        let test_env = HashMap::from([("STATIC_ARTIFACTS_CDN".to_string(), "akamai".to_string())]);
+        let result = purge_cdn(&test_env, "").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_request_times_out() {
+        // Accepts the connection, but never completes the TLS handshake.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let request = http::Request::builder()
+            .method("POST")
+            .uri(format!("https://{}/purge", listener.local_addr().unwrap()))
+            .body(String::new())
+            .unwrap();
+
+        let result = send_request_within(request, Duration::from_millis(100)).await;
+
+        assert!(result.unwrap_err().contains("timed out"));
+    }
+
+    #[test]
+    fn fastly_request_purges_service_or_surrogate_key() {
+        let mut test_env = HashMap::from([
+            (
+                "STATIC_ARTIFACTS_FASTLY_SERVICE_ID".to_string(),
+                "SU1Z0isxPaozGVKXdv0eY".to_string(),
+            ),
+            (
+                "STATIC_ARTIFACTS_FASTLY_API_TOKEN".to_string(),
+                "test-token".to_string(),
+            ),
+        ]);
+        let request = fastly_request(&test_env).unwrap();
+        assert_eq!(request.method(), "POST");
+        assert_eq!(
+            request.uri(),
+            "https://api.fastly.com/service/SU1Z0isxPaozGVKXdv0eY/purge_all"
+        );
+        assert_eq!(request.headers()["fastly-key"], "test-token");
+
+        test_env.insert(
+            "STATIC_ARTIFACTS_FASTLY_SURROGATE_KEY".to_string(),
+            "static artifacts/v2?".to_string(),
+        );
+        let request = fastly_request(&test_env).unwrap();
+        assert_eq!(
+            request.uri(),
+            "https://api.fastly.com/service/SU1Z0isxPaozGVKXdv0eY/purge/static%20artifacts%2Fv2%3F"
+        );
+
+        test_env.remove("STATIC_ARTIFACTS_FASTLY_API_TOKEN");
+        assert!(fastly_request(&test_env).is_err());
+    }
+
+    #[tokio::test]
+    async fn invalidate_cloudfront_with_session_token() {
+        let replay_client = StaticReplayClient::new(vec![invalidation_event()]);
+        let cloudfront = make_cloudfront_client(&replay_client);
+
+        let result = invalidate_cloudfront(
+            &cloudfront,
+            &cloudfront_env(),
+            "sub/path/",
+            SystemTime::now(),
+        )
+        .await;
+
+        assert!(result.is_ok(), "{result:?}");
+        let request = replay_client.actual_requests().next().unwrap();
+        assert_eq!(request.uri(), INVALIDATION_URI);
+        assert_eq!(
+            request.headers().get("x-amz-security-token"),
+            Some("test-session-token")
+        );
+        let body = String::from_utf8_lossy(request.body().bytes().unwrap());
+        assert!(body.contains(
+            "<Paths><Quantity>1</Quantity><Items><Path>/sub/path/*</Path></Items></Paths>"
+        ));
+    }
+
+    #[tokio::test]
+    async fn invalidate_cloudfront_configured_paths() {
+        let replay_client = StaticReplayClient::new(vec![invalidation_event()]);
+        let cloudfront = make_cloudfront_client(&replay_client);
+        let mut test_env = cloudfront_env();
+        test_env.insert(
+            "STATIC_ARTIFACTS_CLOUDFRONT_PATHS".to_string(),
+            "/index.html, /assets/*".to_string(),
+        );
+
+        let result =
+            invalidate_cloudfront(&cloudfront, &test_env, "sub/path/", SystemTime::now()).await;
+
+        assert!(result.is_ok(), "{result:?}");
+        let request = replay_client.actual_requests().next().unwrap();
+        let body = String::from_utf8_lossy(request.body().bytes().unwrap());
+        assert!(body.contains(
+            "<Quantity>2</Quantity><Items><Path>/index.html</Path><Path>/assets/*</Path></Items>"
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+//! Retries storage requests that fail transiently, and downloads whose body is interrupted, with
+//! exponential backoff.
+
+use std::{collections::HashMap, future::Future, hash::BuildHasher, time::Duration};
+
+use aws_sdk_s3::config::retry::RetryConfig;
+
+use crate::{errors::ReleaseArtifactsError, parse_tuning_var};
+
+const DEFAULT_MAX_RETRIES: u32 = 2;
+
+const DEFAULT_INITIAL_BACKOFF_MS: u64 = 1000;
+
+// The SDK's default max backoff.
+const MAX_BACKOFF: Duration = Duration::from_secs(20);
+
+/// The retry config of the S3 clients, from `STATIC_ARTIFACTS_MAX_RETRIES` &
+/// `STATIC_ARTIFACTS_RETRY_BACKOFF_MS`.
+pub(crate) fn retry_config<S: BuildHasher>(env: &HashMap<String, String, S>) -> RetryConfig {
+    let max_retries: u32 =
+        parse_tuning_var(env, "STATIC_ARTIFACTS_MAX_RETRIES").unwrap_or(DEFAULT_MAX_RETRIES);
+    let initial_backoff = Duration::from_millis(
+        parse_tuning_var(env, "STATIC_ARTIFACTS_RETRY_BACKOFF_MS")
+            .unwrap_or(DEFAULT_INITIAL_BACKOFF_MS),
+    );
+    RetryConfig::standard()
+        .with_max_attempts(max_retries.saturating_add(1))
+        .with_initial_backoff(initial_backoff)
+        .with_max_backoff(MAX_BACKOFF)
+}
+
+/// Runs the download, and again after a backoff while its body is interrupted, up to the max
+/// attempts of the client's retry config.
+pub(crate) async fn retry_interrupted<T, F, Fut>(
+    s3: &aws_sdk_s3::Client,
+    download: F,
+) -> Result<T, ReleaseArtifactsError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, ReleaseArtifactsError>>,
+{
+    let (max_attempts, initial_backoff) =
+        s3.config().retry_config().map_or((1, Duration::ZERO), |c| {
+            (c.max_attempts(), c.initial_backoff())
+        });
+    let mut attempt = 1;
+    loop {
+        match download().await {
+            Err(error) if error.is_interrupted_transfer() && attempt < max_attempts => {
+                let backoff = backoff(initial_backoff, attempt);
+                eprintln!(
+                    "release-phase warning: download interrupted, retrying in {}ms, attempt {} of {max_attempts}: {error:?}",
+                    backoff.as_millis(),
+                    attempt + 1
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// The backoff before the retry that follows the attempt, doubling from the initial backoff.
+fn backoff(initial_backoff: Duration, attempt: u32) -> Duration {
+    initial_backoff
+        .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fs,
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use aws_config::BehaviorVersion;
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::{body::SdkBody, date_time::Format, DateTime};
+    use uuid::Uuid;
+
+    use super::{backoff, retry_config};
+    use crate::{
+        download_with_client, errors::ReleaseArtifactsError, list_with_client,
+        make_s3_test_credentials, upload_with_client, ArchiveVerifier, ConflictPolicy,
+    };
+
+    const BUCKET_URI: &str = "https://test-bucket.s3.us-east-1.amazonaws.com";
+
+    fn make_retrying_client(replay_client: &StaticReplayClient) -> aws_sdk_s3::Client {
+        let env = HashMap::from([
+            ("STATIC_ARTIFACTS_MAX_RETRIES".to_string(), "2".to_string()),
+            (
+                "STATIC_ARTIFACTS_RETRY_BACKOFF_MS".to_string(),
+                "1".to_string(),
+            ),
+        ]);
+        aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .retry_config(retry_config(&env))
+                .http_client(replay_client.clone())
+                .build(),
+        )
+    }
+
+    // A 500 response to the request, followed by the given response to its retry.
+    fn failure_then(
+        method: &str,
+        uri: &str,
+        response: http::Response<SdkBody>,
+    ) -> Vec<ReplayEvent> {
+        let request = || {
+            http::Request::builder()
+                .method(method)
+                .uri(format!("{BUCKET_URI}{uri}"))
+                .body(SdkBody::empty())
+                .unwrap()
+        };
+        vec![
+            ReplayEvent::new(
+                request(),
+                http::Response::builder()
+                    .status(500)
+                    .body(SdkBody::from(
+                        "<Error><Code>InternalError</Code><Message>We encountered an internal error. Please try again.</Message></Error>",
+                    ))
+                    .unwrap(),
+            ),
+            ReplayEvent::new(request(), response),
+        ]
+    }
+
+    #[test]
+    fn retry_config_from_env() {
+        let config = retry_config(&HashMap::<String, String>::new());
+        assert_eq!(config.max_attempts(), 3);
+        assert_eq!(config.initial_backoff(), Duration::from_secs(1));
+        let config = retry_config(&HashMap::from([(
+            "STATIC_ARTIFACTS_MAX_RETRIES".to_string(),
+            "0".to_string(),
+        )]));
+        assert_eq!(config.max_attempts(), 1);
+
+        let initial = Duration::from_millis(500);
+        assert_eq!(backoff(initial, 1), Duration::from_millis(500));
+        assert_eq!(backoff(initial, 3), Duration::from_secs(2));
+        assert_eq!(backoff(initial, 10), Duration::from_secs(20));
+    }
+
+    #[tokio::test]
+    async fn list_is_retried_after_failure() {
+        let replay_client = StaticReplayClient::new(failure_then(
+            "GET",
+            "/?list-type=2&prefix=sub%2Fpath%2F",
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(
+                    "<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>",
+                ))
+                .unwrap(),
+        ));
+        let s3 = make_retrying_client(&replay_client);
+
+        let result =
+            list_with_client(&s3, &"test-bucket".to_string(), &"sub/path/".to_string()).await;
+
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(replay_client.actual_requests().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn get_is_retried_after_failure() {
+        let archive_data =
+            fs::read("test/fixtures/static-artifacts.tgz").expect("test fixture file should exist");
+        let replay_client = StaticReplayClient::new(failure_then(
+            "GET",
+            "/sub/path/static-artifacts.tgz?x-id=GetObject",
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(archive_data))
+                .unwrap(),
+        ));
+        let s3 = make_retrying_client(&replay_client);
+        let output_dir_name = format!("test-output-retry-{}", Uuid::new_v4());
+        let output_dir = Path::new(output_dir_name.as_str());
+
+        let result = download_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/static-artifacts.tgz".to_string(),
+            output_dir,
+            &ArchiveVerifier::default(),
+            ConflictPolicy::Overwrite,
+        )
+        .await;
+        let extracted = output_dir.join("index.html").is_file();
+        fs::remove_dir_all(output_dir).unwrap_or_default();
+
+        assert!(result.is_ok(), "{result:?}");
+        assert!(extracted);
+        assert_eq!(replay_client.actual_requests().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn put_is_retried_after_failure() {
+        let archive_name = format!("test-retry-{}.tgz", Uuid::new_v4());
+        fs::copy("test/fixtures/static-artifacts.tgz", &archive_name)
+            .expect("test fixture file should be copied");
+        let replay_client = StaticReplayClient::new(failure_then(
+            "PUT",
+            "/sub/path/static-artifacts.tgz?x-id=PutObject",
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::empty())
+                .unwrap(),
+        ));
+        let s3 = make_retrying_client(&replay_client);
+
+        let result = upload_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/static-artifacts.tgz".to_string(),
+            &archive_name,
+        )
+        .await;
+        fs::remove_file(&archive_name).unwrap_or_default();
+
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(replay_client.actual_requests().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn put_is_retried_with_resynced_clock_after_clock_skew() {
+        let archive_name = format!("test-retry-skew-{}.tgz", Uuid::new_v4());
+        fs::copy("test/fixtures/static-artifacts.tgz", &archive_name)
+            .expect("test fixture file should be copied");
+        let server_date = DateTime::from_secs(DateTime::from(SystemTime::now()).secs() - 3600)
+            .fmt(Format::HttpDate)
+            .unwrap();
+        let request = || {
+            http::Request::builder()
+                .method("PUT")
+                .uri(format!(
+                    "{BUCKET_URI}/sub/path/static-artifacts.tgz?x-id=PutObject"
+                ))
+                .body(SdkBody::empty())
+                .unwrap()
+        };
+        let replay_client = StaticReplayClient::new(vec![
+            ReplayEvent::new(
+                request(),
+                http::Response::builder()
+                    .status(403)
+                    .header("date", server_date)
+                    .body(SdkBody::from(
+                        "<Error><Code>RequestTimeTooSkewed</Code><Message>The difference between the request time and the current time is too large.</Message></Error>",
+                    ))
+                    .unwrap(),
+            ),
+            ReplayEvent::new(
+                request(),
+                http::Response::builder()
+                    .status(200)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+            ),
+        ]);
+        let s3 = make_retrying_client(&replay_client);
+
+        let result = upload_with_client(
+            &s3,
+            &"test-bucket".to_string(),
+            &"sub/path/static-artifacts.tgz".to_string(),
+            &archive_name,
+        )
+        .await;
+        fs::remove_file(&archive_name).unwrap_or_default();
+
+        assert!(result.is_ok(), "{result:?}");
+        let signing_times: Vec<String> = replay_client
+            .actual_requests()
+            .map(|request| {
+                request
+                    .headers()
+                    .get("x-amz-date")
+                    .expect("request should be signed")
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(signing_times.len(), 2);
+        // Signed at the service's time, an hour behind this system's, in the same basic ISO 8601
+        // format, which sorts chronologically.
+        assert!(signing_times[1] < signing_times[0], "{signing_times:?}");
+    }
+
+    #[tokio::test]
+    async fn retries_are_limited() {
+        let mut events = failure_then(
+            "GET",
+            "/?list-type=2&prefix=sub%2Fpath%2F",
+            http::Response::builder()
+                .status(503)
+                .body(SdkBody::from(
+                    "<Error><Code>SlowDown</Code><Message>Please reduce your request rate.</Message></Error>",
+                ))
+                .unwrap(),
+        );
+        events.extend(failure_then(
+            "GET",
+            "/?list-type=2&prefix=sub%2Fpath%2F",
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::empty())
+                .unwrap(),
+        ));
+        let replay_client = StaticReplayClient::new(events);
+        let s3 = make_retrying_client(&replay_client);
+
+        let result =
+            list_with_client(&s3, &"test-bucket".to_string(), &"sub/path/".to_string()).await;
+
+        assert_eq!(replay_client.actual_requests().count(), 3);
+        assert!(matches!(
+            result,
+            Err(ReleaseArtifactsError::StorageError {
+                status: Some(500),
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,758 @@
+//! Signs the digest of each archive when saved, and verifies it when loaded, with Ed25519 keys,
+//! or keylessly, with a short-lived Fulcio certificate for an OIDC identity token.
+
+use std::{
+    collections::HashMap,
+    fs,
+    hash::BuildHasher,
+    path::{Path, PathBuf},
+};
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine as _,
+};
+use ring::{
+    rand::SystemRandom,
+    signature::{
+        EcdsaKeyPair, Ed25519KeyPair, KeyPair, UnparsedPublicKey, ECDSA_P256_SHA256_ASN1,
+        ECDSA_P256_SHA256_ASN1_SIGNING, ED25519,
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use x509_parser::{
+    certificate::X509Certificate, der_parser::der::parse_der_utf8string, extensions::GeneralName,
+    parse_x509_certificate, pem::Pem,
+};
+
+use crate::{
+    checksum::file_checksum,
+    errors::ReleaseArtifactsError,
+    pre_extract::run_pre_extract_command,
+    purge::{fetch_response, required},
+};
+
+/// The S3 user metadata key, stored as the header `x-amz-meta-release-phase-signature`.
+pub(crate) const SIGNATURE_METADATA_KEY: &str = "release-phase-signature";
+
+/// The signature metadata of archives signed keylessly, whose certificate exceeds the size of S3
+/// metadata, so the signature is stored beside the archive, in `<key>.sig`.
+pub(crate) const KEYLESS_SIGNATURE_METADATA: &str = "keyless";
+
+const DEFAULT_FULCIO_URL: &str = "https://fulcio.sigstore.dev";
+
+// Fulcio's certificate extensions of the token's issuer, DER-encoded, & its deprecated raw form.
+const OIDC_ISSUER_OID: &str = "1.3.6.1.4.1.57264.1.8";
+const OIDC_ISSUER_V1_OID: &str = "1.3.6.1.4.1.57264.1.1";
+
+// The DER of a P-256 SubjectPublicKeyInfo, up to its uncompressed point.
+const P256_SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+// A keyless signature, bundled with the certificate of its ephemeral key.
+#[derive(Debug, Serialize, Deserialize)]
+struct KeylessSignature {
+    certificate: String,
+    signature: String,
+}
+
+/// Verifies archives before they are extracted, by their signature and the pre-extract
+/// command. The default verifies nothing.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveVerifier {
+    verify_key: Option<Vec<u8>>,
+    keyless: Option<KeylessVerifier>,
+    required: bool,
+    pre_extract_command: Option<String>,
+}
+
+impl ArchiveVerifier {
+    pub fn from_env<S: BuildHasher>(
+        env: &HashMap<String, String, S>,
+    ) -> Result<ArchiveVerifier, ReleaseArtifactsError> {
+        let verify_key = env
+            .get("STATIC_ARTIFACTS_VERIFY_KEY")
+            .map(|key| {
+                hex::decode(key.trim()).map_err(|e| {
+                    ReleaseArtifactsError::SignatureInvalid(format!(
+                        "STATIC_ARTIFACTS_VERIFY_KEY is not hex-encoded: {e}"
+                    ))
+                })
+            })
+            .transpose()?;
+        let required = env
+            .get("STATIC_ARTIFACTS_REQUIRE_SIGNATURE")
+            .is_some_and(|v| v == "1" || v == "true");
+        let keyless = KeylessVerifier::from_env(env)?;
+        if required && verify_key.is_none() && keyless.is_none() {
+            return Err(ReleaseArtifactsError::ConfigMissing(
+                "STATIC_ARTIFACTS_VERIFY_KEY or STATIC_ARTIFACTS_VERIFY_IDENTITY is required with STATIC_ARTIFACTS_REQUIRE_SIGNATURE"
+                    .to_string(),
+            ));
+        }
+        let pre_extract_command = env
+            .get("STATIC_ARTIFACTS_PRE_EXTRACT_COMMAND")
+            .filter(|command| !command.trim().is_empty())
+            .cloned();
+        Ok(ArchiveVerifier {
+            verify_key,
+            keyless,
+            required,
+            pre_extract_command,
+        })
+    }
+
+    pub fn verify(
+        &self,
+        archive_path: &Path,
+        signature: Option<&str>,
+    ) -> Result<(), ReleaseArtifactsError> {
+        self.verify_signature(archive_path, signature)?;
+        if let Some(command) = &self.pre_extract_command {
+            run_pre_extract_command(command, archive_path)?;
+        }
+        Ok(())
+    }
+
+    /// Whether verifying needs the whole archive as a file, before it may be extracted, rather
+    /// than extracting it as it is received.
+    pub(crate) fn needs_archive_file(&self) -> bool {
+        self.verify_key.is_some()
+            || self.keyless.is_some()
+            || self.required
+            || self.pre_extract_command.is_some()
+    }
+
+    fn verify_signature(
+        &self,
+        archive_path: &Path,
+        signature: Option<&str>,
+    ) -> Result<(), ReleaseArtifactsError> {
+        let Some(signature) = signature else {
+            return self.unverified(archive_path, "is not signed");
+        };
+        if is_keyless(signature) {
+            let Some(keyless) = &self.keyless else {
+                return self.unverified(
+                    archive_path,
+                    "is signed keylessly, without STATIC_ARTIFACTS_VERIFY_IDENTITY",
+                );
+            };
+            let signature = serde_json::from_str(signature).map_err(|e| {
+                ReleaseArtifactsError::SignatureInvalid(format!(
+                    "keyless signature of archive {archive_path:?} is invalid: {e}"
+                ))
+            })?;
+            return keyless.verify(archive_path, &signature);
+        }
+        let Some(verify_key) = &self.verify_key else {
+            return self.unverified(
+                archive_path,
+                "is signed with a key, without STATIC_ARTIFACTS_VERIFY_KEY",
+            );
+        };
+        let signature = hex::decode(signature.trim()).map_err(|e| {
+            ReleaseArtifactsError::SignatureInvalid(format!(
+                "signature of archive {archive_path:?} is not hex-encoded: {e}"
+            ))
+        })?;
+        let message = digest_message(archive_path)?;
+        // Even when signatures are not required, an invalid one is rejected, as it means tampering.
+        UnparsedPublicKey::new(&ED25519, verify_key)
+            .verify(message.as_bytes(), &signature)
+            .map_err(|_| {
+                ReleaseArtifactsError::SignatureInvalid(format!(
+                    "signature of archive {archive_path:?} does not match STATIC_ARTIFACTS_VERIFY_KEY"
+                ))
+            })
+    }
+
+    // Accepts an archive whose signature cannot be verified, unless signatures are required.
+    fn unverified(&self, archive_path: &Path, reason: &str) -> Result<(), ReleaseArtifactsError> {
+        if self.required {
+            return Err(ReleaseArtifactsError::SignatureInvalid(format!(
+                "archive {archive_path:?} {reason}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Verifies keyless signatures by their certificate: issued by one of the CA certificates, for
+// code signing, to the identity by the OIDC issuer. Without a transparency log to prove when the
+// archive was signed, the certificate's expiry, minutes after it was issued, is not checked.
+#[derive(Debug, Clone)]
+struct KeylessVerifier {
+    identity: String,
+    oidc_issuer: String,
+    ca_certificates: Vec<Vec<u8>>,
+}
+
+impl KeylessVerifier {
+    fn from_env<S: BuildHasher>(
+        env: &HashMap<String, String, S>,
+    ) -> Result<Option<KeylessVerifier>, ReleaseArtifactsError> {
+        let Some(identity) = env.get("STATIC_ARTIFACTS_VERIFY_IDENTITY") else {
+            return Ok(None);
+        };
+        let oidc_issuer = required(env, "STATIC_ARTIFACTS_VERIFY_OIDC_ISSUER")?;
+        let ca_certificates = parse_pem_certificates(required(
+            env,
+            "STATIC_ARTIFACTS_VERIFY_CA_CERTS",
+        )?)
+        .map_err(|e| {
+            ReleaseArtifactsError::ConfigInvalid(format!("STATIC_ARTIFACTS_VERIFY_CA_CERTS {e}"))
+        })?;
+        if ca_certificates.is_empty()
+            || ca_certificates
+                .iter()
+                .any(|der| parse_x509_certificate(der).is_err())
+        {
+            return Err(ReleaseArtifactsError::ConfigInvalid(
+                "STATIC_ARTIFACTS_VERIFY_CA_CERTS is not a list of PEM certificates".to_string(),
+            ));
+        }
+        Ok(Some(KeylessVerifier {
+            identity: identity.trim().to_string(),
+            oidc_issuer: oidc_issuer.trim().to_string(),
+            ca_certificates,
+        }))
+    }
+
+    fn verify(
+        &self,
+        archive_path: &Path,
+        signature: &KeylessSignature,
+    ) -> Result<(), ReleaseArtifactsError> {
+        let invalid = |reason: String| {
+            ReleaseArtifactsError::SignatureInvalid(format!(
+                "keyless signature of archive {archive_path:?} {reason}"
+            ))
+        };
+        let certificate_der = parse_pem_certificates(&signature.certificate)
+            .map_err(invalid)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| invalid("has no certificate".to_string()))?;
+        let (_, certificate) = parse_x509_certificate(&certificate_der)
+            .map_err(|e| invalid(format!("has an invalid certificate: {e}")))?;
+        let issued = self.ca_certificates.iter().any(|ca_der| {
+            parse_x509_certificate(ca_der).is_ok_and(|(_, ca)| {
+                ca.subject().as_raw() == certificate.issuer().as_raw()
+                    && certificate.verify_signature(Some(ca.public_key())).is_ok()
+            })
+        });
+        if !issued {
+            return Err(invalid(
+                "has a certificate not issued by STATIC_ARTIFACTS_VERIFY_CA_CERTS".to_string(),
+            ));
+        }
+        let code_signing = certificate
+            .extended_key_usage()
+            .ok()
+            .flatten()
+            .is_some_and(|usage| usage.value.code_signing);
+        if !code_signing {
+            return Err(invalid(
+                "has a certificate that is not for code signing".to_string(),
+            ));
+        }
+        let identities = certificate_identities(&certificate);
+        if !identities.contains(&self.identity.as_str()) {
+            return Err(invalid(format!(
+                "is by {identities:?}, not STATIC_ARTIFACTS_VERIFY_IDENTITY"
+            )));
+        }
+        let oidc_issuer = certificate_oidc_issuer(&certificate);
+        if oidc_issuer != Some(self.oidc_issuer.as_str()) {
+            return Err(invalid(format!(
+                "is from OIDC issuer {oidc_issuer:?}, not STATIC_ARTIFACTS_VERIFY_OIDC_ISSUER"
+            )));
+        }
+        let signature = hex::decode(signature.signature.trim())
+            .map_err(|e| invalid(format!("is not hex-encoded: {e}")))?;
+        let message = digest_message(archive_path)?;
+        UnparsedPublicKey::new(
+            &ECDSA_P256_SHA256_ASN1,
+            certificate.public_key().subject_public_key.data.as_ref(),
+        )
+        .verify(message.as_bytes(), &signature)
+        .map_err(|_| invalid("does not match its certificate".to_string()))
+    }
+}
+
+// The email addresses & URIs that the certificate was issued to.
+fn certificate_identities<'a>(certificate: &'a X509Certificate<'_>) -> Vec<&'a str> {
+    certificate
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|names| {
+            names
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::RFC822Name(identity) | GeneralName::URI(identity) => {
+                        Some(*identity)
+                    }
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn certificate_oidc_issuer<'a>(certificate: &'a X509Certificate<'_>) -> Option<&'a str> {
+    let extension = |oid: &str| {
+        certificate
+            .iter_extensions()
+            .find(|extension| extension.oid.to_id_string() == oid)
+    };
+    if let Some(extension) = extension(OIDC_ISSUER_OID) {
+        return parse_der_utf8string(extension.value)
+            .ok()
+            .and_then(|(_, issuer)| issuer.as_str().ok());
+    }
+    extension(OIDC_ISSUER_V1_OID).and_then(|extension| std::str::from_utf8(extension.value).ok())
+}
+
+// The DER of each certificate in the PEM, ignoring other blocks.
+fn parse_pem_certificates(pem: &str) -> Result<Vec<Vec<u8>>, String> {
+    Pem::iter_from_buffer(pem.as_bytes())
+        .filter_map(|block| match block {
+            Ok(block) if block.label == "CERTIFICATE" => Some(Ok(block.contents)),
+            Ok(_) => None,
+            Err(e) => Some(Err(format!("is not valid PEM: {e}"))),
+        })
+        .collect()
+}
+
+/// Whether the signature is keyless, bundled with its certificate as JSON.
+pub(crate) fn is_keyless(signature: &str) -> bool {
+    signature.trim_start().starts_with('{')
+}
+
+/// Whether saving signs archives, with a key or keylessly.
+pub(crate) fn signs_archives<S: BuildHasher>(env: &HashMap<String, String, S>) -> bool {
+    env.contains_key("STATIC_ARTIFACTS_SIGNING_KEY")
+        || env.contains_key("STATIC_ARTIFACTS_SIGNING_OIDC_TOKEN")
+}
+
+/// Returns the signature of the archive: hex-encoded, when a signing key is configured, or
+/// otherwise keyless, when an OIDC identity token is, with an ephemeral key whose certificate is
+/// issued by Fulcio for the token.
+pub(crate) async fn sign_archive<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    archive_path: &Path,
+) -> Result<Option<String>, ReleaseArtifactsError> {
+    if let Some(signing_key) = env.get("STATIC_ARTIFACTS_SIGNING_KEY") {
+        return sign_with_key(signing_key, archive_path).map(Some);
+    }
+    let Some(token) = env.get("STATIC_ARTIFACTS_SIGNING_OIDC_TOKEN") else {
+        return Ok(None);
+    };
+    let fulcio_url = env
+        .get("STATIC_ARTIFACTS_FULCIO_URL")
+        .map_or(DEFAULT_FULCIO_URL, String::as_str);
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+        .map_err(|_| ReleaseArtifactsError::SigningFailed("generating a key".to_string()))?;
+    let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+        .map_err(|e| ReleaseArtifactsError::SigningFailed(format!("loading the key: {e}")))?;
+    let certificate = request_certificate(fulcio_url, token.trim(), &key_pair, &rng).await?;
+    keyless_signature(&key_pair, certificate, archive_path).map(Some)
+}
+
+fn sign_with_key(signing_key: &str, archive_path: &Path) -> Result<String, ReleaseArtifactsError> {
+    let seed = hex::decode(signing_key.trim()).map_err(|e| {
+        ReleaseArtifactsError::SignatureInvalid(format!(
+            "STATIC_ARTIFACTS_SIGNING_KEY is not hex-encoded: {e}"
+        ))
+    })?;
+    let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed).map_err(|e| {
+        ReleaseArtifactsError::SignatureInvalid(format!(
+            "STATIC_ARTIFACTS_SIGNING_KEY is not an Ed25519 key seed: {e}"
+        ))
+    })?;
+    let message = digest_message(archive_path)?;
+    Ok(hex::encode(key_pair.sign(message.as_bytes())))
+}
+
+// Requests a certificate for the key from Fulcio, which requires the token's subject to be
+// signed with the key, as proof of possessing it.
+async fn request_certificate(
+    fulcio_url: &str,
+    token: &str,
+    key_pair: &EcdsaKeyPair,
+    rng: &SystemRandom,
+) -> Result<String, ReleaseArtifactsError> {
+    let proof = key_pair
+        .sign(rng, token_subject(token)?.as_bytes())
+        .map_err(|_| {
+            ReleaseArtifactsError::SigningFailed("signing the proof of possession".to_string())
+        })?;
+    let body = json!({
+        "credentials": { "oidcIdentityToken": token },
+        "publicKeyRequest": {
+            "publicKey": {
+                "algorithm": "ECDSA",
+                "content": public_key_pem(key_pair.public_key().as_ref()),
+            },
+            "proofOfPossession": STANDARD.encode(proof.as_ref()),
+        },
+    });
+    let url = format!("{}/api/v2/signingCert", fulcio_url.trim_end_matches('/'));
+    let request = http::Request::builder()
+        .method("POST")
+        .uri(url.as_str())
+        .header("content-type", "application/json")
+        .header("accept", "application/json")
+        .body(body.to_string())
+        .map_err(|e| {
+            ReleaseArtifactsError::SigningFailed(format!("building certificate request: {e}"))
+        })?;
+    let response = fetch_response(request).await.map_err(|e| {
+        ReleaseArtifactsError::SigningFailed(format!("requesting a certificate: {e}"))
+    })?;
+    leaf_certificate(&response)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SigningCertificateResponse {
+    signed_certificate_embedded_sct: Option<SignedCertificate>,
+    signed_certificate_detached_sct: Option<SignedCertificate>,
+}
+
+#[derive(Deserialize)]
+struct SignedCertificate {
+    chain: CertificateChain,
+}
+
+#[derive(Deserialize)]
+struct CertificateChain {
+    certificates: Vec<String>,
+}
+
+// The PEM of the certificate issued by Fulcio, first in its chain.
+fn leaf_certificate(response: &str) -> Result<String, ReleaseArtifactsError> {
+    let response: SigningCertificateResponse = serde_json::from_str(response).map_err(|e| {
+        ReleaseArtifactsError::SigningFailed(format!("reading the certificate response: {e}"))
+    })?;
+    response
+        .signed_certificate_embedded_sct
+        .or(response.signed_certificate_detached_sct)
+        .and_then(|signed| signed.chain.certificates.into_iter().next())
+        .ok_or_else(|| {
+            ReleaseArtifactsError::SigningFailed(
+                "the certificate response has no certificate".to_string(),
+            )
+        })
+}
+
+// The token's email, or otherwise its subject, from its payload, unverified, as Fulcio verifies
+// the token.
+fn token_subject(token: &str) -> Result<String, ReleaseArtifactsError> {
+    let invalid = || {
+        ReleaseArtifactsError::SigningFailed(
+            "STATIC_ARTIFACTS_SIGNING_OIDC_TOKEN is not a JWT with an email or subject".to_string(),
+        )
+    };
+    let payload = token.split('.').nth(1).ok_or_else(invalid)?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|_| invalid())?;
+    let claims: Value = serde_json::from_slice(&payload).map_err(|_| invalid())?;
+    claims
+        .get("email")
+        .or_else(|| claims.get("sub"))
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or_else(invalid)
+}
+
+fn public_key_pem(public_key: &[u8]) -> String {
+    let mut der = P256_SPKI_PREFIX.to_vec();
+    der.extend_from_slice(public_key);
+    let encoded = STANDARD.encode(der);
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(64)
+        .map(|line| std::str::from_utf8(line).expect("base64 should be ASCII"))
+        .collect();
+    format!(
+        "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+        lines.join("\n")
+    )
+}
+
+// Signs the archive with the ephemeral key, bundled with the key's certificate.
+fn keyless_signature(
+    key_pair: &EcdsaKeyPair,
+    certificate: String,
+    archive_path: &Path,
+) -> Result<String, ReleaseArtifactsError> {
+    let message = digest_message(archive_path)?;
+    let signature = key_pair
+        .sign(&SystemRandom::new(), message.as_bytes())
+        .map_err(|_| {
+            ReleaseArtifactsError::SigningFailed(format!("signing archive {archive_path:?}"))
+        })?;
+    serde_json::to_string(&KeylessSignature {
+        certificate,
+        signature: hex::encode(signature.as_ref()),
+    })
+    .map_err(|e| ReleaseArtifactsError::SigningFailed(format!("encoding the signature: {e}")))
+}
+
+/// The key of the object beside the archive holding its keyless signature.
+pub(crate) fn signature_key(bucket_key: &str) -> String {
+    format!("{bucket_key}.sig")
+}
+
+pub(crate) fn signature_path(archive_path: &Path) -> PathBuf {
+    let mut path = archive_path.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+pub(crate) fn read_signature_file(archive_path: &Path) -> Option<String> {
+    fs::read_to_string(signature_path(archive_path)).ok()
+}
+
+/// Writes the signature beside the archive, or removes a stale one when unsigned.
+pub(crate) fn write_signature_file(
+    archive_path: &Path,
+    signature: Option<&str>,
+) -> Result<(), ReleaseArtifactsError> {
+    let path = signature_path(archive_path);
+    if let Some(signature) = signature {
+        fs::write(&path, signature).map_err(|e| {
+            ReleaseArtifactsError::ArchiveError(e, format!("writing signature {path:?}"))
+        })
+    } else {
+        fs::remove_file(&path).unwrap_or_default();
+        Ok(())
+    }
+}
+
+// What is signed: the archive's digest, like `sha256:<hex>`.
+fn digest_message(archive_path: &Path) -> Result<String, ReleaseArtifactsError> {
+    Ok(format!("sha256:{}", file_checksum(archive_path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs, path::Path};
+
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use rcgen::{
+        BasicConstraints, CertificateParams, CustomExtension, DnType, ExtendedKeyUsagePurpose,
+        Ia5String, IsCa, Issuer, SanType,
+    };
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+    };
+
+    use super::{
+        keyless_signature, leaf_certificate, sign_archive, token_subject, ArchiveVerifier,
+    };
+
+    const TEST_SEED: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    fn test_verify_key() -> String {
+        let seed = hex::decode(TEST_SEED).unwrap();
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed).unwrap();
+        hex::encode(key_pair.public_key().as_ref())
+    }
+
+    // A CA like Fulcio's, & a key pair with the certificate it issues for the identity.
+    fn test_keyless_certificate(identity: &str) -> (String, EcdsaKeyPair, String) {
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "test-fulcio");
+        let ca_certificate = ca_params.self_signed(&ca_key).unwrap();
+        let issuer = Issuer::new(ca_params, ca_key);
+
+        let key = rcgen::KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.subject_alt_names =
+            vec![SanType::Rfc822Name(Ia5String::try_from(identity).unwrap())];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::CodeSigning];
+        params.custom_extensions = vec![CustomExtension::from_oid_content(
+            &[1, 3, 6, 1, 4, 1, 57264, 1, 1],
+            b"https://accounts.example.com".to_vec(),
+        )];
+        let certificate = params.signed_by(&key, &issuer).unwrap();
+        let key_pair = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_ASN1_SIGNING,
+            &key.serialize_der(),
+            &SystemRandom::new(),
+        )
+        .unwrap();
+        (ca_certificate.pem(), key_pair, certificate.pem())
+    }
+
+    fn test_keyless_env(identity: &str, ca_certificates: &str) -> HashMap<String, String> {
+        HashMap::from([
+            (
+                "STATIC_ARTIFACTS_VERIFY_IDENTITY".to_string(),
+                identity.to_string(),
+            ),
+            (
+                "STATIC_ARTIFACTS_VERIFY_OIDC_ISSUER".to_string(),
+                "https://accounts.example.com".to_string(),
+            ),
+            (
+                "STATIC_ARTIFACTS_VERIFY_CA_CERTS".to_string(),
+                ca_certificates.to_string(),
+            ),
+            (
+                "STATIC_ARTIFACTS_REQUIRE_SIGNATURE".to_string(),
+                "1".to_string(),
+            ),
+        ])
+    }
+
+    #[tokio::test]
+    async fn sign_and_verify_archive() {
+        let archive_path = Path::new("test/fixtures/static-artifacts.tgz");
+        let sign_env = HashMap::from([(
+            "STATIC_ARTIFACTS_SIGNING_KEY".to_string(),
+            TEST_SEED.to_string(),
+        )]);
+        let signature = sign_archive(&sign_env, archive_path)
+            .await
+            .unwrap()
+            .expect("should sign with the key");
+
+        let verify_env = HashMap::from([
+            ("STATIC_ARTIFACTS_VERIFY_KEY".to_string(), test_verify_key()),
+            (
+                "STATIC_ARTIFACTS_REQUIRE_SIGNATURE".to_string(),
+                "1".to_string(),
+            ),
+        ]);
+        let verifier = ArchiveVerifier::from_env(&verify_env).unwrap();
+        assert!(verifier.verify(archive_path, Some(&signature)).is_ok());
+        assert!(verifier.verify(archive_path, None).is_err());
+        let tampered_archive_path = Path::new("test-tampered-static-artifacts.tgz");
+        let mut tampered_archive = fs::read(archive_path).unwrap();
+        tampered_archive.push(0);
+        fs::write(tampered_archive_path, tampered_archive).unwrap();
+        assert!(verifier
+            .verify(tampered_archive_path, Some(&signature))
+            .is_err());
+        fs::remove_file(tampered_archive_path).unwrap_or_default();
+    }
+
+    #[test]
+    fn sign_and_verify_archive_keylessly() {
+        let archive_path = Path::new("test/fixtures/static-artifacts.tgz");
+        let identity = "release@example.com";
+        let (ca_certificate, key_pair, certificate) = test_keyless_certificate(identity);
+        let signature = keyless_signature(&key_pair, certificate, archive_path).unwrap();
+
+        let verifier =
+            ArchiveVerifier::from_env(&test_keyless_env(identity, &ca_certificate)).unwrap();
+        assert!(verifier.verify(archive_path, Some(&signature)).is_ok());
+        assert!(verifier.verify(archive_path, None).is_err());
+
+        let other_identity_verifier =
+            ArchiveVerifier::from_env(&test_keyless_env("someone@example.com", &ca_certificate))
+                .unwrap();
+        assert!(other_identity_verifier
+            .verify(archive_path, Some(&signature))
+            .is_err());
+
+        let (other_ca_certificate, _, _) = test_keyless_certificate(identity);
+        let other_ca_verifier =
+            ArchiveVerifier::from_env(&test_keyless_env(identity, &other_ca_certificate)).unwrap();
+        assert!(other_ca_verifier
+            .verify(archive_path, Some(&signature))
+            .is_err());
+
+        let tampered_archive_path = Path::new("test-tampered-keyless-static-artifacts.tgz");
+        let mut tampered_archive = fs::read(archive_path).unwrap();
+        tampered_archive.push(0);
+        fs::write(tampered_archive_path, tampered_archive).unwrap();
+        assert!(verifier
+            .verify(tampered_archive_path, Some(&signature))
+            .is_err());
+        fs::remove_file(tampered_archive_path).unwrap_or_default();
+
+        let key_verifier = ArchiveVerifier::from_env(&HashMap::from([(
+            "STATIC_ARTIFACTS_VERIFY_KEY".to_string(),
+            test_verify_key(),
+        )]))
+        .unwrap();
+        assert!(key_verifier.verify(archive_path, Some(&signature)).is_ok());
+    }
+
+    #[test]
+    fn keyless_verification_requires_its_config() {
+        let test_env = HashMap::from([(
+            "STATIC_ARTIFACTS_VERIFY_IDENTITY".to_string(),
+            "release@example.com".to_string(),
+        )]);
+        assert!(ArchiveVerifier::from_env(&test_env).is_err());
+
+        let mut test_env = test_keyless_env("release@example.com", "not a certificate");
+        assert!(ArchiveVerifier::from_env(&test_env).is_err());
+        test_env.remove("STATIC_ARTIFACTS_VERIFY_CA_CERTS");
+        assert!(ArchiveVerifier::from_env(&test_env).is_err());
+    }
+
+    #[test]
+    fn token_subject_prefers_email() {
+        let token = |claims: &str| format!("e30.{}.c2ln", URL_SAFE_NO_PAD.encode(claims));
+        assert_eq!(
+            token_subject(&token(r#"{"sub":"1234","email":"release@example.com"}"#)).unwrap(),
+            "release@example.com"
+        );
+        assert_eq!(
+            token_subject(&token(r#"{"sub":"repo:example/app:ref:refs/heads/main"}"#)).unwrap(),
+            "repo:example/app:ref:refs/heads/main"
+        );
+        assert!(token_subject(&token(r#"{"aud":"sigstore"}"#)).is_err());
+        assert!(token_subject("not-a-token").is_err());
+    }
+
+    #[test]
+    fn leaf_certificate_from_fulcio_response() {
+        let response = r#"{"signedCertificateEmbeddedSct":{"chain":{"certificates":["LEAF","INTERMEDIATE"]}}}"#;
+        assert_eq!(leaf_certificate(response).unwrap(), "LEAF");
+        let response = r#"{"signedCertificateDetachedSct":{"chain":{"certificates":["LEAF"]},"signedCertificateTimestamp":"c2N0"}}"#;
+        assert_eq!(leaf_certificate(response).unwrap(), "LEAF");
+        assert!(leaf_certificate(
+            r#"{"signedCertificateEmbeddedSct":{"chain":{"certificates":[]}}}"#
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn verify_without_configuration() {
+        let archive_path = Path::new("test/fixtures/static-artifacts.tgz");
+        let test_env: HashMap<String, String> = HashMap::new();
+        assert!(sign_archive(&test_env, archive_path)
+            .await
+            .unwrap()
+            .is_none());
+        let verifier = ArchiveVerifier::from_env(&test_env).unwrap();
+        assert!(verifier.verify(archive_path, None).is_ok());
+
+        let test_env = HashMap::from([(
+            "STATIC_ARTIFACTS_REQUIRE_SIGNATURE".to_string(),
+            "1".to_string(),
+        )]);
+        assert!(ArchiveVerifier::from_env(&test_env).is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! Loads each artifacts dir once per dyno, when several processes boot at the same time, with a
+//! lock file beside the dir.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    future::Future,
+    hash::BuildHasher,
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
+
+use fs4::FileExt;
+use uuid::Uuid;
+
+use crate::{errors::ReleaseArtifactsError, log_info, parse_tuning_var};
+
+const DEFAULT_LOCK_TIMEOUT_SECONDS: u64 = 600;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs the load of the dir, unless another process is already loading it, in which case this
+/// waits for that load, and returns its loaded key, or loads again if it failed. The load is given
+/// whether it holds the dir's lock.
+pub(crate) async fn load_once<S, F, Fut>(
+    env: &HashMap<String, String, S>,
+    dir: &Path,
+    load: F,
+) -> Result<String, ReleaseArtifactsError>
+where
+    S: BuildHasher,
+    F: FnOnce(bool) -> Fut,
+    Fut: Future<Output = Result<String, ReleaseArtifactsError>>,
+{
+    let Some(paths) = LockPaths::of(dir) else {
+        return load(false).await;
+    };
+    let timeout = Duration::from_secs(
+        parse_tuning_var(env, "STATIC_ARTIFACTS_LOAD_LOCK_TIMEOUT")
+            .unwrap_or(DEFAULT_LOCK_TIMEOUT_SECONDS),
+    );
+    match acquire(&paths, timeout).await {
+        Ok(Acquired::Lock(lock)) => {
+            let result = load(true).await;
+            if let Ok(loaded_key) = &result {
+                if let Err(error) =
+                    fs::write(&paths.loaded_key, format!("{}\n{loaded_key}", lock.id))
+                {
+                    eprintln!(
+                        "release-phase warning: failed to write {:?} for other processes: {error:?}",
+                        paths.loaded_key
+                    );
+                }
+            }
+            result
+        }
+        Ok(Acquired::Loaded(loaded_key)) => {
+            log_info!(
+                "load-release-artifacts reusing {loaded_key}, loaded into {dir:?} by another process"
+            );
+            Ok(loaded_key)
+        }
+        Ok(Acquired::TimedOut) => {
+            eprintln!(
+                "release-phase warning: loading into {dir:?} anyway, after waiting {}s for {:?}, STATIC_ARTIFACTS_LOAD_LOCK_TIMEOUT",
+                timeout.as_secs(),
+                paths.lock
+            );
+            load(false).await
+        }
+        Err(error) => {
+            eprintln!(
+                "release-phase warning: loading into {dir:?} without the lock {:?}: {error:?}",
+                paths.lock
+            );
+            load(false).await
+        }
+    }
+}
+
+/// The file that the loaded key of the dir is written to, for the processes that waited for it.
+pub(crate) fn loaded_key_path(dir: &Path) -> Option<PathBuf> {
+    let name = dir.file_name()?.to_string_lossy();
+    Some(dir.with_file_name(format!(".{name}.loaded-key")))
+}
+
+/// The lock file beside the dir, locked by the process loading into it.
+pub(crate) fn load_lock_path(dir: &Path) -> Option<PathBuf> {
+    let name = dir.file_name()?.to_string_lossy();
+    Some(dir.with_file_name(format!(".{name}.load-lock")))
+}
+
+struct LockPaths {
+    lock: PathBuf,
+    loaded_key: PathBuf,
+}
+
+impl LockPaths {
+    fn of(dir: &Path) -> Option<Self> {
+        Some(LockPaths {
+            lock: load_lock_path(dir)?,
+            loaded_key: loaded_key_path(dir)?,
+        })
+    }
+}
+
+enum Acquired {
+    Lock(LoadLock),
+    /// The key loaded by the process that held the lock.
+    Loaded(String),
+    TimedOut,
+}
+
+// The locked lock file of this process's load. The lock is released when the file is closed, by
+// the kernel when the process exits, so a lock is never left behind. Its contents, the pid & id
+// of the holder, are cleared when dropped.
+struct LoadLock {
+    file: File,
+    id: String,
+}
+
+impl Drop for LoadLock {
+    fn drop(&mut self) {
+        self.file.set_len(0).unwrap_or_default();
+    }
+}
+
+async fn acquire(paths: &LockPaths, timeout: Duration) -> io::Result<Acquired> {
+    if let Some(parent) = paths.lock.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    // The lock file stays in place, so that every process locks the same file.
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&paths.lock)?;
+    let started = Instant::now();
+    // The ids of the loads waited for, any of which may have written the loaded key.
+    let mut waited_for: Vec<String> = vec![];
+    loop {
+        // The lock holder writes its loaded key before releasing the lock.
+        if let Some(loaded_key) = read_loaded_key(&paths.loaded_key, &waited_for) {
+            return Ok(Acquired::Loaded(loaded_key));
+        }
+        if try_lock(&file)? {
+            let id = Uuid::new_v4().to_string();
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            writeln!(file, "{} {id}", process::id())?;
+            return Ok(Acquired::Lock(LoadLock { file, id }));
+        }
+        let holder = fs::read_to_string(&paths.lock)?;
+        // Not yet written when the holder has only just locked it.
+        if let [pid, holder_id] = holder.split_whitespace().collect::<Vec<_>>()[..] {
+            if !waited_for.iter().any(|id| id == holder_id) {
+                log_info!(
+                    "load-release-artifacts waiting for process {pid} to finish loading, {:?}",
+                    paths.lock
+                );
+                waited_for.push(holder_id.to_string());
+            }
+        }
+        if started.elapsed() >= timeout {
+            return Ok(Acquired::TimedOut);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+// Takes the exclusive lock of the file, unless another open file holds it.
+fn try_lock(file: &File) -> io::Result<bool> {
+    match file.try_lock_exclusive() {
+        Ok(()) => Ok(true),
+        Err(error) if error.raw_os_error() == fs4::lock_contended_error().raw_os_error() => {
+            Ok(false)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+// The loaded key written by one of the loads, if it succeeded.
+fn read_loaded_key(path: &Path, load_ids: &[String]) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let (id, loaded_key) = content.split_once('\n')?;
+    load_ids
+        .iter()
+        .any(|i| i == id)
+        .then(|| loaded_key.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fs,
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use uuid::Uuid;
+
+    use super::{load_once, LockPaths};
+    use crate::errors::ReleaseArtifactsError;
+
+    fn test_dir() -> PathBuf {
+        PathBuf::from(format!("single-flight-test-{}", Uuid::new_v4())).join("static-artifacts")
+    }
+
+    #[tokio::test]
+    async fn load_once_waits_for_concurrent_load() {
+        let dir = test_dir();
+        let env: HashMap<String, String> = HashMap::new();
+        let loads = AtomicUsize::new(0);
+        let counter = &loads;
+        let load = |_: bool| async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok("release-v102.tgz".to_string())
+        };
+        let (first, second) =
+            tokio::join!(load_once(&env, &dir, load), load_once(&env, &dir, load));
+        let lock_contents = fs::read_to_string(LockPaths::of(&dir).unwrap().lock);
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap_or_default();
+
+        assert_eq!(first.unwrap(), "release-v102.tgz");
+        assert_eq!(second.unwrap(), "release-v102.tgz");
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(lock_contents.unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn load_once_waits_for_load_with_three_contenders() {
+        let dir = test_dir();
+        let env: HashMap<String, String> = HashMap::new();
+        let loads = AtomicUsize::new(0);
+        let counter = &loads;
+        let load = |_: bool| async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok("release-v102.tgz".to_string())
+        };
+        let (first, second, third) = tokio::join!(
+            load_once(&env, &dir, load),
+            load_once(&env, &dir, load),
+            load_once(&env, &dir, load)
+        );
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap_or_default();
+
+        assert_eq!(first.unwrap(), "release-v102.tgz");
+        assert_eq!(second.unwrap(), "release-v102.tgz");
+        assert_eq!(third.unwrap(), "release-v102.tgz");
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn load_once_loads_again_after_failed_load() {
+        let dir = test_dir();
+        let env: HashMap<String, String> = HashMap::new();
+        let loads = AtomicUsize::new(0);
+        let counter = &loads;
+        let load = |_: bool| async move {
+            let attempt = counter.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            if attempt == 0 {
+                Err(ReleaseArtifactsError::StorageKeyNotFound(
+                    "Not Found".to_string(),
+                ))
+            } else {
+                Ok("release-v102.tgz".to_string())
+            }
+        };
+        let (first, second) =
+            tokio::join!(load_once(&env, &dir, load), load_once(&env, &dir, load));
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap_or_default();
+
+        assert!(first.is_err());
+        assert_eq!(second.unwrap(), "release-v102.tgz");
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn load_once_takes_over_lock_left_by_exited_process() {
+        let dir = test_dir();
+        let paths = LockPaths::of(&dir).unwrap();
+        fs::create_dir_all(dir.parent().unwrap()).unwrap();
+        fs::write(&paths.lock, format!("999999999 {}\n", Uuid::new_v4())).unwrap();
+        let env = HashMap::from([(
+            "STATIC_ARTIFACTS_LOAD_LOCK_TIMEOUT".to_string(),
+            "5".to_string(),
+        )]);
+        let result = load_once(&env, &dir, |holds_lock| async move {
+            assert!(holds_lock);
+            Ok::<_, ReleaseArtifactsError>("release-v102.tgz".to_string())
+        })
+        .await;
+        let loaded_key = fs::read_to_string(&paths.loaded_key);
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap_or_default();
+
+        assert_eq!(result.unwrap(), "release-v102.tgz");
+        assert!(loaded_key.unwrap().ends_with("\nrelease-v102.tgz"));
+    }
+}
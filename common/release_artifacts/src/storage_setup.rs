@@ -0,0 +1,346 @@
+//! Infrastructure as code for the `s3` storage at `STATIC_ARTIFACTS_URL`, printed by
+//! `print-storage-setup`.
+
+use std::{collections::HashMap, fmt::Write, hash::BuildHasher, str::FromStr};
+
+use serde_json::{json, Value};
+
+use crate::{
+    detect_storage_scheme, errors::ReleaseArtifactsError, gc::Retention,
+    generate_s3_storage_prefix, provision,
+};
+
+const RESOURCE_NAME: &str = "release_artifacts";
+
+/// The infrastructure as code tool to print the storage setup for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetupFormat {
+    Terraform,
+    CloudFormation,
+}
+
+impl FromStr for SetupFormat {
+    type Err = ReleaseArtifactsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "terraform" => Ok(SetupFormat::Terraform),
+            "cloudformation" => Ok(SetupFormat::CloudFormation),
+            _ => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+                "storage setup format '{s}' is not terraform or cloudformation"
+            ))),
+        }
+    }
+}
+
+// The parts of the storage config that the setup depends on.
+struct StorageSetup {
+    bucket_name: String,
+    bucket_region: Option<String>,
+    bucket_key_prefix: String,
+    abort_uploads_days: i32,
+    aborts_uploads: bool,
+}
+
+/// Returns the storage setup for the `s3` storage configured in env, in the given format.
+pub fn storage_setup<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    format: SetupFormat,
+) -> Result<String, ReleaseArtifactsError> {
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"s3" => {
+            let (bucket_name, bucket_region, bucket_key_prefix) = generate_s3_storage_prefix(env)?;
+            let setup = StorageSetup {
+                bucket_name,
+                bucket_region,
+                bucket_key_prefix,
+                abort_uploads_days: provision::abort_uploads_days(env)?,
+                aborts_uploads: Retention::from_env(env)?
+                    .is_some_and(|retention| retention.abort_uploads_after.is_some()),
+            };
+            Ok(match format {
+                SetupFormat::Terraform => terraform(&setup),
+                SetupFormat::CloudFormation => cloudformation(&setup),
+            })
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+// The least privileged policy for the buildpack's use of the bucket.
+fn iam_policy(setup: &StorageSetup) -> Value {
+    let bucket_arn = format!("arn:aws:s3:::{}", setup.bucket_name);
+    let mut list_actions = vec!["s3:ListBucket"];
+    if setup.aborts_uploads {
+        list_actions.push("s3:ListBucketMultipartUploads");
+    }
+    let mut list_statement = json!({
+        "Sid": "ListArtifacts",
+        "Effect": "Allow",
+        "Action": list_actions,
+        "Resource": bucket_arn,
+    });
+    if !setup.bucket_key_prefix.is_empty() {
+        list_statement["Condition"] = json!({
+            "StringLike": { "s3:prefix": [format!("{}*", setup.bucket_key_prefix)] }
+        });
+    }
+    json!({
+        "Version": "2012-10-17",
+        "Statement": [
+            {
+                "Sid": "ReadWriteArtifacts",
+                "Effect": "Allow",
+                "Action": [
+                    "s3:GetObject",
+                    "s3:PutObject",
+                    "s3:DeleteObject",
+                    "s3:AbortMultipartUpload",
+                    "s3:GetObjectTagging",
+                    "s3:PutObjectTagging",
+                ],
+                "Resource": format!("{bucket_arn}/{}*", setup.bucket_key_prefix),
+            },
+            list_statement,
+            {
+                "Sid": "CheckPublicAccess",
+                "Effect": "Allow",
+                "Action": [
+                    "s3:GetBucketPublicAccessBlock",
+                    "s3:GetBucketPolicyStatus",
+                    "s3:GetBucketAcl",
+                ],
+                "Resource": bucket_arn,
+            },
+        ],
+    })
+}
+
+fn terraform(setup: &StorageSetup) -> String {
+    let bucket = format!("aws_s3_bucket.{RESOURCE_NAME}.id");
+    let policy = serde_json::to_string_pretty(&iam_policy(setup)).unwrap_or_default();
+    let mut output = String::new();
+    let _ = writeln!(
+        output,
+        "# Storage for release artifacts, at s3://{}/{}",
+        setup.bucket_name, setup.bucket_key_prefix
+    );
+    if let Some(region) = &setup.bucket_region {
+        let _ = writeln!(output, "# Apply with the aws provider in region {region}.");
+    }
+    let _ = write!(
+        output,
+        r#"
+resource "aws_s3_bucket" "{RESOURCE_NAME}" {{
+  bucket = "{bucket_name}"
+}}
+
+resource "aws_s3_bucket_public_access_block" "{RESOURCE_NAME}" {{
+  bucket                  = {bucket}
+  block_public_acls       = true
+  ignore_public_acls      = true
+  block_public_policy     = true
+  restrict_public_buckets = true
+}}
+
+resource "aws_s3_bucket_server_side_encryption_configuration" "{RESOURCE_NAME}" {{
+  bucket = {bucket}
+  rule {{
+    apply_server_side_encryption_by_default {{
+      sse_algorithm = "AES256"
+    }}
+  }}
+}}
+
+# Archives are retained by count, with STATIC_ARTIFACTS_RETAIN_COUNT, which lifecycle rules
+# cannot express, so only incomplete uploads expire.
+resource "aws_s3_bucket_lifecycle_configuration" "{RESOURCE_NAME}" {{
+  bucket = {bucket}
+  rule {{
+    id     = "{rule_id}"
+    status = "Enabled"
+    filter {{
+      prefix = "{prefix}"
+    }}
+    abort_incomplete_multipart_upload {{
+      days_after_initiation = {days}
+    }}
+  }}
+}}
+
+# Set STATIC_ARTIFACTS_ACCESS_KEY_ID & STATIC_ARTIFACTS_SECRET_ACCESS_KEY from this access key.
+resource "aws_iam_user" "{RESOURCE_NAME}" {{
+  name = "release-artifacts-{bucket_name}"
+}}
+
+resource "aws_iam_access_key" "{RESOURCE_NAME}" {{
+  user = aws_iam_user.{RESOURCE_NAME}.name
+}}
+
+resource "aws_iam_user_policy" "{RESOURCE_NAME}" {{
+  name   = "release-artifacts"
+  user   = aws_iam_user.{RESOURCE_NAME}.name
+  policy = <<-EOT
+{policy}
+  EOT
+}}
+"#,
+        bucket_name = setup.bucket_name,
+        rule_id = provision::LIFECYCLE_RULE_ID,
+        prefix = setup.bucket_key_prefix,
+        days = setup.abort_uploads_days,
+        policy = indent(&policy, "    "),
+    );
+    output
+}
+
+fn cloudformation(setup: &StorageSetup) -> String {
+    let template = json!({
+        "AWSTemplateFormatVersion": "2010-09-09",
+        "Description": format!(
+            "Storage for release artifacts, at s3://{}/{}",
+            setup.bucket_name, setup.bucket_key_prefix
+        ),
+        "Resources": {
+            "ReleaseArtifactsBucket": {
+                "Type": "AWS::S3::Bucket",
+                "Properties": {
+                    "BucketName": setup.bucket_name,
+                    "PublicAccessBlockConfiguration": {
+                        "BlockPublicAcls": true,
+                        "IgnorePublicAcls": true,
+                        "BlockPublicPolicy": true,
+                        "RestrictPublicBuckets": true,
+                    },
+                    "BucketEncryption": {
+                        "ServerSideEncryptionConfiguration": [{
+                            "ServerSideEncryptionByDefault": { "SSEAlgorithm": "AES256" },
+                        }],
+                    },
+                    "LifecycleConfiguration": {
+                        "Rules": [{
+                            "Id": provision::LIFECYCLE_RULE_ID,
+                            "Status": "Enabled",
+                            "Prefix": setup.bucket_key_prefix,
+                            "AbortIncompleteMultipartUpload": {
+                                "DaysAfterInitiation": setup.abort_uploads_days,
+                            },
+                        }],
+                    },
+                },
+            },
+            "ReleaseArtifactsUser": {
+                "Type": "AWS::IAM::User",
+                "Properties": {
+                    "Policies": [{
+                        "PolicyName": "release-artifacts",
+                        "PolicyDocument": iam_policy(setup),
+                    }],
+                },
+            },
+            "ReleaseArtifactsAccessKey": {
+                "Type": "AWS::IAM::AccessKey",
+                "Properties": { "UserName": { "Ref": "ReleaseArtifactsUser" } },
+            },
+        },
+        "Outputs": {
+            "StaticArtifactsAccessKeyId": {
+                "Value": { "Ref": "ReleaseArtifactsAccessKey" },
+            },
+            "StaticArtifactsSecretAccessKey": {
+                "Value": { "Fn::GetAtt": ["ReleaseArtifactsAccessKey", "SecretAccessKey"] },
+            },
+        },
+    });
+    let mut output = serde_json::to_string_pretty(&template).unwrap_or_default();
+    output.push('\n');
+    output
+}
+
+fn indent(text: &str, prefix: &str) -> String {
+    text.lines()
+        .map(|line| format!("{prefix}{line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::Value;
+
+    use super::{storage_setup, SetupFormat};
+
+    fn test_env(url: &str) -> HashMap<String, String> {
+        HashMap::from([
+            ("STATIC_ARTIFACTS_URL".to_string(), url.to_string()),
+            (
+                "STATIC_ARTIFACTS_REGION".to_string(),
+                "eu-west-1".to_string(),
+            ),
+        ])
+    }
+
+    #[test]
+    fn setup_format_from_str() {
+        assert_eq!(
+            "terraform".parse::<SetupFormat>().unwrap(),
+            SetupFormat::Terraform
+        );
+        assert_eq!(
+            "cloudformation".parse::<SetupFormat>().unwrap(),
+            SetupFormat::CloudFormation
+        );
+        assert!("pulumi".parse::<SetupFormat>().is_err());
+    }
+
+    #[test]
+    fn storage_setup_terraform_scopes_policy_to_prefix() {
+        let mut env = test_env("s3://test-bucket/sub/path");
+        env.insert(
+            "STATIC_ARTIFACTS_ABORT_UPLOADS_HOURS".to_string(),
+            "36".to_string(),
+        );
+
+        let output = storage_setup(&env, SetupFormat::Terraform).unwrap();
+
+        assert!(output.contains("bucket = \"test-bucket\""), "{output}");
+        assert!(output.contains("# Apply with the aws provider in region eu-west-1."));
+        assert!(output.contains("prefix = \"sub/path/\""));
+        assert!(output.contains("days_after_initiation = 2"));
+        assert!(output.contains("\"arn:aws:s3:::test-bucket/sub/path/*\""));
+        assert!(output.contains("\"s3:ListBucketMultipartUploads\""));
+        assert!(output.contains("\"sub/path/*\""));
+    }
+
+    #[test]
+    fn storage_setup_cloudformation_is_a_template() {
+        let output =
+            storage_setup(&test_env("s3://test-bucket"), SetupFormat::CloudFormation).unwrap();
+        let template: Value = serde_json::from_str(&output).unwrap();
+
+        let bucket = &template["Resources"]["ReleaseArtifactsBucket"]["Properties"];
+        assert_eq!(bucket["BucketName"], "test-bucket");
+        assert_eq!(
+            bucket["LifecycleConfiguration"]["Rules"][0]["AbortIncompleteMultipartUpload"]
+                ["DaysAfterInitiation"],
+            7
+        );
+        let statements = &template["Resources"]["ReleaseArtifactsUser"]["Properties"]["Policies"]
+            [0]["PolicyDocument"]["Statement"];
+        assert_eq!(statements[0]["Resource"], "arn:aws:s3:::test-bucket/*");
+        assert_eq!(
+            statements[1]["Action"],
+            serde_json::json!(["s3:ListBucket"])
+        );
+        assert!(statements[1].get("Condition").is_none());
+    }
+
+    #[test]
+    fn storage_setup_requires_s3_url() {
+        let result = storage_setup(&test_env("file:///tmp/artifacts"), SetupFormat::Terraform);
+        assert!(result.is_err());
+    }
+}
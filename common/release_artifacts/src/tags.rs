@@ -0,0 +1,279 @@
+//! Tags of stored archives, such as their pipeline stage or a pin, stored as S3 object tags, or in
+//! a `.tags` file beside the archive.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    hash::BuildHasher,
+    path::{Path, PathBuf},
+};
+
+use aws_sdk_s3::types::{Tag, Tagging};
+
+use crate::{
+    detect_storage_scheme, errors::ReleaseArtifactsError, generate_archive_name,
+    generate_file_storage_location, generate_s3_client, generate_s3_storage_location,
+    guard_s3_credentials,
+};
+
+/// The tag naming the pipeline stage that saved an archive, from `STATIC_ARTIFACTS_STAGE`.
+pub(crate) const STAGE_TAG: &str = "release-phase-stage";
+
+/// The tag marking an archive as pinned, kept by garbage collection regardless of retention.
+pub(crate) const PINNED_TAG: &str = "release-phase-pinned";
+
+pub(crate) type Tags = BTreeMap<String, String>;
+
+/// Returns the tags to set on archives saved with the given env.
+pub(crate) fn save_tags<S: BuildHasher>(env: &HashMap<String, String, S>) -> Tags {
+    env.get("STATIC_ARTIFACTS_STAGE")
+        .map(|stage| stage.trim())
+        .filter(|stage| !stage.is_empty())
+        .map(|stage| Tags::from([(STAGE_TAG.to_string(), stage.to_string())]))
+        .unwrap_or_default()
+}
+
+/// Sets the tags from `save_tags` on the archive just stored with the given env, if any.
+pub(crate) async fn tag_stored_archive<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<(), ReleaseArtifactsError> {
+    let tags = save_tags(env);
+    if tags.is_empty() {
+        return Ok(());
+    }
+    let archive_name = generate_archive_name(env)?;
+    match detect_storage_scheme(env) {
+        Ok(scheme) if scheme == *"file" => {
+            let archive_path = generate_file_storage_location(env, &archive_name)?;
+            write_file_tags(&archive_path, &tags)
+        }
+        Ok(scheme) if scheme == *"s3" => {
+            let (bucket_name, bucket_region, bucket_key) =
+                generate_s3_storage_location(env, &archive_name)?;
+            let s3 = generate_s3_client(env, bucket_region).await;
+            put_s3_tags(&s3, &bucket_name, &bucket_key, &tags).await
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Pins the archive of the given release, so that garbage collection keeps it, such as a
+/// known-good release during an incident freeze.
+pub async fn pin<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    release_id: &str,
+) -> Result<(), ReleaseArtifactsError> {
+    update_release_tags(env, release_id, |tags| {
+        tags.insert(PINNED_TAG.to_string(), "true".to_string());
+    })
+    .await
+}
+
+/// Unpins the archive of the given release, so that garbage collection applies retention to it.
+pub async fn unpin<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    release_id: &str,
+) -> Result<(), ReleaseArtifactsError> {
+    update_release_tags(env, release_id, |tags| {
+        tags.remove(PINNED_TAG);
+    })
+    .await
+}
+
+// Reads, updates, & writes back the tags of the archive of the given release, which must exist.
+async fn update_release_tags<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+    release_id: &str,
+    update: impl FnOnce(&mut Tags),
+) -> Result<(), ReleaseArtifactsError> {
+    let mut release_env: HashMap<String, String> =
+        env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    release_env.insert("RELEASE_ID".to_string(), release_id.to_string());
+    let archive_name = generate_archive_name(&release_env)?;
+    match detect_storage_scheme(&release_env) {
+        Ok(scheme) if scheme == *"file" => {
+            let archive_path = generate_file_storage_location(&release_env, &archive_name)?;
+            if !archive_path.is_file() {
+                return Err(ReleaseArtifactsError::StorageKeyNotFound(format!(
+                    "{archive_path:?}"
+                )));
+            }
+            let mut tags = read_file_tags(&archive_path);
+            update(&mut tags);
+            write_file_tags(&archive_path, &tags)
+        }
+        Ok(scheme) if scheme == *"s3" => {
+            guard_s3_credentials(&release_env)?;
+            let (bucket_name, bucket_region, bucket_key) =
+                generate_s3_storage_location(&release_env, &archive_name)?;
+            let s3 = generate_s3_client(&release_env, bucket_region).await;
+            let mut tags = get_s3_tags(&s3, &bucket_name, &bucket_key).await?;
+            update(&mut tags);
+            put_s3_tags(&s3, &bucket_name, &bucket_key, &tags).await
+        }
+        Ok(scheme) => Err(ReleaseArtifactsError::StorageURLUnsupportedScheme(scheme)),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn tags_path(archive_path: &Path) -> PathBuf {
+    let mut path = archive_path.as_os_str().to_owned();
+    path.push(".tags");
+    PathBuf::from(path)
+}
+
+/// Reads the tags of an archive in file storage, which has none without a `.tags` file.
+pub(crate) fn read_file_tags(archive_path: &Path) -> Tags {
+    fs::read_to_string(tags_path(archive_path))
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Writes the tags of an archive in file storage, removing the `.tags` file when there are none.
+pub(crate) fn write_file_tags(
+    archive_path: &Path,
+    tags: &Tags,
+) -> Result<(), ReleaseArtifactsError> {
+    let path = tags_path(archive_path);
+    if tags.is_empty() {
+        fs::remove_file(&path).unwrap_or_default();
+        return Ok(());
+    }
+    let content = tags
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    fs::write(&path, content).map_err(|e| {
+        ReleaseArtifactsError::ArchiveError(e, format!("writing archive tags {path:?}"))
+    })
+}
+
+pub(crate) async fn get_s3_tags(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_key: &str,
+) -> Result<Tags, ReleaseArtifactsError> {
+    let output = s3
+        .get_object_tagging()
+        .bucket(bucket_name)
+        .key(bucket_key)
+        .send()
+        .await?;
+    Ok(output
+        .tag_set()
+        .iter()
+        .map(|tag| (tag.key().to_string(), tag.value().to_string()))
+        .collect())
+}
+
+pub(crate) async fn put_s3_tags(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_key: &str,
+    tags: &Tags,
+) -> Result<(), ReleaseArtifactsError> {
+    if tags.is_empty() {
+        s3.delete_object_tagging()
+            .bucket(bucket_name)
+            .key(bucket_key)
+            .send()
+            .await?;
+        return Ok(());
+    }
+    let tag_set = tags
+        .iter()
+        .map(|(key, value)| Tag::builder().key(key).value(value).build())
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|tag_set| Tagging::builder().set_tag_set(Some(tag_set)).build())
+        .map_err(|e| ReleaseArtifactsError::StorageError {
+            message: format!("building archive tags: {e}"),
+            request_id: None,
+            extended_request_id: None,
+            status: None,
+        })?;
+    s3.put_object_tagging()
+        .bucket(bucket_name)
+        .key(bucket_key)
+        .tagging(tag_set)
+        .send()
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs, path::PathBuf};
+
+    use uuid::Uuid;
+
+    use super::{
+        pin, read_file_tags, save_tags, tags_path, unpin, write_file_tags, Tags, PINNED_TAG,
+        STAGE_TAG,
+    };
+    use crate::errors::ReleaseArtifactsError;
+
+    #[test]
+    fn file_tags_roundtrip() {
+        let archive_path = PathBuf::from(format!("tags-test-{}.tgz", Uuid::new_v4()));
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_STAGE".to_string(),
+            "review-app".to_string(),
+        );
+        let tags = save_tags(&test_env);
+
+        write_file_tags(&archive_path, &tags).unwrap();
+        let read = read_file_tags(&archive_path);
+        write_file_tags(&archive_path, &Tags::new()).unwrap();
+        let tags_file_exists = tags_path(&archive_path).exists();
+        fs::remove_file(tags_path(&archive_path)).unwrap_or_default();
+
+        assert_eq!(read.get(STAGE_TAG).map(String::as_str), Some("review-app"));
+        assert!(!tags_file_exists);
+        assert!(read_file_tags(&archive_path).is_empty());
+    }
+
+    #[tokio::test]
+    async fn pin_and_unpin_file_archive() {
+        let storage_path = std::env::temp_dir().join(format!("pin-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&storage_path).unwrap();
+        let archive_path = storage_path.join("release-v102.tgz");
+        fs::write(&archive_path, "archive").unwrap();
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            format!("file://{}", storage_path.to_string_lossy()),
+        );
+        write_file_tags(
+            &archive_path,
+            &Tags::from([(STAGE_TAG.to_string(), "production".to_string())]),
+        )
+        .unwrap();
+
+        pin(&test_env, "v102").await.unwrap();
+        let pinned = read_file_tags(&archive_path);
+        unpin(&test_env, "v102").await.unwrap();
+        let unpinned = read_file_tags(&archive_path);
+        let missing = pin(&test_env, "v101").await;
+        fs::remove_dir_all(&storage_path).unwrap_or_default();
+
+        assert!(pinned.contains_key(PINNED_TAG));
+        assert!(!unpinned.contains_key(PINNED_TAG));
+        assert_eq!(
+            unpinned.get(STAGE_TAG).map(String::as_str),
+            Some("production")
+        );
+        assert!(matches!(
+            missing,
+            Err(ReleaseArtifactsError::StorageKeyNotFound(_))
+        ));
+    }
+}
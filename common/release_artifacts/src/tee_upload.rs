@@ -0,0 +1,607 @@
+//! Uploads an archive to S3 while it is being created, teeing the compressed bytes to the local
+//! archive file.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use aws_sdk_s3::{
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart},
+};
+use tokio::sync::mpsc;
+
+use crate::{
+    checksum::{Checksum, CHECKSUM_METADATA_KEY},
+    errors::ReleaseArtifactsError,
+    filter::ArtifactFilter,
+    journal::{self, JournalPart, SaveJournal},
+    write_archive, ArchiveCompression, ARCHIVE_WRITE_BUFFER_BYTES,
+};
+
+// Bytes handed to the upload at a time.
+const CHUNK_BYTES: usize = 256 * 1024;
+
+// Chunks that may wait for the upload, bounding memory to about 4MB plus one part.
+const CHANNEL_CHUNKS: usize = 16;
+
+// Archives up to this size are uploaded with a single PutObject, larger ones in parts of at
+// least this size. S3 requires every part but the last to be at least 5MB.
+pub(crate) const PART_BYTES: usize = 8 * 1024 * 1024;
+
+/// An archive uploaded by `archive_and_upload_with_client`.
+#[derive(Debug)]
+pub(crate) struct TeeUploaded {
+    /// The hex-encoded SHA-256 digest of the archive.
+    pub(crate) checksum: String,
+    /// Whether the checksum is stored as the object's metadata, which a multipart upload cannot
+    /// be, as it starts before the archive is complete.
+    pub(crate) checksum_stored: bool,
+}
+
+struct TeeWriter<W: Write> {
+    file: W,
+    chunk: Vec<u8>,
+    sender: mpsc::Sender<Vec<u8>>,
+}
+
+impl<W: Write> TeeWriter<W> {
+    fn send_chunk(&mut self) -> io::Result<()> {
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_BYTES));
+        self.sender
+            .blocking_send(chunk)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "archive upload stopped early"))
+    }
+
+    // Sends the last chunk and flushes the file, closing the channel once dropped.
+    fn finish(mut self) -> io::Result<W> {
+        if !self.chunk.is_empty() {
+            self.send_chunk()?;
+        }
+        self.file.flush()?;
+        Ok(self.file)
+    }
+}
+
+impl<W: Write> Write for TeeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.chunk.extend_from_slice(&buf[..written]);
+        if self.chunk.len() >= CHUNK_BYTES {
+            self.send_chunk()?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Creates the archive of `source` at `archive_path`, uploading it to the bucket as it is
+/// written. The object is only completed once the archive is, so a failure to archive never
+/// leaves a truncated object behind. With a `journal_key`, the progress of an upload in parts is
+/// recorded there, so that the upload can be recovered should this process crash.
+pub(crate) async fn archive_and_upload_with_client(
+    s3: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    bucket_key: &str,
+    journal_key: Option<&str>,
+    source: &Path,
+    archive_path: &Path,
+    filter: &ArtifactFilter,
+) -> Result<TeeUploaded, ReleaseArtifactsError> {
+    let output_file = File::create(archive_path).map_err(|e| {
+        ReleaseArtifactsError::ArchiveError(
+            e,
+            format!("during archive_and_upload File::create({archive_path:?})"),
+        )
+    })?;
+    let (sender, mut receiver) = mpsc::channel(CHANNEL_CHUNKS);
+    let source: PathBuf = source.to_path_buf();
+    let compression = ArchiveCompression::from_archive_path(archive_path);
+    let filter = filter.clone();
+    let archiver = tokio::task::spawn_blocking(move || {
+        let writer = TeeWriter {
+            file: BufWriter::with_capacity(ARCHIVE_WRITE_BUFFER_BYTES, output_file),
+            chunk: Vec::with_capacity(CHUNK_BYTES),
+            sender,
+        };
+        write_archive(&source, writer, compression, &filter)?
+            .finish()
+            .map_err(|e| {
+                ReleaseArtifactsError::ArchiveError(
+                    e,
+                    "during archive_and_upload finish".to_string(),
+                )
+            })
+    });
+
+    let mut upload = PartUpload::new(s3, bucket_name, bucket_key, journal_key);
+    let mut checksum = Checksum::new();
+    let mut upload_result = Ok(());
+    while let Some(chunk) = receiver.recv().await {
+        checksum.update(&chunk);
+        upload.buffer.extend_from_slice(&chunk);
+        if upload.buffer.len() >= PART_BYTES {
+            upload_result = upload.upload_part().await;
+            if upload_result.is_err() {
+                break;
+            }
+        }
+    }
+    // Stops archiving, if the upload failed.
+    drop(receiver);
+    let archive_result = archiver.await.map_err(|e| {
+        ReleaseArtifactsError::ArchiveError(
+            io::Error::other(e),
+            "during archive_and_upload archiving thread".to_string(),
+        )
+    });
+
+    match (upload_result, archive_result) {
+        (Ok(()), Ok(Ok(_))) => {
+            let checksum = checksum.finish();
+            let checksum_stored = upload.complete(&checksum).await?;
+            Ok(TeeUploaded {
+                checksum,
+                checksum_stored,
+            })
+        }
+        (Err(error), _) | (Ok(()), Ok(Err(error)) | Err(error)) => {
+            upload.abort().await;
+            Err(error)
+        }
+    }
+}
+
+struct PartUpload<'a> {
+    s3: &'a aws_sdk_s3::Client,
+    bucket_name: &'a str,
+    bucket_key: &'a str,
+    journal_key: Option<&'a str>,
+    upload_id: Option<String>,
+    parts: Vec<CompletedPart>,
+    // Set once the last part is uploaded, for the journal.
+    uploaded: bool,
+    buffer: Vec<u8>,
+}
+
+impl<'a> PartUpload<'a> {
+    fn new(
+        s3: &'a aws_sdk_s3::Client,
+        bucket_name: &'a str,
+        bucket_key: &'a str,
+        journal_key: Option<&'a str>,
+    ) -> Self {
+        PartUpload {
+            s3,
+            bucket_name,
+            bucket_key,
+            journal_key,
+            upload_id: None,
+            parts: vec![],
+            uploaded: false,
+            buffer: Vec::with_capacity(PART_BYTES + CHUNK_BYTES),
+        }
+    }
+
+    // Records the upload & its parts so far in the journal, on a best-effort basis, since the
+    // journal only matters should this process crash.
+    async fn write_journal(&self) {
+        let (Some(journal_key), Some(upload_id)) = (self.journal_key, &self.upload_id) else {
+            return;
+        };
+        let journal = SaveJournal {
+            bucket_key: self.bucket_key.to_string(),
+            upload_id: upload_id.clone(),
+            parts: self
+                .parts
+                .iter()
+                .map(|part| JournalPart {
+                    part_number: part.part_number().unwrap_or_default(),
+                    e_tag: part.e_tag().map(String::from),
+                })
+                .collect(),
+            uploaded: self.uploaded,
+        };
+        if let Err(error) =
+            journal::write_journal_with_client(self.s3, self.bucket_name, journal_key, &journal)
+                .await
+        {
+            eprintln!(
+                "save-release-artifacts failed to write save journal '{journal_key}', continuing: {error:?}"
+            );
+        }
+    }
+
+    // Deletes the journal once the upload is completed or aborted.
+    async fn delete_journal(&self) {
+        let (Some(journal_key), Some(_)) = (self.journal_key, &self.upload_id) else {
+            return;
+        };
+        if let Err(error) =
+            journal::delete_journal_with_client(self.s3, self.bucket_name, journal_key).await
+        {
+            eprintln!(
+                "save-release-artifacts failed to delete save journal '{journal_key}', continuing: {error:?}"
+            );
+        }
+    }
+
+    // Uploads the buffer as the next part, starting the multipart upload with the first part.
+    async fn upload_part(&mut self) -> Result<(), ReleaseArtifactsError> {
+        if self.upload_id.is_none() {
+            let created = self
+                .s3
+                .create_multipart_upload()
+                .bucket(self.bucket_name)
+                .key(self.bucket_key)
+                .send()
+                .await?;
+            self.upload_id = Some(created.upload_id().unwrap_or_default().to_string());
+            self.write_journal().await;
+        }
+        let upload_id = self.upload_id.clone().unwrap_or_default();
+        let part_number = i32::try_from(self.parts.len() + 1).unwrap_or(i32::MAX);
+        let body = std::mem::replace(
+            &mut self.buffer,
+            Vec::with_capacity(PART_BYTES + CHUNK_BYTES),
+        );
+        let uploaded = self
+            .s3
+            .upload_part()
+            .bucket(self.bucket_name)
+            .key(self.bucket_key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(body))
+            .send()
+            .await?;
+        self.parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .set_e_tag(uploaded.e_tag().map(String::from))
+                .build(),
+        );
+        self.write_journal().await;
+        Ok(())
+    }
+
+    // Returns whether the checksum was stored as metadata, only when not uploaded in parts.
+    async fn complete(mut self, checksum: &str) -> Result<bool, ReleaseArtifactsError> {
+        let Some(upload_id) = self.upload_id.clone() else {
+            let body = std::mem::take(&mut self.buffer);
+            self.s3
+                .put_object()
+                .bucket(self.bucket_name)
+                .key(self.bucket_key)
+                .metadata(CHECKSUM_METADATA_KEY, checksum)
+                .body(ByteStream::from(body))
+                .send()
+                .await?;
+            return Ok(true);
+        };
+        self.uploaded = true;
+        if self.buffer.is_empty() {
+            self.write_journal().await;
+        } else if let Err(error) = self.upload_part().await {
+            self.abort().await;
+            return Err(error);
+        }
+        let completed = self
+            .s3
+            .complete_multipart_upload()
+            .bucket(self.bucket_name)
+            .key(self.bucket_key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(std::mem::take(&mut self.parts)))
+                    .build(),
+            )
+            .send()
+            .await;
+        if let Err(error) = completed {
+            self.abort().await;
+            return Err(error.into());
+        }
+        self.delete_journal().await;
+        Ok(false)
+    }
+
+    // Aborts the multipart upload, if started, so that its parts are not billed for. The journal
+    // is kept when aborting fails, so that the upload can be aborted later.
+    async fn abort(&self) {
+        let Some(upload_id) = &self.upload_id else {
+            return;
+        };
+        match self
+            .s3
+            .abort_multipart_upload()
+            .bucket(self.bucket_name)
+            .key(self.bucket_key)
+            .upload_id(upload_id)
+            .send()
+            .await
+        {
+            Ok(_) => self.delete_journal().await,
+            Err(error) => eprintln!(
+                "save-release-artifacts failed to abort multipart upload '{upload_id}': {:?}",
+                ReleaseArtifactsError::from(error)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use aws_config::BehaviorVersion;
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+    use uuid::Uuid;
+
+    use super::{archive_and_upload_with_client, PART_BYTES};
+    use crate::{checksum::file_checksum, filter::ArtifactFilter, make_s3_test_credentials};
+
+    fn test_s3_client(replay_client: &StaticReplayClient) -> aws_sdk_s3::Client {
+        aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_s3_test_credentials())
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .http_client(replay_client.clone())
+                .build(),
+        )
+    }
+
+    fn replay_event(method: &str, uri: &str, response_body: &str) -> ReplayEvent {
+        ReplayEvent::new(
+            http::Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .header("ETag", "\"test-etag\"")
+                .body(SdkBody::from(response_body))
+                .unwrap(),
+        )
+    }
+
+    // Pseudo-random bytes, which gzip cannot shrink.
+    fn incompressible_bytes(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state.to_le_bytes()[0]
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn archive_and_upload_puts_small_archive() {
+        let replay_client = StaticReplayClient::new(vec![replay_event(
+            "PUT",
+            "https://test-bucket.s3.us-east-1.amazonaws.com/static-artifacts.tgz?x-id=PutObject",
+            "",
+        )]);
+        let s3 = test_s3_client(&replay_client);
+        let archive_path = PathBuf::from(format!("tee-upload-test-{}.tgz", Uuid::new_v4()));
+
+        let result = archive_and_upload_with_client(
+            &s3,
+            "test-bucket",
+            "static-artifacts.tgz",
+            None,
+            &PathBuf::from("test/fixtures/static-artifacts"),
+            &archive_path,
+            &ArtifactFilter::default(),
+        )
+        .await;
+        let archive_size = fs::metadata(&archive_path).map(|m| m.len());
+        let archive_checksum = file_checksum(&archive_path);
+        let request = replay_client.actual_requests().next();
+        let uploaded_size = request
+            .as_ref()
+            .and_then(|r| r.body().bytes().map(<[u8]>::len));
+        let uploaded_checksum = request
+            .as_ref()
+            .and_then(|r| r.headers().get("x-amz-meta-release-phase-sha256"))
+            .map(ToString::to_string);
+        fs::remove_file(&archive_path).unwrap_or_default();
+
+        let uploaded = result.unwrap();
+        assert_eq!(
+            archive_size.unwrap(),
+            uploaded_size.unwrap() as u64,
+            "uploaded bytes should match the local archive"
+        );
+        assert!(uploaded.checksum_stored);
+        assert_eq!(uploaded.checksum, archive_checksum.unwrap());
+        assert_eq!(uploaded_checksum, Some(uploaded.checksum));
+    }
+
+    #[tokio::test]
+    async fn archive_and_upload_uploads_large_archive_in_parts() {
+        let key_uri = "https://test-bucket.s3.us-east-1.amazonaws.com/static-artifacts.tgz";
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event(
+                "POST",
+                &format!("{key_uri}?uploads&x-id=CreateMultipartUpload"),
+                "<InitiateMultipartUploadResult><Bucket>test-bucket</Bucket><Key>static-artifacts.tgz</Key><UploadId>TESTUPLOADID</UploadId></InitiateMultipartUploadResult>",
+            ),
+            replay_event(
+                "PUT",
+                &format!("{key_uri}?x-id=UploadPart&partNumber=1&uploadId=TESTUPLOADID"),
+                "",
+            ),
+            replay_event(
+                "PUT",
+                &format!("{key_uri}?x-id=UploadPart&partNumber=2&uploadId=TESTUPLOADID"),
+                "",
+            ),
+            replay_event(
+                "POST",
+                &format!("{key_uri}?uploadId=TESTUPLOADID"),
+                "<CompleteMultipartUploadResult><Bucket>test-bucket</Bucket><Key>static-artifacts.tgz</Key><ETag>\"test-etag\"</ETag></CompleteMultipartUploadResult>",
+            ),
+        ]);
+        let s3 = test_s3_client(&replay_client);
+        let unique = Uuid::new_v4();
+        let source_path = PathBuf::from(format!("tee-upload-source-{unique}"));
+        fs::create_dir_all(&source_path).unwrap();
+        fs::write(
+            source_path.join("large.bin"),
+            incompressible_bytes(PART_BYTES + PART_BYTES / 2),
+        )
+        .unwrap();
+        let archive_path = source_path.with_extension("tgz");
+
+        let result = archive_and_upload_with_client(
+            &s3,
+            "test-bucket",
+            "static-artifacts.tgz",
+            None,
+            &source_path,
+            &archive_path,
+            &ArtifactFilter::default(),
+        )
+        .await;
+        let archive_size = fs::metadata(&archive_path).map(|m| m.len());
+        let uploaded_size: usize = replay_client
+            .actual_requests()
+            .filter_map(|r| r.body().bytes().map(<[u8]>::len))
+            .filter(|len| *len > 1024)
+            .sum();
+        let uris: Vec<String> = replay_client
+            .actual_requests()
+            .map(|r| r.uri().to_string())
+            .collect();
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+        fs::remove_file(&archive_path).unwrap_or_default();
+
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(uris.len(), 4);
+        assert!(uris[1].contains("partNumber=1"));
+        assert!(uris[2].contains("partNumber=2"));
+        assert!(uris[3].contains("uploadId=TESTUPLOADID"));
+        assert_eq!(archive_size.unwrap(), uploaded_size as u64);
+    }
+
+    #[tokio::test]
+    async fn archive_and_upload_journals_upload_in_parts() {
+        let key_uri = "https://test-bucket.s3.us-east-1.amazonaws.com/static-artifacts.tgz";
+        let journal_uri = "https://test-bucket.s3.us-east-1.amazonaws.com/release-phase-journal/static-artifacts.tgz.json";
+        let replay_client = StaticReplayClient::new(vec![
+            replay_event(
+                "POST",
+                &format!("{key_uri}?uploads&x-id=CreateMultipartUpload"),
+                "<InitiateMultipartUploadResult><Bucket>test-bucket</Bucket><Key>static-artifacts.tgz</Key><UploadId>TESTUPLOADID</UploadId></InitiateMultipartUploadResult>",
+            ),
+            replay_event("PUT", &format!("{journal_uri}?x-id=PutObject"), ""),
+            replay_event(
+                "PUT",
+                &format!("{key_uri}?x-id=UploadPart&partNumber=1&uploadId=TESTUPLOADID"),
+                "",
+            ),
+            replay_event("PUT", &format!("{journal_uri}?x-id=PutObject"), ""),
+            replay_event(
+                "PUT",
+                &format!("{key_uri}?x-id=UploadPart&partNumber=2&uploadId=TESTUPLOADID"),
+                "",
+            ),
+            replay_event("PUT", &format!("{journal_uri}?x-id=PutObject"), ""),
+            replay_event(
+                "POST",
+                &format!("{key_uri}?uploadId=TESTUPLOADID"),
+                "<CompleteMultipartUploadResult><Bucket>test-bucket</Bucket><Key>static-artifacts.tgz</Key><ETag>\"test-etag\"</ETag></CompleteMultipartUploadResult>",
+            ),
+            replay_event("DELETE", &format!("{journal_uri}?x-id=DeleteObject"), ""),
+        ]);
+        let s3 = test_s3_client(&replay_client);
+        let unique = Uuid::new_v4();
+        let source_path = PathBuf::from(format!("tee-upload-source-{unique}"));
+        fs::create_dir_all(&source_path).unwrap();
+        fs::write(
+            source_path.join("large.bin"),
+            incompressible_bytes(PART_BYTES + PART_BYTES / 2),
+        )
+        .unwrap();
+        let archive_path = source_path.with_extension("tgz");
+
+        let result = archive_and_upload_with_client(
+            &s3,
+            "test-bucket",
+            "static-artifacts.tgz",
+            Some("release-phase-journal/static-artifacts.tgz.json"),
+            &source_path,
+            &archive_path,
+            &ArtifactFilter::default(),
+        )
+        .await;
+        let requests: Vec<(String, String)> = replay_client
+            .actual_requests()
+            .map(|r| {
+                (
+                    format!("{} {}", r.method(), r.uri()),
+                    r.body()
+                        .bytes()
+                        .map(|b| String::from_utf8_lossy(b).to_string())
+                        .unwrap_or_default(),
+                )
+            })
+            .collect();
+        fs::remove_dir_all(&source_path).unwrap_or_default();
+        fs::remove_file(&archive_path).unwrap_or_default();
+
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(requests.len(), 8);
+        let journals: Vec<&String> = requests
+            .iter()
+            .filter(|(request, _)| request.starts_with("PUT") && request.contains("journal"))
+            .map(|(_, body)| body)
+            .collect();
+        assert_eq!(journals.len(), 3);
+        assert!(journals[0].contains("\"parts\":[]"), "{}", journals[0]);
+        assert!(journals[1].contains("\"part_number\":1"), "{}", journals[1]);
+        assert!(
+            journals[1].contains("\"uploaded\":false"),
+            "{}",
+            journals[1]
+        );
+        assert!(journals[2].contains("\"part_number\":2"), "{}", journals[2]);
+        assert!(journals[2].contains("\"uploaded\":true"), "{}", journals[2]);
+        assert!(requests[7].0.starts_with("DELETE") && requests[7].0.contains("journal"));
+    }
+
+    #[tokio::test]
+    async fn archive_and_upload_sends_nothing_when_archiving_fails() {
+        let replay_client = StaticReplayClient::new(vec![]);
+        let s3 = test_s3_client(&replay_client);
+        let archive_path = PathBuf::from(format!("tee-upload-test-{}.tgz", Uuid::new_v4()));
+
+        let result = archive_and_upload_with_client(
+            &s3,
+            "test-bucket",
+            "static-artifacts.tgz",
+            None,
+            &PathBuf::from("non-existent-path"),
+            &archive_path,
+            &ArtifactFilter::default(),
+        )
+        .await;
+        fs::remove_file(&archive_path).unwrap_or_default();
+
+        assert!(result.is_err());
+        assert_eq!(replay_client.actual_requests().count(), 0);
+    }
+}
@@ -0,0 +1,261 @@
+//! Reports the storage usage of each save, warning when the archive grew more than
+//! `STATIC_ARTIFACTS_GROWTH_WARNING_PERCENT`.
+
+use std::{collections::HashMap, fmt, fs, hash::BuildHasher, path::Path, time::Duration};
+
+use serde::Serialize;
+
+use crate::{errors::ReleaseArtifactsError, format_size, gc::is_archive_name, StoredArchive};
+
+const DEFAULT_GROWTH_WARNING_PERCENT: u64 = 50;
+
+/// The usage of artifact storage by a save.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SaveReport {
+    /// The size of the compressed archive.
+    pub archive_size: u64,
+    /// The size of the files archived, before compression.
+    pub source_size: u64,
+    /// `source-size` divided by `archive-size`.
+    pub compression_ratio: f64,
+    /// How long storing the archive took, including archiving, which is concurrent with the
+    /// upload.
+    pub upload_duration_ms: u64,
+    /// `archive-size` per second of `upload-duration-ms`.
+    pub throughput_bytes_per_second: u64,
+    /// The archives in storage after garbage collection, `None` when they could not be listed.
+    pub stored_archives: Option<usize>,
+    /// The size of the archive saved before this one, `None` for the first, or when they could
+    /// not be listed.
+    pub previous_archive_size: Option<u64>,
+}
+
+impl SaveReport {
+    pub(crate) fn new(archive_size: u64, source_size: u64, upload_duration: Duration) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        let compression_ratio = if archive_size == 0 {
+            0.0
+        } else {
+            source_size as f64 / archive_size as f64
+        };
+        #[allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        let throughput_bytes_per_second = if upload_duration.is_zero() {
+            0
+        } else {
+            (archive_size as f64 / upload_duration.as_secs_f64()) as u64
+        };
+        SaveReport {
+            archive_size,
+            source_size,
+            compression_ratio,
+            upload_duration_ms: u64::try_from(upload_duration.as_millis()).unwrap_or(u64::MAX),
+            throughput_bytes_per_second,
+            stored_archives: None,
+            previous_archive_size: None,
+        }
+    }
+
+    /// The percent that the archive grew since the previous one, when it grew.
+    #[must_use]
+    pub fn growth_percent(&self) -> Option<u64> {
+        self.previous_archive_size
+            .filter(|previous| *previous > 0 && self.archive_size > *previous)
+            .map(|previous| (self.archive_size - previous).saturating_mul(100) / previous)
+    }
+
+    /// A warning when the archive grew more than the percent since the previous one.
+    #[must_use]
+    pub fn growth_warning(&self, warning_percent: u64) -> Option<String> {
+        let growth_percent = self.growth_percent().filter(|p| *p > warning_percent)?;
+        Some(format!(
+            "archive grew {growth_percent}% since the previous release, from {} to {}, more than STATIC_ARTIFACTS_GROWTH_WARNING_PERCENT={warning_percent}. Check that caches, sourcemaps, or other build by-products are not included in the artifacts by accident.",
+            format_size(self.previous_archive_size.unwrap_or_default()),
+            format_size(self.archive_size),
+        ))
+    }
+
+    /// The report as JSON, for the result of `save-release-artifacts --json`.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+impl fmt::Display for SaveReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "archive {} from {} ({:.1}x compression), stored in {:.1}s at {}/s",
+            format_size(self.archive_size),
+            format_size(self.source_size),
+            self.compression_ratio,
+            Duration::from_millis(self.upload_duration_ms).as_secs_f64(),
+            format_size(self.throughput_bytes_per_second),
+        )?;
+        if let Some(stored_archives) = self.stored_archives {
+            write!(f, ", {stored_archives} archives now stored")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the percent of growth between releases to warn at, from
+/// `STATIC_ARTIFACTS_GROWTH_WARNING_PERCENT`, or `None` when it is `0`, disabling the warning.
+pub(crate) fn growth_warning_percent<S: BuildHasher>(
+    env: &HashMap<String, String, S>,
+) -> Result<Option<u64>, ReleaseArtifactsError> {
+    let Some(value) = env.get("STATIC_ARTIFACTS_GROWTH_WARNING_PERCENT") else {
+        return Ok(Some(DEFAULT_GROWTH_WARNING_PERCENT));
+    };
+    match value.trim().parse::<u64>() {
+        Ok(0) => Ok(None),
+        Ok(percent) => Ok(Some(percent)),
+        Err(_) => Err(ReleaseArtifactsError::ConfigInvalid(format!(
+            "STATIC_ARTIFACTS_GROWTH_WARNING_PERCENT '{value}' is not a whole number of percent, or 0 to disable"
+        ))),
+    }
+}
+
+// The size of the newest stored archive other than the one just saved, from the listing,
+// oldest first.
+pub(crate) fn previous_archive_size(archives: &[StoredArchive], saved_name: &str) -> Option<u64> {
+    archives
+        .iter()
+        .rev()
+        .filter(|archive| is_archive_name(&archive.key))
+        .find(|archive| archive.key.rsplit('/').next() != Some(saved_name))
+        .map(|archive| archive.size)
+}
+
+// The total size of the regular files at the path, a file or a directory, without following
+// symlinks, like the archive does.
+pub(crate) fn source_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if metadata.is_dir() {
+        fs::read_dir(path).map_or(0, |entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| source_size(&entry.path()))
+                .sum()
+        })
+    } else if metadata.is_file() {
+        metadata.len()
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{growth_warning_percent, previous_archive_size, source_size, SaveReport};
+    use crate::StoredArchive;
+
+    #[test]
+    fn save_report_computes_ratio_and_throughput() {
+        let mut report = SaveReport::new(1024 * 1024, 4 * 1024 * 1024, Duration::from_secs(2));
+        assert!((report.compression_ratio - 4.0).abs() < f64::EPSILON);
+        assert_eq!(report.upload_duration_ms, 2000);
+        assert_eq!(report.throughput_bytes_per_second, 512 * 1024);
+        report.stored_archives = Some(3);
+        assert_eq!(
+            report.to_string(),
+            "archive 1.0 MiB from 4.0 MiB (4.0x compression), stored in 2.0s at 512.0 KiB/s, 3 archives now stored"
+        );
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "archive-size": 1_048_576,
+                "source-size": 4_194_304,
+                "compression-ratio": 4.0,
+                "upload-duration-ms": 2000,
+                "throughput-bytes-per-second": 524_288,
+                "stored-archives": 3,
+                "previous-archive-size": null,
+            })
+        );
+    }
+
+    #[test]
+    fn save_report_of_empty_archive() {
+        let report = SaveReport::new(0, 0, Duration::ZERO);
+        assert!(report.compression_ratio.abs() < f64::EPSILON);
+        assert_eq!(report.throughput_bytes_per_second, 0);
+    }
+
+    #[test]
+    fn save_report_warns_on_growth() {
+        let mut report = SaveReport::new(3 * 1024 * 1024, 0, Duration::from_secs(1));
+        assert_eq!(report.growth_percent(), None);
+        assert_eq!(report.growth_warning(50), None);
+        report.previous_archive_size = Some(2 * 1024 * 1024);
+        assert_eq!(report.growth_percent(), Some(50));
+        assert_eq!(report.growth_warning(50), None);
+        report.previous_archive_size = Some(1024 * 1024);
+        assert_eq!(report.growth_percent(), Some(200));
+        assert!(report.growth_warning(50).is_some_and(|warning| warning
+            .starts_with("archive grew 200% since the previous release, from 1.0 MiB to 3.0 MiB")));
+        report.previous_archive_size = Some(4 * 1024 * 1024);
+        assert_eq!(report.growth_percent(), None);
+    }
+
+    #[test]
+    fn growth_warning_percent_from_env() {
+        let mut test_env: HashMap<String, String> = HashMap::new();
+        assert_eq!(growth_warning_percent(&test_env).unwrap(), Some(50));
+        test_env.insert(
+            "STATIC_ARTIFACTS_GROWTH_WARNING_PERCENT".to_string(),
+            "200".to_string(),
+        );
+        assert_eq!(growth_warning_percent(&test_env).unwrap(), Some(200));
+        test_env.insert(
+            "STATIC_ARTIFACTS_GROWTH_WARNING_PERCENT".to_string(),
+            "0".to_string(),
+        );
+        assert_eq!(growth_warning_percent(&test_env).unwrap(), None);
+        test_env.insert(
+            "STATIC_ARTIFACTS_GROWTH_WARNING_PERCENT".to_string(),
+            "50%".to_string(),
+        );
+        assert!(growth_warning_percent(&test_env).is_err());
+    }
+
+    #[test]
+    fn previous_archive_size_skips_saved_archive() {
+        let archives = [
+            ("prefix/release-v1.tgz", 1),
+            ("prefix/release-v2.tgz", 2),
+            ("prefix/manifest.json", 3),
+            ("prefix/release-v3.tgz", 4),
+        ]
+        .iter()
+        .map(|(key, size)| StoredArchive {
+            key: (*key).to_string(),
+            size: *size,
+            last_modified: SystemTime::UNIX_EPOCH,
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(previous_archive_size(&archives, "release-v3.tgz"), Some(2));
+        assert_eq!(
+            previous_archive_size(&archives[..1], "release-v1.tgz"),
+            None
+        );
+    }
+
+    #[test]
+    fn source_size_sums_files() {
+        assert!(source_size(Path::new("test/fixtures/static-artifacts")) > 0);
+        assert_eq!(source_size(Path::new("test/fixtures/does-not-exist")), 0);
+    }
+}
@@ -0,0 +1,272 @@
+//! Tests against the real S3 bucket of `TEST_S3_BUCKET` & `TEST_S3_REGION`, skipped unless it is
+//! set. To test against an emulator, such as `localstack`, also set `AWS_ENDPOINT_URL`.
+
+// Required due to: https://github.com/rust-lang/rust/issues/95513
+#![allow(unused_crate_dependencies)]
+
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use aws_sdk_s3::{
+    config::{Credentials, Region},
+    primitives::ByteStream,
+    types::{Delete, ObjectIdentifier},
+};
+use release_artifacts::{gc, list_stored_archives, load, save, Retention};
+use uuid::Uuid;
+
+// Larger than the 8MiB parts of uploads, so that saving uses several parts.
+const MULTIPART_BYTES: usize = 20 * 1024 * 1024;
+
+// More than the 1,000 keys of a ListObjectsV2 page.
+const PAGINATED_ARCHIVES: usize = 1_005;
+
+struct TestBucket {
+    name: String,
+    region: String,
+    prefix: String,
+    access_key_id: String,
+    secret_access_key: String,
+    client: aws_sdk_s3::Client,
+}
+
+impl TestBucket {
+    // Returns the bucket from env, or `None` to skip the test.
+    async fn from_env() -> Option<TestBucket> {
+        let Some(name) = env::var("TEST_S3_BUCKET").ok().filter(|n| !n.is_empty()) else {
+            eprintln!("skipping S3 integration test, TEST_S3_BUCKET is not set");
+            return None;
+        };
+        let region = env::var("TEST_S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let access_key_id = env::var("AWS_ACCESS_KEY_ID").expect("AWS_ACCESS_KEY_ID should be set");
+        let secret_access_key =
+            env::var("AWS_SECRET_ACCESS_KEY").expect("AWS_SECRET_ACCESS_KEY should be set");
+        let config = aws_config::from_env()
+            .region(Region::new(region.clone()))
+            .credentials_provider(Credentials::new(
+                access_key_id.clone(),
+                secret_access_key.clone(),
+                None,
+                None,
+                "S3 integration test",
+            ))
+            .load()
+            .await;
+        Some(TestBucket {
+            name,
+            region,
+            prefix: format!("release-phase-integration/{}", Uuid::new_v4()),
+            access_key_id,
+            secret_access_key,
+            client: aws_sdk_s3::Client::new(&config),
+        })
+    }
+
+    fn env(&self, release_id: &str) -> HashMap<String, String> {
+        HashMap::from([
+            (
+                "STATIC_ARTIFACTS_URL".to_string(),
+                format!("s3://{}/{}", self.name, self.prefix),
+            ),
+            ("STATIC_ARTIFACTS_REGION".to_string(), self.region.clone()),
+            (
+                "STATIC_ARTIFACTS_ACCESS_KEY_ID".to_string(),
+                self.access_key_id.clone(),
+            ),
+            (
+                "STATIC_ARTIFACTS_SECRET_ACCESS_KEY".to_string(),
+                self.secret_access_key.clone(),
+            ),
+            ("RELEASE_ID".to_string(), release_id.to_string()),
+        ])
+    }
+
+    // Deletes every object under the test's prefix.
+    async fn clean_up(&self) {
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.name)
+            .prefix(format!("{}/", self.prefix))
+            .into_paginator()
+            .send();
+        while let Some(page) = pages.next().await {
+            let page = page.expect("test objects should be listed");
+            let objects: Vec<ObjectIdentifier> = page
+                .contents()
+                .iter()
+                .filter_map(|object| object.key())
+                .map(|key| {
+                    ObjectIdentifier::builder()
+                        .key(key)
+                        .build()
+                        .expect("object identifier should be built")
+                })
+                .collect();
+            if objects.is_empty() {
+                continue;
+            }
+            self.client
+                .delete_objects()
+                .bucket(&self.name)
+                .delete(
+                    Delete::builder()
+                        .set_objects(Some(objects))
+                        .build()
+                        .expect("delete should be built"),
+                )
+                .send()
+                .await
+                .expect("test objects should be deleted");
+        }
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("s3-integration-{name}-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).expect("temp dir should be created");
+    dir
+}
+
+// Bytes that do not compress, so that the archive stays larger than a part.
+fn incompressible_bytes(len: usize) -> Vec<u8> {
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state.to_le_bytes()[0]
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn save_and_load_multipart_archive() {
+    let Some(bucket) = TestBucket::from_env().await else {
+        return;
+    };
+    let source_dir = temp_dir("source");
+    let data = incompressible_bytes(MULTIPART_BYTES);
+    fs::write(source_dir.join("large.bin"), &data).expect("test file should be written");
+    fs::write(source_dir.join("small.txt"), "small").expect("test file should be written");
+    let destination_dir = temp_dir("destination");
+
+    let saved = save(&bucket.env("v1"), &source_dir).await;
+    let loaded = load(&bucket.env("v1"), &destination_dir).await;
+    let loaded_data = fs::read(destination_dir.join("large.bin"));
+    let loaded_small = fs::read_to_string(destination_dir.join("small.txt"));
+    bucket.clean_up().await;
+    fs::remove_dir_all(&source_dir).unwrap_or_default();
+    fs::remove_dir_all(&destination_dir).unwrap_or_default();
+
+    saved.expect("archive should be saved");
+    assert_eq!(
+        loaded.expect("archive should be loaded"),
+        format!("{}/release-v1.tgz", bucket.prefix)
+    );
+    assert!(loaded_data.is_ok_and(|loaded_data| loaded_data == data));
+    assert_eq!(loaded_small.expect("small file should be loaded"), "small");
+}
+
+#[tokio::test]
+async fn load_falls_back_to_latest_archive() {
+    let Some(bucket) = TestBucket::from_env().await else {
+        return;
+    };
+    let source_dir = temp_dir("source");
+    let destination_dir = temp_dir("destination");
+
+    fs::write(source_dir.join("version.txt"), "v1").expect("test file should be written");
+    let saved_v1 = save(&bucket.env("v1"), &source_dir).await;
+    fs::write(source_dir.join("version.txt"), "v2").expect("test file should be written");
+    let saved_v2 = save(&bucket.env("v2"), &source_dir).await;
+    let loaded = load(&bucket.env("v3"), &destination_dir).await;
+    let loaded_version = fs::read_to_string(destination_dir.join("version.txt"));
+    bucket.clean_up().await;
+    fs::remove_dir_all(&source_dir).unwrap_or_default();
+    fs::remove_dir_all(&destination_dir).unwrap_or_default();
+
+    saved_v1.expect("v1 archive should be saved");
+    saved_v2.expect("v2 archive should be saved");
+    assert_eq!(
+        loaded.expect("latest archive should be loaded"),
+        format!("{}/release-v2.tgz", bucket.prefix)
+    );
+    assert_eq!(loaded_version.expect("version should be loaded"), "v2");
+}
+
+#[tokio::test]
+async fn save_and_load_withstand_injected_faults() {
+    let Some(bucket) = TestBucket::from_env().await else {
+        return;
+    };
+    let source_dir = temp_dir("source");
+    fs::write(source_dir.join("version.txt"), "v1").expect("test file should be written");
+    let destination_dir = temp_dir("destination");
+    let mut faulty_env = bucket.env("v1");
+    faulty_env.insert(
+        "STATIC_ARTIFACTS_FAULTS".to_string(),
+        "error=0.2,slow-ms=5,seed=7".to_string(),
+    );
+    faulty_env.insert("STATIC_ARTIFACTS_MAX_RETRIES".to_string(), "10".to_string());
+
+    let saved = save(&faulty_env, &source_dir).await;
+    let loaded = load(&faulty_env, &destination_dir).await;
+    let loaded_version = fs::read_to_string(destination_dir.join("version.txt"));
+    bucket.clean_up().await;
+    fs::remove_dir_all(&source_dir).unwrap_or_default();
+    fs::remove_dir_all(&destination_dir).unwrap_or_default();
+
+    saved.expect("archive should be saved, retrying injected errors");
+    loaded.expect("archive should be loaded, retrying injected errors");
+    assert_eq!(loaded_version.expect("version should be loaded"), "v1");
+}
+
+#[tokio::test]
+async fn list_and_gc_paginated_archives() {
+    let Some(bucket) = TestBucket::from_env().await else {
+        return;
+    };
+    let mut uploads = tokio::task::JoinSet::new();
+    for i in 0..PAGINATED_ARCHIVES {
+        let client = bucket.client.clone();
+        let bucket_name = bucket.name.clone();
+        let key = format!("{}/release-p{i:04}.tgz", bucket.prefix);
+        uploads.spawn(async move {
+            client
+                .put_object()
+                .bucket(bucket_name)
+                .key(key)
+                .body(ByteStream::from_static(b""))
+                .send()
+                .await
+        });
+    }
+    while let Some(upload) = uploads.join_next().await {
+        upload
+            .expect("upload task should complete")
+            .expect("test archive should be uploaded");
+    }
+    let env = bucket.env("v1");
+
+    let listed = list_stored_archives(&env).await;
+    let collected = gc(
+        &env,
+        &Retention {
+            retain: Some(2),
+            ..Retention::default()
+        },
+    )
+    .await;
+    let remaining = list_stored_archives(&env).await;
+    bucket.clean_up().await;
+
+    assert_eq!(
+        listed.expect("archives should be listed").len(),
+        PAGINATED_ARCHIVES
+    );
+    let report = collected.expect("old archives should be deleted");
+    assert_eq!(report.kept, 2);
+    assert_eq!(report.deleted.len(), PAGINATED_ARCHIVES - 2);
+    assert_eq!(remaining.expect("archives should be listed").len(), 2);
+}
@@ -0,0 +1,59 @@
+//! Takes the flags & options out of the args of the buildpack's executables, leaving their
+//! positional args.
+
+/// Removes `name` from the args, returning whether it was there.
+pub fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let len = args.len();
+    args.retain(|a| a != name);
+    args.len() != len
+}
+
+/// Removes `name <value>` from the args, returning the value.
+pub fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|a| a == name) {
+        Some(i) if i + 1 < args.len() => {
+            let value = args.remove(i + 1);
+            args.remove(i);
+            Ok(Some(value))
+        }
+        Some(_) => Err(format!("{name} requires a value")),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{take_flag, take_option};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn take_flag_removes_flag() {
+        let mut test_args = args(&["--json", "static-artifacts"]);
+        assert!(take_flag(&mut test_args, "--json"));
+        assert!(!take_flag(&mut test_args, "--json"));
+        assert_eq!(test_args, args(&["static-artifacts"]));
+    }
+
+    #[test]
+    fn take_option_removes_option_and_value() {
+        let mut test_args = args(&["static-artifacts", "--channel", "assets"]);
+        assert_eq!(
+            take_option(&mut test_args, "--channel"),
+            Ok(Some("assets".to_string()))
+        );
+        assert_eq!(take_option(&mut test_args, "--channel"), Ok(None));
+        assert_eq!(test_args, args(&["static-artifacts"]));
+    }
+
+    #[test]
+    fn take_option_requires_value() {
+        let mut test_args = args(&["--channel"]);
+        assert_eq!(
+            take_option(&mut test_args, "--channel"),
+            Err("--channel requires a value".to_string())
+        );
+    }
+}
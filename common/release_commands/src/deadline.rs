@@ -0,0 +1,390 @@
+//! Bounds the duration of the whole release sequence with `RELEASE_PHASE_MAX_DURATION`,
+//! terminating running commands at the deadline, then killing them after the grace period.
+
+use std::{
+    env, fs, io,
+    process::{Child, ExitStatus},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::Error;
+
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+// How often a running command is checked for having exited, while waiting on the deadline.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// When the release sequence must be done by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline {
+    pub at: Instant,
+    pub max_duration: Duration,
+    /// How long a command may take to exit after `SIGTERM`, before it is killed.
+    pub grace_period: Duration,
+}
+
+impl Deadline {
+    /// The deadline from `RELEASE_PHASE_MAX_DURATION`, counted from the given start, with the
+    /// grace period of `RELEASE_PHASE_GRACE_PERIOD`, or else 30 seconds. Both are seconds, or
+    /// with an `s`, `m`, or `h` suffix, like `"20m"`.
+    pub fn from_env(start: Instant) -> Result<Option<Deadline>, Error> {
+        let Some(max_duration) = env::var("RELEASE_PHASE_MAX_DURATION")
+            .ok()
+            .filter(|v| !v.is_empty())
+        else {
+            return Ok(None);
+        };
+        let max_duration = parse_duration(&max_duration)
+            .filter(|d| !d.is_zero())
+            .ok_or_else(|| {
+                Error::DurationInvalid(format!("RELEASE_PHASE_MAX_DURATION={max_duration}"))
+            })?;
+        let grace_period = match env::var("RELEASE_PHASE_GRACE_PERIOD") {
+            Ok(grace_period) if !grace_period.is_empty() => parse_duration(&grace_period)
+                .ok_or_else(|| {
+                    Error::DurationInvalid(format!("RELEASE_PHASE_GRACE_PERIOD={grace_period}"))
+                })?,
+            _ => DEFAULT_GRACE_PERIOD,
+        };
+        Ok(Some(Deadline {
+            at: start + max_duration,
+            max_duration,
+            grace_period,
+        }))
+    }
+
+    #[must_use]
+    pub fn is_passed(&self) -> bool {
+        Instant::now() >= self.at
+    }
+}
+
+fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    let (number, multiplier) = match duration.chars().last()? {
+        's' => (&duration[..duration.len() - 1], 1),
+        'm' => (&duration[..duration.len() - 1], 60),
+        'h' => (&duration[..duration.len() - 1], 60 * 60),
+        _ => (duration, 1),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .map(Duration::from_secs)
+}
+
+/// Waits for the child to exit, terminating it once the deadline passes. When the child is the
+/// leader of its own process group, the whole group is terminated, and what remains of it is
+/// killed after the child exits.
+pub(crate) fn wait_until(
+    child: &mut Child,
+    deadline: Option<Deadline>,
+    in_own_group: bool,
+) -> io::Result<ExitStatus> {
+    let reaped = wait_for_leader(child, deadline, in_own_group)?;
+    if in_own_group && !reaped {
+        // Until the leader is reaped, its pid, which is the group's id, cannot be reused, so
+        // only what remains of its group is killed. Without `/proc`, the leader was reaped to
+        // find that it exited, and the group is left alone.
+        signal(child, Stop::Kill, true);
+    }
+    child.wait()
+}
+
+// Waits for the child to exit, returning whether it was reaped.
+fn wait_for_leader(
+    child: &mut Child,
+    deadline: Option<Deadline>,
+    in_own_group: bool,
+) -> io::Result<bool> {
+    let Some(deadline) = deadline else {
+        return wait_before(child, None).map(|reaped| reaped.unwrap_or(true));
+    };
+    if let Some(reaped) = wait_before(child, Some(deadline.at))? {
+        return Ok(reaped);
+    }
+    signal(child, Stop::Terminate, in_own_group);
+    if let Some(reaped) = wait_before(child, Some(Instant::now() + deadline.grace_period))? {
+        return Ok(reaped);
+    }
+    if !signal(child, Stop::Kill, in_own_group) {
+        // When its group cannot be signalled, the child itself is still killed.
+        child.kill()?;
+    }
+    wait_before(child, None).map(|reaped| reaped.unwrap_or(true))
+}
+
+// Polls the child until it exits, or the instant passes, returning whether it was reaped once it
+// exited.
+fn wait_before(child: &mut Child, instant: Option<Instant>) -> io::Result<Option<bool>> {
+    loop {
+        if let Some(reaped) = exited(child)? {
+            return Ok(Some(reaped));
+        }
+        let now = Instant::now();
+        let pause = match instant {
+            Some(instant) if now >= instant => return Ok(None),
+            Some(instant) => POLL_INTERVAL.min(instant - now),
+            None => POLL_INTERVAL,
+        };
+        thread::sleep(pause);
+    }
+}
+
+// Whether the child exited, and if so, whether it was reaped. With `/proc`, it is seen exited as
+// a zombie, without reaping it.
+fn exited(child: &mut Child) -> io::Result<Option<bool>> {
+    match fs::read_to_string(format!("/proc/{}/stat", child.id())) {
+        // The state follows the command name, which is in parentheses.
+        Ok(stat) => Ok(stat
+            .rsplit(") ")
+            .next()
+            .is_some_and(|fields| fields.starts_with('Z'))
+            .then_some(false)),
+        Err(_) => Ok(child.try_wait()?.map(|_| true)),
+    }
+}
+
+// How a command is stopped once the deadline passes: terminated, then killed after the grace
+// period.
+#[derive(Debug, Clone, Copy)]
+enum Stop {
+    Terminate,
+    Kill,
+}
+
+// Sends the signal to the child, or its process group, returning whether it was sent. It fails
+// when no process of the group remains, which is left at that.
+#[cfg(unix)]
+fn signal(child: &Child, stop: Stop, to_group: bool) -> bool {
+    use nix::{
+        errno::Errno,
+        sys::signal::{kill, killpg, Signal},
+        unistd::Pid,
+    };
+
+    let Ok(pid) = i32::try_from(child.id()).map(Pid::from_raw) else {
+        return false;
+    };
+    let signal = match stop {
+        Stop::Terminate => Signal::SIGTERM,
+        Stop::Kill => Signal::SIGKILL,
+    };
+    let sent = if to_group {
+        killpg(pid, signal)
+    } else {
+        kill(pid, signal)
+    };
+    match sent {
+        Ok(()) => true,
+        Err(Errno::ESRCH) => false,
+        Err(error) => {
+            eprintln!("release-phase warning: sending {signal} to process {pid}, {error}");
+            false
+        }
+    }
+}
+
+// Windows has no signals, so the child is only killed, once the grace period passes.
+#[cfg(not(unix))]
+fn signal(_child: &Child, _stop: Stop, _to_group: bool) -> bool {
+    false
+}
+
+// The tests signal process groups, which only Unix has.
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use std::{
+        env, fs,
+        os::unix::process::{CommandExt, ExitStatusExt},
+        process::Command,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{exited, parse_duration, wait_until, Deadline};
+
+    #[test]
+    fn parse_duration_with_suffixes() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("45s"), Some(Duration::from_secs(45)));
+        assert_eq!(parse_duration("20m"), Some(Duration::from_secs(20 * 60)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn wait_until_terminates_at_deadline() {
+        let mut child = Command::new("sleep")
+            .arg("10")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        let status = wait_until(
+            &mut child,
+            Some(Deadline {
+                at: started + Duration::from_millis(200),
+                max_duration: Duration::from_millis(200),
+                grace_period: Duration::from_secs(5),
+            }),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(status.signal(), Some(15));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn wait_until_kills_after_grace_period() {
+        let mut child = Command::new("sh")
+            .args(["-c", "trap '' TERM; sleep 10"])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        let status = wait_until(
+            &mut child,
+            Some(Deadline {
+                at: started + Duration::from_millis(200),
+                max_duration: Duration::from_millis(200),
+                grace_period: Duration::from_millis(200),
+            }),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(status.signal(), Some(9));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn wait_until_kills_without_kill_command() {
+        // Reruns the test below without `kill` in the PATH, like on a minimal run image.
+        let output = Command::new(env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "deadline::tests::wait_until_with_empty_path",
+                "--ignored",
+            ])
+            .env("PATH", "")
+            .output()
+            .unwrap();
+
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+
+    #[test]
+    #[ignore = "run with an empty PATH by wait_until_kills_without_kill_command"]
+    fn wait_until_with_empty_path() {
+        assert!(Command::new("kill").arg("-0").arg("1").status().is_err());
+        let mut child = Command::new("/bin/sleep")
+            .arg("10")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        let status = wait_until(
+            &mut child,
+            Some(Deadline {
+                at: started + Duration::from_millis(200),
+                max_duration: Duration::from_millis(200),
+                grace_period: Duration::from_millis(200),
+            }),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(status.signal(), Some(9));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    // Whether the process is gone, or a zombie, awaiting its reaping by init.
+    fn is_exited(pid: &str) -> bool {
+        (0..100).any(|_| {
+            let exited = fs::read_to_string(format!("/proc/{pid}/stat")).map_or(true, |stat| {
+                stat.rsplit(") ")
+                    .next()
+                    .unwrap_or_default()
+                    .starts_with('Z')
+            });
+            if !exited {
+                thread::sleep(Duration::from_millis(50));
+            }
+            exited
+        })
+    }
+
+    #[test]
+    fn wait_until_kills_processes_left_in_group() {
+        let pid_path = std::env::temp_dir().join(format!("group-{}.pid", std::process::id()));
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("sleep 30 & echo $! > {}", pid_path.display()))
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let status = wait_until(&mut child, None, true).unwrap();
+        let pid = fs::read_to_string(&pid_path).unwrap();
+        fs::remove_file(&pid_path).unwrap();
+
+        assert!(status.success());
+        assert!(is_exited(pid.trim()));
+    }
+
+    #[test]
+    fn exited_leaves_child_unreaped() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let exited = (0..100)
+            .find_map(|_| {
+                let exited = exited(&mut child).unwrap();
+                thread::sleep(Duration::from_millis(50));
+                exited
+            })
+            .expect("child exited");
+        let unreaped = fs::metadata(format!("/proc/{}", child.id())).is_ok();
+        let status = child.wait().unwrap();
+
+        assert!(!exited);
+        assert!(unreaped);
+        assert!(status.success());
+    }
+
+    #[test]
+    fn wait_until_terminates_group_at_deadline() {
+        let pid_path = std::env::temp_dir().join(format!("deadline-{}.pid", std::process::id()));
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("sleep 30 & echo $! > {}; wait", pid_path.display()))
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        let status = wait_until(
+            &mut child,
+            Some(Deadline {
+                at: started + Duration::from_millis(200),
+                max_duration: Duration::from_millis(200),
+                grace_period: Duration::from_secs(5),
+            }),
+            true,
+        )
+        .unwrap();
+        let pid = fs::read_to_string(&pid_path).unwrap();
+        fs::remove_file(&pid_path).unwrap();
+
+        assert_eq!(status.signal(), Some(15));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(is_exited(pid.trim()));
+    }
+}
@@ -0,0 +1,282 @@
+//! Streams the events of a release sequence as JSON lines, to the unix socket of
+//! `RELEASE_PHASE_EVENTS_SOCKET` or the file descriptor of `RELEASE_PHASE_EVENTS_FD`.
+
+use std::{
+    env,
+    ffi::OsStr,
+    fs::OpenOptions,
+    io::{self, Write},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use crate::execute::{exit_signal, Step, StepOutcome};
+
+/// A stream of step events, each a line of JSON with an `event` of `started`, `finished`,
+/// `failed`, or `skipped`.
+pub struct EventStream {
+    writer: Mutex<Option<Box<dyn Write + Send>>>,
+}
+
+impl EventStream {
+    /// Connects to the socket or opens the file descriptor given in env, when either is set.
+    /// Failing to connect only warns, returning no stream.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        if let Some(path) = env::var_os("RELEASE_PHASE_EVENTS_SOCKET").filter(|p| !p.is_empty()) {
+            return connect_socket(&path)
+                .map_err(|error| {
+                    eprintln!(
+                        "release-phase warning: connecting to events socket {path:?}, {error}"
+                    );
+                })
+                .ok();
+        }
+        let fd = env::var("RELEASE_PHASE_EVENTS_FD").ok()?;
+        let Ok(fd) = fd.parse::<u32>() else {
+            eprintln!("release-phase warning: events fd '{fd}' is not a file descriptor number");
+            return None;
+        };
+        // Reopened by path, rather than adopted, so that the descriptor is not closed twice.
+        OpenOptions::new()
+            .append(true)
+            .open(format!("/dev/fd/{fd}"))
+            .map(EventStream::new)
+            .map_err(|error| eprintln!("release-phase warning: opening events fd {fd}, {error}"))
+            .ok()
+    }
+
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        EventStream {
+            writer: Mutex::new(Some(Box::new(writer))),
+        }
+    }
+
+    pub fn step_started(&self, step: &Step) {
+        self.send(&step_event("started", step));
+    }
+
+    pub fn step_finished(&self, step: &Step, outcome: &StepOutcome) {
+        let (event, (field, value)) = match outcome {
+            StepOutcome::Exited(status) if status.success() => {
+                ("finished", ("status", json!(status.code())))
+            }
+            StepOutcome::Exited(status) => ("failed", ("status", json!(status.code()))),
+            StepOutcome::ExecFailed => ("failed", ("error", json!("command failed to start"))),
+            StepOutcome::AlreadyCompleted => ("skipped", ("reason", json!("already completed"))),
+            StepOutcome::Skipped => ("skipped", ("reason", json!("by request"))),
+        };
+        let mut event = step_event(event, step);
+        event[field] = value;
+        // Without a status code, as the process was terminated by a signal.
+        if let StepOutcome::Exited(status) = outcome {
+            if let Some(signal) = exit_signal(*status) {
+                event["signal"] = json!(signal);
+            }
+        }
+        self.send(&event);
+    }
+
+    fn send(&self, event: &Value) {
+        let Ok(mut writer) = self.writer.lock() else {
+            return;
+        };
+        let Some(stream) = writer.as_mut() else {
+            return;
+        };
+        let mut line = event.to_string();
+        line.push('\n');
+        // Written whole & flushed, so that the agent sees each event as it happens.
+        if let Err(error) = stream
+            .write_all(line.as_bytes())
+            .and_then(|()| stream.flush())
+        {
+            eprintln!("release-phase warning: streaming events stopped, {error}");
+            *writer = None;
+        }
+    }
+}
+
+// Connects to the unix socket, which only Unix has.
+#[cfg(unix)]
+fn connect_socket(path: &OsStr) -> io::Result<EventStream> {
+    std::os::unix::net::UnixStream::connect(path).map(EventStream::new)
+}
+
+#[cfg(not(unix))]
+fn connect_socket(_path: &OsStr) -> io::Result<EventStream> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "unix sockets are not supported on this platform",
+    ))
+}
+
+fn step_event(event: &str, step: &Step) -> Value {
+    json!({
+        "event": event,
+        "step": step.number,
+        "total": step.total,
+        "kind": step.kind,
+        "command": step.executable.to_string(),
+        "time_ms": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| u64::try_from(time.as_millis()).unwrap_or_default()),
+    })
+}
+
+// The tests stream events to unix sockets.
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use std::{
+        env,
+        io::{self, BufRead, BufReader, Write},
+        os::unix::{net::UnixListener, process::ExitStatusExt},
+        process::ExitStatus,
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::Value;
+
+    use super::EventStream;
+    use crate::{
+        execute::{Step, StepOutcome},
+        Executable,
+    };
+
+    // Collects what is written, shared with the test.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn events(&self) -> Vec<Value> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    // Fails every write.
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn migrate() -> Executable {
+        Executable {
+            command: "rake".to_string(),
+            args: Some(vec!["db:migrate".to_string()]),
+            ..Executable::default()
+        }
+    }
+
+    #[test]
+    fn step_events_are_json_lines() {
+        let buffer = SharedBuffer::default();
+        let events = EventStream::new(buffer.clone());
+        let executable = migrate();
+        let step = Step {
+            number: 2,
+            total: 3,
+            kind: "release command",
+            executable: &executable,
+        };
+
+        events.step_started(&step);
+        events.step_finished(&step, &StepOutcome::Exited(ExitStatus::from_raw(1 << 8)));
+        events.step_finished(&step, &StepOutcome::AlreadyCompleted);
+
+        let events = buffer.events();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["event"], "started");
+        assert_eq!(events[0]["step"], 2);
+        assert_eq!(events[0]["total"], 3);
+        assert_eq!(events[0]["command"], "rake db:migrate");
+        assert!(events[0]["time_ms"].as_u64().unwrap() > 0);
+        assert_eq!(events[1]["event"], "failed");
+        assert_eq!(events[1]["status"], 1);
+        assert_eq!(events[2]["event"], "skipped");
+        assert_eq!(events[2]["reason"], "already completed");
+    }
+
+    #[test]
+    fn step_events_include_terminating_signal() {
+        let buffer = SharedBuffer::default();
+        let events = EventStream::new(buffer.clone());
+        let executable = migrate();
+        let step = Step {
+            number: 1,
+            total: 1,
+            kind: "release command",
+            executable: &executable,
+        };
+
+        events.step_finished(&step, &StepOutcome::Exited(ExitStatus::from_raw(9)));
+
+        let events = buffer.events();
+        assert_eq!(events[0]["event"], "failed");
+        assert_eq!(events[0]["status"], Value::Null);
+        assert_eq!(events[0]["signal"], 9);
+    }
+
+    #[test]
+    fn step_events_stop_after_failed_write() {
+        let events = EventStream::new(BrokenPipe);
+        let executable = migrate();
+        let step = Step {
+            number: 1,
+            total: 1,
+            kind: "release command",
+            executable: &executable,
+        };
+
+        events.step_started(&step);
+
+        assert!(events.writer.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn step_events_stream_to_socket() {
+        let socket_path = env::temp_dir().join(format!("events-{}.sock", std::process::id()));
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let events =
+            EventStream::new(std::os::unix::net::UnixStream::connect(&socket_path).unwrap());
+        let (connection, _) = listener.accept().unwrap();
+        let executable = migrate();
+        let step = Step {
+            number: 1,
+            total: 1,
+            kind: "release command",
+            executable: &executable,
+        };
+
+        events.step_started(&step);
+        let mut line = String::new();
+        BufReader::new(connection).read_line(&mut line).unwrap();
+        std::fs::remove_file(&socket_path).unwrap();
+
+        let event: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(event["event"], "started");
+    }
+}
@@ -0,0 +1,141 @@
+//! Stable JSON of resolved plans & execution reports, each with a `schema-version`.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{execute::exit_signal, ExecutionReport, ResolvedPlan, StepOutcome, StepReport};
+
+/// The `schema-version` of `ResolvedPlan::to_json`, incremented when a field is removed, or its
+/// meaning changes. Fields may be added within a version.
+pub const PLAN_SCHEMA_VERSION: u32 = 1;
+
+/// The `schema-version` of `ExecutionReport::to_json`.
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Versioned<'a, T: Serialize> {
+    #[serde(rename = "schema-version")]
+    schema_version: u32,
+    #[serde(flatten)]
+    document: &'a T,
+}
+
+impl ResolvedPlan {
+    /// The plan as JSON, with its `schema-version`, its `steps`, & each step's `provenance`.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(&Versioned {
+            schema_version: PLAN_SCHEMA_VERSION,
+            document: self,
+        })
+    }
+}
+
+impl ExecutionReport {
+    /// The report as JSON, with its `schema-version`, the `status` of the sequence, `succeeded`
+    /// or `failed`, its `exit-code` & `error`, and the `outcome` of each step that got to run.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        let report = json!({
+            "schema-version": REPORT_SCHEMA_VERSION,
+            "status": if self.result.is_ok() { "succeeded" } else { "failed" },
+            "exit-code": self.exit_code(),
+            "error": self.result.as_ref().err().map(ToString::to_string),
+            "steps": self.steps.iter().map(step_json).collect::<Vec<Value>>(),
+        });
+        serde_json::to_string_pretty(&report)
+    }
+}
+
+fn step_json(step: &StepReport) -> Value {
+    let (outcome, status, signal) = match &step.outcome {
+        StepOutcome::Exited(status) if status.success() => ("finished", status.code(), None),
+        // Without a status code, when the process was terminated by a signal.
+        StepOutcome::Exited(status) => ("failed", status.code(), exit_signal(*status)),
+        StepOutcome::ExecFailed => ("failed-to-start", None, None),
+        StepOutcome::AlreadyCompleted => ("already-completed", None, None),
+        StepOutcome::Skipped => ("skipped", None, None),
+    };
+    json!({
+        "number": step.number,
+        "kind": step.kind,
+        "command": step.executable,
+        "outcome": outcome,
+        "status": status,
+        "signal": signal,
+    })
+}
+
+// The tests report processes terminated by signals, which only Unix has.
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+    use serde_json::{json, Value};
+    use toml::toml;
+
+    use crate::{simulate, Error, Executable, ExecutionReport, StepOutcome, StepReport};
+
+    #[test]
+    fn plan_to_json_is_versioned() {
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "rake"
+            args = ["db:migrate"]
+        }
+        .into();
+        let plan = simulate(&project_config, toml::Table::new()).unwrap();
+        let json: Value = serde_json::from_str(&plan.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "schema-version": 1,
+                "steps": [{
+                    "number": 1,
+                    "kind": "release command",
+                    "after": [],
+                    "provenance": "project",
+                    "command": "rake",
+                    "args": ["db:migrate"],
+                    "source": null,
+                }],
+                "artifacts": false,
+                "artifact-channels": {},
+            })
+        );
+    }
+
+    #[test]
+    fn report_to_json_is_versioned() {
+        let executable = |command: &str| Executable {
+            command: command.to_string(),
+            ..Executable::default()
+        };
+        let report = ExecutionReport {
+            steps: vec![
+                StepReport {
+                    number: 1,
+                    kind: "release command",
+                    executable: executable("migrate"),
+                    outcome: StepOutcome::AlreadyCompleted,
+                },
+                StepReport {
+                    number: 2,
+                    kind: "release command",
+                    executable: executable("seed"),
+                    outcome: StepOutcome::Exited(ExitStatus::from_raw(9)),
+                },
+            ],
+            result: Err(Error::ReleaseCommandExitedError("seed".to_string())),
+        };
+        let json: Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["schema-version"], json!(1));
+        assert_eq!(json["status"], json!("failed"));
+        assert_eq!(json["exit-code"], json!(137));
+        assert_eq!(json["error"], json!("Command exited with error, seed"));
+        assert_eq!(json["steps"][0]["outcome"], json!("already-completed"));
+        assert_eq!(json["steps"][1]["command"]["command"], json!("seed"));
+        assert_eq!(json["steps"][1]["outcome"], json!("failed"));
+        assert_eq!(json["steps"][1]["status"], Value::Null);
+        assert_eq!(json["steps"][1]["signal"], json!(9));
+    }
+}
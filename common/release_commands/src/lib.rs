@@ -1,19 +1,69 @@
 use std::{
+    collections::{BTreeMap, HashMap},
+    env,
     fmt::{self, Debug},
-    path::Path,
+    fs,
+    hash::BuildHasher,
+    path::{Component, Path, PathBuf},
+    process::{self, Command},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
 };
 
-use libcnb::{read_toml_file, write_toml_file, TomlFileError};
+use libcnb_common::toml_file::{read_toml_file, TomlFileError};
 use libherokubuildpack::toml::toml_select_value;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Eq, PartialEq, Debug, Default, Clone)]
+mod args;
+mod deadline;
+mod events;
+mod execute;
+mod export;
+mod lint;
+mod render;
+mod schema;
+mod simulate;
+mod verify;
+
+pub use args::{take_flag, take_option};
+pub use deadline::Deadline;
+pub use events::EventStream;
+pub use execute::{
+    environment_snapshot, execute, execute_with, CommandRunner, ExecHooks, ExecOptions,
+    ExecutionReport, ProcessRunner, Step, StepAction, StepOutcome, StepOutput, StepReport,
+};
+pub use export::{PLAN_SCHEMA_VERSION, REPORT_SCHEMA_VERSION};
+pub use lint::lint_project_config;
+pub use schema::project_config_json_schema;
+pub use simulate::{simulate, Provenance, ResolvedCommand, ResolvedPlan, ResolvedStep};
+pub use verify::{commands_digest_path, verify_commands_config, CommandsVerification};
+
+#[derive(Deserialize, Serialize, JsonSchema, Eq, PartialEq, Debug, Default, Clone)]
 pub struct ReleaseCommands {
     #[serde(rename = "release-build")]
     pub release_build: Option<Executable>,
     pub release: Option<Vec<Executable>>,
+    #[serde(rename = "artifact-channels")]
+    pub artifact_channels: Option<BTreeMap<String, ArtifactChannel>>,
+    pub storage: Option<BTreeMap<String, StorageProfile>>,
+    pub artifacts: Option<ArtifactsConfig>,
+    /// Only save & load release artifacts, such as those built during the app's own build,
+    /// without any release commands.
+    #[serde(rename = "artifacts-only")]
+    pub artifacts_only: Option<bool>,
+    /// The dir, relative to the app dir, whose contents are saved & loaded as the default
+    /// release artifacts, instead of `static-artifacts/`, such as a framework's `public/` or
+    /// `dist/` output.
+    #[serde(rename = "artifact-dir")]
+    pub artifact_dir: Option<String>,
 }
 
+/// The dir of the default release artifacts, when `artifact-dir` is not configured.
+pub const DEFAULT_ARTIFACT_DIR: &str = "static-artifacts";
+
 impl fmt::Display for ReleaseCommands {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -29,11 +79,407 @@ impl fmt::Display for ReleaseCommands {
     }
 }
 
-#[derive(Deserialize, Serialize, Eq, PartialEq, Debug, Default, Clone)]
+impl ReleaseCommands {
+    // Merges a `release-commands.d` fragment into this config: its release commands follow
+    // these, and its artifact channels, storage profiles, & artifacts config are added unless
+    // already configured. The release-build command may only come from the app or Build Plan,
+    // because the command that saves its artifacts is generated at build.
+    fn merge_fragment(&mut self, fragment: ReleaseCommands, path: &Path) -> Result<(), Error> {
+        if fragment.release_build.is_some() {
+            return Err(Error::ReleaseCommandsFragmentInvalid(
+                path.display().to_string(),
+            ));
+        }
+        for executable in fragment.release.iter().flatten() {
+            executable.validate()?;
+            self.guard_artifacts_only(executable)?;
+        }
+        if let Some(release) = fragment.release {
+            self.release.get_or_insert_with(Vec::new).extend(release);
+        }
+        for (name, channel) in fragment.artifact_channels.into_iter().flatten() {
+            if !is_valid_name(&name) {
+                return Err(Error::ArtifactChannelNameInvalid(name));
+            }
+            self.artifact_channels
+                .get_or_insert_with(BTreeMap::new)
+                .entry(name)
+                .or_insert(channel);
+        }
+        for (name, profile) in fragment.storage.into_iter().flatten() {
+            self.storage
+                .get_or_insert_with(BTreeMap::new)
+                .entry(name)
+                .or_insert(profile);
+        }
+        if self.artifacts.is_none() {
+            self.artifacts = fragment.artifacts;
+        }
+        Ok(())
+    }
+
+    /// The dir of the default release artifacts, relative to the app dir.
+    #[must_use]
+    pub fn artifact_dir(&self) -> &str {
+        self.artifact_dir.as_deref().unwrap_or(DEFAULT_ARTIFACT_DIR)
+    }
+
+    /// Whether release artifacts are saved & loaded, for a release-build command, or when
+    /// `artifacts-only`.
+    #[must_use]
+    pub fn has_artifacts(&self) -> bool {
+        self.release_build.is_some() || self.is_artifacts_only()
+    }
+
+    /// Whether any command declares an `idempotency-key`, whose completion is recorded in
+    /// artifact storage.
+    #[must_use]
+    pub fn uses_idempotency_keys(&self) -> bool {
+        self.release_build
+            .iter()
+            .chain(self.release.iter().flatten())
+            .any(|executable| executable.idempotency_key.is_some())
+    }
+
+    fn is_artifacts_only(&self) -> bool {
+        self.artifacts_only == Some(true)
+    }
+
+    fn guard_artifacts_only(&self, executable: &Executable) -> Result<(), Error> {
+        if self.is_artifacts_only() {
+            return Err(Error::ArtifactsOnlyReleaseCommand(executable.to_string()));
+        }
+        Ok(())
+    }
+
+    // Checks that command names are unique, and that each command only depends on commands
+    // before it, so that the dependencies cannot form a cycle.
+    fn validate_dependencies(&self) -> Result<(), Error> {
+        let mut names = vec![];
+        for executable in self
+            .release_build
+            .iter()
+            .chain(self.release.iter().flatten())
+        {
+            for dependency in executable.depends_on.iter().flatten() {
+                if !names.contains(&dependency) {
+                    return Err(Error::CommandDependencyInvalid(dependency.clone()));
+                }
+            }
+            if let Some(name) = &executable.name {
+                if !is_valid_name(name) || names.contains(&name) {
+                    return Err(Error::CommandNameInvalid(name.clone()));
+                }
+                names.push(name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the sequence executed during Release Phase as a single shell command line,
+    /// such as for a Procfile `release` process.
+    #[must_use]
+    pub fn to_shell_command(&self) -> String {
+        let executables: Vec<&Executable> = self
+            .release_build
+            .iter()
+            .filter(|b| !b.runs_at_build())
+            .chain(self.release.iter().flatten())
+            .collect();
+        if executables
+            .iter()
+            .all(|e| e.run_on.unwrap_or_default() == RunOn::Success)
+        {
+            return executables
+                .iter()
+                .map(|e| e.to_shell_command())
+                .collect::<Vec<_>>()
+                .join(" && ");
+        }
+        // The outcome is kept in `$s`, so that the sequence continues after a failure with the
+        // commands that run on it, and still exits with the status of the first failure.
+        let steps: Vec<String> = executables
+            .iter()
+            .map(|e| {
+                let command = e.to_shell_command();
+                match e.run_on.unwrap_or_default() {
+                    RunOn::Success => format!("[ $s -ne 0 ] || {command} || s=$?"),
+                    RunOn::Failure => format!("[ $s -eq 0 ] || {command}"),
+                    RunOn::Always => format!("{command} || {{ r=$?; [ $s -ne 0 ] || s=$r; }}"),
+                }
+            })
+            .collect();
+        format!("s=0; {}; exit $s", steps.join("; "))
+    }
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Eq, PartialEq, Debug, Default, Clone)]
 pub struct Executable {
     pub command: String,
     pub args: Option<Vec<String>>,
     pub source: Option<String>,
+    #[serde(rename = "run-at", skip_serializing_if = "Option::is_none")]
+    pub run_at: Option<RunAt>,
+    /// Names the command's completion when a release is rerun, so that it is skipped if it
+    /// already succeeded for the release, such as a migration before a failed save.
+    #[serde(rename = "idempotency-key", skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Which outcome of the commands before it the command runs on: `success` (the default),
+    /// `failure`, such as for alerting, or `always`, such as for cleanup.
+    #[serde(rename = "run-on", skip_serializing_if = "Option::is_none")]
+    pub run_on: Option<RunOn>,
+    /// Names the command, for the `depends-on` of commands after it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The named commands before it that the command waits for, rather than the command just
+    /// before it, so that it runs alongside any other commands whose dependencies are done. It
+    /// still waits for the release-build command & the saving of artifacts.
+    #[serde(rename = "depends-on", skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<String>>,
+    /// Runs the command with a pseudo-terminal as its stdin & output, for tools that prompt, or
+    /// render progress, differently without a TTY. Its output is still captured & streamed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tty: Option<bool>,
+    /// Input piped to the command, like `{ file = "db/seed.sql" }` or `{ text = "yes" }`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<Stdin>,
+    #[serde(flatten)]
+    pub limits: ResourceLimits,
+}
+
+/// Input piped to a command, so that commands like `psql < seed.sql` need no shell wrapper.
+#[derive(Deserialize, Serialize, JsonSchema, Eq, PartialEq, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Stdin {
+    /// A file, relative to the app dir.
+    File(String),
+    /// Inline text, piped as is.
+    Text(String),
+}
+
+impl Stdin {
+    // Redirects the input of the shell command.
+    fn redirect(&self, command: &str) -> String {
+        match self {
+            Stdin::File(path) => format!("{command} < {}", shell_quote(path)),
+            Stdin::Text(text) => format!("printf '%s' {} | {command}", shell_quote(text)),
+        }
+    }
+}
+
+/// Resource limits applied to a command before it is executed, so that a runaway command
+/// cannot exhaust the dyno, and fail the commands that follow it.
+#[derive(Deserialize, Serialize, JsonSchema, Eq, PartialEq, Debug, Default, Clone)]
+pub struct ResourceLimits {
+    /// Maximum data memory, the heap & other private writable memory, in bytes, or with a `K`,
+    /// `M`, or `G` suffix, like `"512M"`. Address space reserved without being writable, as by
+    /// Node & Go, is not counted.
+    #[serde(rename = "max-memory", skip_serializing_if = "Option::is_none")]
+    pub max_memory: Option<String>,
+    /// Scheduling priority, from `-20` (highest) to `19` (lowest).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+    #[serde(rename = "max-open-files", skip_serializing_if = "Option::is_none")]
+    pub max_open_files: Option<u64>,
+}
+
+impl ResourceLimits {
+    fn is_empty(&self) -> bool {
+        self == &ResourceLimits::default()
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(max_memory) = &self.max_memory {
+            if parse_memory_size(max_memory).is_none() {
+                return Err(Error::ResourceLimitInvalid(format!(
+                    "max-memory = \"{max_memory}\""
+                )));
+            }
+        }
+        if let Some(nice) = self.nice {
+            if !(-20..=19).contains(&nice) {
+                return Err(Error::ResourceLimitInvalid(format!("nice = {nice}")));
+            }
+        }
+        Ok(())
+    }
+
+    // Shell script applying the limits, and then replacing itself with the command given as its
+    // arguments, with its input, so that no unsafe pre-exec hook is needed.
+    fn to_shell_script(&self, stdin: Option<&Stdin>) -> String {
+        let mut script = vec![];
+        if let Some(bytes) = self.max_memory.as_deref().and_then(parse_memory_size) {
+            script.push(format!("ulimit -d {}", bytes / 1024));
+        }
+        if let Some(max_open_files) = self.max_open_files {
+            script.push(format!("ulimit -n {max_open_files}"));
+        }
+        let exec = match self.nice {
+            Some(nice) => format!("exec nice -n {nice} \"$0\" \"$@\""),
+            None => "exec \"$0\" \"$@\"".to_string(),
+        };
+        script.push(match stdin {
+            Some(stdin) => stdin.redirect(&exec),
+            None => exec,
+        });
+        script.join(" && ")
+    }
+}
+
+fn parse_memory_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (number, multiplier) = match size.chars().last()?.to_ascii_uppercase() {
+        'K' => (&size[..size.len() - 1], 1024),
+        'M' => (&size[..size.len() - 1], 1024 * 1024),
+        'G' => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (size, 1),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|bytes| *bytes > 0)
+}
+
+/// When the release-build command runs: during release (the default), or during CNB build,
+/// for asset builds that do not need runtime config.
+#[derive(Deserialize, Serialize, JsonSchema, Eq, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RunAt {
+    Build,
+    Release,
+}
+
+/// Which outcome of the release sequence so far a release command runs on. After a failure,
+/// the sequence continues with the commands that run on `failure` or `always`, still failing.
+#[derive(Deserialize, Serialize, JsonSchema, Eq, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RunOn {
+    #[default]
+    Success,
+    Failure,
+    Always,
+}
+
+impl fmt::Display for RunOn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RunOn::Success => "success",
+            RunOn::Failure => "failure",
+            RunOn::Always => "always",
+        })
+    }
+}
+
+impl Executable {
+    #[must_use]
+    pub fn runs_at_build(&self) -> bool {
+        self.run_at == Some(RunAt::Build)
+    }
+
+    /// Whether the command runs, given whether a command before it failed.
+    #[must_use]
+    pub fn runs_on(&self, failed: bool) -> bool {
+        match self.run_on.unwrap_or_default() {
+            RunOn::Success => !failed,
+            RunOn::Failure => failed,
+            RunOn::Always => true,
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(key) = &self.idempotency_key {
+            if !is_valid_name(key) {
+                return Err(Error::IdempotencyKeyInvalid(key.clone()));
+            }
+        }
+        self.limits.validate()
+    }
+
+    /// Whether the process of this command can start: with `tty`, it needs the `script` of
+    /// util-linux, whose options `to_process` uses.
+    pub fn check_process(&self) -> Result<(), Error> {
+        if self.tty == Some(true) && !has_util_linux_script() {
+            return Err(Error::TtyUnsupported(self.command.clone()));
+        }
+        Ok(())
+    }
+
+    /// Creates the process for this command, with its resource limits applied.
+    #[must_use]
+    pub fn to_process(&self) -> Command {
+        let args = self.args.clone().unwrap_or_default();
+        let mut words = if self.limits.is_empty() && self.stdin.is_none() {
+            vec![self.command.clone()]
+        } else {
+            vec![
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                self.limits.to_shell_script(self.stdin.as_ref()),
+                self.command.clone(),
+            ]
+        };
+        words.extend(args);
+        if self.tty == Some(true) {
+            // `script` runs the command line with its shell, under a pseudo-terminal whose
+            // output it copies to its own, exiting with the command's status.
+            let command_line = words
+                .iter()
+                .map(|word| shell_quote(word))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let mut cmd = Command::new("script");
+            cmd.args([
+                "--quiet",
+                "--return",
+                "--command",
+                &command_line,
+                "/dev/null",
+            ])
+            .env("SHELL", "/bin/sh");
+            return cmd;
+        }
+        let mut cmd = Command::new(&words[0]);
+        cmd.args(&words[1..]);
+        cmd
+    }
+
+    #[must_use]
+    pub fn to_shell_command(&self) -> String {
+        let command = std::iter::once(&self.command)
+            .chain(self.args.iter().flatten())
+            .map(|word| shell_quote(word))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match &self.stdin {
+            Some(stdin) => stdin.redirect(&command),
+            None => command,
+        }
+    }
+}
+
+// Whether the `script` on the PATH is util-linux's. BSD's, as on macOS, & BusyBox's take other
+// options, so they would fail, or run the command without a tty. Checked once per process.
+fn has_util_linux_script() -> bool {
+    static UTIL_LINUX_SCRIPT: OnceLock<bool> = OnceLock::new();
+    *UTIL_LINUX_SCRIPT.get_or_init(|| {
+        Command::new("script")
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("util-linux"))
+    })
+}
+
+fn shell_quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c))
+    {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
 }
 
 impl fmt::Display for Executable {
@@ -52,6 +498,67 @@ impl fmt::Display for Executable {
     }
 }
 
+/// A named set of release artifacts, saved & loaded independently of the default
+/// `static-artifacts/` and of other channels.
+#[derive(Deserialize, Serialize, JsonSchema, Eq, PartialEq, Debug, Default, Clone)]
+pub struct ArtifactChannel {
+    pub dir: String,
+    #[serde(rename = "key-prefix")]
+    pub key_prefix: Option<String>,
+}
+
+/// Options for handling the default `static-artifacts/`.
+#[derive(Deserialize, Serialize, JsonSchema, Eq, PartialEq, Debug, Default, Clone)]
+pub struct ArtifactsConfig {
+    /// Download the latest archive during build, so that the first boot may skip it.
+    pub prefetch: Option<bool>,
+    /// Env vars to append the loaded artifacts' path to, in addition to `STATIC_ARTIFACTS_PATH`.
+    #[serde(rename = "path-env")]
+    pub path_env: Option<Vec<String>>,
+    /// Absolute path to extract the loaded artifacts into, instead of the app dir, such as
+    /// when it is mounted read-only at runtime.
+    #[serde(rename = "extract-dir")]
+    pub extract_dir: Option<String>,
+    /// Glob patterns of the paths to archive, relative to `static-artifacts/`, instead of all.
+    pub include: Option<Vec<String>>,
+    /// Glob patterns of the paths to leave out of the archive, such as `node_modules`.
+    pub exclude: Option<Vec<String>>,
+}
+
+/// The storage profile applied when `STATIC_ARTIFACTS_PROFILE` & `STATIC_ARTIFACTS_URL` are not
+/// set, such as a platform-managed bucket inherited from the Build Plan.
+pub const DEFAULT_STORAGE_PROFILE: &str = "default";
+
+/// Artifact storage config, selected at runtime by name with `STATIC_ARTIFACTS_PROFILE`, or else
+/// the `default` profile.
+///
+/// Credentials are not stored in config, instead the profile may name the env vars that
+/// hold them.
+#[derive(Deserialize, Serialize, JsonSchema, Eq, PartialEq, Debug, Default, Clone)]
+pub struct StorageProfile {
+    pub url: Option<String>,
+    pub region: Option<String>,
+    #[serde(rename = "access-key-id-var")]
+    pub access_key_id_var: Option<String>,
+    #[serde(rename = "secret-access-key-var")]
+    pub secret_access_key_var: Option<String>,
+    /// The pipeline stage that archives saved with this profile are tagged with.
+    pub stage: Option<String>,
+    pub gc: Option<GcConfig>,
+}
+
+/// Garbage collection of old archives, as a `[gc]` table in a storage profile.
+#[derive(Deserialize, Serialize, JsonSchema, Eq, PartialEq, Debug, Default, Clone)]
+pub struct GcConfig {
+    /// The number of newest archives to keep, of those without a stage count.
+    pub retain: Option<usize>,
+    /// The number of newest archives to keep for each pipeline stage.
+    pub stages: Option<BTreeMap<String, usize>>,
+    /// The days within which archives are kept, even beyond the counts.
+    #[serde(rename = "retain-days")]
+    pub retain_days: Option<u64>,
+}
+
 #[derive(Debug)]
 pub enum Error {
     ReleaseCommandsMustBeArray,
@@ -64,6 +571,22 @@ pub enum Error {
     TomlWriteReleaseCommandsFileError(TomlFileError),
     ReleaseCommandExecError(std::io::Error),
     ReleaseCommandExitedError(String),
+    ReleaseSequenceStopped(usize),
+    ReleaseCommandsFragmentInvalid(String),
+    ArtifactsOnlyReleaseCommand(String),
+    ArtifactChannelNameInvalid(String),
+    ArtifactChannelNotConfigured(String),
+    ArtifactDirInvalid(String),
+    ArtifactsPrefetchWithoutBuild,
+    StorageProfileNotConfigured(String),
+    StorageProfileConflict(String, String),
+    ResourceLimitInvalid(String),
+    IdempotencyKeyInvalid(String),
+    CommandNameInvalid(String),
+    CommandDependencyInvalid(String),
+    DurationInvalid(String),
+    ReleaseSequenceTimedOut(std::time::Duration),
+    TtyUnsupported(String),
 }
 
 impl fmt::Display for Error {
@@ -107,6 +630,70 @@ impl fmt::Display for Error {
             Error::ReleaseCommandExitedError(error) => {
                 write!(f, "Command exited with error, {error}")
             }
+            Error::ReleaseSequenceStopped(step) => {
+                write!(f, "Release sequence stopped before step {step}, by request.")
+            }
+            Error::ReleaseCommandsFragmentInvalid(path) => write!(
+                f,
+                "Configuration of `release-build` is not allowed in `{path}`, only in `project.toml` or the Build Plan."
+            ),
+            Error::ArtifactChannelNameInvalid(name) => write!(
+                f,
+                "Artifact channel name `{name}` is invalid, it may only contain letters, numbers, `-`, and `_`."
+            ),
+            Error::ArtifactChannelNotConfigured(name) => write!(
+                f,
+                "Artifact channel `{name}` is not configured in `release-commands.toml`."
+            ),
+            Error::ArtifactDirInvalid(dir) => write!(
+                f,
+                "Artifact dir `{dir}` is invalid, it must be a dir within the app dir, such as `public`."
+            ),
+            Error::ArtifactsPrefetchWithoutBuild => write!(
+                f,
+                "Artifacts `prefetch = true` requires a `release-build` command with `run-at = \"build\"`, otherwise the prefetched archive is the previous release's, which is never loaded."
+            ),
+            Error::StorageProfileNotConfigured(name) => write!(
+                f,
+                "Storage profile `{name}` (from STATIC_ARTIFACTS_PROFILE) is not configured in `release-commands.toml`."
+            ),
+            Error::StorageProfileConflict(name, var) => write!(
+                f,
+                "Storage profile `{name}` sets {var}, which is already set to another value. Unset {var}, or select another profile, so that one bucket's location & credentials are never mixed with another's."
+            ),
+            Error::ResourceLimitInvalid(limit) => write!(
+                f,
+                "Configuration of resource limit `{limit}` is invalid."
+            ),
+            Error::IdempotencyKeyInvalid(key) => write!(
+                f,
+                "Idempotency key `{key}` is invalid, it may only contain letters, numbers, `-`, and `_`."
+            ),
+            Error::CommandNameInvalid(name) => write!(
+                f,
+                "Command name `{name}` is invalid, it must be unique, and may only contain letters, numbers, `-`, and `_`."
+            ),
+            Error::CommandDependencyInvalid(name) => write!(
+                f,
+                "Command dependency `{name}` is invalid, it must name a command before the one that depends on it."
+            ),
+            Error::ArtifactsOnlyReleaseCommand(command) => write!(
+                f,
+                "Release command `{command}` is not allowed with `artifacts-only = true`, which only saves & loads release artifacts."
+            ),
+            Error::DurationInvalid(duration) => write!(
+                f,
+                "Duration `{duration}` is invalid, it must be seconds, or with an `s`, `m`, or `h` suffix."
+            ),
+            Error::ReleaseSequenceTimedOut(max_duration) => write!(
+                f,
+                "Release sequence exceeded its max duration of {}s.",
+                max_duration.as_secs()
+            ),
+            Error::TtyUnsupported(command) => write!(
+                f,
+                "Command `{command}` sets `tty = true`, which requires the `script` command of util-linux, but the `script` on the PATH is missing or another implementation, such as BSD's or BusyBox's. Install util-linux, or remove `tty`."
+            ),
         }
     }
 }
@@ -117,19 +704,21 @@ pub fn generate_commands_config(
 ) -> Result<ReleaseCommands, Error> {
     // Extract the namespaced keys from project.toml
     let mut project_commands = toml::Table::new();
-    if let Some(release_config) =
-        toml_select_value(vec!["com", "heroku", "phase", "release"], project_config).cloned()
-    {
-        project_commands.insert("release".to_string(), release_config);
-    };
-    if let Some(release_build_config) = toml_select_value(
-        vec!["com", "heroku", "phase", "release-build"],
-        project_config,
-    )
-    .cloned()
-    {
-        project_commands.insert("release-build".to_string(), release_build_config);
-    };
+    for key in [
+        "release",
+        "release-build",
+        "storage",
+        "artifacts",
+        "artifact-channels",
+        "artifacts-only",
+        "artifact-dir",
+    ] {
+        if let Some(config) =
+            toml_select_value(vec!["com", "heroku", "phase", key], project_config).cloned()
+        {
+            project_commands.insert(key.to_string(), config);
+        }
+    }
 
     // Create main command config from project
     let mut commands = project_commands
@@ -153,20 +742,253 @@ pub fn generate_commands_config(
         commands.release_build = inherited_commands.release_build;
     }
 
+    if commands.artifacts_only.is_none() {
+        commands.artifacts_only = inherited_commands.artifacts_only;
+    }
+
+    if commands.artifact_dir.is_none() {
+        commands.artifact_dir = inherited_commands.artifact_dir;
+    }
+    if let Some(artifact_dir) = &commands.artifact_dir {
+        if !is_valid_artifact_dir(artifact_dir) {
+            return Err(Error::ArtifactDirInvalid(artifact_dir.clone()));
+        }
+    }
+
+    // Combine inherited + project storage profiles, project profiles take precedence by name
+    if let Some(inherited) = inherited_commands.storage {
+        let mut profiles = inherited;
+        profiles.extend(commands.storage.unwrap_or_default());
+        commands.storage = Some(profiles);
+    }
+
+    // Combine inherited + project artifact channels, project channels take precedence by name
+    if let Some(inherited) = inherited_commands.artifact_channels {
+        let mut channels = inherited;
+        channels.extend(commands.artifact_channels.unwrap_or_default());
+        commands.artifact_channels = Some(channels);
+    }
+    if let Some(channels) = commands.artifact_channels.as_mut() {
+        for (name, channel) in channels.iter_mut() {
+            if !is_valid_name(name) {
+                return Err(Error::ArtifactChannelNameInvalid(name.clone()));
+            }
+            channel.key_prefix.get_or_insert_with(|| name.clone());
+        }
+    }
+
+    for executable in commands
+        .release_build
+        .iter()
+        .chain(commands.release.iter().flatten())
+    {
+        executable.validate()?;
+    }
+    for executable in commands.release.iter().flatten() {
+        commands.guard_artifacts_only(executable)?;
+    }
+    // Only an archive saved during build is still the one to load when first booting.
+    if commands.artifacts.as_ref().and_then(|a| a.prefetch) == Some(true)
+        && !commands
+            .release_build
+            .as_ref()
+            .is_some_and(Executable::runs_at_build)
+    {
+        return Err(Error::ArtifactsPrefetchWithoutBuild);
+    }
+
     // When Release Build is defined, add the artifacts saver exec as the first release command, immediately after release-build
-    if commands.release_build.is_some() {
-        let save_exec = Executable {
+    // (unless release-build runs during CNB build, which saves the artifacts itself)
+    // When artifacts-only without Release Build, the artifacts saved are those of the app's build
+    let saves_artifacts = match &commands.release_build {
+        Some(release_build) => !release_build.runs_at_build(),
+        None => commands.is_artifacts_only(),
+    };
+    if saves_artifacts {
+        let mut save_execs = vec![Executable {
             command: "save-release-artifacts".to_string(),
-            args: Some(vec!["static-artifacts/".to_string()]),
+            args: Some(vec![format!(
+                "{}/",
+                commands.artifact_dir().trim_end_matches('/')
+            )]),
             source: Some("Heroku Release Phase Buildpack".to_string()),
-        };
-        commands.release = Some([vec![save_exec], commands.release.map_or(vec![], |v| v)].concat());
+            ..Executable::default()
+        }];
+        // followed by a saver exec for each artifact channel
+        for (name, channel) in commands.artifact_channels.iter().flatten() {
+            save_execs.push(Executable {
+                command: "save-release-artifacts".to_string(),
+                args: Some(vec![
+                    "--channel".to_string(),
+                    name.clone(),
+                    channel.dir.clone(),
+                ]),
+                source: Some("Heroku Release Phase Buildpack".to_string()),
+                ..Executable::default()
+            });
+        }
+        commands.release = Some([save_execs, commands.release.map_or(vec![], |v| v)].concat());
     }
+    commands.validate_dependencies()?;
 
     Ok(commands)
 }
 
+// The artifact dir must be within the app dir, and not the app dir itself, which would archive
+// the app's source along with its artifacts.
+fn is_valid_artifact_dir(dir: &str) -> bool {
+    let path = Path::new(dir);
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Finds an artifact channel by name in the given release-commands.toml.
+pub fn find_artifact_channel(
+    commands_toml_path: &Path,
+    name: &str,
+) -> Result<ArtifactChannel, Error> {
+    read_commands_config(commands_toml_path)?
+        .artifact_channels
+        .and_then(|mut channels| channels.remove(name))
+        .map(|mut channel| {
+            channel.key_prefix.get_or_insert_with(|| name.to_string());
+            channel
+        })
+        .ok_or_else(|| Error::ArtifactChannelNotConfigured(name.to_string()))
+}
+
+/// Applies the storage profile named by `STATIC_ARTIFACTS_PROFILE` in the given env, from the
+/// given release-commands.toml. Storage env vars already set take precedence over the profile,
+/// except the URL & credentials, which fail with `Error::StorageProfileConflict` when already set
+/// to other values than the profile's.
+///
+/// Without `STATIC_ARTIFACTS_PROFILE`, applies the `default` profile, if configured, unless
+/// `STATIC_ARTIFACTS_URL` is set, so that storage configured for the app is never mixed with the
+/// default's credentials.
+pub fn apply_storage_profile<S: BuildHasher>(
+    commands_toml_path: &Path,
+    env: &mut HashMap<String, String, S>,
+) -> Result<(), Error> {
+    let (name, is_default) = match env.get("STATIC_ARTIFACTS_PROFILE") {
+        Some(name) => (name.clone(), false),
+        None if !env.contains_key("STATIC_ARTIFACTS_URL") && commands_toml_path.is_file() => {
+            (DEFAULT_STORAGE_PROFILE.to_string(), true)
+        }
+        None => return Ok(()),
+    };
+    let profile = match read_commands_config(commands_toml_path)?
+        .storage
+        .and_then(|mut profiles| profiles.remove(&name))
+    {
+        Some(profile) => profile,
+        None if is_default => return Ok(()),
+        None => return Err(Error::StorageProfileNotConfigured(name)),
+    };
+    let credential_from_var = |var: Option<String>| var.and_then(|v| env::var(v).ok());
+    let gc = profile.gc.unwrap_or_default();
+    let vars = [
+        ("STATIC_ARTIFACTS_URL", profile.url),
+        ("STATIC_ARTIFACTS_REGION", profile.region),
+        (
+            "STATIC_ARTIFACTS_ACCESS_KEY_ID",
+            credential_from_var(profile.access_key_id_var),
+        ),
+        (
+            "STATIC_ARTIFACTS_SECRET_ACCESS_KEY",
+            credential_from_var(profile.secret_access_key_var),
+        ),
+        ("STATIC_ARTIFACTS_STAGE", profile.stage),
+        (
+            "STATIC_ARTIFACTS_RETAIN_COUNT",
+            gc.retain.map(|retain| retain.to_string()),
+        ),
+        (
+            "STATIC_ARTIFACTS_RETAIN_DAYS",
+            gc.retain_days.map(|days| days.to_string()),
+        ),
+        (
+            "STATIC_ARTIFACTS_RETAIN_STAGES",
+            gc.stages.map(|stages| {
+                stages
+                    .iter()
+                    .map(|(stage, retain)| format!("{stage}={retain}"))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }),
+        ),
+    ];
+    if let Some((key, _)) = vars.iter().find(|(key, value)| {
+        STORAGE_PROFILE_EXCLUSIVE_VARS.contains(key)
+            && value
+                .as_ref()
+                .zip(env.get(*key))
+                .is_some_and(|(value, set)| value != set)
+    }) {
+        return Err(Error::StorageProfileConflict(name, (*key).to_string()));
+    }
+    for (key, value) in vars {
+        if let Some(value) = value {
+            env.entry(key.to_string()).or_insert(value);
+        }
+    }
+    Ok(())
+}
+
+// The vars of a profile that may not be overridden, so that one bucket's credentials are never
+// sent to another.
+const STORAGE_PROFILE_EXCLUSIVE_VARS: [&str; 3] = [
+    "STATIC_ARTIFACTS_URL",
+    "STATIC_ARTIFACTS_ACCESS_KEY_ID",
+    "STATIC_ARTIFACTS_SECRET_ACCESS_KEY",
+];
+
+/// Directory beside release-commands.toml, from which `*.toml` fragments are merged into its
+/// config in lexical order, so that several buildpacks may each install their own commands.
+pub const COMMANDS_FRAGMENTS_DIR: &str = "release-commands.d";
+
+/// Reads the given release-commands.toml, merged with the fragments in the `release-commands.d`
+/// directory beside it.
 pub fn read_commands_config(commands_toml_path: &Path) -> Result<ReleaseCommands, Error> {
+    let mut commands = read_commands_file(commands_toml_path)?;
+    for fragment_path in commands_fragment_paths(commands_toml_path)? {
+        let fragment = read_commands_file(&fragment_path)?;
+        commands.merge_fragment(fragment, &fragment_path)?;
+    }
+    commands.validate_dependencies()?;
+    Ok(commands)
+}
+
+// Lists the `*.toml` fragments beside the given release-commands.toml, in lexical order.
+fn commands_fragment_paths(commands_toml_path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let Some(fragments_dir) = commands_toml_path
+        .parent()
+        .map(|dir| dir.join(COMMANDS_FRAGMENTS_DIR))
+        .filter(|dir| dir.is_dir())
+    else {
+        return Ok(vec![]);
+    };
+    let mut paths = fs::read_dir(&fragments_dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| Error::TomlReleaseCommandsFileError(TomlFileError::IoError(e)))?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "toml") && path.is_file());
+    paths.sort();
+    Ok(paths)
+}
+
+fn read_commands_file(commands_toml_path: &Path) -> Result<ReleaseCommands, Error> {
     let commands_toml = if commands_toml_path.is_file() {
         read_toml_file::<toml::Value>(commands_toml_path)
             .map_err(Error::TomlReleaseCommandsFileError)?
@@ -179,26 +1001,106 @@ pub fn read_commands_config(commands_toml_path: &Path) -> Result<ReleaseCommands
         .map_err(Error::TomlReleaseCommandsDeserializeError)
 }
 
-pub fn write_commands_config(dir: &Path, commands: &ReleaseCommands) -> Result<(), Error> {
-    let commands_toml_path = dir.join("release-commands.toml");
-    write_toml_file(&commands, commands_toml_path).map_err(Error::TomlWriteReleaseCommandsFileError)
+/// Returns the path of release-commands.toml in the given dir.
+#[must_use]
+pub fn commands_toml_path(dir: &Path) -> PathBuf {
+    dir.join("release-commands.toml")
+}
+
+// Distinguishes the temp files of concurrent writes within a process.
+static WRITE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Renders the config as release-commands.toml. When given the project.toml source that it was
+/// generated from, comments & unknown keys in its `com.heroku.phase` tables are carried over,
+/// wherever the config matches what the project declared.
+pub fn render_commands_config(
+    commands: &ReleaseCommands,
+    project_toml: Option<&str>,
+) -> Result<String, Error> {
+    let rendered = toml::to_string(commands).map_err(|e| {
+        Error::TomlWriteReleaseCommandsFileError(TomlFileError::TomlSerializationError(e))
+    })?;
+    Ok(project_toml
+        .and_then(|project_toml| render::carry_over_project_toml(&rendered, project_toml))
+        .unwrap_or(rendered))
+}
+
+/// Writes release-commands.toml in the given dir, returning its path. The file is written
+/// beside it first and then renamed into place, so that readers and concurrent writers never
+/// see a partially written file. Its digest is recorded beside it, for `verify_commands_config`.
+pub fn write_commands_config(dir: &Path, commands: &ReleaseCommands) -> Result<PathBuf, Error> {
+    write_commands_toml(dir, &render_commands_config(commands, None)?)
+}
+
+/// Writes release-commands.toml in the given dir like `write_commands_config`, carrying over
+/// the comments & unknown keys of the project.toml source that it was generated from.
+pub fn write_commands_config_from_project(
+    dir: &Path,
+    commands: &ReleaseCommands,
+    project_toml: &str,
+) -> Result<PathBuf, Error> {
+    write_commands_toml(dir, &render_commands_config(commands, Some(project_toml))?)
+}
+
+fn write_commands_toml(dir: &Path, contents: &str) -> Result<PathBuf, Error> {
+    let path = commands_toml_path(dir);
+    write_atomically(dir, &path, contents)?;
+    write_atomically(
+        dir,
+        &verify::commands_digest_path(&path),
+        &verify::digest(contents.as_bytes()),
+    )?;
+    Ok(path)
+}
+
+fn write_atomically(dir: &Path, path: &Path, contents: &str) -> Result<(), Error> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = dir.join(format!(
+        ".{file_name}.{}.{}.tmp",
+        process::id(),
+        WRITE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temp_path, contents)
+        .and_then(|()| fs::rename(&temp_path, path))
+        .map_err(|e| {
+            fs::remove_file(&temp_path).unwrap_or_default();
+            Error::TomlWriteReleaseCommandsFileError(TomlFileError::IoError(e))
+        })
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::{BTreeMap, HashMap};
     use std::env;
-    use std::fs::remove_file;
-    use std::path::PathBuf;
+    use std::fs::{self, remove_file};
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
 
-    use libcnb::read_toml_file;
+    use libcnb_common::toml_file::read_toml_file;
     use libherokubuildpack::toml::toml_select_value;
     use toml::toml;
 
+    use crate::apply_storage_profile;
+    use crate::commands_digest_path;
+    use crate::commands_toml_path;
+    use crate::find_artifact_channel;
     use crate::generate_commands_config;
+    use crate::has_util_linux_script;
     use crate::read_commands_config;
+    use crate::render_commands_config;
     use crate::write_commands_config;
+    use crate::ArtifactChannel;
+    use crate::ArtifactsConfig;
+    use crate::Error;
     use crate::Executable;
+    use crate::GcConfig;
     use crate::ReleaseCommands;
+    use crate::ResourceLimits;
+    use crate::RunAt;
+    use crate::RunOn;
+    use crate::Stdin;
+    use crate::StorageProfile;
+    use crate::DEFAULT_STORAGE_PROFILE;
 
     #[test]
     fn generate_commands_config_for_project_release() {
@@ -221,11 +1123,27 @@ mod tests {
                     command: "bash".to_string(),
                     args: Some(vec!["-c".to_string(), "echo '1'".to_string()]),
                     source: None,
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 },
                 Executable {
                     command: "bash".to_string(),
                     args: Some(vec!["-c".to_string(), "echo '2'".to_string()]),
                     source: None,
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 }
             ])
         );
@@ -248,18 +1166,420 @@ mod tests {
                 command: "bash".to_string(),
                 args: Some(vec!["-c".to_string(), "echo 'test build'".to_string()]),
                 source: None,
+                run_at: None,
+                idempotency_key: None,
+                run_on: None,
+                name: None,
+                depends_on: None,
+                tty: None,
+                stdin: None,
+                limits: ResourceLimits::default(),
             })
         );
         assert_eq!(
-            result.release,
-            Some(vec![Executable {
-                command: "save-release-artifacts".to_string(),
-                args: Some(vec!["static-artifacts/".to_string()]),
-                source: Some("Heroku Release Phase Buildpack".to_string()),
-            }])
+            result.release,
+            Some(vec![Executable {
+                command: "save-release-artifacts".to_string(),
+                args: Some(vec!["static-artifacts/".to_string()]),
+                source: Some("Heroku Release Phase Buildpack".to_string()),
+                run_at: None,
+                idempotency_key: None,
+                run_on: None,
+                name: None,
+                depends_on: None,
+                tty: None,
+                stdin: None,
+                limits: ResourceLimits::default(),
+            }])
+        );
+    }
+
+    #[test]
+    fn generate_commands_config_for_project_release_build_at_build() {
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "bash"
+
+            [com.heroku.phase.release-build]
+            command = "bash"
+            args = ["-c", "echo 'test build'"]
+            run-at = "build"
+        }
+        .into();
+        let inherit_config = toml::Table::new();
+        let result = generate_commands_config(&project_config, inherit_config).unwrap();
+        assert_eq!(
+            result.release_build.map(|b| b.run_at),
+            Some(Some(RunAt::Build))
+        );
+        assert_eq!(
+            result.release,
+            Some(vec![Executable {
+                command: "bash".to_string(),
+                args: None,
+                source: None,
+                run_at: None,
+                idempotency_key: None,
+                run_on: None,
+                name: None,
+                depends_on: None,
+                tty: None,
+                stdin: None,
+                limits: ResourceLimits::default(),
+            }]),
+            "artifacts are saved during build, rather than by a release command"
+        );
+    }
+
+    #[test]
+    fn to_shell_command_renders_sequence() {
+        let commands = ReleaseCommands {
+            release_build: Some(Executable {
+                command: "bash".to_string(),
+                args: Some(vec!["-c".to_string(), "npm run build".to_string()]),
+                ..Executable::default()
+            }),
+            release: Some(vec![
+                Executable {
+                    command: "save-release-artifacts".to_string(),
+                    args: Some(vec!["static-artifacts/".to_string()]),
+                    ..Executable::default()
+                },
+                Executable {
+                    command: "bash".to_string(),
+                    args: Some(vec![
+                        "-c".to_string(),
+                        "echo 'done'".to_string(),
+                        String::new(),
+                    ]),
+                    ..Executable::default()
+                },
+            ]),
+            ..ReleaseCommands::default()
+        };
+        assert_eq!(
+            commands.to_shell_command(),
+            r"bash -c 'npm run build' && save-release-artifacts static-artifacts/ && bash -c 'echo '\''done'\''' ''"
+        );
+    }
+
+    #[test]
+    fn generate_commands_config_for_resource_limits() {
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase.release-build]
+            command = "npm"
+            max-memory = "2G"
+            nice = 10
+            max-open-files = 1024
+        }
+        .into();
+        let inherit_config = toml::Table::new();
+        let result = generate_commands_config(&project_config, inherit_config).unwrap();
+        assert_eq!(
+            result.release_build.map(|b| b.limits),
+            Some(ResourceLimits {
+                max_memory: Some("2G".to_string()),
+                nice: Some(10),
+                max_open_files: Some(1024),
+            })
+        );
+    }
+
+    #[test]
+    fn generate_commands_config_fails_for_invalid_resource_limits() {
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "migrate"
+            max-memory = "lots"
+        }
+        .into();
+        let inherit_config = toml::Table::new();
+        assert!(matches!(
+            generate_commands_config(&project_config, inherit_config),
+            Err(Error::ResourceLimitInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn generate_commands_config_for_idempotency_keys() {
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "rake"
+            args = ["db:migrate"]
+            idempotency-key = "migrate"
+        }
+        .into();
+        let result = generate_commands_config(&project_config, toml::Table::new()).unwrap();
+        assert!(result.uses_idempotency_keys());
+        assert_eq!(
+            result.release.unwrap()[0].idempotency_key.as_deref(),
+            Some("migrate")
+        );
+        assert!(!ReleaseCommands::default().uses_idempotency_keys());
+
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "rake"
+            idempotency-key = "../migrate"
+        }
+        .into();
+        assert!(matches!(
+            generate_commands_config(&project_config, toml::Table::new()),
+            Err(Error::IdempotencyKeyInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn generate_commands_config_for_run_on() {
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "rake"
+            args = ["db:migrate"]
+
+            [[com.heroku.phase.release]]
+            command = "notify-oncall"
+            run-on = "failure"
+        }
+        .into();
+        let release = generate_commands_config(&project_config, toml::Table::new())
+            .unwrap()
+            .release
+            .unwrap();
+        assert_eq!(release[0].run_on, None);
+        assert_eq!(release[1].run_on, Some(RunOn::Failure));
+        assert!(!release[1].runs_on(false));
+        assert!(release[1].runs_on(true));
+
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "rake"
+            run-on = "sometimes"
+        }
+        .into();
+        assert!(matches!(
+            generate_commands_config(&project_config, toml::Table::new()),
+            Err(Error::TomlProjectDeserializeError(_))
+        ));
+    }
+
+    #[test]
+    fn generate_commands_config_for_dependencies() {
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "rake"
+            name = "migrate"
+
+            [[com.heroku.phase.release]]
+            command = "warm-cache"
+            depends-on = ["migrate"]
+        }
+        .into();
+        let release = generate_commands_config(&project_config, toml::Table::new())
+            .unwrap()
+            .release
+            .unwrap();
+        assert_eq!(release[1].depends_on, Some(vec!["migrate".to_string()]));
+
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "warm-cache"
+            depends-on = ["migrate"]
+
+            [[com.heroku.phase.release]]
+            command = "rake"
+            name = "migrate"
+        }
+        .into();
+        assert!(matches!(
+            generate_commands_config(&project_config, toml::Table::new()),
+            Err(Error::CommandDependencyInvalid(name)) if name == "migrate"
+        ));
+
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "rake"
+            name = "migrate"
+
+            [[com.heroku.phase.release]]
+            command = "rails"
+            name = "migrate"
+        }
+        .into();
+        assert!(matches!(
+            generate_commands_config(&project_config, toml::Table::new()),
+            Err(Error::CommandNameInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn to_shell_command_renders_run_on() {
+        let commands = ReleaseCommands {
+            release: Some(vec![
+                Executable {
+                    command: "migrate".to_string(),
+                    ..Executable::default()
+                },
+                Executable {
+                    command: "alert".to_string(),
+                    run_on: Some(RunOn::Failure),
+                    ..Executable::default()
+                },
+                Executable {
+                    command: "cleanup".to_string(),
+                    run_on: Some(RunOn::Always),
+                    ..Executable::default()
+                },
+            ]),
+            ..ReleaseCommands::default()
+        };
+        let shell_command = commands.to_shell_command();
+        assert_eq!(
+            shell_command,
+            "s=0; [ $s -ne 0 ] || migrate || s=$?; [ $s -eq 0 ] || alert; cleanup || { r=$?; [ $s -ne 0 ] || s=$r; }; exit $s"
+        );
+
+        let status = |migrate: &str| {
+            Command::new("/bin/sh")
+                .arg("-c")
+                .arg(format!(
+                    "migrate() {{ {migrate}; }}; alert() {{ echo alert; }}; cleanup() {{ echo cleanup; }}; {shell_command}"
+                ))
+                .output()
+                .unwrap()
+        };
+        let output = status("return 3");
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "alert\ncleanup\n");
+        let output = status("true");
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "cleanup\n");
+    }
+
+    #[test]
+    fn to_process_applies_resource_limits() {
+        let executable = Executable {
+            command: "sh".to_string(),
+            args: Some(vec!["-c".to_string(), "ulimit -n; ulimit -d".to_string()]),
+            limits: ResourceLimits {
+                max_memory: Some("512M".to_string()),
+                max_open_files: Some(64),
+                nice: Some(5),
+            },
+            ..Executable::default()
+        };
+        let output = executable.to_process().output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "64\n524288\n");
+    }
+
+    #[test]
+    fn to_process_pipes_stdin() {
+        let seed_path = env::temp_dir().join(format!("seed-{}.sql", std::process::id()));
+        fs::write(&seed_path, "insert into plans;\n").unwrap();
+        let file_input = Executable {
+            command: "cat".to_string(),
+            stdin: Some(Stdin::File(seed_path.to_string_lossy().to_string())),
+            ..Executable::default()
+        };
+        let text_input = Executable {
+            command: "cat".to_string(),
+            stdin: Some(Stdin::Text("it's yes".to_string())),
+            limits: ResourceLimits {
+                nice: Some(5),
+                ..ResourceLimits::default()
+            },
+            ..Executable::default()
+        };
+        let file_output = file_input.to_process().output().unwrap();
+        let text_output = text_input.to_process().output().unwrap();
+        fs::remove_file(&seed_path).unwrap();
+
+        assert_eq!(
+            String::from_utf8_lossy(&file_output.stdout),
+            "insert into plans;\n"
+        );
+        assert_eq!(String::from_utf8_lossy(&text_output.stdout), "it's yes");
+    }
+
+    #[test]
+    fn to_shell_command_renders_stdin() {
+        let executable: Executable = toml::from_str(
+            r#"
+            command = "psql"
+            args = ["-v", "ON_ERROR_STOP=1"]
+            stdin = { file = "db/seed.sql" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            executable.to_shell_command(),
+            "psql -v ON_ERROR_STOP=1 < db/seed.sql"
+        );
+        assert_eq!(
+            Executable {
+                command: "confirm".to_string(),
+                stdin: Some(Stdin::Text("y\n".to_string())),
+                ..Executable::default()
+            }
+            .to_shell_command(),
+            "printf '%s' 'y\n' | confirm"
+        );
+    }
+
+    #[test]
+    fn to_process_allocates_tty() {
+        if !has_util_linux_script() {
+            eprintln!("skipping to_process_allocates_tty, without the script of util-linux");
+            return;
+        }
+        let executable = Executable {
+            command: "sh".to_string(),
+            args: Some(vec![
+                "-c".to_string(),
+                "[ -t 0 ] && [ -t 1 ] && echo 'a tty' && exit 3".to_string(),
+            ]),
+            tty: Some(true),
+            ..Executable::default()
+        };
+        let output = executable.to_process().output().unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "a tty\r\n");
+    }
+
+    #[test]
+    fn check_process_requires_util_linux_script_for_tty() {
+        let executable = Executable {
+            command: "sh".to_string(),
+            ..Executable::default()
+        };
+        assert!(executable.check_process().is_ok());
+        let tty_executable = Executable {
+            tty: Some(true),
+            ..executable
+        };
+        assert_eq!(
+            tty_executable.check_process().is_ok(),
+            has_util_linux_script()
         );
     }
 
+    #[test]
+    fn to_shell_command_skips_release_build_at_build() {
+        let commands = ReleaseCommands {
+            release_build: Some(Executable {
+                command: "npm".to_string(),
+                run_at: Some(RunAt::Build),
+                ..Executable::default()
+            }),
+            release: Some(vec![Executable {
+                command: "migrate".to_string(),
+                ..Executable::default()
+            }]),
+            ..ReleaseCommands::default()
+        };
+        assert_eq!(commands.to_shell_command(), "migrate");
+    }
+
     #[test]
     fn generate_commands_config_when_not_defined() {
         let project_config: toml::Value = toml! {
@@ -302,21 +1622,53 @@ mod tests {
                     command: "buildplan1".to_string(),
                     args: None,
                     source: None,
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 },
                 Executable {
                     command: "buildplan2".to_string(),
                     args: None,
                     source: None,
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 },
                 Executable {
                     command: "project1".to_string(),
                     args: None,
                     source: None,
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 },
                 Executable {
                     command: "project2".to_string(),
                     args: None,
                     source: None,
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 }
             ])
         );
@@ -343,6 +1695,14 @@ mod tests {
                 command: "buildplan1".to_string(),
                 args: None,
                 source: None,
+                run_at: None,
+                idempotency_key: None,
+                run_on: None,
+                name: None,
+                depends_on: None,
+                tty: None,
+                stdin: None,
+                limits: ResourceLimits::default(),
             })
         );
         assert_eq!(
@@ -351,6 +1711,14 @@ mod tests {
                 command: "save-release-artifacts".to_string(),
                 args: Some(vec!["static-artifacts/".to_string()]),
                 source: Some("Heroku Release Phase Buildpack".to_string()),
+                run_at: None,
+                idempotency_key: None,
+                run_on: None,
+                name: None,
+                depends_on: None,
+                tty: None,
+                stdin: None,
+                limits: ResourceLimits::default(),
             }])
         );
     }
@@ -375,6 +1743,14 @@ mod tests {
                 command: "project1".to_string(),
                 args: None,
                 source: None,
+                run_at: None,
+                idempotency_key: None,
+                run_on: None,
+                name: None,
+                depends_on: None,
+                tty: None,
+                stdin: None,
+                limits: ResourceLimits::default(),
             })
         );
         assert_eq!(
@@ -383,6 +1759,14 @@ mod tests {
                 command: "save-release-artifacts".to_string(),
                 args: Some(vec!["static-artifacts/".to_string()]),
                 source: Some("Heroku Release Phase Buildpack".to_string()),
+                run_at: None,
+                idempotency_key: None,
+                run_on: None,
+                name: None,
+                depends_on: None,
+                tty: None,
+                stdin: None,
+                limits: ResourceLimits::default(),
             }])
         );
     }
@@ -424,26 +1808,30 @@ mod tests {
                     command: "save-release-artifacts".to_string(),
                     args: Some(vec!["static-artifacts/".to_string()]),
                     source: Some("Heroku Release Phase Buildpack".to_string()),
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 },
                 Executable {
                     command: "buildplan1".to_string(),
-                    args: None,
-                    source: None,
+                    ..Executable::default()
                 },
                 Executable {
                     command: "buildplan2".to_string(),
-                    args: None,
-                    source: None,
+                    ..Executable::default()
                 },
                 Executable {
                     command: "project1".to_string(),
-                    args: None,
-                    source: None,
+                    ..Executable::default()
                 },
                 Executable {
                     command: "project2".to_string(),
-                    args: None,
-                    source: None,
+                    ..Executable::default()
                 }
             ])
         );
@@ -451,12 +1839,471 @@ mod tests {
             result.release_build,
             Some(Executable {
                 command: "projectbuild1".to_string(),
-                args: None,
-                source: None,
+                ..Executable::default()
+            })
+        );
+    }
+
+    #[test]
+    fn generate_commands_config_artifacts_only() {
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase]
+            artifacts-only = true
+        }
+        .into();
+        let result = generate_commands_config(&project_config, toml::Table::new()).unwrap();
+        assert!(result.has_artifacts());
+        assert_eq!(
+            result.release,
+            Some(vec![Executable {
+                command: "save-release-artifacts".to_string(),
+                args: Some(vec!["static-artifacts/".to_string()]),
+                source: Some("Heroku Release Phase Buildpack".to_string()),
+                ..Executable::default()
+            }])
+        );
+
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase]
+            artifacts-only = true
+
+            [[com.heroku.phase.release]]
+            command = "migrate"
+        }
+        .into();
+        assert!(matches!(
+            generate_commands_config(&project_config, toml::Table::new()),
+            Err(Error::ArtifactsOnlyReleaseCommand(command)) if command == "migrate"
+        ));
+    }
+
+    #[test]
+    fn generate_commands_config_with_artifact_dir() {
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase]
+            artifacts-only = true
+            artifact-dir = "public/"
+        }
+        .into();
+        let result = generate_commands_config(&project_config, toml::Table::new()).unwrap();
+        assert_eq!(result.artifact_dir(), "public/");
+        assert_eq!(
+            result.release.expect("release commands")[0].args,
+            Some(vec!["public/".to_string()])
+        );
+
+        let mut inherit_config = toml::Table::new();
+        inherit_config.insert("artifact-dir".to_string(), "dist".to_string().into());
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase.release-build]
+            command = "npm"
+        }
+        .into();
+        let result = generate_commands_config(&project_config, inherit_config).unwrap();
+        assert_eq!(result.artifact_dir(), "dist");
+        assert_eq!(
+            result.release.expect("release commands")[0].args,
+            Some(vec!["dist/".to_string()])
+        );
+
+        for artifact_dir in ["", ".", "../shared", "/srv/public"] {
+            let project_config: toml::Value = toml! {
+                [com.heroku.phase]
+                artifacts-only = true
+            }
+            .into();
+            let mut inherit_config = toml::Table::new();
+            inherit_config.insert("artifact-dir".to_string(), artifact_dir.into());
+            assert!(
+                matches!(
+                    generate_commands_config(&project_config, inherit_config),
+                    Err(Error::ArtifactDirInvalid(dir)) if dir == artifact_dir
+                ),
+                "{artifact_dir:?} is invalid"
+            );
+        }
+        assert_eq!(
+            ReleaseCommands::default().artifact_dir(),
+            "static-artifacts"
+        );
+    }
+
+    #[test]
+    fn generate_commands_config_for_artifact_channels() {
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase.release-build]
+            command = "projectbuild1"
+
+            [com.heroku.phase.artifact-channels.docs]
+            dir = "public/docs"
+
+            [com.heroku.phase.artifact-channels.ml-model]
+            dir = "model/"
+            key-prefix = "models"
+        }
+        .into();
+
+        let mut inherit_channel = toml::Table::new();
+        inherit_channel.insert("dir".to_string(), "buildplan/docs".to_string().into());
+        let mut inherit_channel_2 = toml::Table::new();
+        inherit_channel_2.insert("dir".to_string(), "buildplan/assets".to_string().into());
+        let mut inherit_channels = toml::Table::new();
+        inherit_channels.insert("docs".to_string(), inherit_channel.into());
+        inherit_channels.insert("assets".to_string(), inherit_channel_2.into());
+        let mut inherit_config = toml::Table::new();
+        inherit_config.insert("artifact-channels".to_string(), inherit_channels.into());
+
+        let result = generate_commands_config(&project_config, inherit_config).unwrap();
+        let channels = result.artifact_channels.expect("artifact channels");
+        assert_eq!(
+            channels.get("docs"),
+            Some(&ArtifactChannel {
+                dir: "public/docs".to_string(),
+                key_prefix: Some("docs".to_string()),
+            })
+        );
+        assert_eq!(
+            channels.get("assets"),
+            Some(&ArtifactChannel {
+                dir: "buildplan/assets".to_string(),
+                key_prefix: Some("assets".to_string()),
+            })
+        );
+        assert_eq!(
+            channels.get("ml-model"),
+            Some(&ArtifactChannel {
+                dir: "model/".to_string(),
+                key_prefix: Some("models".to_string()),
+            })
+        );
+        let save_args: Vec<Vec<String>> = result
+            .release
+            .expect("release commands")
+            .into_iter()
+            .filter_map(|e| e.args)
+            .collect();
+        assert_eq!(
+            save_args,
+            vec![
+                vec!["static-artifacts/".to_string()],
+                vec![
+                    "--channel".to_string(),
+                    "assets".to_string(),
+                    "buildplan/assets".to_string()
+                ],
+                vec![
+                    "--channel".to_string(),
+                    "docs".to_string(),
+                    "public/docs".to_string()
+                ],
+                vec![
+                    "--channel".to_string(),
+                    "ml-model".to_string(),
+                    "model/".to_string()
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn generate_commands_config_inherits_storage_profiles() {
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase.storage.staging]
+            url = "s3://project-staging-bucket"
+        }
+        .into();
+        let inherit_config = toml! {
+            [storage.default]
+            url = "s3://platform-bucket"
+            access-key-id-var = "PLATFORM_ACCESS_KEY_ID"
+
+            [storage.staging]
+            url = "s3://platform-staging-bucket"
+        };
+
+        let result = generate_commands_config(&project_config, inherit_config).unwrap();
+        let profiles = result.storage.expect("storage profiles");
+        assert_eq!(
+            profiles.get(DEFAULT_STORAGE_PROFILE),
+            Some(&StorageProfile {
+                url: Some("s3://platform-bucket".to_string()),
+                access_key_id_var: Some("PLATFORM_ACCESS_KEY_ID".to_string()),
+                ..StorageProfile::default()
+            })
+        );
+        assert_eq!(
+            profiles.get("staging"),
+            Some(&StorageProfile {
+                url: Some("s3://project-staging-bucket".to_string()),
+                ..StorageProfile::default()
+            }),
+            "project profiles take precedence"
+        );
+    }
+
+    #[test]
+    fn generate_commands_config_for_artifact_channel_with_invalid_name() {
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase.artifact-channels."../docs"]
+            dir = "public/docs"
+        }
+        .into();
+        let inherit_config = toml::Table::new();
+        let result = generate_commands_config(&project_config, inherit_config);
+        assert!(
+            matches!(result, Err(Error::ArtifactChannelNameInvalid(name)) if name == "../docs")
+        );
+    }
+
+    #[test]
+    fn find_artifact_channel_succeeds() {
+        let commands_toml_path =
+            PathBuf::from("tests/fixtures/uses_artifact_channels/release-commands.toml");
+        assert_eq!(
+            find_artifact_channel(&commands_toml_path, "docs").unwrap(),
+            ArtifactChannel {
+                dir: "public/docs".to_string(),
+                key_prefix: Some("docs".to_string()),
+            }
+        );
+        assert_eq!(
+            find_artifact_channel(&commands_toml_path, "ml-model").unwrap(),
+            ArtifactChannel {
+                dir: "model/".to_string(),
+                key_prefix: Some("models".to_string()),
+            }
+        );
+        assert!(matches!(
+            find_artifact_channel(&commands_toml_path, "missing"),
+            Err(Error::ArtifactChannelNotConfigured(_))
+        ));
+    }
+
+    #[test]
+    fn generate_commands_config_for_storage_profiles() {
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase.storage.production]
+            url = "s3://production-bucket/assets"
+            region = "us-east-1"
+            stage = "production"
+
+            [com.heroku.phase.storage.production.gc]
+            stages = { production = 10, review-app = 2 }
+            retain-days = 14
+
+            [com.heroku.phase.storage.staging]
+            url = "s3://staging-bucket"
+            access-key-id-var = "STAGING_AWS_ACCESS_KEY_ID"
+            secret-access-key-var = "STAGING_AWS_SECRET_ACCESS_KEY"
+        }
+        .into();
+        let inherit_config = toml::Table::new();
+        let result = generate_commands_config(&project_config, inherit_config).unwrap();
+        let profiles = result.storage.expect("storage profiles");
+        assert_eq!(
+            profiles.get("production"),
+            Some(&StorageProfile {
+                url: Some("s3://production-bucket/assets".to_string()),
+                region: Some("us-east-1".to_string()),
+                access_key_id_var: None,
+                secret_access_key_var: None,
+                stage: Some("production".to_string()),
+                gc: Some(GcConfig {
+                    retain: None,
+                    stages: Some(BTreeMap::from([
+                        ("production".to_string(), 10),
+                        ("review-app".to_string(), 2),
+                    ])),
+                    retain_days: Some(14),
+                }),
+            })
+        );
+        assert_eq!(
+            profiles.get("staging"),
+            Some(&StorageProfile {
+                url: Some("s3://staging-bucket".to_string()),
+                region: None,
+                access_key_id_var: Some("STAGING_AWS_ACCESS_KEY_ID".to_string()),
+                secret_access_key_var: Some("STAGING_AWS_SECRET_ACCESS_KEY".to_string()),
+                stage: None,
+                gc: None,
+            })
+        );
+    }
+
+    #[test]
+    fn generate_commands_config_for_artifacts_prefetch() {
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase.release-build]
+            command = "bash"
+            run-at = "build"
+
+            [com.heroku.phase.artifacts]
+            prefetch = true
+            extract-dir = "/tmp/static-artifacts"
+            exclude = ["node_modules", "*.map"]
+        }
+        .into();
+        let inherit_config = toml::Table::new();
+        let result = generate_commands_config(&project_config, inherit_config).unwrap();
+        assert_eq!(
+            result.artifacts,
+            Some(ArtifactsConfig {
+                prefetch: Some(true),
+                path_env: None,
+                extract_dir: Some("/tmp/static-artifacts".to_string()),
+                include: None,
+                exclude: Some(vec!["node_modules".to_string(), "*.map".to_string()]),
             })
         );
     }
 
+    #[test]
+    fn generate_commands_config_fails_for_prefetch_without_build() {
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase.release-build]
+            command = "bash"
+
+            [com.heroku.phase.artifacts]
+            prefetch = true
+        }
+        .into();
+        assert!(matches!(
+            generate_commands_config(&project_config, toml::Table::new()),
+            Err(Error::ArtifactsPrefetchWithoutBuild)
+        ));
+    }
+
+    #[test]
+    fn apply_storage_profile_fills_in_storage_env() {
+        let commands_toml_path =
+            PathBuf::from("tests/fixtures/uses_storage_profiles/release-commands.toml");
+
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_PROFILE".to_string(),
+            "production".to_string(),
+        );
+        test_env.insert(
+            "STATIC_ARTIFACTS_REGION".to_string(),
+            "eu-west-1".to_string(),
+        );
+        apply_storage_profile(&commands_toml_path, &mut test_env).unwrap();
+        assert_eq!(
+            test_env.get("STATIC_ARTIFACTS_URL"),
+            Some(&"s3://production-bucket/assets".to_string())
+        );
+        assert_eq!(
+            test_env.get("STATIC_ARTIFACTS_REGION"),
+            Some(&"eu-west-1".to_string()),
+            "env vars already set take precedence"
+        );
+        assert_eq!(test_env.get("STATIC_ARTIFACTS_ACCESS_KEY_ID"), None);
+        assert_eq!(
+            test_env.get("STATIC_ARTIFACTS_STAGE"),
+            Some(&"production".to_string())
+        );
+        assert_eq!(test_env.get("STATIC_ARTIFACTS_RETAIN_COUNT"), None);
+        assert_eq!(
+            test_env.get("STATIC_ARTIFACTS_RETAIN_DAYS"),
+            Some(&"14".to_string())
+        );
+        assert_eq!(
+            test_env.get("STATIC_ARTIFACTS_RETAIN_STAGES"),
+            Some(&"production=10,review-app=2".to_string())
+        );
+    }
+
+    #[test]
+    fn apply_storage_profile_applies_default_profile() {
+        let commands_toml_path =
+            PathBuf::from("tests/fixtures/uses_storage_profiles/release-commands.toml");
+
+        let mut test_env = HashMap::new();
+        apply_storage_profile(&commands_toml_path, &mut test_env).unwrap();
+        assert_eq!(
+            test_env.get("STATIC_ARTIFACTS_URL"),
+            Some(&"s3://platform-bucket".to_string())
+        );
+
+        let mut test_env = HashMap::new();
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            "s3://app-bucket".to_string(),
+        );
+        apply_storage_profile(&commands_toml_path, &mut test_env).unwrap();
+        assert_eq!(
+            test_env.get("STATIC_ARTIFACTS_REGION"),
+            None,
+            "the default profile does not apply to storage configured for the app"
+        );
+
+        let mut test_env = HashMap::new();
+        apply_storage_profile(
+            Path::new("tests/fixtures/uses_release/release-commands.toml"),
+            &mut test_env,
+        )
+        .unwrap();
+        assert!(test_env.is_empty());
+    }
+
+    #[test]
+    fn apply_storage_profile_without_profile_is_noop() {
+        let mut test_env: HashMap<String, String> = HashMap::new();
+        apply_storage_profile(Path::new("non-existent-path"), &mut test_env).unwrap();
+        assert!(test_env.is_empty());
+    }
+
+    #[test]
+    fn apply_storage_profile_fails_for_conflicting_url() {
+        let commands_toml_path =
+            PathBuf::from("tests/fixtures/uses_storage_profiles/release-commands.toml");
+        let mut test_env = HashMap::from([
+            (
+                "STATIC_ARTIFACTS_PROFILE".to_string(),
+                "production".to_string(),
+            ),
+            (
+                "STATIC_ARTIFACTS_URL".to_string(),
+                "s3://app-bucket".to_string(),
+            ),
+            (
+                "STATIC_ARTIFACTS_ACCESS_KEY_ID".to_string(),
+                "app-key".to_string(),
+            ),
+        ]);
+        let expected_env = test_env.clone();
+        assert!(matches!(
+            apply_storage_profile(&commands_toml_path, &mut test_env),
+            Err(Error::StorageProfileConflict(name, var))
+                if name == "production" && var == "STATIC_ARTIFACTS_URL"
+        ));
+        assert_eq!(test_env, expected_env, "the env is left unchanged");
+
+        test_env.insert(
+            "STATIC_ARTIFACTS_URL".to_string(),
+            "s3://production-bucket/assets".to_string(),
+        );
+        apply_storage_profile(&commands_toml_path, &mut test_env).unwrap();
+        assert_eq!(
+            test_env.get("STATIC_ARTIFACTS_STAGE"),
+            Some(&"production".to_string()),
+            "the same URL as the profile's does not conflict"
+        );
+    }
+
+    #[test]
+    fn apply_storage_profile_fails_for_unknown_profile() {
+        let commands_toml_path =
+            PathBuf::from("tests/fixtures/uses_storage_profiles/release-commands.toml");
+        let mut test_env = HashMap::new();
+        test_env.insert("STATIC_ARTIFACTS_PROFILE".to_string(), "qa".to_string());
+        assert!(matches!(
+            apply_storage_profile(&commands_toml_path, &mut test_env),
+            Err(Error::StorageProfileNotConfigured(name)) if name == "qa"
+        ));
+    }
+
     #[test]
     fn read_commands_config_for_release_commands() {
         let commands_config = read_commands_config(
@@ -473,6 +2320,14 @@ mod tests {
                         "echo 'Release in release-commands.toml'".to_string()
                     ]),
                     source: None,
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 },
                 Executable {
                     command: "bash".to_string(),
@@ -481,6 +2336,14 @@ mod tests {
                         "echo 'Another release command in release-commands.toml'".to_string()
                     ]),
                     source: None,
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 }
             ])
         );
@@ -502,11 +2365,62 @@ mod tests {
                     "echo 'Release Build in release-commands.toml'".to_string()
                 ]),
                 source: None,
+                run_at: None,
+                idempotency_key: None,
+                run_on: None,
+                name: None,
+                depends_on: None,
+                tty: None,
+                stdin: None,
+                limits: ResourceLimits::default(),
             })
         );
         assert_eq!(commands_config.release, None);
     }
 
+    #[test]
+    fn read_commands_config_merges_fragments() {
+        let commands_config = read_commands_config(
+            PathBuf::from("tests/fixtures/uses_commands_fragments/release-commands.toml").as_path(),
+        )
+        .unwrap();
+        assert_eq!(
+            commands_config
+                .release
+                .unwrap()
+                .iter()
+                .map(|e| e.command.as_str())
+                .collect::<Vec<_>>(),
+            vec!["rake", "bin/migrate", "bin/warm-cache"]
+        );
+        let profiles = commands_config.storage.unwrap();
+        assert_eq!(
+            profiles.get("production").and_then(|p| p.url.clone()),
+            Some("s3://app-bucket".to_string()),
+            "release-commands.toml takes precedence over fragments"
+        );
+        assert_eq!(
+            profiles.get("cache").and_then(|p| p.url.clone()),
+            Some("s3://cache-bucket".to_string())
+        );
+    }
+
+    #[test]
+    fn merge_fragment_rejects_release_build() {
+        let mut commands = ReleaseCommands::default();
+        let fragment = ReleaseCommands {
+            release_build: Some(Executable {
+                command: "bash".to_string(),
+                ..Executable::default()
+            }),
+            ..ReleaseCommands::default()
+        };
+        assert!(matches!(
+            commands.merge_fragment(fragment, Path::new("release-commands.d/build.toml")),
+            Err(Error::ReleaseCommandsFragmentInvalid(path)) if path == "release-commands.d/build.toml"
+        ));
+    }
+
     #[test]
     fn read_commands_config_when_undefined() {
         let commands_config = read_commands_config(
@@ -545,27 +2459,58 @@ mod tests {
                     command: "bash".to_string(),
                     args: Some(vec!["-c".to_string(), "echo '1'".to_string()]),
                     source: None,
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 },
                 Executable {
                     command: "bash".to_string(),
                     args: Some(vec!["-c".to_string(), "echo '2'".to_string()]),
                     source: None,
+                    run_at: None,
+                    idempotency_key: None,
+                    run_on: None,
+                    name: None,
+                    depends_on: None,
+                    tty: None,
+                    stdin: None,
+                    limits: ResourceLimits::default(),
                 },
             ]),
             release_build: Some(Executable {
                 command: "bash".to_string(),
                 args: Some(vec!["-c".to_string(), "echo '3'".to_string()]),
                 source: None,
+                run_at: None,
+                idempotency_key: None,
+                run_on: None,
+                name: None,
+                depends_on: None,
+                tty: None,
+                stdin: None,
+                limits: ResourceLimits::default(),
             }),
+            artifact_channels: None,
+            storage: None,
+            artifacts: None,
+            artifacts_only: None,
+            artifact_dir: None,
         };
 
         let dir = env::temp_dir();
-        write_commands_config(&dir, &release_commands).expect("toml file is written");
-        let generated_path = dir.join("release-commands.toml");
+        let generated_path =
+            write_commands_config(&dir, &release_commands).expect("toml file is written");
+        assert_eq!(generated_path, commands_toml_path(&dir));
 
         let generated_toml =
             read_toml_file::<toml::Value>(&generated_path).expect("toml file is read");
         remove_file(&generated_path).expect("toml file is deleted");
+        remove_file(commands_digest_path(&generated_path)).expect("digest file is deleted");
 
         assert_eq!(
             toml_select_value(vec!["release"], &generated_toml),
@@ -581,17 +2526,99 @@ mod tests {
         let release_commands = ReleaseCommands {
             release: None,
             release_build: None,
+            artifact_channels: None,
+            storage: None,
+            artifacts: None,
+            artifacts_only: None,
+            artifact_dir: None,
         };
 
         let dir = env::temp_dir();
-        write_commands_config(&dir, &release_commands).expect("toml file is written");
-        let generated_path = dir.join("release-commands.toml");
+        let generated_path =
+            write_commands_config(&dir, &release_commands).expect("toml file is written");
+        assert_eq!(generated_path, commands_toml_path(&dir));
 
         let generated_toml =
             read_toml_file::<toml::Value>(&generated_path).expect("toml file is read");
         remove_file(&generated_path).expect("toml file is deleted");
+        remove_file(commands_digest_path(&generated_path)).expect("digest file is deleted");
 
         let table = generated_toml.as_table().expect("a toml table");
         assert!(table.is_empty());
     }
+
+    #[test]
+    fn write_commands_config_replaces_without_leaving_temp_files() {
+        let dir = env::temp_dir().join(format!("write-commands-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let release_commands = ReleaseCommands {
+            release: Some(vec![Executable {
+                command: "bash".to_string(),
+                ..Executable::default()
+            }]),
+            ..ReleaseCommands::default()
+        };
+        write_commands_config(&dir, &ReleaseCommands::default()).unwrap();
+        let path = write_commands_config(&dir, &release_commands).unwrap();
+
+        let read = read_commands_config(&path);
+        let entries = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(read.unwrap().release, release_commands.release);
+        assert_eq!(entries, 2, "release-commands.toml & its digest");
+    }
+
+    #[test]
+    fn render_commands_config_carries_over_project_comments_and_unknown_keys() {
+        let project_toml = r#"
+[_]
+schema-version = "0.2"
+
+# Migrations must finish before the new release boots.
+[[com.heroku.phase.release]]
+command = "rake"
+args = ["db:migrate"]
+owner = "data-team" # Not read by the buildpack.
+
+[com.heroku.phase.release-build]
+command = "npm" # Builds the assets.
+args = ["run", "build"]
+
+# Notes for the team.
+[com.heroku.phase.notes]
+runbook = "https://example.com/runbook"
+"#;
+        let project_config: toml::Value = toml::from_str(project_toml).unwrap();
+        let inherit_config = toml! {
+            [[release]]
+            command = "bin/inherited"
+            source = "Another Buildpack"
+        };
+        let commands = generate_commands_config(&project_config, inherit_config).unwrap();
+
+        let rendered = render_commands_config(&commands, Some(project_toml)).unwrap();
+
+        assert!(rendered.contains("# Migrations must finish before the new release boots."));
+        assert!(rendered.contains("# Builds the assets."));
+        assert!(rendered.contains("owner = \"data-team\""));
+        assert!(rendered.contains("# Notes for the team."));
+        assert!(rendered.contains("[notes]"));
+        assert_eq!(
+            toml::from_str::<ReleaseCommands>(&rendered).unwrap(),
+            commands,
+            "the rendered config reads back the same"
+        );
+        let release = toml::from_str::<toml::Table>(&rendered).unwrap()["release"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["command"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            release,
+            vec!["save-release-artifacts", "bin/inherited", "rake"],
+            "release commands keep their order"
+        );
+    }
 }
@@ -0,0 +1,190 @@
+//! Checks the `com.heroku.phase` config of a project.toml before it is pushed, such as for
+//! typos in key names, which the buildpack otherwise ignores.
+
+use crate::{generate_commands_config, Error, ReleaseCommands};
+
+const PHASE_KEYS: [&str; 7] = [
+    "release",
+    "release-build",
+    "artifact-channels",
+    "storage",
+    "artifacts",
+    "artifacts-only",
+    "artifact-dir",
+];
+
+const EXECUTABLE_KEYS: [&str; 13] = [
+    "command",
+    "args",
+    "source",
+    "run-at",
+    "idempotency-key",
+    "run-on",
+    "name",
+    "depends-on",
+    "tty",
+    "stdin",
+    "max-memory",
+    "nice",
+    "max-open-files",
+];
+
+const ARTIFACT_CHANNEL_KEYS: [&str; 2] = ["dir", "key-prefix"];
+
+const STORAGE_PROFILE_KEYS: [&str; 6] = [
+    "url",
+    "region",
+    "access-key-id-var",
+    "secret-access-key-var",
+    "stage",
+    "gc",
+];
+
+const GC_KEYS: [&str; 3] = ["retain", "retain-days", "stages"];
+
+const ARTIFACTS_KEYS: [&str; 5] = ["prefetch", "path-env", "extract-dir", "include", "exclude"];
+
+/// Generates the release config from the given project.toml, as the buildpack would without
+/// any inherited config, returning it with warnings for each key that the buildpack ignores.
+pub fn lint_project_config(
+    project_config: &toml::Value,
+) -> Result<(ReleaseCommands, Vec<String>), Error> {
+    let commands = generate_commands_config(project_config, toml::Table::new())?;
+    let mut warnings = vec![];
+    let phase = project_config
+        .get("com")
+        .and_then(|v| v.get("heroku"))
+        .and_then(|v| v.get("phase"))
+        .and_then(toml::Value::as_table);
+    if let Some(phase) = phase {
+        let path = "com.heroku.phase";
+        check_keys(phase, &PHASE_KEYS, path, &mut warnings);
+        for (index, executable) in phase
+            .get("release")
+            .and_then(toml::Value::as_array)
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            check_table(
+                executable,
+                &EXECUTABLE_KEYS,
+                &format!("{path}.release[{index}]"),
+                &mut warnings,
+            );
+        }
+        if let Some(release_build) = phase.get("release-build") {
+            check_table(
+                release_build,
+                &EXECUTABLE_KEYS,
+                &format!("{path}.release-build"),
+                &mut warnings,
+            );
+        }
+        for (name, channel) in named_tables(phase, "artifact-channels") {
+            check_table(
+                channel,
+                &ARTIFACT_CHANNEL_KEYS,
+                &format!("{path}.artifact-channels.{name}"),
+                &mut warnings,
+            );
+        }
+        for (name, profile) in named_tables(phase, "storage") {
+            let profile_path = format!("{path}.storage.{name}");
+            check_table(profile, &STORAGE_PROFILE_KEYS, &profile_path, &mut warnings);
+            if let Some(gc) = profile.get("gc") {
+                check_table(gc, &GC_KEYS, &format!("{profile_path}.gc"), &mut warnings);
+            }
+        }
+        if let Some(artifacts) = phase.get("artifacts") {
+            check_table(
+                artifacts,
+                &ARTIFACTS_KEYS,
+                &format!("{path}.artifacts"),
+                &mut warnings,
+            );
+        }
+    }
+    Ok((commands, warnings))
+}
+
+fn named_tables<'a>(
+    phase: &'a toml::Table,
+    key: &str,
+) -> impl Iterator<Item = (&'a String, &'a toml::Value)> {
+    phase
+        .get(key)
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flatten()
+}
+
+fn check_table(value: &toml::Value, known: &[&str], path: &str, warnings: &mut Vec<String>) {
+    if let Some(table) = value.as_table() {
+        check_keys(table, known, path, warnings);
+    }
+}
+
+fn check_keys(table: &toml::Table, known: &[&str], path: &str, warnings: &mut Vec<String>) {
+    for key in table.keys() {
+        if !known.contains(&key.as_str()) {
+            warnings.push(format!(
+                "`{path}.{key}` is not a known key, and is ignored by the buildpack."
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::toml;
+
+    use super::lint_project_config;
+    use crate::Error;
+
+    #[test]
+    fn lint_project_config_warns_of_unknown_keys() {
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "rake"
+            arg = ["db:migrate"]
+
+            [com.heroku.phase.storage.production]
+            url = "s3://production-bucket"
+
+            [com.heroku.phase.storage.production.gc]
+            retian = 5
+
+            [com.heroku.phase.artifact]
+            prefetch = true
+        }
+        .into();
+        let (commands, warnings) = lint_project_config(&project_config).unwrap();
+        assert_eq!(commands.release.map(|r| r.len()), Some(1));
+        assert_eq!(
+            warnings,
+            vec![
+                "`com.heroku.phase.artifact` is not a known key, and is ignored by the buildpack."
+                    .to_string(),
+                "`com.heroku.phase.release[0].arg` is not a known key, and is ignored by the buildpack."
+                    .to_string(),
+                "`com.heroku.phase.storage.production.gc.retian` is not a known key, and is ignored by the buildpack."
+                    .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_project_config_fails_for_invalid_config() {
+        let project_config: toml::Value = toml! {
+            [[com.heroku.phase.release]]
+            command = "rake"
+            max-memory = "lots"
+        }
+        .into();
+        assert!(matches!(
+            lint_project_config(&project_config),
+            Err(Error::ResourceLimitInvalid(_))
+        ));
+    }
+}
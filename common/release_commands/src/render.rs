@@ -0,0 +1,126 @@
+//! Renders release-commands.toml, keeping the comments & unknown keys of the `com.heroku.phase`
+//! tables in project.toml.
+
+use toml_edit::{DocumentMut, Item, Table, TableLike};
+
+// The `com.heroku.phase` keys that `generate_commands_config` reads. Any others are the user's
+// own, and are carried over as is.
+const KNOWN_KEYS: [&str; 5] = [
+    "release",
+    "release-build",
+    "artifact-channels",
+    "storage",
+    "artifacts",
+];
+
+/// Carries over the comments & unknown keys of the given project.toml source into the
+/// rendered config. Returns `None` when either fails to parse, or the project has no
+/// `com.heroku.phase` table, leaving the rendered config as is.
+pub(crate) fn carry_over_project_toml(rendered: &str, project_toml: &str) -> Option<String> {
+    let mut document = rendered.parse::<DocumentMut>().ok()?;
+    let project = project_toml.parse::<DocumentMut>().ok()?;
+    let phase = project
+        .get("com")
+        .and_then(|item| item.get("heroku"))
+        .and_then(|item| item.get("phase"))
+        .and_then(Item::as_table_like)?;
+    carry_over_table(document.as_table_mut(), phase);
+    renumber_tables(document.as_table_mut(), &mut 0);
+    Some(document.to_string())
+}
+
+fn carry_over_table(generated: &mut Table, project: &dyn TableLike) {
+    for (key, project_item) in project.iter() {
+        match generated.get_mut(key) {
+            Some(item) => carry_over_item(item, project_item),
+            None if !KNOWN_KEYS.contains(&key) => {
+                generated.insert(key, project_item.clone());
+            }
+            None => {}
+        }
+    }
+}
+
+fn carry_over_item(generated: &mut Item, project: &Item) {
+    if item_matches(generated, project) {
+        *generated = project.clone();
+        return;
+    }
+    match (generated, project) {
+        (Item::ArrayOfTables(generated), Item::ArrayOfTables(project)) => {
+            let mut unmatched: Vec<&Table> = project.iter().collect();
+            for table in generated.iter_mut() {
+                let table_item = Item::Table(table.clone());
+                if let Some(index) = unmatched
+                    .iter()
+                    .position(|p| item_matches(&table_item, &Item::Table((*p).clone())))
+                {
+                    *table = unmatched.remove(index).clone();
+                }
+            }
+        }
+        (Item::Table(generated), Item::Table(project)) => {
+            for (key, item) in generated.iter_mut() {
+                if let Some(project_item) = project.get(&key) {
+                    carry_over_item(item, project_item);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Whether the project's item declares everything in the generated item, allowing it extra,
+// unknown keys.
+fn item_matches(generated: &Item, project: &Item) -> bool {
+    match (item_to_value(generated), item_to_value(project)) {
+        (Some(generated), Some(project)) => is_subset(&generated, &project),
+        _ => false,
+    }
+}
+
+fn item_to_value(item: &Item) -> Option<toml::Value> {
+    let mut document = DocumentMut::new();
+    document.insert("value", item.clone());
+    toml::from_str::<toml::Table>(&document.to_string())
+        .ok()?
+        .remove("value")
+}
+
+fn is_subset(generated: &toml::Value, project: &toml::Value) -> bool {
+    match (generated, project) {
+        (toml::Value::Table(generated), toml::Value::Table(project)) => {
+            generated.iter().all(|(key, value)| {
+                project
+                    .get(key)
+                    .is_some_and(|project_value| is_subset(value, project_value))
+            })
+        }
+        (toml::Value::Array(generated), toml::Value::Array(project)) => {
+            generated.len() == project.len()
+                && generated
+                    .iter()
+                    .zip(project)
+                    .all(|(generated, project)| is_subset(generated, project))
+        }
+        _ => generated == project,
+    }
+}
+
+// Tables carried over from project.toml keep their positions in it, so tables are renumbered in
+// the order of the rendered config, or else they would be rendered out of order.
+fn renumber_tables(table: &mut Table, next_position: &mut usize) {
+    table.set_position(*next_position);
+    *next_position += 1;
+    for (_, item) in table.iter_mut() {
+        match item {
+            Item::Table(table) => renumber_tables(table, next_position),
+            Item::ArrayOfTables(tables) => {
+                for table in tables.iter_mut() {
+                    renumber_tables(table, next_position);
+                }
+            }
+            _ => {}
+        }
+    }
+}
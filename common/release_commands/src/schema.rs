@@ -0,0 +1,63 @@
+//! JSON Schema of the release config in project.toml, generated from the config model, for
+//! editor autocompletion & external validators.
+
+use schemars::schema_for;
+use serde_json::{json, Value};
+
+use crate::ReleaseCommands;
+
+/// Returns the JSON Schema of a project.toml, describing the release config under
+/// `[com.heroku.phase]`, and allowing any other keys.
+pub fn project_config_json_schema() -> serde_json::Result<String> {
+    let mut phase = serde_json::to_value(schema_for!(ReleaseCommands))?;
+    let definitions = phase
+        .as_object_mut()
+        .and_then(|phase| {
+            phase.remove("$schema");
+            phase.remove("definitions")
+        })
+        .unwrap_or_else(|| json!({}));
+    let nested = |key: &str, schema: Value| {
+        json!({
+            "type": "object",
+            "properties": { key: schema },
+        })
+    };
+    let schema = json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "project.toml release config",
+        "type": "object",
+        "properties": {
+            "com": nested("heroku", nested("phase", phase)),
+        },
+        "definitions": definitions,
+    });
+    serde_json::to_string_pretty(&schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::project_config_json_schema;
+
+    #[test]
+    fn project_config_json_schema_describes_phase_config() {
+        let schema: serde_json::Value =
+            serde_json::from_str(&project_config_json_schema().unwrap()).unwrap();
+        let phase = &schema["properties"]["com"]["properties"]["heroku"]["properties"]["phase"];
+        for key in [
+            "release",
+            "release-build",
+            "artifact-channels",
+            "storage",
+            "artifacts",
+            "artifacts-only",
+            "artifact-dir",
+        ] {
+            assert!(phase["properties"].get(key).is_some(), "{key} is described");
+        }
+        let executable = &schema["definitions"]["Executable"]["properties"];
+        assert!(executable.get("max-memory").is_some());
+        assert!(executable.get("run-at").is_some());
+        assert!(schema["definitions"].get("StorageProfile").is_some());
+    }
+}
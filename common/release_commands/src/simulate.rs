@@ -0,0 +1,254 @@
+//! Simulates the release sequence that a project.toml & Build Plan resolve to, without building or
+//! executing anything.
+
+use std::{collections::BTreeMap, fmt};
+
+use libherokubuildpack::toml::toml_select_value;
+use serde::Serialize;
+
+use crate::{
+    execute::{release_sequence, step_dependencies},
+    generate_commands_config, ArtifactChannel, Error, Executable,
+};
+
+/// The release sequence that a config resolves to. Deterministic for the same config, so that
+/// plans may be compared or stored.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedPlan {
+    /// The steps of the release sequence, in order.
+    pub steps: Vec<ResolvedStep>,
+    /// The release-build command, when it runs during CNB build instead of the release.
+    #[serde(rename = "build-command", skip_serializing_if = "Option::is_none")]
+    pub build_command: Option<ResolvedCommand>,
+    /// Whether release artifacts are saved & loaded.
+    pub artifacts: bool,
+    #[serde(rename = "artifact-channels")]
+    pub artifact_channels: BTreeMap<String, ArtifactChannel>,
+}
+
+/// A step of the release sequence.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedStep {
+    pub number: usize,
+    /// `release-build command` or `release command`.
+    pub kind: String,
+    /// The numbers of the steps it waits for.
+    pub after: Vec<usize>,
+    #[serde(flatten)]
+    pub command: ResolvedCommand,
+}
+
+/// A command, with where it was configured.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedCommand {
+    pub provenance: Provenance,
+    #[serde(flatten)]
+    pub executable: Executable,
+}
+
+/// Where a command of the plan was configured.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provenance {
+    /// The app's project.toml.
+    Project,
+    /// The `release-phase` Build Plan requirement of another buildpack.
+    BuildPlan,
+    /// Added by this buildpack, such as the saving of release artifacts.
+    Injected,
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Provenance::Project => "project.toml",
+            Provenance::BuildPlan => "Build Plan",
+            Provenance::Injected => "injected",
+        })
+    }
+}
+
+impl fmt::Display for ResolvedPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(build_command) = &self.build_command {
+            writeln!(
+                f,
+                "  during build, release-build command: {} [{}]",
+                build_command.executable, build_command.provenance
+            )?;
+        }
+        for step in &self.steps {
+            writeln!(
+                f,
+                "  {}/{} {}: {} [{}]",
+                step.number,
+                self.steps.len(),
+                step.kind,
+                step.command.executable,
+                step.command.provenance
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Resolves the release sequence of the given project.toml & inherited Build Plan config, as
+/// the buildpack would at build, with the steps that the buildpack injects.
+pub fn simulate(
+    project_config: &toml::Value,
+    build_plan: toml::Table,
+) -> Result<ResolvedPlan, Error> {
+    let inherited_release_count = build_plan
+        .get("release")
+        .and_then(toml::Value::as_array)
+        .map_or(0, Vec::len);
+    let project_has_release_build = toml_select_value(
+        vec!["com", "heroku", "phase", "release-build"],
+        project_config,
+    )
+    .is_some();
+    let commands = generate_commands_config(project_config, build_plan)?;
+
+    // Release commands are ordered as the injected artifact savers, then the inherited
+    // commands, then the project's.
+    let release_count = commands.release.as_ref().map_or(0, Vec::len);
+    let project_release_count =
+        toml_select_value(vec!["com", "heroku", "phase", "release"], project_config)
+            .and_then(toml::Value::as_array)
+            .map_or(0, Vec::len);
+    let injected_count =
+        release_count.saturating_sub(inherited_release_count + project_release_count);
+    let release_build_provenance = if project_has_release_build {
+        Provenance::Project
+    } else {
+        Provenance::BuildPlan
+    };
+    let release_provenance = |index: usize| {
+        if index < injected_count {
+            Provenance::Injected
+        } else if index < injected_count + inherited_release_count {
+            Provenance::BuildPlan
+        } else {
+            Provenance::Project
+        }
+    };
+
+    let sequence = release_sequence(&commands);
+    let dependencies = step_dependencies(&sequence);
+    let release_build_steps = sequence.len() - release_count;
+    let steps = sequence
+        .iter()
+        .zip(dependencies)
+        .enumerate()
+        .map(|(index, ((kind, executable), after))| ResolvedStep {
+            number: index + 1,
+            kind: (*kind).to_string(),
+            after: after.into_iter().map(|d| d + 1).collect(),
+            command: ResolvedCommand {
+                provenance: if index < release_build_steps {
+                    release_build_provenance
+                } else {
+                    release_provenance(index - release_build_steps)
+                },
+                executable: (*executable).clone(),
+            },
+        })
+        .collect();
+
+    Ok(ResolvedPlan {
+        steps,
+        build_command: commands
+            .release_build
+            .as_ref()
+            .filter(|release_build| release_build.runs_at_build())
+            .map(|release_build| ResolvedCommand {
+                provenance: release_build_provenance,
+                executable: release_build.clone(),
+            }),
+        artifacts: commands.has_artifacts(),
+        artifact_channels: commands.artifact_channels.clone().unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::toml;
+
+    use super::{simulate, Provenance};
+
+    #[test]
+    fn simulate_resolves_steps_with_provenance() {
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase.release-build]
+            command = "npm"
+            args = ["run", "build"]
+
+            [[com.heroku.phase.release]]
+            command = "rake"
+            args = ["db:migrate"]
+            name = "migrate"
+
+            [[com.heroku.phase.release]]
+            command = "notify"
+            depends-on = ["migrate"]
+        }
+        .into();
+        let build_plan = toml! {
+            [[release]]
+            command = "bash"
+            args = ["-c", "echo inherited"]
+        };
+
+        let plan = simulate(&project_config, build_plan).unwrap();
+        let steps: Vec<(&str, Provenance, Vec<usize>)> = plan
+            .steps
+            .iter()
+            .map(|s| {
+                (
+                    s.command.executable.command.as_str(),
+                    s.command.provenance,
+                    s.after.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("npm", Provenance::Project, vec![]),
+                ("save-release-artifacts", Provenance::Injected, vec![1]),
+                ("bash", Provenance::BuildPlan, vec![2]),
+                ("rake", Provenance::Project, vec![3]),
+                ("notify", Provenance::Project, vec![2, 4]),
+            ]
+        );
+        assert!(plan.artifacts);
+        assert_eq!(plan.build_command, None);
+        assert_eq!(
+            plan.to_string(),
+            "  1/5 release-build command: npm run build [project.toml]
+  2/5 release command: save-release-artifacts static-artifacts/ (Heroku Release Phase Buildpack) [injected]
+  3/5 release command: bash -c echo inherited [Build Plan]
+  4/5 release command: rake db:migrate [project.toml]
+  5/5 release command: notify [project.toml]
+"
+        );
+    }
+
+    #[test]
+    fn simulate_resolves_release_build_at_build() {
+        let project_config: toml::Value = toml::Table::new().into();
+        let build_plan = toml! {
+            [release-build]
+            command = "npm"
+            run-at = "build"
+        };
+
+        let plan = simulate(&project_config, build_plan).unwrap();
+        assert!(plan.steps.is_empty());
+        let build_command = plan
+            .build_command
+            .expect("release-build should run at build");
+        assert_eq!(build_command.provenance, Provenance::BuildPlan);
+        assert_eq!(build_command.executable.command, "npm");
+    }
+}
@@ -0,0 +1,97 @@
+//! Records the digest of release-commands.toml at build, to check it for changes at release.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use libcnb_common::toml_file::TomlFileError;
+use ring::digest;
+
+use crate::Error;
+
+/// Whether release-commands.toml matches the digest recorded when it was written.
+#[derive(Debug, Eq, PartialEq)]
+pub enum CommandsVerification {
+    Unchanged,
+    Changed {
+        recorded: String,
+        actual: String,
+    },
+    /// No digest was recorded, such as by earlier versions of the buildpack.
+    NotRecorded,
+}
+
+/// The path of the digest recorded beside the given release-commands.toml.
+#[must_use]
+pub fn commands_digest_path(commands_toml_path: &Path) -> PathBuf {
+    let mut path = commands_toml_path.as_os_str().to_owned();
+    path.push(".sha256");
+    PathBuf::from(path)
+}
+
+/// Compares the given release-commands.toml against the digest recorded when it was written.
+/// Fragments in `release-commands.d` are not included, as other buildpacks may add them later.
+pub fn verify_commands_config(commands_toml_path: &Path) -> Result<CommandsVerification, Error> {
+    let contents = fs::read(commands_toml_path)
+        .map_err(|e| Error::TomlReleaseCommandsFileError(TomlFileError::IoError(e)))?;
+    let actual = digest(&contents);
+    match fs::read_to_string(commands_digest_path(commands_toml_path)) {
+        Ok(recorded) if recorded.trim() == actual => Ok(CommandsVerification::Unchanged),
+        Ok(recorded) => Ok(CommandsVerification::Changed {
+            recorded: recorded.trim().to_string(),
+            actual,
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(CommandsVerification::NotRecorded),
+        Err(e) => Err(Error::TomlReleaseCommandsFileError(TomlFileError::IoError(
+            e,
+        ))),
+    }
+}
+
+// The digest of the file's contents, like `sha256:<hex>`.
+pub(crate) fn digest(contents: &[u8]) -> String {
+    format!(
+        "sha256:{}",
+        hex::encode(digest::digest(&digest::SHA256, contents))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::{commands_digest_path, verify_commands_config, CommandsVerification};
+    use crate::{write_commands_config, Error, ReleaseCommands};
+
+    #[test]
+    fn verify_commands_config_detects_changes() {
+        let dir = env::temp_dir().join(format!("verify-commands-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = write_commands_config(&dir, &ReleaseCommands::default()).unwrap();
+        let unchanged = verify_commands_config(&path).unwrap();
+        fs::write(&path, "[[release]]\ncommand = \"curl\"\n").unwrap();
+        let changed = verify_commands_config(&path).unwrap();
+        fs::remove_file(commands_digest_path(&path)).unwrap();
+        let not_recorded = verify_commands_config(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(unchanged, CommandsVerification::Unchanged);
+        let CommandsVerification::Changed { recorded, actual } = changed else {
+            panic!("expected a change, got {changed:?}");
+        };
+        assert!(recorded.starts_with("sha256:"));
+        assert!(actual.starts_with("sha256:"));
+        assert_ne!(recorded, actual);
+        assert_eq!(not_recorded, CommandsVerification::NotRecorded);
+    }
+
+    #[test]
+    fn verify_commands_config_fails_without_commands_file() {
+        let path = env::temp_dir().join("verify-commands-config-missing/release-commands.toml");
+        assert!(matches!(
+            verify_commands_config(&path),
+            Err(Error::TomlReleaseCommandsFileError(_))
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+//! Logging shared by the Release Phase executables, at the level of `RELEASE_PHASE_LOG_LEVEL`:
+//! `quiet`, `info` (the default), or `debug`.
+
+use std::{env, sync::OnceLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Quiet,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    #[must_use]
+    pub fn parse(value: Option<&str>) -> LogLevel {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            Some("quiet" | "error") => LogLevel::Quiet,
+            Some("debug") => LogLevel::Debug,
+            _ => LogLevel::Info,
+        }
+    }
+}
+
+/// The level set for this process, read once from `RELEASE_PHASE_LOG_LEVEL`.
+pub fn level() -> LogLevel {
+    static LEVEL: OnceLock<LogLevel> = OnceLock::new();
+    *LEVEL.get_or_init(|| LogLevel::parse(env::var("RELEASE_PHASE_LOG_LEVEL").ok().as_deref()))
+}
+
+#[must_use]
+pub fn enabled(level: LogLevel) -> bool {
+    self::level() >= level
+}
+
+/// Writes progress to stderr, unless quiet.
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        if $crate::enabled($crate::LogLevel::Info) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Writes detail to stderr, only when debugging.
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        if $crate::enabled($crate::LogLevel::Debug) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogLevel;
+
+    #[test]
+    fn parse_log_level() {
+        assert_eq!(LogLevel::parse(Some("quiet")), LogLevel::Quiet);
+        assert_eq!(LogLevel::parse(Some(" DEBUG ")), LogLevel::Debug);
+        assert_eq!(LogLevel::parse(Some("info")), LogLevel::Info);
+        assert_eq!(LogLevel::parse(Some("unknown")), LogLevel::Info);
+        assert_eq!(LogLevel::parse(None), LogLevel::Info);
+        assert!(LogLevel::Debug > LogLevel::Info);
+    }
+}
@@ -0,0 +1,583 @@
+//! Typed builders for the `release-phase` Build Plan `[requires.metadata]` of other buildpacks.
+//!
+//! ```
+//! use release_phase_plan::{ReleaseCommand, ReleasePhasePlan};
+//!
+//! let plan = ReleasePhasePlan::builder()
+//!     .source("My Awesome Buildpack")
+//!     .release_build(ReleaseCommand::new("bash").args(["-c", "npm run build"]))
+//!     .release(ReleaseCommand::new("bash").args(["-c", "echo 'Hello world!'"]))
+//!     .build()
+//!     .expect("plan should be valid");
+//!
+//! // With libcnb, pass the plan as the metadata of the requirement:
+//! //   let mut release_phase_req = Require::new(release_phase_plan::BUILD_PLAN_ID);
+//! //   release_phase_req.metadata(plan)?;
+//! assert!(plan.to_table().is_ok());
+//! ```
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::Serialize;
+
+/// The Build Plan name provided by the Release Phase buildpack.
+pub const BUILD_PLAN_ID: &str = "release-phase";
+
+/// Metadata for a `release-phase` Build Plan requirement.
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReleasePhasePlan {
+    #[serde(rename = "release-build", skip_serializing_if = "Option::is_none")]
+    release_build: Option<ReleaseCommand>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    release: Vec<ReleaseCommand>,
+    #[serde(rename = "artifact-dir", skip_serializing_if = "Option::is_none")]
+    artifact_dir: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    storage: BTreeMap<String, StorageProfile>,
+}
+
+impl ReleasePhasePlan {
+    #[must_use]
+    pub fn builder() -> ReleasePhasePlanBuilder {
+        ReleasePhasePlanBuilder::default()
+    }
+
+    pub fn to_table(&self) -> Result<toml::Table, toml::ser::Error> {
+        toml::Table::try_from(self)
+    }
+}
+
+/// A command executed during Release Phase.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseCommand {
+    command: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "depends-on", skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<String>,
+    #[serde(rename = "run-on", skip_serializing_if = "Option::is_none")]
+    run_on: Option<RunOn>,
+    #[serde(rename = "idempotency-key", skip_serializing_if = "Option::is_none")]
+    idempotency_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tty: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stdin: Option<Stdin>,
+    #[serde(rename = "max-memory", skip_serializing_if = "Option::is_none")]
+    max_memory: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nice: Option<i32>,
+    #[serde(rename = "max-open-files", skip_serializing_if = "Option::is_none")]
+    max_open_files: Option<u64>,
+}
+
+impl ReleaseCommand {
+    #[must_use]
+    pub fn new(command: impl Into<String>) -> Self {
+        ReleaseCommand {
+            command: command.into(),
+            args: vec![],
+            source: None,
+            name: None,
+            depends_on: vec![],
+            run_on: None,
+            idempotency_key: None,
+            tty: None,
+            stdin: None,
+            max_memory: None,
+            nice: None,
+            max_open_files: None,
+        }
+    }
+
+    #[must_use]
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    #[must_use]
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Names the buildpack declaring the command, shown in Release Phase output.
+    #[must_use]
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Names the command, for the `depends_on` of commands after it.
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Appends a named command before it that the command waits for, rather than the command
+    /// just before it.
+    #[must_use]
+    pub fn depends_on(mut self, name: impl Into<String>) -> Self {
+        self.depends_on.push(name.into());
+        self
+    }
+
+    /// Sets which outcome of the commands before it the command runs on.
+    #[must_use]
+    pub fn run_on(mut self, run_on: RunOn) -> Self {
+        self.run_on = Some(run_on);
+        self
+    }
+
+    /// Names the command's completion, so that it is skipped when a release is rerun after it
+    /// succeeded.
+    #[must_use]
+    pub fn idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Runs the command with a pseudo-terminal as its stdin & output.
+    #[must_use]
+    pub fn tty(mut self, tty: bool) -> Self {
+        self.tty = Some(tty);
+        self
+    }
+
+    /// Pipes the input to the command.
+    #[must_use]
+    pub fn stdin(mut self, stdin: Stdin) -> Self {
+        self.stdin = Some(stdin);
+        self
+    }
+
+    /// Limits the command's data memory, in bytes, or with a `K`, `M`, or `G` suffix, like
+    /// `"512M"`.
+    #[must_use]
+    pub fn max_memory(mut self, max_memory: impl Into<String>) -> Self {
+        self.max_memory = Some(max_memory.into());
+        self
+    }
+
+    /// Sets the command's scheduling priority, from `-20` (highest) to `19` (lowest).
+    #[must_use]
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.nice = Some(nice);
+        self
+    }
+
+    /// Limits the number of files the command may open.
+    #[must_use]
+    pub fn max_open_files(mut self, max_open_files: u64) -> Self {
+        self.max_open_files = Some(max_open_files);
+        self
+    }
+}
+
+/// Which outcome of the commands before it a release command runs on.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunOn {
+    Success,
+    Failure,
+    Always,
+}
+
+/// Input piped to a release command.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Stdin {
+    /// A file, relative to the app dir.
+    File(String),
+    /// Inline text, piped as is.
+    Text(String),
+}
+
+/// A storage profile, whose credentials are read from the env vars it names, never written in
+/// config.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StorageProfile {
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<String>,
+    #[serde(rename = "access-key-id-var", skip_serializing_if = "Option::is_none")]
+    access_key_id_var: Option<String>,
+    #[serde(
+        rename = "secret-access-key-var",
+        skip_serializing_if = "Option::is_none"
+    )]
+    secret_access_key_var: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stage: Option<String>,
+    #[serde(skip_serializing_if = "StorageGc::is_empty")]
+    gc: StorageGc,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+struct StorageGc {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retain: Option<usize>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    stages: BTreeMap<String, usize>,
+    #[serde(rename = "retain-days", skip_serializing_if = "Option::is_none")]
+    retain_days: Option<u64>,
+}
+
+impl StorageGc {
+    fn is_empty(&self) -> bool {
+        self == &StorageGc::default()
+    }
+}
+
+impl StorageProfile {
+    #[must_use]
+    pub fn new(url: impl Into<String>) -> Self {
+        StorageProfile {
+            url: url.into(),
+            region: None,
+            access_key_id_var: None,
+            secret_access_key_var: None,
+            stage: None,
+            gc: StorageGc::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// Names the env vars holding the access key id & secret access key.
+    #[must_use]
+    pub fn credential_vars(
+        mut self,
+        access_key_id_var: impl Into<String>,
+        secret_access_key_var: impl Into<String>,
+    ) -> Self {
+        self.access_key_id_var = Some(access_key_id_var.into());
+        self.secret_access_key_var = Some(secret_access_key_var.into());
+        self
+    }
+
+    /// Sets the pipeline stage that archives saved with the profile are tagged with.
+    #[must_use]
+    pub fn stage(mut self, stage: impl Into<String>) -> Self {
+        self.stage = Some(stage.into());
+        self
+    }
+
+    /// Sets the number of newest archives to keep, of those without a stage count.
+    #[must_use]
+    pub fn retain_count(mut self, count: usize) -> Self {
+        self.gc.retain = Some(count);
+        self
+    }
+
+    /// Sets the number of newest archives to keep of the pipeline stage.
+    #[must_use]
+    pub fn retain_stage(mut self, stage: impl Into<String>, count: usize) -> Self {
+        self.gc.stages.insert(stage.into(), count);
+        self
+    }
+
+    /// Sets the days within which archives are kept, even beyond the counts.
+    #[must_use]
+    pub fn retain_days(mut self, days: u64) -> Self {
+        self.gc.retain_days = Some(days);
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ReleasePhasePlanBuilder {
+    source: Option<String>,
+    release_build: Option<ReleaseCommand>,
+    release: Vec<ReleaseCommand>,
+    artifact_dir: Option<String>,
+    storage: BTreeMap<String, StorageProfile>,
+}
+
+impl ReleasePhasePlanBuilder {
+    /// Sets the source of every command that does not set its own.
+    #[must_use]
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Sets the single `release-build` command, replacing any set before.
+    #[must_use]
+    pub fn release_build(mut self, command: ReleaseCommand) -> Self {
+        self.release_build = Some(command);
+        self
+    }
+
+    /// Appends a `release` command, executed in the order added.
+    #[must_use]
+    pub fn release(mut self, command: ReleaseCommand) -> Self {
+        self.release.push(command);
+        self
+    }
+
+    /// Sets the dir of the release artifacts, relative to the app dir, such as the framework's
+    /// `public` or `dist` output, instead of `static-artifacts`.
+    #[must_use]
+    pub fn artifact_dir(mut self, dir: impl Into<String>) -> Self {
+        self.artifact_dir = Some(dir.into());
+        self
+    }
+
+    /// Adds a storage profile, such as a platform-managed bucket as the `default` profile, which
+    /// the app's own profile of the same name replaces.
+    #[must_use]
+    pub fn storage(mut self, name: impl Into<String>, profile: StorageProfile) -> Self {
+        self.storage.insert(name.into(), profile);
+        self
+    }
+
+    pub fn build(self) -> Result<ReleasePhasePlan, Error> {
+        if self.release_build.is_none() && self.release.is_empty() && self.storage.is_empty() {
+            return Err(Error::NoCommands);
+        }
+        let with_source = |mut command: ReleaseCommand| {
+            if command.command.trim().is_empty() {
+                return Err(Error::CommandEmpty);
+            }
+            if command.source.is_none() {
+                command.source.clone_from(&self.source);
+            }
+            Ok(command)
+        };
+        Ok(ReleasePhasePlan {
+            release_build: self.release_build.clone().map(with_source).transpose()?,
+            release: self
+                .release
+                .clone()
+                .into_iter()
+                .map(with_source)
+                .collect::<Result<_, _>>()?,
+            artifact_dir: self.artifact_dir.clone(),
+            storage: self.storage.clone(),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    CommandEmpty,
+    NoCommands,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CommandEmpty => write!(f, "Release Phase command must not be empty."),
+            Error::NoCommands => write!(
+                f,
+                "Release Phase plan must declare `release` or `release-build` commands, or `storage`."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use release_commands::{
+        generate_commands_config, Executable, GcConfig, ResourceLimits, StorageProfile as Profile,
+    };
+    use toml::toml;
+
+    use crate::{Error, ReleaseCommand, ReleasePhasePlan, RunOn, Stdin, StorageProfile};
+
+    #[test]
+    fn build_outputs_requires_metadata() {
+        let plan = ReleasePhasePlan::builder()
+            .source("My Awesome Buildpack")
+            .release_build(ReleaseCommand::new("bash").args(["-c", "npm run build"]))
+            .release(ReleaseCommand::new("bash").args(["-c", "echo '1'"]))
+            .release(ReleaseCommand::new("migrate").source("Another Buildpack"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            plan.to_table().unwrap(),
+            toml! {
+                [release-build]
+                command = "bash"
+                args = ["-c", "npm run build"]
+                source = "My Awesome Buildpack"
+
+                [[release]]
+                command = "bash"
+                args = ["-c", "echo '1'"]
+                source = "My Awesome Buildpack"
+
+                [[release]]
+                command = "migrate"
+                source = "Another Buildpack"
+            }
+        );
+    }
+
+    #[test]
+    fn artifact_dir_is_inherited_by_release_commands() {
+        let plan = ReleasePhasePlan::builder()
+            .release_build(ReleaseCommand::new("npm").args(["run", "build"]))
+            .artifact_dir("dist")
+            .build()
+            .unwrap();
+        let result =
+            generate_commands_config(&toml::Table::new().into(), plan.to_table().unwrap()).unwrap();
+        assert_eq!(result.artifact_dir(), "dist");
+        assert_eq!(
+            result.release.expect("release commands")[0].args,
+            Some(vec!["dist/".to_string()])
+        );
+    }
+
+    #[test]
+    fn build_fails_without_commands() {
+        assert_eq!(
+            ReleasePhasePlan::builder().source("x").build(),
+            Err(Error::NoCommands)
+        );
+    }
+
+    #[test]
+    fn build_fails_for_empty_command() {
+        assert_eq!(
+            ReleasePhasePlan::builder()
+                .release(ReleaseCommand::new(" "))
+                .build(),
+            Err(Error::CommandEmpty)
+        );
+    }
+
+    #[test]
+    fn command_options_are_inherited_by_release_commands() {
+        let plan = ReleasePhasePlan::builder()
+            .release(
+                ReleaseCommand::new("psql")
+                    .name("seed")
+                    .idempotency_key("seed-v1")
+                    .stdin(Stdin::File("db/seed.sql".to_string()))
+                    .max_memory("512M")
+                    .nice(10)
+                    .max_open_files(1024),
+            )
+            .release(
+                ReleaseCommand::new("notify")
+                    .depends_on("seed")
+                    .run_on(RunOn::Always)
+                    .tty(true),
+            )
+            .build()
+            .unwrap();
+        let result =
+            generate_commands_config(&toml::Table::new().into(), plan.to_table().unwrap()).unwrap();
+        assert_eq!(
+            result.release,
+            Some(vec![
+                Executable {
+                    command: "psql".to_string(),
+                    name: Some("seed".to_string()),
+                    idempotency_key: Some("seed-v1".to_string()),
+                    stdin: Some(release_commands::Stdin::File("db/seed.sql".to_string())),
+                    limits: ResourceLimits {
+                        max_memory: Some("512M".to_string()),
+                        nice: Some(10),
+                        max_open_files: Some(1024),
+                    },
+                    ..Executable::default()
+                },
+                Executable {
+                    command: "notify".to_string(),
+                    depends_on: Some(vec!["seed".to_string()]),
+                    run_on: Some(release_commands::RunOn::Always),
+                    tty: Some(true),
+                    ..Executable::default()
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn storage_is_inherited_by_release_commands() {
+        let plan = ReleasePhasePlan::builder()
+            .storage(
+                "default",
+                StorageProfile::new("s3://platform-bucket")
+                    .region("us-east-1")
+                    .credential_vars("PLATFORM_ACCESS_KEY_ID", "PLATFORM_SECRET_ACCESS_KEY")
+                    .stage("production")
+                    .retain_count(5)
+                    .retain_stage("review-app", 2)
+                    .retain_days(14),
+            )
+            .storage(
+                "staging",
+                StorageProfile::new("s3://platform-staging-bucket"),
+            )
+            .build()
+            .unwrap();
+        let project_config: toml::Value = toml! {
+            [com.heroku.phase.storage.staging]
+            url = "s3://project-staging-bucket"
+        }
+        .into();
+        let result = generate_commands_config(&project_config, plan.to_table().unwrap()).unwrap();
+        let profiles = result.storage.expect("storage profiles");
+        assert_eq!(
+            profiles.get("default"),
+            Some(&Profile {
+                url: Some("s3://platform-bucket".to_string()),
+                region: Some("us-east-1".to_string()),
+                access_key_id_var: Some("PLATFORM_ACCESS_KEY_ID".to_string()),
+                secret_access_key_var: Some("PLATFORM_SECRET_ACCESS_KEY".to_string()),
+                stage: Some("production".to_string()),
+                gc: Some(GcConfig {
+                    retain: Some(5),
+                    stages: Some([("review-app".to_string(), 2)].into()),
+                    retain_days: Some(14),
+                }),
+            })
+        );
+        assert_eq!(
+            profiles
+                .get("staging")
+                .and_then(|profile| profile.url.as_deref()),
+            Some("s3://project-staging-bucket"),
+            "project profiles take precedence"
+        );
+    }
+
+    #[test]
+    fn plan_is_inherited_by_release_commands() {
+        let plan = ReleasePhasePlan::builder()
+            .release(ReleaseCommand::new("bash").arg("migrate.sh"))
+            .build()
+            .unwrap();
+        let result =
+            generate_commands_config(&toml::Table::new().into(), plan.to_table().unwrap()).unwrap();
+        assert_eq!(
+            result.release,
+            Some(vec![Executable {
+                command: "bash".to_string(),
+                args: Some(vec!["migrate.sh".to_string()]),
+                ..Executable::default()
+            }])
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Brings up `MinIO` beside the app containers of an integration test, to save & load artifacts
+//! across containers, as in the release & web dynos of an app.
+
+use crate::{retry, start_container_entrypoint, DEFAULT_RETRIES, DEFAULT_RETRY_DELAY};
+use libcnb_test::{ContainerConfig, ContainerContext, TestContext};
+use std::process::{Command, Output};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_MINIO_IMAGE: &str = "minio/minio:latest";
+pub const MINIO_PORT: u16 = 9000;
+pub const MINIO_ACCESS_KEY_ID: &str = "release-phase-test";
+pub const MINIO_SECRET_ACCESS_KEY: &str = "release-phase-test-secret";
+pub const MINIO_BUCKET: &str = "release-phase-test";
+
+/// A `MinIO` server with an empty bucket, removed when dropped.
+pub struct Minio {
+    container_name: String,
+    endpoint_url: String,
+}
+
+impl Minio {
+    /// Starts `MinIO`, from the image of `INTEGRATION_TEST_MINIO_IMAGE`, or else
+    /// `minio/minio:latest`, waiting until it is ready & its bucket is created.
+    #[must_use]
+    pub fn start() -> Minio {
+        let unique = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("should not be an earlier time")
+            .as_nanos();
+        let container_name = format!("release-phase-test-minio-{unique}");
+        let image = std::env::var("INTEGRATION_TEST_MINIO_IMAGE")
+            .unwrap_or(DEFAULT_MINIO_IMAGE.to_string());
+        docker(&[
+            "run",
+            "--detach",
+            "--name",
+            &container_name,
+            "--env",
+            &format!("MINIO_ROOT_USER={MINIO_ACCESS_KEY_ID}"),
+            "--env",
+            &format!("MINIO_ROOT_PASSWORD={MINIO_SECRET_ACCESS_KEY}"),
+            &image,
+            "server",
+            "/data",
+        ]);
+        // Constructed before waiting, so that the container is removed if MinIO never starts.
+        let mut minio = Minio {
+            container_name,
+            endpoint_url: String::new(),
+        };
+        // App containers run on the default bridge network, as `MinIO` does, so reach it by its IP.
+        let ip_address = docker(&[
+            "inspect",
+            "--format",
+            "{{range .NetworkSettings.Networks}}{{.IPAddress}}{{end}}",
+            &minio.container_name,
+        ]);
+        minio.endpoint_url = format!("http://{}:{MINIO_PORT}", ip_address.trim());
+
+        // The `mc` client, bundled in the MinIO image, fails to set an alias until the server
+        // accepts connections.
+        retry(DEFAULT_RETRIES, DEFAULT_RETRY_DELAY, || {
+            minio.try_mc(&[
+                "alias",
+                "set",
+                "local",
+                &format!("http://127.0.0.1:{MINIO_PORT}"),
+                MINIO_ACCESS_KEY_ID,
+                MINIO_SECRET_ACCESS_KEY,
+            ])
+        })
+        .unwrap();
+        minio.mc(&["mb", &format!("local/{MINIO_BUCKET}")]);
+        minio
+    }
+
+    /// The S3 endpoint, reachable from app containers.
+    #[must_use]
+    pub fn endpoint_url(&self) -> &str {
+        &self.endpoint_url
+    }
+
+    /// Sets the env for the release-phase executables of an app container to store artifacts
+    /// under the prefix of the bucket.
+    pub fn configure<'a>(
+        &self,
+        config: &'a mut ContainerConfig,
+        prefix: &str,
+    ) -> &'a mut ContainerConfig {
+        config
+            .env(
+                "STATIC_ARTIFACTS_URL",
+                format!("s3://{MINIO_BUCKET}/{prefix}"),
+            )
+            .env("STATIC_ARTIFACTS_REGION", "us-east-1")
+            .env("STATIC_ARTIFACTS_ACCESS_KEY_ID", MINIO_ACCESS_KEY_ID)
+            .env(
+                "STATIC_ARTIFACTS_SECRET_ACCESS_KEY",
+                MINIO_SECRET_ACCESS_KEY,
+            )
+            .env("STATIC_ARTIFACTS_ENDPOINT_URL", &self.endpoint_url)
+    }
+
+    /// Lists the keys stored under the prefix of the bucket, relative to the prefix, sorted.
+    #[must_use]
+    pub fn list_keys(&self, prefix: &str) -> Vec<String> {
+        let listing = self.mc(&[
+            "ls",
+            "--recursive",
+            &format!("local/{MINIO_BUCKET}/{prefix}/"),
+        ]);
+        // Each line is like `[2024-10-01 12:00:00 UTC]  1.2KiB STANDARD release-v1.tgz`.
+        let mut keys: Vec<String> = listing
+            .lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .map(ToString::to_string)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    fn mc(&self, args: &[&str]) -> String {
+        self.try_mc(args).unwrap()
+    }
+
+    fn try_mc(&self, args: &[&str]) -> Result<String, String> {
+        let mut exec_args = vec!["exec", self.container_name.as_str(), "mc"];
+        exec_args.extend_from_slice(args);
+        try_docker(&exec_args)
+    }
+}
+
+impl Drop for Minio {
+    fn drop(&mut self) {
+        let _ = try_docker(&["rm", "--force", "--volumes", &self.container_name]);
+    }
+}
+
+/// An app image built by an integration test, with `MinIO` for its artifact storage, under a
+/// prefix of the bucket unique to the test.
+pub struct Compose<'a> {
+    ctx: &'a TestContext<'a>,
+    minio: Minio,
+    prefix: String,
+}
+
+impl<'a> Compose<'a> {
+    /// Starts `MinIO` for the app image of the test context.
+    #[must_use]
+    pub fn up(ctx: &'a TestContext<'a>) -> Compose<'a> {
+        let minio = Minio::start();
+        let prefix = minio
+            .container_name
+            .trim_start_matches("release-phase-test-minio-")
+            .to_string();
+        Compose { ctx, minio, prefix }
+    }
+
+    #[must_use]
+    pub fn minio(&self) -> &Minio {
+        &self.minio
+    }
+
+    /// Runs an app container with the entrypoint, such as `release` or `web`, configured to
+    /// store artifacts in `MinIO`, then calls `in_container` with its logs complete.
+    pub fn run(
+        &self,
+        entrypoint: &str,
+        config: &mut ContainerConfig,
+        in_container: impl Fn(&ContainerContext),
+    ) {
+        start_container_entrypoint(
+            self.ctx,
+            self.minio.configure(config, &self.prefix),
+            &entrypoint.to_string(),
+            in_container,
+        );
+    }
+
+    /// Lists the keys of the stored artifacts, such as `release-v1.tgz`, sorted.
+    #[must_use]
+    pub fn stored_keys(&self) -> Vec<String> {
+        self.minio.list_keys(&self.prefix)
+    }
+}
+
+fn docker(args: &[&str]) -> String {
+    try_docker(args).unwrap()
+}
+
+fn try_docker(args: &[&str]) -> Result<String, String> {
+    let Output {
+        status,
+        stdout,
+        stderr,
+    } = Command::new("docker")
+        .args(args)
+        .output()
+        .expect("docker CLI should be installed for integration tests");
+    if status.success() {
+        Ok(String::from_utf8_lossy(&stdout).to_string())
+    } else {
+        Err(format!(
+            "docker {} failed with {status}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&stderr)
+        ))
+    }
+}
@@ -0,0 +1,224 @@
+//! Synthesizes fixture apps for integration tests.
+//!
+//! ```no_run
+//! use release_phase_plan::ReleaseCommand;
+//! use test_support::{fixture::FixtureApp, release_phase_and_procfile_integration_test};
+//!
+//! let app = FixtureApp::new()
+//!     .release(ReleaseCommand::new("bash").args(["-c", "echo 'Hello!'"]))
+//!     .release_build_artifacts(&[("index.html", "Hello static world!")])
+//!     .web_server()
+//!     .create();
+//! release_phase_and_procfile_integration_test(app.path(), |ctx| {
+//!     // …
+//! });
+//! ```
+
+use release_phase_plan::{Error, ReleaseCommand, ReleasePhasePlan, ReleasePhasePlanBuilder};
+use std::fmt::Write;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The script that `release_build_artifacts` writes, relative to the app.
+pub const BUILD_ARTIFACTS_SCRIPT: &str = "bin/build-artifacts";
+
+static FIXTURE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// An app to build in an integration test, with its `project.toml`, `Procfile`, & other files.
+#[derive(Debug, Default, Clone)]
+pub struct FixtureApp {
+    plan: ReleasePhasePlanBuilder,
+    project_toml_extra: Vec<String>,
+    procfile: Vec<(String, String)>,
+    files: Vec<(PathBuf, String, bool)>,
+}
+
+impl FixtureApp {
+    #[must_use]
+    pub fn new() -> Self {
+        FixtureApp::default()
+    }
+
+    /// Appends a `release` command to `project.toml`, executed in the order added.
+    #[must_use]
+    pub fn release(mut self, command: ReleaseCommand) -> Self {
+        self.plan = self.plan.release(command);
+        self
+    }
+
+    /// Sets the `release-build` command of `project.toml`.
+    #[must_use]
+    pub fn release_build(mut self, command: ReleaseCommand) -> Self {
+        self.plan = self.plan.release_build(command);
+        self
+    }
+
+    /// Sets the `release-build` command to a script writing the files, as paths relative to
+    /// `static-artifacts` & their contents, like an asset build would.
+    #[must_use]
+    pub fn release_build_artifacts(self, files: &[(&str, &str)]) -> Self {
+        let mut script = String::from(
+            "#!/usr/bin/env bash\nset -euo pipefail\necho 'Build in Release Phase Buildpack!'\n",
+        );
+        for (path, contents) in files {
+            let path = format!(
+                "'/workspace/static-artifacts/{}'",
+                path.replace('\'', "'\\''")
+            );
+            // The heredoc ends at a delimiter that is not a line of the contents.
+            let mut delimiter = String::from("EOF");
+            while contents.lines().any(|line| line == delimiter) {
+                delimiter.push('_');
+            }
+            writeln!(
+                script,
+                "mkdir -p \"$(dirname {path})\"\ncat > {path} <<'{delimiter}'\n{contents}\n{delimiter}"
+            )
+            .expect("writing to a String succeeds");
+        }
+        self.executable(BUILD_ARTIFACTS_SCRIPT, script)
+            .release_build(ReleaseCommand::new("bash").arg(BUILD_ARTIFACTS_SCRIPT))
+    }
+
+    /// Appends TOML to `project.toml`, for config that the release commands do not cover, such
+    /// as `[com.heroku.phase.artifact-channels.docs]`.
+    #[must_use]
+    pub fn project_toml(mut self, toml: impl Into<String>) -> Self {
+        self.project_toml_extra.push(toml.into());
+        self
+    }
+
+    /// Adds a process type to the `Procfile`, which requires the `heroku/procfile` buildpack.
+    #[must_use]
+    pub fn procfile(mut self, process_type: impl Into<String>, command: impl Into<String>) -> Self {
+        self.procfile.push((process_type.into(), command.into()));
+        self
+    }
+
+    /// Adds a `web` process serving the loaded artifacts on `PORT`, for `assert_web_response`.
+    #[must_use]
+    pub fn web_server(self) -> Self {
+        self.procfile(
+            "web",
+            "python3 -m http.server \"$PORT\" --directory static-artifacts",
+        )
+    }
+
+    /// Adds a file, at a path relative to the app.
+    #[must_use]
+    pub fn file(mut self, path: impl Into<PathBuf>, contents: impl Into<String>) -> Self {
+        self.files.push((path.into(), contents.into(), false));
+        self
+    }
+
+    /// Adds an executable file, at a path relative to the app.
+    #[must_use]
+    pub fn executable(mut self, path: impl Into<PathBuf>, contents: impl Into<String>) -> Self {
+        self.files.push((path.into(), contents.into(), true));
+        self
+    }
+
+    /// Renders `project.toml`, for the Release Phase buildpack to build with.
+    #[must_use]
+    pub fn project_toml_contents(&self) -> String {
+        let mut project_toml = String::from(
+            "[_]\nschema-version = \"0.2\"\n\n[[io.buildpacks.group]]\nuri = \"heroku/release-phase\"\n",
+        );
+        match self.plan.clone().build() {
+            Ok(plan) => project_toml.push_str(&phase_toml(&plan)),
+            Err(Error::NoCommands) => {}
+            Err(error) => panic!("fixture app release commands should be valid: {error}"),
+        }
+        for extra in &self.project_toml_extra {
+            project_toml.push('\n');
+            project_toml.push_str(extra);
+            project_toml.push('\n');
+        }
+        project_toml
+    }
+
+    /// Writes the app into a new temporary directory, deleted when the returned fixture drops.
+    #[must_use]
+    pub fn create(&self) -> GeneratedFixture {
+        let pid = std::process::id();
+        let count = FIXTURE_COUNT.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!("release-phase-fixture-{pid}-{count}"));
+        let fixture = GeneratedFixture { dir };
+        self.write(&fixture.dir);
+        fixture
+    }
+
+    /// Writes the app into the directory.
+    pub fn write(&self, dir: &Path) {
+        write_file(
+            &dir.join("project.toml"),
+            &self.project_toml_contents(),
+            false,
+        );
+        if !self.procfile.is_empty() {
+            let procfile = self.procfile.iter().fold(
+                String::new(),
+                |mut procfile, (process_type, command)| {
+                    writeln!(procfile, "{process_type}: {command}")
+                        .expect("writing to a String succeeds");
+                    procfile
+                },
+            );
+            write_file(&dir.join("Procfile"), &procfile, false);
+        }
+        for (path, contents, is_executable) in &self.files {
+            write_file(&dir.join(path), contents, *is_executable);
+        }
+    }
+}
+
+/// A fixture app written to a temporary directory, which is deleted when dropped.
+pub struct GeneratedFixture {
+    dir: PathBuf,
+}
+
+impl GeneratedFixture {
+    /// The absolute path of the app, to pass as the fixture of integration tests.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        self.dir
+            .to_str()
+            .expect("temporary directory path should be UTF-8")
+    }
+}
+
+impl Drop for GeneratedFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// The `[com.heroku.phase]` tables of `project.toml`, from the Build Plan metadata, which shares
+// their format.
+fn phase_toml(plan: &ReleasePhasePlan) -> String {
+    let mut phase = toml::Table::new();
+    phase.insert(
+        "phase".to_string(),
+        plan.to_table()
+            .expect("release commands should serialize")
+            .into(),
+    );
+    let mut heroku = toml::Table::new();
+    heroku.insert("heroku".to_string(), phase.into());
+    let mut com = toml::Table::new();
+    com.insert("com".to_string(), heroku.into());
+    let toml = toml::to_string(&com).expect("release commands should serialize");
+    format!("\n{toml}")
+}
+
+fn write_file(path: &Path, contents: &str, is_executable: bool) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    fs::write(path, contents).unwrap();
+    if is_executable {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! Reads files out of the image built by an integration test, running `bash` in a container of the
+//! image, rather than the CNB launcher.
+
+use libcnb_test::{ContainerConfig, TestContext};
+
+/// The layer of the Release Phase buildpack, with `release-commands.toml` & `bin/`.
+pub const RELEASE_PHASE_LAYER: &str = "/layers/heroku_release-phase/main";
+
+// Printed to stderr by the helper scripts, since container logs do not show the exit status.
+const NOT_FOUND_MARKER: &str = "release-phase-test: not found";
+
+/// Reads the file at the absolute path in the image. Panics when it does not exist.
+#[must_use]
+pub fn read_image_file(ctx: &TestContext, path: &str) -> String {
+    let (stdout, stderr) = run_in_image(
+        ctx,
+        &format!("if [ -f \"$1\" ]; then cat -- \"$1\"; else echo '{NOT_FOUND_MARKER}' >&2; fi"),
+        path,
+    );
+    assert!(
+        !stderr.contains(NOT_FOUND_MARKER),
+        "image should contain file {path}"
+    );
+    stdout
+}
+
+/// Whether a file, directory, or symlink exists at the absolute path in the image.
+#[must_use]
+pub fn image_path_exists(ctx: &TestContext, path: &str) -> bool {
+    let (stdout, _) = run_in_image(
+        ctx,
+        "if [ -e \"$1\" ] || [ -L \"$1\" ]; then echo exists; fi",
+        path,
+    );
+    stdout.trim() == "exists"
+}
+
+/// Asserts that the absolute path in the image is an executable file, following symlinks, such
+/// as the executables installed into the Release Phase layer.
+pub fn assert_image_executable(ctx: &TestContext, path: &str) {
+    let (stdout, _) = run_in_image(
+        ctx,
+        "if [ -f \"$1\" ] && [ -x \"$1\" ]; then echo executable; fi",
+        path,
+    );
+    assert_eq!(
+        stdout.trim(),
+        "executable",
+        "image should contain executable {path}"
+    );
+}
+
+/// Reads & parses the `release-commands.toml` generated by the build. Panics when the build
+/// did not write it, such as when no release commands are configured.
+#[must_use]
+pub fn read_release_commands_toml(ctx: &TestContext) -> toml::Table {
+    let path = format!("{RELEASE_PHASE_LAYER}/release-commands.toml");
+    read_image_file(ctx, &path)
+        .parse::<toml::Table>()
+        .unwrap_or_else(|error| panic!("{path} should be valid TOML: {error}"))
+}
+
+// Runs the bash script in a container of the image, with the argument as `$1`, returning its
+// stdout & stderr.
+fn run_in_image(ctx: &TestContext, script: &str, arg: &str) -> (String, String) {
+    let mut output = (String::new(), String::new());
+    ctx.start_container(
+        ContainerConfig::new()
+            .entrypoint("bash")
+            .command(["-c", script, "bash", arg]),
+        |container| {
+            let logs = container.logs_wait();
+            output = (logs.stdout, logs.stderr);
+        },
+    );
+    output
+}
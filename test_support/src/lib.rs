@@ -1,6 +1,10 @@
 // This module is only used for testing, where using unwrap() is acceptable.
 #![allow(clippy::unwrap_used)]
 
+pub mod compose;
+pub mod fixture;
+pub mod image;
+
 use libcnb::data::buildpack_id;
 use libcnb_test::{
     assert_contains, BuildConfig, BuildpackReference, ContainerConfig, ContainerContext,
@@ -16,9 +20,68 @@ pub const PORT: u16 = 8080;
 pub const DEFAULT_RETRIES: u32 = 10;
 pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
 
+// A builder & the target the buildpack is compiled for, to run an integration test with.
+struct IntegrationTestTarget {
+    builder: String,
+    target_triple: &'static str,
+}
+
+/// Returns each combination of the comma-separated builders of `INTEGRATION_TEST_CNB_BUILDER`
+/// and architectures of `INTEGRATION_TEST_CNB_ARCH`, `amd64` or `arm64`, for a test grid in one
+/// invocation. Without `INTEGRATION_TEST_CNB_ARCH`, each builder runs once, for the arch it runs
+/// on the host. Combinations that cannot run on the host are skipped.
 #[must_use]
-fn get_integration_test_builder() -> String {
-    std::env::var("INTEGRATION_TEST_CNB_BUILDER").unwrap_or(DEFAULT_BUILDER.to_string())
+fn get_integration_test_targets() -> Vec<IntegrationTestTarget> {
+    let builders = env_list("INTEGRATION_TEST_CNB_BUILDER")
+        .unwrap_or_else(|| vec![DEFAULT_BUILDER.to_string()]);
+    let arches = env_list("INTEGRATION_TEST_CNB_ARCH");
+    let mut targets = vec![];
+    for builder in builders {
+        // TODO: Once Pack build supports `--platform` and libcnb-test adjusted accordingly, build
+        // for arches other than the host's. libcnb-test does not yet pass `--run-image` either,
+        // so the run image is always the builder's default.
+        //
+        // The buildpack runs on the builder's arch for the host, which is ARM64 iff the builder
+        // supports multi-arch and the host is ARM64.
+        let runnable_arch = if builder == "heroku/builder:24" && cfg!(target_arch = "aarch64") {
+            "arm64"
+        } else {
+            "amd64"
+        };
+        let builder_arches = arches
+            .clone()
+            .unwrap_or_else(|| vec![runnable_arch.to_string()]);
+        for arch in builder_arches {
+            let target_triple = match arch.as_str() {
+                "amd64" => "x86_64-unknown-linux-musl",
+                "arm64" => "aarch64-unknown-linux-musl",
+                _ => panic!("INTEGRATION_TEST_CNB_ARCH should list amd64 or arm64, not {arch}"),
+            };
+            if arch != runnable_arch {
+                println!("skipping integration test with {builder} for {arch}, on this host it runs {runnable_arch}");
+                continue;
+            }
+            targets.push(IntegrationTestTarget {
+                builder: builder.clone(),
+                target_triple,
+            });
+        }
+    }
+    targets
+}
+
+fn env_list(name: &str) -> Option<Vec<String>> {
+    std::env::var(name)
+        .ok()
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+        })
+        .filter(|items| !items.is_empty())
 }
 
 pub fn release_phase_integration_test(fixture: &str, test_body: fn(TestContext)) {
@@ -70,23 +133,20 @@ fn integration_test_with_config(
         .map(PathBuf::from)
         .expect("The CARGO_MANIFEST_DIR should be automatically set by Cargo when running tests but it was not");
 
-    let builder = get_integration_test_builder();
     let app_dir = cargo_manifest_dir.join("tests").join(fixture);
 
-    // TODO: Once Pack build supports `--platform` and libcnb-test adjusted accordingly, change this
-    // to allow configuring the target arch independently of the builder name (eg via env var).
-    let target_triple = match builder.as_str() {
-        // Compile the buildpack for ARM64 iff the builder supports multi-arch and the host is ARM64.
-        "heroku/builder:24" if cfg!(target_arch = "aarch64") => "aarch64-unknown-linux-musl",
-        _ => "x86_64-unknown-linux-musl",
-    };
-
-    let mut build_config = BuildConfig::new(builder, app_dir);
-    build_config.buildpacks(buildpacks);
-    build_config.target_triple(target_triple);
-    with_config(&mut build_config);
+    for target in get_integration_test_targets() {
+        println!(
+            "running integration test with {} for {}",
+            target.builder, target.target_triple
+        );
+        let mut build_config = BuildConfig::new(target.builder, &app_dir);
+        build_config.buildpacks(buildpacks);
+        build_config.target_triple(target.target_triple);
+        with_config(&mut build_config);
 
-    TestRunner::default().build(build_config, test_body);
+        TestRunner::default().build(build_config, test_body);
+    }
 }
 
 pub fn retry<T, E>(
@@ -180,3 +240,41 @@ where
         Some(error) => panic::resume_unwind(error),
     }
 }
+
+/// Polls the container's logs until lines of stdout or stderr match the predicate, returning the
+/// matched lines, stdout's first. Polls often at first, for logs that appear quickly, then backs
+/// off, since each poll fetches the whole log. Panics with the logs when the timeout passes first.
+pub fn wait_for_log<P>(container: &ContainerContext, predicate: P, timeout: Duration) -> Vec<String>
+where
+    P: Fn(&str) -> bool,
+{
+    let start_time = SystemTime::now();
+    let mut poll_interval = Duration::from_millis(10);
+    loop {
+        let container_logs = container.logs_now();
+        let matched_lines: Vec<String> = container_logs
+            .stdout
+            .lines()
+            .chain(container_logs.stderr.lines())
+            .filter(|line| predicate(line))
+            .map(ToString::to_string)
+            .collect();
+        if !matched_lines.is_empty() {
+            return matched_lines;
+        }
+        let elapsed = SystemTime::now()
+            .duration_since(start_time)
+            .expect("should not be an earlier time");
+        assert!(
+            elapsed < timeout,
+            "no log line matched within {timeout:?}
+------ begin container logs (stderr) ------
+{}------ end (stderr) & begin (stdout) ------
+{}------ end container logs ------",
+            container_logs.stderr,
+            container_logs.stdout
+        );
+        std::thread::sleep(poll_interval.min(timeout.saturating_sub(elapsed)));
+        poll_interval = (poll_interval * 2).min(Duration::from_millis(500));
+    }
+}